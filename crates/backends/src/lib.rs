@@ -0,0 +1,161 @@
+//! Builds the `StatePersistence` backend selected by `AppConfig::database_type`.
+//!
+//! The filesystem and in-memory backends live in the core library; every other backend lives in
+//! its own crate, behind a cargo feature of the same name. All features are enabled by default,
+//! so a deployment can switch stores by changing its configuration only. Selecting a backend
+//! whose feature is disabled is reported as a configuration error.
+//!
+//! # Example
+//! ```rust
+//! use template_teleporter_backends::create_backend;
+//! use template_teleporter_core::{AppConfig, DatabaseType, StateManager};
+//! # async fn run() -> template_teleporter_core::Result<()> {
+//! let config = AppConfig::new(DatabaseType::Memory, "TemplateState");
+//! let manager = StateManager::new(create_backend(&config).await?);
+//! # Ok(())
+//! # }
+//! ```
+
+use template_teleporter_core::{
+    AppConfig, CoreError, DatabaseType, FilesystemBackend, InMemoryBackend, Result,
+    RetryingBackend, StatePersistence,
+};
+
+#[cfg(test)]
+#[path = "lib_tests.rs"]
+mod tests;
+
+/// The environment variable holding the Cosmos DB master key, unless the `cosmosdb` section of
+/// the configuration names another one.
+pub const DEFAULT_COSMOSDB_KEY_ENV: &str = "COSMOSDB_KEY";
+
+/// Creates the backend selected by `config.database_type`, configured from the backend's section
+/// of the configuration.
+///
+/// The configuration is validated first, so configurations built in code are checked like
+/// those read by `parse_config`. Every backend keeps `AppConfig::history_retention` versions of
+/// each state in its history. Backends with a schema (SQLite, PostgreSQL) migrate it; tables
+/// and containers of cloud backends are expected to be provisioned already. The backend is
+/// wrapped in a `RetryingBackend` with the default `RetryPolicy`, so transient failures of its
+/// calls are retried with exponential backoff.
+///
+/// # Errors
+/// Returns `CoreError::MissingConfiguration` or `CoreError::InvalidConfiguration` if the
+/// configuration is incomplete or invalid for the selected backend, or was built without the
+/// backend's feature, and the backend's own error if it cannot be created, e.g.
+/// `CoreError::TransientDatabaseError` if the database cannot be reached.
+pub async fn create_backend(config: &AppConfig) -> Result<Box<dyn StatePersistence>> {
+    config.validate()?;
+    let backend = open_backend(config).await?;
+    Ok(Box::new(RetryingBackend::new(backend)))
+}
+
+/// Creates the backend selected by `config.database_type`, without retries.
+async fn open_backend(config: &AppConfig) -> Result<Box<dyn StatePersistence>> {
+    match config.database_type {
+        DatabaseType::Filesystem => {
+            let settings = section(config, &config.filesystem, "filesystem.path")?;
+            Ok(Box::new(
+                FilesystemBackend::new(&settings.path)?
+                    .with_history_retention(config.history_retention()),
+            ))
+        }
+        DatabaseType::Memory => Ok(Box::new(
+            InMemoryBackend::new().with_history_retention(config.history_retention()),
+        )),
+        DatabaseType::Sqlite => sqlite(config),
+        DatabaseType::Dynamodb => dynamodb(config).await,
+        DatabaseType::Cosmosdb => cosmosdb(config),
+        DatabaseType::Postgres => postgres(config).await,
+    }
+}
+
+fn section<'a, T>(config: &AppConfig, section: &'a Option<T>, setting: &str) -> Result<&'a T> {
+    section.as_ref().ok_or_else(|| {
+        CoreError::MissingConfiguration(format!(
+            "{} (required for databaseType {})",
+            setting, config.database_type
+        ))
+    })
+}
+
+#[allow(dead_code)] // Unused when every backend feature is enabled.
+fn not_compiled_in(config: &AppConfig) -> CoreError {
+    CoreError::InvalidConfiguration(format!(
+        "databaseType {} is not supported by this build; enable the `{}` feature",
+        config.database_type, config.database_type
+    ))
+}
+
+#[cfg(feature = "sqlite")]
+fn sqlite(config: &AppConfig) -> Result<Box<dyn StatePersistence>> {
+    use template_teleporter_sqlite_backend::SqliteBackend;
+
+    let settings = section(config, &config.sqlite, "sqlite.path")?;
+    Ok(Box::new(
+        SqliteBackend::new(&settings.path)?.with_history_retention(config.history_retention()),
+    ))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn sqlite(config: &AppConfig) -> Result<Box<dyn StatePersistence>> {
+    Err(not_compiled_in(config))
+}
+
+#[cfg(feature = "dynamodb")]
+async fn dynamodb(config: &AppConfig) -> Result<Box<dyn StatePersistence>> {
+    use template_teleporter_dynamodb_backend::DynamoDbBackend;
+
+    Ok(Box::new(
+        DynamoDbBackend::from_app_config(config)
+            .await
+            .with_history_retention(config.history_retention()),
+    ))
+}
+
+#[cfg(not(feature = "dynamodb"))]
+async fn dynamodb(config: &AppConfig) -> Result<Box<dyn StatePersistence>> {
+    Err(not_compiled_in(config))
+}
+
+#[cfg(feature = "cosmosdb")]
+fn cosmosdb(config: &AppConfig) -> Result<Box<dyn StatePersistence>> {
+    use template_teleporter_cosmosdb_backend::{CosmosDbBackend, CosmosDbConfig};
+
+    let key_env = config
+        .cosmosdb
+        .as_ref()
+        .and_then(|settings| settings.master_key_env.as_deref())
+        .unwrap_or(DEFAULT_COSMOSDB_KEY_ENV);
+    let master_key = std::env::var(key_env).map_err(|_| {
+        CoreError::MissingConfiguration(format!(
+            "environment variable {} holding the Cosmos DB master key",
+            key_env
+        ))
+    })?;
+    let cosmos_config = CosmosDbConfig::from_app_config(config, master_key)?;
+    Ok(Box::new(
+        CosmosDbBackend::new(cosmos_config)?.with_history_retention(config.history_retention()),
+    ))
+}
+
+#[cfg(not(feature = "cosmosdb"))]
+fn cosmosdb(config: &AppConfig) -> Result<Box<dyn StatePersistence>> {
+    Err(not_compiled_in(config))
+}
+
+#[cfg(feature = "postgres")]
+async fn postgres(config: &AppConfig) -> Result<Box<dyn StatePersistence>> {
+    use template_teleporter_postgres_backend::PostgresBackend;
+
+    Ok(Box::new(
+        PostgresBackend::from_app_config(config)
+            .await?
+            .with_history_retention(config.history_retention()),
+    ))
+}
+
+#[cfg(not(feature = "postgres"))]
+async fn postgres(config: &AppConfig) -> Result<Box<dyn StatePersistence>> {
+    Err(not_compiled_in(config))
+}
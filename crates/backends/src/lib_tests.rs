@@ -0,0 +1,141 @@
+use super::*;
+use chrono::Utc;
+use tempfile::tempdir;
+use template_teleporter_core::{
+    CosmosDbSettings, DynamoDbSettings, FilesystemSettings, SqliteSettings, StateKey, TemplateState,
+};
+
+fn state_for(repo: &str, template_path: &str) -> TemplateState {
+    TemplateState {
+        repo: repo.to_string(),
+        template_path: template_path.to_string(),
+        source_repository: "owner/master".to_string(),
+        master_checksum: "checksum".to_string(),
+        deployed_checksum: None,
+        last_updated_utc: Utc::now(),
+        version: 0,
+    }
+}
+
+/// Writes and reads back a state, to check that the backend is usable.
+async fn assert_round_trip(backend: &dyn StatePersistence) {
+    backend
+        .update_state(&state_for("org/a", "README.md"))
+        .await
+        .unwrap();
+    let stored = backend
+        .get_state(&StateKey::new("org/a", "README.md"))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.version, 1);
+}
+
+#[tokio::test]
+async fn test_create_backend_filesystem() {
+    let dir = tempdir().unwrap();
+    let mut config = AppConfig::new(DatabaseType::Filesystem, "TemplateState");
+    config.filesystem = Some(FilesystemSettings {
+        path: dir.path().join("state"),
+    });
+
+    let backend = create_backend(&config).await.unwrap();
+
+    assert_round_trip(backend.as_ref()).await;
+    assert!(dir.path().join("state").is_dir());
+}
+
+#[tokio::test]
+async fn test_create_backend_memory() {
+    let config = AppConfig::new(DatabaseType::Memory, "TemplateState");
+
+    let backend = create_backend(&config).await.unwrap();
+
+    assert_round_trip(backend.as_ref()).await;
+}
+
+#[tokio::test]
+async fn test_create_backend_sqlite() {
+    let dir = tempdir().unwrap();
+    let mut config = AppConfig::new(DatabaseType::Sqlite, "TemplateState");
+    config.sqlite = Some(SqliteSettings {
+        path: dir.path().join("state.db"),
+    });
+
+    let backend = create_backend(&config).await.unwrap();
+
+    assert_round_trip(backend.as_ref()).await;
+    assert!(dir.path().join("state.db").is_file());
+}
+
+#[tokio::test]
+async fn test_create_backend_applies_history_retention() {
+    let dir = tempdir().unwrap();
+    let mut config = AppConfig::new(DatabaseType::Sqlite, "TemplateState");
+    config.sqlite = Some(SqliteSettings {
+        path: dir.path().join("state.db"),
+    });
+    config.history_retention = Some(2);
+    let backend = create_backend(&config).await.unwrap();
+    let state = state_for("org/a", "README.md");
+    for _ in 0..3 {
+        backend.update_state(&state).await.unwrap();
+    }
+
+    let history = backend.get_state_history(&state.key()).await.unwrap();
+
+    let versions: Vec<u64> = history.iter().map(|state| state.version).collect();
+    assert_eq!(versions, [3, 2]);
+}
+
+#[tokio::test]
+async fn test_create_backend_dynamodb() {
+    let mut config = AppConfig::new(DatabaseType::Dynamodb, "TemplateState")
+        .with_database_endpoint("http://localhost:8000");
+    config.dynamodb = Some(DynamoDbSettings {
+        region: Some("us-east-1".to_string()),
+    });
+
+    // Creating the client does not contact DynamoDB
+    assert!(create_backend(&config).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_create_backend_cosmosdb_reads_master_key_from_environment() {
+    let key_env = "TEMPLATE_TELEPORTER_BACKENDS_TEST_COSMOSDB_KEY";
+    let mut config = AppConfig::new(DatabaseType::Cosmosdb, "TemplateState")
+        .with_database_endpoint("https://account.documents.azure.com");
+    config.cosmosdb = Some(CosmosDbSettings {
+        database: None,
+        master_key_env: Some(key_env.to_string()),
+    });
+
+    let result = create_backend(&config).await;
+    assert!(matches!(result, Err(CoreError::MissingConfiguration(msg)) if msg.contains(key_env)));
+
+    // Only this test reads the variable
+    std::env::set_var(key_env, "a2V5");
+    assert!(create_backend(&config).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_create_backend_postgres_unreachable() {
+    // Nothing listens on port 1, so connecting fails without waiting for a timeout
+    let config = AppConfig::new(DatabaseType::Postgres, "template_state")
+        .with_database_endpoint("postgres://postgres@127.0.0.1:1/state");
+
+    let result = create_backend(&config).await;
+
+    assert!(matches!(result, Err(CoreError::TransientDatabaseError(_))));
+}
+
+#[tokio::test]
+async fn test_create_backend_validates_config() {
+    let config = AppConfig::new(DatabaseType::Filesystem, "TemplateState");
+
+    let result = create_backend(&config).await;
+
+    assert!(
+        matches!(result, Err(CoreError::MissingConfiguration(msg)) if msg.contains("filesystem.path"))
+    );
+}
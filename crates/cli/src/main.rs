@@ -0,0 +1,102 @@
+//! Command line interface of the Template-Teleporter.
+//!
+//! # Usage
+//! ```text
+//! template-teleporter migrate-state --from filesystem.yaml --to sqlite.yaml \
+//!     --checkpoint migration.checkpoint
+//! ```
+
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use template_teleporter_backends::create_backend;
+use template_teleporter_core::{
+    parse_config, Result, StateMigration, DEFAULT_MIGRATION_BATCH_SIZE,
+};
+
+#[cfg(test)]
+#[path = "main_tests.rs"]
+mod tests;
+
+#[derive(Parser, Debug)]
+#[command(name = "template-teleporter", version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Copies every template state from one configured backend to another.
+    ///
+    /// The states of both backends are compared afterwards. An interrupted migration started
+    /// again with the same checkpoint file continues where it stopped.
+    MigrateState {
+        /// The configuration file of the backend to copy from.
+        #[arg(long, value_name = "CONFIG")]
+        from: PathBuf,
+
+        /// The configuration file of the backend to copy to.
+        #[arg(long, value_name = "CONFIG")]
+        to: PathBuf,
+
+        /// The number of states read from the source at a time.
+        #[arg(long, default_value_t = DEFAULT_MIGRATION_BATCH_SIZE)]
+        batch_size: usize,
+
+        /// A file recording the progress, to resume an interrupted migration from.
+        #[arg(long, value_name = "FILE")]
+        checkpoint: Option<PathBuf>,
+
+        /// Skips comparing the states of both backends after copying.
+        #[arg(long)]
+        no_verify: bool,
+    },
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run(Cli::parse()).await {
+        Ok(summary) => {
+            println!("{}", summary);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Runs a command and returns the summary to print.
+async fn run(cli: Cli) -> Result<String> {
+    match cli.command {
+        Command::MigrateState {
+            from,
+            to,
+            batch_size,
+            checkpoint,
+            no_verify,
+        } => {
+            let source = create_backend(&parse_config(&from)?).await?;
+            let target = create_backend(&parse_config(&to)?).await?;
+            let mut migration = StateMigration::new(source.as_ref(), target.as_ref())
+                .with_batch_size(batch_size)
+                .with_verification(!no_verify);
+            if let Some(checkpoint) = checkpoint {
+                migration = migration.with_checkpoint(checkpoint);
+            }
+
+            let report = migration.run().await?;
+            let mut summary = format!("Copied {} states", report.copied);
+            if let Some(key) = &report.resumed_after {
+                summary.push_str(&format!(", resuming after {}", key));
+            }
+            match &report.verified {
+                Some(verified) => summary.push_str(&format!("; verified {}", verified)),
+                None => summary.push_str("; not verified"),
+            }
+            Ok(summary)
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use super::*;
+use chrono::Utc;
+use clap::CommandFactory;
+use std::path::Path;
+use template_teleporter_core::{FilesystemBackend, StateKey, StatePersistence, TemplateState};
+
+fn state_for(repo: &str, template_path: &str) -> TemplateState {
+    TemplateState {
+        repo: repo.to_string(),
+        template_path: template_path.to_string(),
+        source_repository: "owner/master".to_string(),
+        master_checksum: "checksum".to_string(),
+        deployed_checksum: None,
+        last_updated_utc: Utc::now(),
+        version: 0,
+    }
+}
+
+fn write_config(path: &Path, content: &str) {
+    std::fs::write(path, format!("---\ntableName: TemplateState\n{}", content)).unwrap();
+}
+
+#[test]
+fn test_cli_definition() {
+    Cli::command().debug_assert();
+}
+
+#[tokio::test]
+async fn test_migrate_state_from_filesystem_to_sqlite() {
+    let dir = tempfile::tempdir().unwrap();
+    let state_dir = dir.path().join("state");
+    let database = dir.path().join("state.db");
+    let source = FilesystemBackend::new(&state_dir).unwrap();
+    for path in ["README.md", "LICENSE", ".github/CODEOWNERS"] {
+        source
+            .update_state(&state_for("org/a", path))
+            .await
+            .unwrap();
+    }
+    write_config(
+        &dir.path().join("from.yaml"),
+        &format!(
+            "databaseType: filesystem\nfilesystem:\n  path: {}\n",
+            state_dir.display()
+        ),
+    );
+    write_config(
+        &dir.path().join("to.yaml"),
+        &format!(
+            "databaseType: sqlite\nsqlite:\n  path: {}\n",
+            database.display()
+        ),
+    );
+    let checkpoint = dir.path().join("migration.checkpoint");
+    let cli = Cli::try_parse_from([
+        "template-teleporter".as_ref(),
+        "migrate-state".as_ref(),
+        "--from".as_ref(),
+        dir.path().join("from.yaml").as_os_str(),
+        "--to".as_ref(),
+        dir.path().join("to.yaml").as_os_str(),
+        "--batch-size".as_ref(),
+        "2".as_ref(),
+        "--checkpoint".as_ref(),
+        checkpoint.as_os_str(),
+    ])
+    .unwrap();
+
+    let summary = run(cli).await.unwrap();
+
+    assert!(summary.starts_with("Copied 3 states; verified 3 states"));
+    assert!(!checkpoint.exists());
+    let target = create_backend(&parse_config(&dir.path().join("to.yaml")).unwrap())
+        .await
+        .unwrap();
+    let stored = target
+        .get_state(&StateKey::new("org/a", ".github/CODEOWNERS"))
+        .await
+        .unwrap();
+    assert!(stored.is_some());
+}
+
+#[tokio::test]
+async fn test_migrate_state_invalid_config() {
+    let dir = tempfile::tempdir().unwrap();
+    write_config(&dir.path().join("from.yaml"), "databaseType: memory\n");
+    write_config(&dir.path().join("to.yaml"), "databaseType: sqlite\n");
+    let cli = Cli::try_parse_from([
+        "template-teleporter".as_ref(),
+        "migrate-state".as_ref(),
+        "--from".as_ref(),
+        dir.path().join("from.yaml").as_os_str(),
+        "--to".as_ref(),
+        dir.path().join("to.yaml").as_os_str(),
+        "--no-verify".as_ref(),
+    ])
+    .unwrap();
+
+    let result = run(cli).await;
+
+    assert!(result.unwrap_err().to_string().contains("sqlite.path"));
+}
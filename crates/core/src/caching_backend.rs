@@ -0,0 +1,414 @@
+//! Implements a `StatePersistence` decorator which caches the states read from another backend.
+//!
+//! Processing a push reads the state of every template in every target repository, which for
+//! large fan-outs means thousands of reads against the database. A `CachingBackend` keeps the
+//! most recently used states in memory for a limited time, so repeated reads of the same state
+//! are served without a round trip. States written through the cache update it; states written
+//! by other processes are seen once their cached entry expires.
+
+use crate::clock::{Clock, SystemClock};
+use crate::state_manager::StatePersistence;
+use crate::types::{Result, StateKey, StatePage, StateQuery, TemplateState};
+use async_trait::async_trait;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+#[path = "caching_backend_tests.rs"]
+mod tests;
+
+/// The number of states a `CachingBackend` holds, unless configured otherwise.
+pub const DEFAULT_CACHE_CAPACITY: usize = 10_000;
+
+/// How long a `CachingBackend` serves a cached state, unless configured otherwise.
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// The hit and miss counters of a `CachingBackend`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// The number of `get_state` calls served from the cache.
+    pub hits: u64,
+
+    /// The number of `get_state` calls passed on to the backend.
+    pub misses: u64,
+}
+
+impl CacheStats {
+    /// Returns the fraction of `get_state` calls served from the cache, or 0 if there were none.
+    pub fn hit_ratio(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+/// A cached result of `get_state`; `None` records that there is no state for the key.
+#[derive(Debug)]
+struct Entry {
+    state: Option<TemplateState>,
+    expires_at: Instant,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct Cache {
+    entries: HashMap<StateKey, Entry>,
+    /// The keys of the entries by their `last_used` tick, least recently used first.
+    recency: BTreeMap<u64, StateKey>,
+    tick: u64,
+    /// Incremented by every write and invalidation, so that a read which started before a write
+    /// does not cache the state it read.
+    generation: u64,
+    stats: CacheStats,
+}
+
+impl Cache {
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    /// Returns the entry for a key if it has not expired, marking it as recently used.
+    fn get(&mut self, key: &StateKey, now: Instant) -> Option<Option<TemplateState>> {
+        let tick = self.next_tick();
+        let entry = self.entries.get_mut(key)?;
+        if entry.expires_at <= now {
+            self.remove(key);
+            return None;
+        }
+        self.recency.remove(&entry.last_used);
+        self.recency.insert(tick, key.clone());
+        entry.last_used = tick;
+        Some(entry.state.clone())
+    }
+
+    fn insert(&mut self, key: StateKey, state: Option<TemplateState>, expires_at: Instant) {
+        self.remove(&key);
+        let tick = self.next_tick();
+        self.recency.insert(tick, key.clone());
+        self.entries.insert(
+            key,
+            Entry {
+                state,
+                expires_at,
+                last_used: tick,
+            },
+        );
+    }
+
+    fn remove(&mut self, key: &StateKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.last_used);
+        }
+    }
+
+    /// Removes the least recently used entries until at most `capacity` remain.
+    fn evict(&mut self, capacity: usize) {
+        while self.entries.len() > capacity {
+            match self.recency.pop_first() {
+                Some((_, key)) => {
+                    self.entries.remove(&key);
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+/// A `StatePersistence` backend which serves `get_state` from a bounded, expiring cache in front
+/// of another backend.
+///
+/// The cache holds the results of at most `capacity` keys, evicting the least recently used
+/// ones, and serves each for at most `ttl` after it was read or written. Results for keys without
+/// a state are cached as well. Writes go through to the backend and then update the cache:
+///   - `update_state_if` caches the written state with the version the backend assigned.
+///   - `update_state` and `update_states` read the written states back, as the backend does not
+///     return the versions it assigned, and cache them.
+///   - `delete_state` caches that there is no state.
+///   - A failed write, including a `CoreError::StateConflict`, evicts the key, so the next read
+///     fetches the state another writer stored.
+///
+/// `get_states` serves the cached keys and reads the others from the backend in one batch.
+/// `list_states` and `get_state_history` are always passed on to the backend.
+///
+/// Clones share the cache and its counters, so a clone kept aside can report the `CacheStats`
+/// of a backend handed to a `StateManager`.
+///
+/// # Example
+/// ```rust
+/// use std::time::Duration;
+/// use template_teleporter_core::{CachingBackend, InMemoryBackend, StateManager};
+/// let cache = CachingBackend::new(InMemoryBackend::new())
+///     .with_capacity(1_000)
+///     .with_ttl(Duration::from_secs(30));
+/// let manager = StateManager::new(Box::new(cache.clone()));
+/// println!("Cache hit ratio: {:.2}", cache.stats().hit_ratio());
+/// ```
+pub struct CachingBackend<B> {
+    backend: Arc<B>,
+    cache: Arc<Mutex<Cache>>,
+    capacity: usize,
+    ttl: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl<B: StatePersistence> CachingBackend<B> {
+    /// Wraps a backend with a cache of [`DEFAULT_CACHE_CAPACITY`] states, each served for
+    /// [`DEFAULT_CACHE_TTL`], measured with the `SystemClock`.
+    pub fn new(backend: B) -> Self {
+        Self {
+            backend: Arc::new(backend),
+            cache: Arc::new(Mutex::new(Cache::default())),
+            capacity: DEFAULT_CACHE_CAPACITY,
+            ttl: DEFAULT_CACHE_TTL,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Sets the number of states the cache holds. Values below 1 are treated as 1.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// Sets how long a cached state is served after it was read or written.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Sets the clock used to expire cached states.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Returns the wrapped backend.
+    pub fn inner(&self) -> &B {
+        &self.backend
+    }
+
+    /// Returns the hit and miss counters.
+    pub fn stats(&self) -> CacheStats {
+        self.lock().stats
+    }
+
+    /// Returns the number of cached keys, including expired ones not yet removed.
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    /// Returns whether nothing is cached.
+    pub fn is_empty(&self) -> bool {
+        self.lock().entries.is_empty()
+    }
+
+    /// Removes the cached state of a key, e.g. after another process changed it.
+    pub fn invalidate(&self, key: &StateKey) {
+        self.evict(std::slice::from_ref(key));
+    }
+
+    /// Removes every cached state. The counters are kept.
+    pub fn clear(&self) {
+        let mut cache = self.lock();
+        cache.generation += 1;
+        cache.entries.clear();
+        cache.recency.clear();
+    }
+
+    /// Removes the cached states of some keys and returns the new generation of the cache.
+    fn evict(&self, keys: &[StateKey]) -> u64 {
+        let mut cache = self.lock();
+        cache.generation += 1;
+        for key in keys {
+            cache.remove(key);
+        }
+        cache.generation
+    }
+
+    /// Reads a state from the backend and caches it, unless the cache was written to since it
+    /// was at `generation`.
+    async fn load(&self, key: &StateKey, generation: u64) -> Result<Option<TemplateState>> {
+        let state = self.backend.get_state(key).await?;
+
+        let expires_at = self.clock.now() + self.ttl;
+        let mut cache = self.lock();
+        // A write in the meantime may have made the state just read outdated.
+        if cache.generation == generation {
+            cache.insert(key.clone(), state.clone(), expires_at);
+            cache.evict(self.capacity);
+        }
+        Ok(state)
+    }
+
+    /// Reads the states of several keys from the backend and caches them, unless the cache was
+    /// written to since it was at `generation`.
+    async fn load_all(
+        &self,
+        keys: &[StateKey],
+        generation: u64,
+    ) -> Result<Vec<Option<TemplateState>>> {
+        let states = self.backend.get_states(keys).await?;
+
+        let expires_at = self.clock.now() + self.ttl;
+        let mut cache = self.lock();
+        if cache.generation == generation {
+            for (key, state) in keys.iter().zip(&states) {
+                cache.insert(key.clone(), state.clone(), expires_at);
+            }
+            cache.evict(self.capacity);
+        }
+        Ok(states)
+    }
+
+    /// Caches the state of a key as just written; `None` if it was deleted.
+    fn cache_written(&self, key: &StateKey, state: Option<TemplateState>) {
+        let expires_at = self.clock.now() + self.ttl;
+        let mut cache = self.lock();
+        cache.generation += 1;
+        cache.insert(key.clone(), state, expires_at);
+        cache.evict(self.capacity);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Cache> {
+        // A cache entry is written in one step, so the cache stays consistent even if a holder
+        // of the lock panicked.
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+// Manual Clone implementation, as the backend itself need not be Clone.
+impl<B> Clone for CachingBackend<B> {
+    fn clone(&self) -> Self {
+        Self {
+            backend: Arc::clone(&self.backend),
+            cache: Arc::clone(&self.cache),
+            capacity: self.capacity,
+            ttl: self.ttl,
+            clock: Arc::clone(&self.clock),
+        }
+    }
+}
+
+impl<B> fmt::Debug for CachingBackend<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CachingBackend")
+            .field("capacity", &self.capacity)
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl<B: StatePersistence> StatePersistence for CachingBackend<B> {
+    /// Serves the state from the cache, or reads it from the backend and caches it.
+    async fn get_state(&self, key: &StateKey) -> Result<Option<TemplateState>> {
+        let generation = {
+            let mut cache = self.lock();
+            if let Some(state) = cache.get(key, self.clock.now()) {
+                cache.stats.hits += 1;
+                return Ok(state);
+            }
+            cache.stats.misses += 1;
+            cache.generation
+        };
+        self.load(key, generation).await
+    }
+
+    async fn update_state(&self, state: &TemplateState) -> Result<()> {
+        let key = state.key();
+        let result = self.backend.update_state(state).await;
+        let generation = self.evict(std::slice::from_ref(&key));
+        result?;
+        // The write succeeded, so failing to read it back only leaves the key uncached.
+        let _ = self.load(&key, generation).await;
+        Ok(())
+    }
+
+    async fn update_state_if(
+        &self,
+        state: &TemplateState,
+        expected_version: Option<u64>,
+    ) -> Result<u64> {
+        let key = state.key();
+        let result = self.backend.update_state_if(state, expected_version).await;
+        match &result {
+            Ok(version) => self.cache_written(
+                &key,
+                Some(TemplateState {
+                    version: *version,
+                    ..state.clone()
+                }),
+            ),
+            Err(_) => self.invalidate(&key),
+        }
+        result
+    }
+
+    async fn list_states(&self, query: &StateQuery) -> Result<StatePage> {
+        self.backend.list_states(query).await
+    }
+
+    async fn scan_states(&self, page_token: Option<&str>, limit: usize) -> Result<StatePage> {
+        self.backend.scan_states(page_token, limit).await
+    }
+
+    async fn delete_state(&self, key: &StateKey) -> Result<bool> {
+        let result = self.backend.delete_state(key).await;
+        match &result {
+            Ok(_) => self.cache_written(key, None),
+            Err(_) => self.invalidate(key),
+        }
+        result
+    }
+
+    async fn get_state_history(&self, key: &StateKey) -> Result<Vec<TemplateState>> {
+        self.backend.get_state_history(key).await
+    }
+
+    async fn get_states(&self, keys: &[StateKey]) -> Result<Vec<Option<TemplateState>>> {
+        // `None` for the keys which are not cached
+        let mut cached = Vec::with_capacity(keys.len());
+        let mut missed = Vec::new();
+        let generation = {
+            let mut cache = self.lock();
+            let now = self.clock.now();
+            for key in keys {
+                let state = cache.get(key, now);
+                if state.is_some() {
+                    cache.stats.hits += 1;
+                } else {
+                    cache.stats.misses += 1;
+                    missed.push(key.clone());
+                }
+                cached.push(state);
+            }
+            cache.generation
+        };
+
+        let mut loaded = if missed.is_empty() {
+            Vec::new().into_iter()
+        } else {
+            self.load_all(&missed, generation).await?.into_iter()
+        };
+        Ok(cached
+            .into_iter()
+            .map(|state| state.unwrap_or_else(|| loaded.next().flatten()))
+            .collect())
+    }
+
+    async fn update_states(&self, states: &[TemplateState]) -> Result<()> {
+        let keys: Vec<StateKey> = states.iter().map(TemplateState::key).collect();
+        let result = self.backend.update_states(states).await;
+        let generation = self.evict(&keys);
+        result?;
+        // The writes succeeded, so failing to read them back only leaves the keys uncached.
+        let _ = self.load_all(&keys, generation).await;
+        Ok(())
+    }
+}
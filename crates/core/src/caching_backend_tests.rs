@@ -0,0 +1,212 @@
+//! Tests for the caching `StatePersistence` decorator.
+
+use super::*;
+use crate::clock::SimulatedClock;
+use crate::memory_backend::{InMemoryBackend, InjectedFault, StateOperation};
+use crate::types::CoreError;
+use chrono::Utc;
+
+fn state_for(repo: &str, template_path: &str) -> TemplateState {
+    TemplateState {
+        repo: repo.to_string(),
+        template_path: template_path.to_string(),
+        source_repository: "owner/master".to_string(),
+        master_checksum: "checksum".to_string(),
+        deployed_checksum: None,
+        last_updated_utc: Utc::now(),
+        version: 0,
+    }
+}
+
+fn caching(backend: &InMemoryBackend) -> (CachingBackend<InMemoryBackend>, SimulatedClock) {
+    let clock = SimulatedClock::new();
+    let cache = CachingBackend::new(backend.clone())
+        .with_ttl(Duration::from_secs(60))
+        .with_clock(clock.clone());
+    (cache, clock)
+}
+
+#[tokio::test]
+async fn test_caching_backend_serves_repeated_reads_from_cache() {
+    let backend = InMemoryBackend::with_states([state_for("org/a", "README.md")]);
+    let (cache, _) = caching(&backend);
+    let key = StateKey::new("org/a", "README.md");
+    let missing = StateKey::new("org/a", "LICENSE");
+
+    for _ in 0..3 {
+        assert_eq!(cache.get_state(&key).await.unwrap(), backend.state(&key));
+        assert_eq!(cache.get_state(&missing).await.unwrap(), None);
+    }
+
+    assert_eq!(backend.call_count(StateOperation::GetState), 2);
+    assert_eq!(cache.stats(), CacheStats { hits: 4, misses: 2 });
+    assert!((cache.stats().hit_ratio() - 4.0 / 6.0).abs() < f64::EPSILON);
+}
+
+#[tokio::test]
+async fn test_caching_backend_expires_entries() {
+    let backend = InMemoryBackend::with_states([state_for("org/a", "README.md")]);
+    let (cache, clock) = caching(&backend);
+    let key = StateKey::new("org/a", "README.md");
+    cache.get_state(&key).await.unwrap();
+
+    clock.advance(Duration::from_secs(59));
+    cache.get_state(&key).await.unwrap();
+    assert_eq!(backend.call_count(StateOperation::GetState), 1);
+
+    clock.advance(Duration::from_secs(1));
+    cache.get_state(&key).await.unwrap();
+    assert_eq!(backend.call_count(StateOperation::GetState), 2);
+    assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 2 });
+}
+
+#[tokio::test]
+async fn test_caching_backend_evicts_least_recently_used() {
+    let backend = InMemoryBackend::new();
+    let (cache, _) = caching(&backend);
+    let cache = cache.with_capacity(2);
+    let keys: Vec<StateKey> = ["a", "b", "c"]
+        .iter()
+        .map(|path| StateKey::new("org/a", *path))
+        .collect();
+
+    cache.get_state(&keys[0]).await.unwrap();
+    cache.get_state(&keys[1]).await.unwrap();
+    // Using "a" again makes "b" the least recently used entry
+    cache.get_state(&keys[0]).await.unwrap();
+    cache.get_state(&keys[2]).await.unwrap();
+
+    assert_eq!(cache.len(), 2);
+    cache.get_state(&keys[0]).await.unwrap();
+    assert_eq!(cache.stats(), CacheStats { hits: 2, misses: 3 });
+    cache.get_state(&keys[1]).await.unwrap();
+    assert_eq!(cache.stats(), CacheStats { hits: 2, misses: 4 });
+}
+
+#[tokio::test]
+async fn test_caching_backend_writes_through_conditional_updates() {
+    let backend = InMemoryBackend::new();
+    let (cache, _) = caching(&backend);
+    let state = state_for("org/a", "README.md");
+    assert_eq!(cache.get_state(&state.key()).await.unwrap(), None);
+
+    let version = cache.update_state_if(&state, None).await.unwrap();
+
+    let cached = cache.get_state(&state.key()).await.unwrap();
+    assert_eq!(cached, backend.state(&state.key()));
+    assert_eq!(cached.unwrap().version, version);
+    assert_eq!(backend.call_count(StateOperation::GetState), 1);
+}
+
+#[tokio::test]
+async fn test_caching_backend_writes_through_updates() {
+    let backend = InMemoryBackend::with_states([state_for("org/a", "README.md")]);
+    let (cache, _) = caching(&backend);
+    let state = state_for("org/a", "README.md");
+    let cached = cache.get_state(&state.key()).await.unwrap().unwrap();
+
+    cache.update_state(&state).await.unwrap();
+
+    let stored = cache.get_state(&state.key()).await.unwrap().unwrap();
+    assert_eq!(stored.version, cached.version + 1);
+    assert_eq!(Some(stored), backend.state(&state.key()));
+    // The second read is the read-back of the written state
+    assert_eq!(backend.call_count(StateOperation::GetState), 2);
+    assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 1 });
+}
+
+#[tokio::test]
+async fn test_caching_backend_conflict_invalidates_key() {
+    let backend = InMemoryBackend::with_states([state_for("org/a", "README.md")]);
+    let (cache, _) = caching(&backend);
+    let state = state_for("org/a", "README.md");
+    let cached = cache.get_state(&state.key()).await.unwrap().unwrap();
+    // Another process updates the state behind the cache's back
+    backend.fail_nth_call_of(
+        StateOperation::UpdateStateIf,
+        1,
+        InjectedFault::ConcurrentUpdate,
+    );
+
+    let result = cache.update_state_if(&state, Some(cached.version)).await;
+
+    assert!(matches!(result, Err(CoreError::StateConflict { .. })));
+    let current = cache.get_state(&state.key()).await.unwrap().unwrap();
+    assert_eq!(current.version, cached.version + 1);
+}
+
+#[tokio::test]
+async fn test_caching_backend_delete_state_caches_absence() {
+    let backend = InMemoryBackend::with_states([state_for("org/a", "README.md")]);
+    let (cache, _) = caching(&backend);
+    let key = StateKey::new("org/a", "README.md");
+    cache.get_state(&key).await.unwrap();
+
+    assert!(cache.delete_state(&key).await.unwrap());
+
+    assert_eq!(cache.get_state(&key).await.unwrap(), None);
+    assert_eq!(backend.call_count(StateOperation::GetState), 1);
+}
+
+#[tokio::test]
+async fn test_caching_backend_does_not_cache_failed_reads() {
+    let backend = InMemoryBackend::with_states([state_for("org/a", "README.md")]);
+    let (cache, _) = caching(&backend);
+    let key = StateKey::new("org/a", "README.md");
+    backend.fail_nth_call(1, InjectedFault::DatabaseError("timeout".to_string()));
+
+    assert!(cache.get_state(&key).await.is_err());
+
+    assert!(cache.get_state(&key).await.unwrap().is_some());
+    assert_eq!(cache.stats(), CacheStats { hits: 0, misses: 2 });
+}
+
+#[tokio::test]
+async fn test_caching_backend_clones_share_cache() {
+    let backend = InMemoryBackend::with_states([state_for("org/a", "README.md")]);
+    let (cache, _) = caching(&backend);
+    let manager = crate::StateManager::new(Box::new(cache.clone()));
+    let key = StateKey::new("org/a", "README.md");
+
+    manager.get_state(&key).await.unwrap();
+    manager.get_state(&key).await.unwrap();
+
+    assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 1 });
+    cache.clear();
+    assert!(cache.is_empty());
+    manager.get_state(&key).await.unwrap();
+    assert_eq!(cache.stats().misses, 2);
+}
+
+#[tokio::test]
+async fn test_caching_backend_get_states_reads_only_missed_keys() {
+    let backend = InMemoryBackend::with_states([
+        state_for("org/a", "README.md"),
+        state_for("org/b", "README.md"),
+    ]);
+    let (cache, _) = caching(&backend);
+    let a = StateKey::new("org/a", "README.md");
+    let b = StateKey::new("org/b", "README.md");
+    let missing = StateKey::new("org/c", "README.md");
+    cache.get_state(&a).await.unwrap();
+
+    let states = cache
+        .get_states(&[b.clone(), a.clone(), missing.clone()])
+        .await
+        .unwrap();
+
+    assert_eq!(states, [backend.state(&b), backend.state(&a), None]);
+    assert_eq!(backend.call_count(StateOperation::GetStates), 1);
+    assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 3 });
+    // Every key is cached now, so no further batch is read
+    cache.get_states(&[a, b, missing]).await.unwrap();
+    assert_eq!(backend.call_count(StateOperation::GetStates), 1);
+}
+
+#[tokio::test]
+async fn test_caching_backend_conformance() {
+    crate::conformance::run_all(|| async {
+        Arc::new(CachingBackend::new(InMemoryBackend::new())) as Arc<dyn StatePersistence>
+    })
+    .await;
+}
@@ -0,0 +1,96 @@
+//! Abstracts reading and waiting for time, so that code depending on time can be tested with a
+//! `SimulatedClock` instead of actually waiting.
+
+use async_trait::async_trait;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A source of time, for the decorators of `StatePersistence` which wait or expire entries.
+#[async_trait]
+pub trait Clock: Send + Sync {
+    /// Returns the current instant.
+    fn now(&self) -> Instant;
+
+    /// Waits for the given duration.
+    async fn sleep(&self, duration: Duration);
+}
+
+/// A `Clock` which reads the system's monotonic clock and waits with the tokio timer.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+#[async_trait]
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await;
+    }
+}
+
+/// A `Clock` whose time only moves when it is slept on or advanced, for tests.
+///
+/// Sleeping returns immediately after advancing the time by the requested duration. Clones
+/// share the same time, so a test can keep a clone to inspect the sleeps of the code under test.
+#[derive(Debug, Clone)]
+pub struct SimulatedClock {
+    start: Instant,
+    inner: Arc<Mutex<SimulatedTime>>,
+}
+
+#[derive(Debug, Default)]
+struct SimulatedTime {
+    elapsed: Duration,
+    sleeps: Vec<Duration>,
+}
+
+impl SimulatedClock {
+    /// Creates a simulated clock at an arbitrary starting instant.
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            inner: Arc::new(Mutex::new(SimulatedTime::default())),
+        }
+    }
+
+    /// Moves the time forward without recording a sleep, e.g. to simulate slow calls.
+    pub fn advance(&self, duration: Duration) {
+        self.lock().elapsed += duration;
+    }
+
+    /// Returns the time passed since the clock was created.
+    pub fn elapsed(&self) -> Duration {
+        self.lock().elapsed
+    }
+
+    /// Returns the durations slept so far, in order.
+    pub fn sleeps(&self) -> Vec<Duration> {
+        self.lock().sleeps.clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, SimulatedTime> {
+        // The time stays consistent even if a holder of the lock panicked.
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for SimulatedClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Clock for SimulatedClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        let mut time = self.lock();
+        time.elapsed += duration;
+        time.sleeps.push(duration);
+    }
+}
@@ -0,0 +1,524 @@
+//! A behavioural test suite shared by all `StatePersistence` implementations.
+//!
+//! Every backend must behave the same towards the `StateManager` and the `TemplateUpdater`:
+//! versions are assigned on write, conditional updates detect conflicts, listing is ordered by
+//! `StateKey` and paginated with opaque tokens, and every write is recorded in the state's
+//! history. Backend crates run this suite from their tests
+//! by enabling the `conformance` feature of this crate as a dev-dependency:
+//!
+//! ```rust,ignore
+//! #[tokio::test]
+//! async fn test_my_backend_conformance() {
+//!     conformance::run_all(|| async { Arc::new(MyBackend::new_empty()) as Arc<dyn StatePersistence> })
+//!         .await;
+//! }
+//! ```
+//!
+//! Each check gets a fresh, empty backend from the factory, with the default history retention,
+//! and panics if the backend misbehaves.
+//! The checks store timestamps with microsecond precision, the finest precision all backends
+//! are required to preserve.
+
+use crate::state_manager::{StatePersistence, DEFAULT_HISTORY_RETENTION};
+use crate::types::{CoreError, StateKey, StatePage, StateQuery, TemplateState};
+use chrono::{DurationRound, TimeDelta, Utc};
+use std::future::Future;
+use std::sync::Arc;
+
+/// The number of concurrent writers used by the concurrency checks.
+const CONCURRENT_WRITERS: usize = 10;
+
+/// Runs every check of the suite, each against a new backend created by `new_backend`.
+pub async fn run_all<F, Fut>(mut new_backend: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Arc<dyn StatePersistence>>,
+{
+    get_state_not_found(new_backend().await).await;
+    update_and_get_state(new_backend().await).await;
+    update_state_overwrites_and_increments_version(new_backend().await).await;
+    update_state_if(new_backend().await).await;
+    concurrent_update_state_keeps_every_write(new_backend().await).await;
+    concurrent_update_state_if_only_one_wins(new_backend().await).await;
+    list_states_filters_and_sorts(new_backend().await).await;
+    list_states_paginates(new_backend().await).await;
+    list_states_rejects_invalid_page_token(new_backend().await).await;
+    delete_state(new_backend().await).await;
+    state_history_records_every_write(new_backend().await).await;
+    state_history_is_bounded_by_retention(new_backend().await).await;
+    get_states_in_key_order(new_backend().await).await;
+    update_states_writes_every_state(new_backend().await).await;
+}
+
+/// Returns a state with a deployed checksum and a timestamp of microsecond precision.
+pub fn state_for(repo: &str, template_path: &str) -> TemplateState {
+    TemplateState {
+        repo: repo.to_string(),
+        template_path: template_path.to_string(),
+        source_repository: "owner/master".to_string(),
+        master_checksum: "checksum".to_string(),
+        deployed_checksum: Some("deployed".to_string()),
+        last_updated_utc: Utc::now()
+            .duration_trunc(TimeDelta::microseconds(1))
+            .expect("a current timestamp can be truncated"),
+        version: 0,
+    }
+}
+
+fn keys(page: &StatePage) -> Vec<String> {
+    page.states.iter().map(|s| s.key().to_string()).collect()
+}
+
+async fn stored_version(backend: &dyn StatePersistence, key: &StateKey) -> Option<u64> {
+    backend
+        .get_state(key)
+        .await
+        .unwrap()
+        .map(|state| state.version)
+}
+
+/// Getting a state which was never written returns `None`.
+pub async fn get_state_not_found(backend: Arc<dyn StatePersistence>) {
+    let result = backend
+        .get_state(&StateKey::new("org/a", "missing.md"))
+        .await
+        .unwrap();
+
+    assert_eq!(result, None);
+}
+
+/// A written state reads back unchanged, with version 1.
+pub async fn update_and_get_state(backend: Arc<dyn StatePersistence>) {
+    let with_deployed = state_for("org/a", ".github/PULL_REQUEST_TEMPLATE.md");
+    let without_deployed = TemplateState {
+        deployed_checksum: None,
+        ..state_for("org/a", "README.md")
+    };
+
+    for state in [with_deployed, without_deployed] {
+        backend.update_state(&state).await.unwrap();
+        let stored = backend.get_state(&state.key()).await.unwrap();
+        assert_eq!(
+            stored,
+            Some(TemplateState {
+                version: 1,
+                ..state
+            })
+        );
+    }
+}
+
+/// `update_state` replaces every field of a stored state and increments its version.
+pub async fn update_state_overwrites_and_increments_version(backend: Arc<dyn StatePersistence>) {
+    let mut state = state_for("org/a", "README.md");
+    backend.update_state(&state).await.unwrap();
+
+    state.source_repository = "owner/other-master".to_string();
+    state.master_checksum = "updated".to_string();
+    state.deployed_checksum = None;
+    state.version = 42; // Ignored by the backend
+    backend.update_state(&state).await.unwrap();
+
+    let stored = backend.get_state(&state.key()).await.unwrap();
+    assert_eq!(
+        stored,
+        Some(TemplateState {
+            version: 2,
+            ..state
+        })
+    );
+}
+
+/// `update_state_if` only writes if the stored version is the expected one, and reports the
+/// expected and actual versions otherwise.
+pub async fn update_state_if(backend: Arc<dyn StatePersistence>) {
+    let mut state = state_for("org/a", "README.md");
+
+    assert_eq!(backend.update_state_if(&state, None).await.unwrap(), 1);
+    assert_eq!(backend.update_state_if(&state, Some(1)).await.unwrap(), 2);
+
+    // Creating a state which already exists conflicts
+    let result = backend.update_state_if(&state, None).await;
+    assert!(
+        matches!(
+            result,
+            Err(CoreError::StateConflict {
+                expected: None,
+                actual: Some(2),
+                ..
+            })
+        ),
+        "Expected StateConflict, got {:?}",
+        result
+    );
+
+    // Writing with a stale version conflicts and leaves the stored state untouched
+    state.master_checksum = "stale".to_string();
+    match backend.update_state_if(&state, Some(1)).await {
+        Err(CoreError::StateConflict {
+            key,
+            expected,
+            actual,
+        }) => {
+            assert_eq!(key, state.key());
+            assert_eq!((expected, actual), (Some(1), Some(2)));
+        }
+        r => panic!("Expected StateConflict, got {:?}", r),
+    }
+    let stored = backend.get_state(&state.key()).await.unwrap().unwrap();
+    assert_eq!(stored.master_checksum, "checksum");
+    assert_eq!(stored.version, 2);
+
+    // Updating a state which does not exist conflicts and does not create it
+    let missing = state_for("org/a", "missing.md");
+    let result = backend.update_state_if(&missing, Some(1)).await;
+    assert!(
+        matches!(
+            result,
+            Err(CoreError::StateConflict {
+                expected: Some(1),
+                actual: None,
+                ..
+            })
+        ),
+        "Expected StateConflict, got {:?}",
+        result
+    );
+    assert_eq!(stored_version(backend.as_ref(), &missing.key()).await, None);
+}
+
+/// Concurrent `update_state` calls for the same key all succeed and each increments the version.
+pub async fn concurrent_update_state_keeps_every_write(backend: Arc<dyn StatePersistence>) {
+    let tasks = (0..CONCURRENT_WRITERS)
+        .map(|i| {
+            let backend = Arc::clone(&backend);
+            tokio::spawn(async move {
+                let mut state = state_for("org/a", "README.md");
+                state.master_checksum = format!("checksum_{}", i);
+                backend.update_state(&state).await
+            })
+        })
+        .collect::<Vec<_>>();
+    for task in tasks {
+        task.await.unwrap().unwrap();
+    }
+
+    let stored = backend
+        .get_state(&StateKey::new("org/a", "README.md"))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.version, CONCURRENT_WRITERS as u64);
+    assert!(stored.master_checksum.starts_with("checksum_"));
+}
+
+/// Of several writers conditionally updating the same version, exactly one succeeds.
+pub async fn concurrent_update_state_if_only_one_wins(backend: Arc<dyn StatePersistence>) {
+    let key = StateKey::new("org/a", "README.md");
+    for expected in [None, Some(1)] {
+        let tasks = (0..CONCURRENT_WRITERS)
+            .map(|_| {
+                let backend = Arc::clone(&backend);
+                tokio::spawn(async move {
+                    backend
+                        .update_state_if(&state_for("org/a", "README.md"), expected)
+                        .await
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut successes = 0;
+        for task in tasks {
+            match task.await.unwrap() {
+                Ok(_) => successes += 1,
+                Err(CoreError::StateConflict { .. }) => {}
+                Err(e) => panic!("Unexpected error: {:?}", e),
+            }
+        }
+        assert_eq!(
+            successes, 1,
+            "Expected one winner for version {:?}",
+            expected
+        );
+    }
+    assert_eq!(stored_version(backend.as_ref(), &key).await, Some(2));
+}
+
+/// Listing returns states in `StateKey` order, filtered by repository and literal path prefix.
+pub async fn list_states_filters_and_sorts(backend: Arc<dyn StatePersistence>) {
+    for (repo, path) in [
+        ("org/b", ".github/CODEOWNERS"),
+        ("org/a", ".github/PULL_REQUEST_TEMPLATE.md"),
+        ("org/a", "README.md"),
+        ("org/a", ".github/CODEOWNERS"),
+        ("org/a", ".githubx"),
+        ("org/a", "docs/[draft]%_*.md"),
+        ("org/a", "docs/d.md"),
+        ("org/a", "Docs/readme.md"),
+    ] {
+        backend.update_state(&state_for(repo, path)).await.unwrap();
+    }
+
+    let all = backend.list_states(&StateQuery::default()).await.unwrap();
+    assert_eq!(
+        keys(&all),
+        vec![
+            "org/a:.github/CODEOWNERS",
+            "org/a:.github/PULL_REQUEST_TEMPLATE.md",
+            "org/a:.githubx",
+            "org/a:Docs/readme.md",
+            "org/a:README.md",
+            "org/a:docs/[draft]%_*.md",
+            "org/a:docs/d.md",
+            "org/b:.github/CODEOWNERS",
+        ]
+    );
+    assert_eq!(all.next_page_token, None);
+
+    let by_prefix = backend
+        .list_states(&StateQuery::default().with_template_path_prefix(".github/"))
+        .await
+        .unwrap();
+    assert_eq!(
+        keys(&by_prefix),
+        vec![
+            "org/a:.github/CODEOWNERS",
+            "org/a:.github/PULL_REQUEST_TEMPLATE.md",
+            "org/b:.github/CODEOWNERS",
+        ]
+    );
+
+    let by_repo = backend
+        .list_states(
+            &StateQuery::default()
+                .with_repo("org/b")
+                .with_template_path_prefix(".github/"),
+        )
+        .await
+        .unwrap();
+    assert_eq!(keys(&by_repo), vec!["org/b:.github/CODEOWNERS"]);
+
+    // Prefixes match literally and case-sensitively
+    let literal = backend
+        .list_states(&StateQuery::default().with_template_path_prefix("docs/[draft]%_"))
+        .await
+        .unwrap();
+    assert_eq!(keys(&literal), vec!["org/a:docs/[draft]%_*.md"]);
+}
+
+/// Following page tokens visits every matching state exactly once, in order.
+pub async fn list_states_paginates(backend: Arc<dyn StatePersistence>) {
+    for (repo, path) in [
+        ("org/a", "x"),
+        ("org/a", "y"),
+        ("org/a", "z"),
+        ("org/b", "x"),
+        ("org/c", "x"),
+    ] {
+        backend.update_state(&state_for(repo, path)).await.unwrap();
+    }
+
+    async fn list_pages(backend: &dyn StatePersistence, mut query: StateQuery) -> Vec<Vec<String>> {
+        let mut pages = Vec::new();
+        loop {
+            let page = backend.list_states(&query).await.unwrap();
+            pages.push(keys(&page));
+            match page.next_page_token {
+                Some(token) => query = query.with_page_token(token),
+                None => return pages,
+            }
+        }
+    }
+
+    assert_eq!(
+        list_pages(backend.as_ref(), StateQuery::default().with_limit(2)).await,
+        vec![
+            vec!["org/a:x", "org/a:y"],
+            vec!["org/a:z", "org/b:x"],
+            vec!["org/c:x"],
+        ]
+    );
+    assert_eq!(
+        list_pages(
+            backend.as_ref(),
+            StateQuery::default().with_repo("org/a").with_limit(2)
+        )
+        .await,
+        vec![vec!["org/a:x", "org/a:y"], vec!["org/a:z"]]
+    );
+    // A page which exactly exhausts the states may or may not carry a token, but the next page
+    // must then be empty.
+    let pages = list_pages(backend.as_ref(), StateQuery::default().with_limit(5)).await;
+    assert_eq!(pages[0].len(), 5);
+    assert!(pages.iter().skip(1).all(|page| page.is_empty()));
+}
+
+/// An invalid page token is rejected with `CoreError::DatabaseError`.
+pub async fn list_states_rejects_invalid_page_token(backend: Arc<dyn StatePersistence>) {
+    let result = backend
+        .list_states(&StateQuery::default().with_page_token("not-a-token"))
+        .await;
+
+    assert!(
+        matches!(&result, Err(CoreError::DatabaseError(msg)) if msg.contains("page token")),
+        "Expected DatabaseError, got {:?}",
+        result
+    );
+}
+
+/// Deleting removes the state and reports whether it existed.
+pub async fn delete_state(backend: Arc<dyn StatePersistence>) {
+    let state = state_for("org/a", "README.md");
+    let other = state_for("org/a", "CODEOWNERS");
+    backend.update_state(&state).await.unwrap();
+    backend.update_state(&other).await.unwrap();
+
+    assert!(backend.delete_state(&state.key()).await.unwrap());
+    assert_eq!(backend.get_state(&state.key()).await.unwrap(), None);
+    assert!(!backend.delete_state(&state.key()).await.unwrap());
+    assert!(backend.get_state(&other.key()).await.unwrap().is_some());
+
+    // A deleted state can be created again, starting over at version 1 with a new history
+    assert_eq!(backend.update_state_if(&state, None).await.unwrap(), 1);
+    let history = backend.get_state_history(&state.key()).await.unwrap();
+    assert_eq!(history.len(), 1);
+}
+
+/// Successful writes are recorded in the history of their state, newest first; rejected writes
+/// and writes of other states are not.
+pub async fn state_history_records_every_write(backend: Arc<dyn StatePersistence>) {
+    let key = StateKey::new("org/a", "README.md");
+    assert_eq!(backend.get_state_history(&key).await.unwrap(), Vec::new());
+
+    let mut first = state_for("org/a", "README.md");
+    first.master_checksum = "first".to_string();
+    first.deployed_checksum = None;
+    backend.update_state(&first).await.unwrap();
+    let mut second = state_for("org/a", "README.md");
+    second.master_checksum = "second".to_string();
+    backend.update_state_if(&second, Some(1)).await.unwrap();
+    let rejected = state_for("org/a", "README.md");
+    assert!(backend.update_state_if(&rejected, Some(1)).await.is_err());
+    backend
+        .update_state(&state_for("org/a", "CODEOWNERS"))
+        .await
+        .unwrap();
+    let mut third = state_for("org/a", "README.md");
+    third.master_checksum = "third".to_string();
+    backend.update_state(&third).await.unwrap();
+
+    let history = backend.get_state_history(&key).await.unwrap();
+    assert_eq!(
+        history,
+        vec![
+            TemplateState {
+                version: 3,
+                ..third
+            },
+            TemplateState {
+                version: 2,
+                ..second
+            },
+            TemplateState {
+                version: 1,
+                ..first
+            },
+        ]
+    );
+}
+
+/// Only the most recent versions, up to the history retention, are kept.
+pub async fn state_history_is_bounded_by_retention(backend: Arc<dyn StatePersistence>) {
+    let state = state_for("org/a", "README.md");
+    let writes = DEFAULT_HISTORY_RETENTION as u64 + 2;
+    for version in 1..=writes {
+        let state = TemplateState {
+            master_checksum: format!("checksum {}", version),
+            ..state.clone()
+        };
+        backend.update_state(&state).await.unwrap();
+    }
+
+    let history = backend.get_state_history(&state.key()).await.unwrap();
+    let versions: Vec<u64> = history.iter().map(|state| state.version).collect();
+    let expected: Vec<u64> = (3..=writes).rev().collect();
+    assert_eq!(versions, expected);
+    assert_eq!(history[0].master_checksum, format!("checksum {}", writes));
+}
+
+/// `get_states` returns one entry per requested key, in the order requested.
+pub async fn get_states_in_key_order(backend: Arc<dyn StatePersistence>) {
+    let a = state_for("org/a", "README.md");
+    let b = state_for("org/b", "README.md");
+    backend.update_state(&a).await.unwrap();
+    backend.update_state(&b).await.unwrap();
+    let missing = StateKey::new("org/a", "missing.md");
+
+    let states = backend
+        .get_states(&[b.key(), missing, a.key(), b.key()])
+        .await
+        .unwrap();
+
+    let stored_b = Some(TemplateState { version: 1, ..b });
+    assert_eq!(
+        states,
+        vec![
+            stored_b.clone(),
+            None,
+            Some(TemplateState { version: 1, ..a }),
+            stored_b,
+        ]
+    );
+    assert_eq!(backend.get_states(&[]).await.unwrap(), Vec::new());
+}
+
+/// `update_states` writes every state as `update_state` would, in order, recording each write
+/// in the history.
+pub async fn update_states_writes_every_state(backend: Arc<dyn StatePersistence>) {
+    let existing = state_for("org/a", "README.md");
+    backend.update_state(&existing).await.unwrap();
+    let mut first = state_for("org/a", "README.md");
+    first.master_checksum = "first".to_string();
+    let mut second = state_for("org/a", "README.md");
+    second.master_checksum = "second".to_string();
+    let new = state_for("org/b", "README.md");
+
+    backend
+        .update_states(&[first.clone(), new.clone(), second.clone()])
+        .await
+        .unwrap();
+    backend.update_states(&[]).await.unwrap();
+
+    let states = backend
+        .get_states(&[existing.key(), new.key()])
+        .await
+        .unwrap();
+    assert_eq!(
+        states,
+        vec![
+            Some(TemplateState {
+                version: 3,
+                ..second.clone()
+            }),
+            Some(TemplateState { version: 1, ..new }),
+        ]
+    );
+    let history = backend.get_state_history(&existing.key()).await.unwrap();
+    assert_eq!(
+        history,
+        vec![
+            TemplateState {
+                version: 3,
+                ..second
+            },
+            TemplateState {
+                version: 2,
+                ..first
+            },
+            TemplateState {
+                version: 1,
+                ..existing
+            },
+        ]
+    );
+}
@@ -1,19 +1,154 @@
 //! Implements a simple `StatePersistence` backend using the local filesystem.
-//! State is stored as JSON files within a specified base directory.
+//! State is stored as JSON files within a specified base directory, in one directory per
+//! repository and one file per template: `<base>/<repo>/<template path>.json`, with the
+//! repository and template path encoded by [`encode_name`]. Each file also holds the previous
+//! versions of its state, newest first.
+//!
+//! Several processes, e.g. on a shared volume, can use the same directory: every operation
+//! holds an advisory lock on the `.lock` file in the base directory, exclusively for writes and
+//! shared for reads. The lock is advisory, so other programs writing to the directory must
+//! take it as well.
 
-use crate::state_manager::StatePersistence;
-use crate::types::{CoreError, Result, TemplateState};
+use crate::state_manager::{StatePersistence, DEFAULT_HISTORY_RETENTION};
+use crate::types::{CoreError, Result, StateKey, StatePage, StateQuery, TemplateState};
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::fs;
 
 #[cfg(test)]
 #[path = "filesystem_backend_tests.rs"]
 mod tests;
 
+use std::fs::TryLockError;
 use std::io::{ErrorKind, Write};
 use std::path::{Path, PathBuf};
 use std::sync::Arc; // Using Arc for potential future sharing needs, though Mutex might be needed for concurrent writes
-use tokio::sync::Mutex; // Use tokio's Mutex for async locking
+use std::time::Duration;
+use tokio::sync::{Mutex, MutexGuard}; // Use tokio's Mutex for async locking
+
+/// The name of the file in the base directory which processes lock to access the directory.
+/// Encoded repository names never start with `.`, so it cannot collide with a repository
+/// directory.
+const LOCK_FILE_NAME: &str = ".lock";
+
+/// How long to wait before checking again whether another process released the lock file.
+/// The wait doubles up to [`MAX_LOCK_POLL_INTERVAL`].
+const INITIAL_LOCK_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// The longest wait before checking again whether another process released the lock file.
+const MAX_LOCK_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The longest name, in bytes, of a directory or file (without its extension) the backend
+/// creates for a repository or template path. Most filesystems allow 255 bytes per name; the
+/// rest is left for the extensions of state and temporary files.
+const MAX_NAME_LEN: usize = 200;
+
+/// Names which Windows reserves for devices, with any extension.
+const WINDOWS_DEVICE_NAMES: &[&str] = &[
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+/// Encodes a repository or template path as a directory or file name.
+///
+/// Lower-case ASCII letters, digits, `-`, `_` and inner `.` are kept; every other byte is
+/// percent-encoded (e.g. `/` as `%2F`), so distinct values get distinct names and a name can
+/// be decoded back into its value. In particular:
+///   - Upper-case letters are encoded, so names differing only in case do not collide on
+///     case-insensitive filesystems.
+///   - Leading and trailing dots are encoded, so no name is `.`, `..` or hidden, and Windows
+///     does not strip a trailing dot.
+///   - The first letter of a Windows device name such as `nul` is encoded.
+///   - The empty string is encoded as `%`.
+///
+/// Names longer than [`MAX_NAME_LEN`] are shortened and suffixed with `~` and the SHA-256 of
+/// the value. `~` is always encoded otherwise, so shortened names cannot collide with others.
+/// They cannot be decoded, but every state file also holds its key.
+fn encode_name(value: &str) -> String {
+    if value.is_empty() {
+        return "%".to_string();
+    }
+    let bytes = value.as_bytes();
+    let mut encoded = String::with_capacity(bytes.len());
+    for (i, &byte) in bytes.iter().enumerate() {
+        let keep = match byte {
+            b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' => true,
+            b'.' => i > 0 && i + 1 < bytes.len(),
+            _ => false,
+        };
+        if keep {
+            encoded.push(char::from(byte));
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+
+    let stem = encoded.split('.').next().unwrap_or_default();
+    if WINDOWS_DEVICE_NAMES.contains(&stem) {
+        encoded = format!("%{:02X}{}", bytes[0], &encoded[1..]);
+    }
+
+    if encoded.len() > MAX_NAME_LEN {
+        let digest = hex::encode(Sha256::digest(bytes));
+        encoded.truncate(MAX_NAME_LEN - digest.len() - 1);
+        encoded.push('~');
+        encoded.push_str(&digest);
+    }
+    encoded
+}
+
+/// Returns whether a path has the extension of state files. Temporary files of interrupted
+/// writes end in `.json.tmp` and are skipped.
+fn is_state_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "json")
+}
+
+/// Returns whether a path is a temporary file, which a write replaces its state file with.
+fn is_temp_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.ends_with(".json.tmp"))
+}
+
+/// Flushes a directory to disk, so that entries renamed, created or removed in it survive a
+/// crash. Directories can only be opened for this on Unix; elsewhere, this does nothing.
+fn sync_directory(path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    fs::File::open(path)
+        .and_then(|directory| directory.sync_all())
+        .map_err(CoreError::IoError)?;
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// Access to the state directory, held by one operation: the backend's lock within the process
+/// and the advisory lock on the lock file, which is released when the guard is dropped.
+struct DirectoryGuard<'a> {
+    lock_file: &'a fs::File,
+    _guard: MutexGuard<'a, ()>,
+}
+
+impl Drop for DirectoryGuard<'_> {
+    fn drop(&mut self) {
+        // Closing the lock file would release the lock as well, so an error can be ignored.
+        let _ = self.lock_file.unlock();
+    }
+}
+
+/// The content of a state file: the current state, with the previous versions alongside.
+#[derive(Serialize, Deserialize, Debug)]
+struct StateFile {
+    #[serde(flatten)]
+    state: TemplateState,
+
+    /// The previous versions of the state, newest first. Absent in files written before history
+    /// was recorded.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    history: Vec<TemplateState>,
+}
 
 /// A state persistence backend that stores `TemplateState` as JSON files
 /// in a specified directory on the local filesystem.
@@ -23,71 +158,253 @@ pub struct FilesystemBackend {
     // Using Mutex to prevent race conditions if multiple operations happen concurrently
     // on the same file system backend instance. Arc allows sharing the Mutex.
     lock: Arc<Mutex<()>>,
+    // Locked in addition to `lock`, to exclude other processes using the directory.
+    lock_file: fs::File,
+    history_retention: usize,
 }
 
 impl FilesystemBackend {
     /// Creates a new `FilesystemBackend`.
     ///
-    /// Ensures the base directory exists, creating it if necessary.
+    /// Ensures the base directory exists, creating it if necessary. Then, holding the lock
+    /// file, which blocks until other processes release it:
+    ///   - Removes temporary files left behind by writes which were interrupted by a crash.
+    ///   - Moves state files written in the flat layout of earlier versions, directly in the
+    ///     base directory, to their repository directories.
     ///
     /// # Arguments
     /// * `base_path` - The path to the directory where state files will be stored.
     ///
     /// # Returns
     /// A `Result` containing the new `FilesystemBackend` or a `CoreError::IoError` if the
-    /// directory cannot be created.
+    /// directory cannot be created or locked, or the files in it cannot be cleaned up or moved,
+    /// or a `CoreError::DatabaseError` if a file in the flat layout cannot be read as a state.
     pub fn new(base_path: impl AsRef<Path>) -> Result<Self> {
         let path = base_path.as_ref().to_path_buf();
         fs::create_dir_all(&path).map_err(CoreError::IoError)?;
-        Ok(Self {
+        let lock_file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path.join(LOCK_FILE_NAME))
+            .map_err(CoreError::IoError)?;
+        let backend = Self {
             base_path: path,
             lock: Arc::new(Mutex::new(())),
-        })
+            lock_file,
+            history_retention: DEFAULT_HISTORY_RETENTION,
+        };
+
+        backend.lock_file.lock().map_err(CoreError::IoError)?;
+        let result = backend
+            .remove_temp_files()
+            .and_then(|()| backend.migrate_flat_layout());
+        let _ = backend.lock_file.unlock();
+        result.map(|()| backend)
     }
 
-    /// Constructs the full path for a state file based on the template ID.
-    fn get_file_path(&self, template_id: &str) -> PathBuf {
-        // Basic sanitization: replace common path separators to prevent directory traversal issues.
-        // A more robust solution might involve hashing the ID or stricter validation.
-        let sanitized_id = template_id.replace(['/', '\\', ':', '*'], "_");
-        self.base_path.join(format!("{}.json", sanitized_id))
+    /// Sets how many versions of each state are kept in the history, including the current
+    /// version. Values below 1 are treated as 1.
+    pub fn with_history_retention(mut self, retention: usize) -> Self {
+        self.history_retention = retention.max(1);
+        self
     }
-}
 
-#[async_trait]
-impl StatePersistence for FilesystemBackend {
-    /// Retrieves the state for a given template ID by reading its corresponding JSON file.
-    async fn get_state(&self, template_id: &str) -> Result<Option<TemplateState>> {
-        let file_path = self.get_file_path(template_id);
-        let _guard = self.lock.lock().await; // Lock for read operation consistency
+    /// Constructs the full path for a state file based on the repository and template path.
+    fn get_file_path(&self, key: &StateKey) -> PathBuf {
+        self.repo_path(&key.repo)
+            .join(format!("{}.json", encode_name(&key.template_path)))
+    }
+
+    /// Constructs the path of the directory holding the state files of a repository.
+    fn repo_path(&self, repo: &str) -> PathBuf {
+        self.base_path.join(encode_name(repo))
+    }
+
+    /// Waits for exclusive access to the state directory, for an operation which writes to it.
+    async fn lock_exclusive(&self) -> Result<DirectoryGuard<'_>> {
+        self.lock_directory(fs::File::try_lock).await
+    }
+
+    /// Waits for shared access to the state directory, for an operation which only reads it.
+    async fn lock_shared(&self) -> Result<DirectoryGuard<'_>> {
+        self.lock_directory(fs::File::try_lock_shared).await
+    }
+
+    /// Takes the backend's lock, then polls `try_lock` on the lock file until another process
+    /// releases it. Polling, rather than blocking a thread, leaves the lock file unlocked if the
+    /// operation is cancelled while it waits.
+    async fn lock_directory(
+        &self,
+        try_lock: fn(&fs::File) -> std::result::Result<(), TryLockError>,
+    ) -> Result<DirectoryGuard<'_>> {
+        let guard = self.lock.lock().await;
+        let mut interval = INITIAL_LOCK_POLL_INTERVAL;
+        loop {
+            match try_lock(&self.lock_file) {
+                Ok(()) => {
+                    return Ok(DirectoryGuard {
+                        lock_file: &self.lock_file,
+                        _guard: guard,
+                    })
+                }
+                Err(TryLockError::WouldBlock) => {
+                    tokio::time::sleep(interval).await;
+                    interval = (interval * 2).min(MAX_LOCK_POLL_INTERVAL);
+                }
+                Err(TryLockError::Error(e)) => return Err(CoreError::IoError(e)),
+            }
+        }
+    }
+
+    /// Removes the temporary files in the base directory and the repository directories. While
+    /// the lock file is held exclusively, no write is in progress, so all of them are left
+    /// behind by interrupted writes.
+    fn remove_temp_files(&self) -> Result<()> {
+        let mut directories = vec![self.base_path.clone()];
+        for entry in fs::read_dir(&self.base_path).map_err(CoreError::IoError)? {
+            let path = entry.map_err(CoreError::IoError)?.path();
+            if path.is_dir() {
+                directories.push(path);
+            }
+        }
+
+        for directory in directories {
+            let mut removed = false;
+            for entry in fs::read_dir(&directory).map_err(CoreError::IoError)? {
+                let path = entry.map_err(CoreError::IoError)?.path();
+                if path.is_file() && is_temp_file(&path) {
+                    fs::remove_file(&path).map_err(CoreError::IoError)?;
+                    removed = true;
+                }
+            }
+            if removed {
+                sync_directory(&directory)?;
+            }
+        }
+        Ok(())
+    }
 
-        match tokio::fs::read_to_string(&file_path).await {
+    /// Moves the state files of the flat layout, which were named after their key with
+    /// separators replaced by `_` and could collide, into their repository directories. The
+    /// key is read from each file, including files in the legacy format keyed by a
+    /// `templateId` (see `TemplateState`). If a file for the same key exists in the new layout
+    /// already, it was written later and is kept.
+    ///
+    /// # Errors
+    /// Returns a `CoreError::DatabaseError` naming the first file which cannot be read as a
+    /// state file. It is left in place, as are the files not migrated yet, so the migration
+    /// continues once the file is fixed or removed.
+    fn migrate_flat_layout(&self) -> Result<()> {
+        for entry in fs::read_dir(&self.base_path).map_err(CoreError::IoError)? {
+            let path = entry.map_err(CoreError::IoError)?.path();
+            if !path.is_file() || !is_state_file(&path) {
+                continue;
+            }
+            let content = fs::read_to_string(&path).map_err(CoreError::IoError)?;
+            let file: StateFile = serde_json::from_str(&content).map_err(|e| {
+                CoreError::DatabaseError(format!(
+                    "Failed to migrate state file {}: {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+            let target = self.get_file_path(&file.state.key());
+            if target.exists() {
+                fs::remove_file(&path).map_err(CoreError::IoError)?;
+            } else {
+                let repo_path = self.repo_path(&file.state.repo);
+                fs::create_dir_all(&repo_path).map_err(CoreError::IoError)?;
+                fs::rename(&path, &target).map_err(CoreError::IoError)?;
+                sync_directory(&repo_path)?;
+            }
+            sync_directory(&self.base_path)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the state files in the directory of one repository, in no particular order.
+    async fn read_repo_states(&self, repo_path: &Path) -> Result<Vec<TemplateState>> {
+        let mut entries = match tokio::fs::read_dir(repo_path).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(CoreError::IoError(e)),
+        };
+        let mut states = Vec::new();
+        while let Some(entry) = entries.next_entry().await.map_err(CoreError::IoError)? {
+            let path = entry.path();
+            if !is_state_file(&path) {
+                continue;
+            }
+            let content = tokio::fs::read_to_string(&path)
+                .await
+                .map_err(CoreError::IoError)?;
+            let file: StateFile = serde_json::from_str(&content).map_err(|e| {
+                CoreError::DatabaseError(format!(
+                    "Failed to deserialize state file {}: {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+            states.push(file.state);
+        }
+        Ok(states)
+    }
+
+    /// Reads the state file for a key. The caller must hold the lock.
+    async fn read_state_file(&self, key: &StateKey) -> Result<Option<StateFile>> {
+        match tokio::fs::read_to_string(&self.get_file_path(key)).await {
             Ok(content) => {
-                let state: TemplateState = serde_json::from_str(&content).map_err(|e| {
+                let file: StateFile = serde_json::from_str(&content).map_err(|e| {
                     CoreError::DatabaseError(format!(
                         "Failed to deserialize state for {}: {}",
-                        template_id, e
+                        key, e
                     ))
                 })?;
-                Ok(Some(state))
+                Ok(Some(file))
             }
             Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
             Err(e) => Err(CoreError::IoError(e)),
         }
     }
 
-    /// Saves or updates the state for a template by writing it as JSON to the corresponding file.
-    async fn update_state(&self, state: &TemplateState) -> Result<()> {
-        let file_path = self.get_file_path(&state.template_id);
-        let _guard = self.lock.lock().await; // Lock for write operation
+    /// Writes a state with the given version, moving the state it replaces into the history.
+    /// The caller must hold the lock.
+    fn write_state(
+        &self,
+        state: &TemplateState,
+        version: u64,
+        previous: Option<StateFile>,
+    ) -> Result<()> {
+        let file_path = self.get_file_path(&state.key());
+        let history = match previous {
+            Some(previous) => {
+                let mut history = previous.history;
+                history.insert(0, previous.state);
+                history.truncate(self.history_retention - 1);
+                history
+            }
+            None => Vec::new(),
+        };
+        let file = StateFile {
+            state: TemplateState {
+                version,
+                ..state.clone()
+            },
+            history,
+        };
 
-        let content = serde_json::to_string_pretty(state).map_err(|e| {
+        let content = serde_json::to_string_pretty(&file).map_err(|e| {
             CoreError::DatabaseError(format!(
                 "Failed to serialize state for {}: {}",
-                state.template_id, e
+                file.state.key(),
+                e
             ))
         })?;
 
+        let repo_path = self.repo_path(&file.state.repo);
+        let created_repo_path = !repo_path.exists();
+        fs::create_dir_all(&repo_path).map_err(CoreError::IoError)?;
         // Write to a temporary file first, then rename to make the update more atomic.
         let temp_path = file_path.with_extension("json.tmp");
 
@@ -98,7 +415,157 @@ impl StatePersistence for FilesystemBackend {
         temp_file.sync_all().map_err(CoreError::IoError)?; // Ensure data is flushed to disk
 
         fs::rename(&temp_path, &file_path).map_err(CoreError::IoError)?;
+        // Ensure the rename, and the new repository directory, are flushed to disk as well
+        sync_directory(&repo_path)?;
+        if created_repo_path {
+            sync_directory(&self.base_path)?;
+        }
 
         Ok(())
     }
 }
+
+#[async_trait]
+impl StatePersistence for FilesystemBackend {
+    /// Retrieves the state for a given key by reading its corresponding JSON file.
+    async fn get_state(&self, key: &StateKey) -> Result<Option<TemplateState>> {
+        let _guard = self.lock_shared().await?; // Lock for read operation consistency
+        Ok(self.read_state_file(key).await?.map(|file| file.state))
+    }
+
+    /// Saves or updates the state for a template by writing it as JSON to the corresponding file.
+    async fn update_state(&self, state: &TemplateState) -> Result<()> {
+        let _guard = self.lock_exclusive().await?; // Lock for write operation
+
+        let current = match self.read_state_file(&state.key()).await {
+            Ok(current) => current,
+            // A corrupt state file is replaced rather than preventing any further updates.
+            Err(CoreError::DatabaseError(_)) => None,
+            Err(e) => return Err(e),
+        };
+        let current_version = current.as_ref().map_or(0, |current| current.state.version);
+        self.write_state(state, current_version + 1, current)
+    }
+
+    /// Writes the state if the version in its file matches `expected_version`.
+    ///
+    /// The check and the write happen under the backend's lock and the lock file, so they are
+    /// atomic with respect to other users of this `FilesystemBackend` instance and to other
+    /// processes using the directory.
+    async fn update_state_if(
+        &self,
+        state: &TemplateState,
+        expected_version: Option<u64>,
+    ) -> Result<u64> {
+        let key = state.key();
+        let _guard = self.lock_exclusive().await?; // Lock for write operation
+
+        let current = self.read_state_file(&key).await?;
+        let actual_version = current.as_ref().map(|current| current.state.version);
+        if actual_version != expected_version {
+            return Err(CoreError::StateConflict {
+                key,
+                expected: expected_version,
+                actual: actual_version,
+            });
+        }
+
+        let new_version = expected_version.map_or(1, |version| version + 1);
+        self.write_state(state, new_version, current)?;
+        Ok(new_version)
+    }
+
+    /// Lists states by reading every state file in the directory of the queried repository, or
+    /// in all repository directories, then filtering, sorting and paginating them in memory.
+    async fn list_states(&self, query: &StateQuery) -> Result<StatePage> {
+        let _guard = self.lock_shared().await?; // Lock so no file is read while it is replaced
+
+        let repo_paths = match &query.repo {
+            Some(repo) => vec![self.repo_path(repo)],
+            None => {
+                let mut repo_paths = Vec::new();
+                let mut entries = tokio::fs::read_dir(&self.base_path)
+                    .await
+                    .map_err(CoreError::IoError)?;
+                while let Some(entry) = entries.next_entry().await.map_err(CoreError::IoError)? {
+                    if entry
+                        .file_type()
+                        .await
+                        .map_err(CoreError::IoError)?
+                        .is_dir()
+                    {
+                        repo_paths.push(entry.path());
+                    }
+                }
+                repo_paths
+            }
+        };
+
+        let mut states = Vec::new();
+        for repo_path in repo_paths {
+            states.extend(
+                self.read_repo_states(&repo_path)
+                    .await?
+                    .into_iter()
+                    .filter(|state| query.matches(&state.key())),
+            );
+        }
+
+        states.sort_by_key(|state| state.key());
+        query.paginate(states)
+    }
+
+    /// Deletes the state for a template, with its history, by removing its corresponding file.
+    async fn delete_state(&self, key: &StateKey) -> Result<bool> {
+        let file_path = self.get_file_path(key);
+        let _guard = self.lock_exclusive().await?; // Lock for write operation
+
+        match tokio::fs::remove_file(&file_path).await {
+            Ok(()) => {
+                sync_directory(file_path.parent().unwrap_or(&self.base_path))?;
+                Ok(true)
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(CoreError::IoError(e)),
+        }
+    }
+
+    /// Reads the current state and its history from the state file.
+    async fn get_state_history(&self, key: &StateKey) -> Result<Vec<TemplateState>> {
+        let _guard = self.lock_shared().await?; // Lock for read operation consistency
+        let Some(file) = self.read_state_file(key).await? else {
+            return Ok(Vec::new());
+        };
+        let mut history = vec![file.state];
+        history.extend(file.history.into_iter().take(self.history_retention - 1));
+        Ok(history)
+    }
+
+    /// Reads the state files of all keys under a single acquisition of the lock.
+    async fn get_states(&self, keys: &[StateKey]) -> Result<Vec<Option<TemplateState>>> {
+        let _guard = self.lock_shared().await?; // Lock for read operation consistency
+        let mut states = Vec::with_capacity(keys.len());
+        for key in keys {
+            states.push(self.read_state_file(key).await?.map(|file| file.state));
+        }
+        Ok(states)
+    }
+
+    /// Writes the state files of all states under a single acquisition of the lock. Each file is
+    /// replaced atomically, but the batch is not: if a write fails, the states before it remain
+    /// written.
+    async fn update_states(&self, states: &[TemplateState]) -> Result<()> {
+        let _guard = self.lock_exclusive().await?; // Lock for write operation
+        for state in states {
+            let current = match self.read_state_file(&state.key()).await {
+                Ok(current) => current,
+                // A corrupt state file is replaced, as by `update_state`.
+                Err(CoreError::DatabaseError(_)) => None,
+                Err(e) => return Err(e),
+            };
+            let current_version = current.as_ref().map_or(0, |current| current.state.version);
+            self.write_state(state, current_version + 1, current)?;
+        }
+        Ok(())
+    }
+}
@@ -2,7 +2,7 @@
 
 use super::*; // Import items from filesystem_backend.rs
 use crate::state_manager::StatePersistence; // Import the trait
-use crate::types::TemplateState;
+use crate::types::{StateKey, StateQuery, TemplateState};
 use chrono::Utc;
 use futures::future;
 use std::fs;
@@ -30,10 +30,13 @@ async fn test_filesystem_backend_update_and_get_state() {
 
     let template_id = "my/template";
     let state = TemplateState {
-        template_id: template_id.to_string(),
+        repo: "org/target".to_string(),
+        template_path: template_id.to_string(),
         source_repository: "owner/repo".to_string(),
-        current_checksum: "checksum123".to_string(),
+        master_checksum: "checksum123".to_string(),
+        deployed_checksum: None,
         last_updated_utc: Utc::now(),
+        version: 1, // Backends store a new state with version 1
     };
 
     // 1. Update state
@@ -41,13 +44,15 @@ async fn test_filesystem_backend_update_and_get_state() {
     assert!(update_result.is_ok());
 
     // Verify file exists and content is correct (optional deep check)
-    let file_path = base_path.join("my_template.json"); // Check sanitized name
+    let file_path = base_path.join("org%2Ftarget").join("my%2Ftemplate.json"); // Check encoded name
     assert!(file_path.exists());
     let content = fs::read_to_string(&file_path).unwrap();
     assert!(content.contains("checksum123"));
 
     // 2. Get state
-    let get_result = backend.get_state(template_id).await;
+    let get_result = backend
+        .get_state(&StateKey::new("org/target", template_id))
+        .await;
     assert!(get_result.is_ok());
     let retrieved_state_opt = get_result.unwrap();
     assert!(retrieved_state_opt.is_some());
@@ -61,7 +66,9 @@ async fn test_filesystem_backend_get_state_not_found() {
     let backend = FilesystemBackend::new(&base_path).unwrap();
 
     let template_id = "non-existent-template";
-    let get_result = backend.get_state(template_id).await;
+    let get_result = backend
+        .get_state(&StateKey::new("org/target", template_id))
+        .await;
 
     assert!(get_result.is_ok());
     assert!(get_result.unwrap().is_none());
@@ -75,27 +82,41 @@ async fn test_filesystem_backend_update_overwrites_existing() {
 
     let template_id = "overwrite/template";
     let initial_state = TemplateState {
-        template_id: template_id.to_string(),
+        repo: "org/target".to_string(),
+        template_path: template_id.to_string(),
         source_repository: "owner/repo".to_string(),
-        current_checksum: "checksum_initial".to_string(),
+        master_checksum: "checksum_initial".to_string(),
+        deployed_checksum: None,
         last_updated_utc: Utc::now(),
+        version: 0,
     };
     let updated_state = TemplateState {
-        template_id: template_id.to_string(),
+        repo: "org/target".to_string(),
+        template_path: template_id.to_string(),
         source_repository: "owner/repo".to_string(),
-        current_checksum: "checksum_updated".to_string(),
+        master_checksum: "checksum_updated".to_string(),
+        deployed_checksum: None,
         last_updated_utc: Utc::now(), // Timestamps will differ slightly, maybe ignore in comparison if needed
+        version: 0,
     };
 
     // Write initial state
     backend.update_state(&initial_state).await.unwrap();
-    let retrieved_initial = backend.get_state(template_id).await.unwrap().unwrap();
-    assert_eq!(retrieved_initial.current_checksum, "checksum_initial");
+    let retrieved_initial = backend
+        .get_state(&StateKey::new("org/target", template_id))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(retrieved_initial.master_checksum, "checksum_initial");
 
     // Write updated state
     backend.update_state(&updated_state).await.unwrap();
-    let retrieved_updated = backend.get_state(template_id).await.unwrap().unwrap();
-    assert_eq!(retrieved_updated.current_checksum, "checksum_updated");
+    let retrieved_updated = backend
+        .get_state(&StateKey::new("org/target", template_id))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(retrieved_updated.master_checksum, "checksum_updated");
     // Note: Comparing the full updated_state might fail due to timestamp differences.
     // assert_eq!(retrieved_updated, updated_state); // This might fail
 }
@@ -107,12 +128,17 @@ async fn test_filesystem_backend_get_state_invalid_json() {
     let backend = FilesystemBackend::new(&base_path).unwrap();
 
     let template_id = "invalid-json-template";
-    let file_path = base_path.join("invalid-json-template.json");
 
     // Create a file with invalid JSON content
-    fs::write(&file_path, "{ invalid json ").unwrap();
+    write_state_file(
+        &backend,
+        &StateKey::new("org/target", template_id),
+        "{ invalid json ",
+    );
 
-    let get_result = backend.get_state(template_id).await;
+    let get_result = backend
+        .get_state(&StateKey::new("org/target", template_id))
+        .await;
 
     assert!(get_result.is_err());
     match get_result.err().unwrap() {
@@ -131,16 +157,22 @@ async fn test_filesystem_backend_get_state_io_error() {
     let backend = FilesystemBackend::new(&base_path).unwrap();
 
     let template_id = "io-error-template";
-    let file_path = base_path.join("io-error-template.json");
+    let file_path = backend.get_file_path(&StateKey::new("org/target", template_id));
 
     // Write valid JSON, then make the file unreadable (chmod 000)
     use std::os::unix::fs::PermissionsExt;
-    fs::write(&file_path, r#"{"template_id":"id","source_repository":"repo","current_checksum":"sum","last_updated_utc":"2020-01-01T00:00:00Z"}"#).unwrap();
+    write_state_file(
+        &backend,
+        &StateKey::new("org/target", template_id),
+        r#"{"repo":"org/target","templatePath":"io-error-template","sourceRepository":"repo","masterChecksum":"sum","deployedChecksum":null,"lastUpdatedUtc":"2020-01-01T00:00:00Z"}"#,
+    );
     let mut perms = fs::metadata(&file_path).unwrap().permissions();
     perms.set_mode(0o000);
     fs::set_permissions(&file_path, perms.clone()).unwrap();
 
-    let get_result = backend.get_state(template_id).await;
+    let get_result = backend
+        .get_state(&StateKey::new("org/target", template_id))
+        .await;
 
     // Restore permissions for cleanup (chmod 644)
     perms.set_mode(0o644);
@@ -166,15 +198,19 @@ async fn test_filesystem_backend_update_state_io_error() {
     fs::set_permissions(&base_path, perms.clone()).unwrap();
 
     let state = TemplateState {
-        template_id: "fail".to_string(),
+        repo: "org/target".to_string(),
+        template_path: "fail".to_string(),
         source_repository: "repo".to_string(),
-        current_checksum: "sum".to_string(),
+        master_checksum: "sum".to_string(),
+        deployed_checksum: None,
         last_updated_utc: Utc::now(),
+        version: 0,
     };
 
     let result = backend.update_state(&state).await;
 
     // Restore permissions for cleanup
+    #[allow(clippy::permissions_set_readonly_false)]
     perms.set_readonly(false);
     fs::set_permissions(&base_path, perms).unwrap();
 
@@ -200,10 +236,13 @@ async fn test_filesystem_backend_handles_concurrent_updates() {
         .map(|i| {
             let backend_clone = Arc::clone(&backend);
             let state = TemplateState {
-                template_id: template_id.to_string(),
+                repo: "org/target".to_string(),
+                template_path: template_id.to_string(),
                 source_repository: "owner/repo".to_string(),
-                current_checksum: format!("checksum_{}", i),
+                master_checksum: format!("checksum_{}", i),
+                deployed_checksum: None,
                 last_updated_utc: Utc::now(),
+                version: 0,
             };
             tokio::spawn(async move { backend_clone.update_state(&state).await })
         })
@@ -218,9 +257,520 @@ async fn test_filesystem_backend_handles_concurrent_updates() {
     }
 
     // Verify the final state reflects one of the updates (likely the last one)
-    let final_state_opt = backend.get_state(template_id).await.unwrap();
+    let final_state_opt = backend
+        .get_state(&StateKey::new("org/target", template_id))
+        .await
+        .unwrap();
     assert!(final_state_opt.is_some());
     let final_state = final_state_opt.unwrap();
-    assert!(final_state.current_checksum.starts_with("checksum_")); // Checksum should be one of the written ones
-    println!("Final checksum: {}", final_state.current_checksum); // See which one "won"
+    assert!(final_state.master_checksum.starts_with("checksum_")); // Checksum should be one of the written ones
+    println!("Final checksum: {}", final_state.master_checksum); // See which one "won"
+}
+
+/// Writes the state file of a key directly, creating its repository directory.
+fn write_state_file(backend: &FilesystemBackend, key: &StateKey, content: &str) {
+    let file_path = backend.get_file_path(key);
+    fs::create_dir_all(file_path.parent().unwrap()).unwrap();
+    fs::write(file_path, content).unwrap();
+}
+
+fn state_for(repo: &str, template_path: &str) -> TemplateState {
+    TemplateState {
+        repo: repo.to_string(),
+        template_path: template_path.to_string(),
+        source_repository: "owner/master".to_string(),
+        master_checksum: "checksum".to_string(),
+        deployed_checksum: Some("checksum".to_string()),
+        last_updated_utc: Utc::now(),
+        version: 0,
+    }
+}
+
+#[tokio::test]
+async fn test_filesystem_backend_list_states_filters_and_sorts() {
+    let dir = tempdir().unwrap();
+    let backend = FilesystemBackend::new(dir.path()).unwrap();
+    for (repo, path) in [
+        ("org/b", "README.md"),
+        ("org/a", ".github/PULL_REQUEST_TEMPLATE.md"),
+        ("org/a", "README.md"),
+        ("org/a", ".github/CODEOWNERS"),
+    ] {
+        backend.update_state(&state_for(repo, path)).await.unwrap();
+    }
+    // Leftover temporary files of interrupted writes are ignored.
+    fs::write(dir.path().join("org%2Fa").join("stale.json.tmp"), "{").unwrap();
+
+    let all = backend.list_states(&StateQuery::default()).await.unwrap();
+    let keys: Vec<_> = all.states.iter().map(|s| s.key().to_string()).collect();
+    assert_eq!(
+        keys,
+        vec![
+            "org/a:.github/CODEOWNERS",
+            "org/a:.github/PULL_REQUEST_TEMPLATE.md",
+            "org/a:README.md",
+            "org/b:README.md",
+        ]
+    );
+    assert_eq!(all.next_page_token, None);
+
+    let query = StateQuery::default()
+        .with_repo("org/a")
+        .with_template_path_prefix(".github/");
+    let filtered = backend.list_states(&query).await.unwrap();
+    let paths: Vec<_> = filtered
+        .states
+        .iter()
+        .map(|s| s.template_path.as_str())
+        .collect();
+    assert_eq!(
+        paths,
+        vec![".github/CODEOWNERS", ".github/PULL_REQUEST_TEMPLATE.md"]
+    );
+}
+
+#[tokio::test]
+async fn test_filesystem_backend_list_states_paginates() {
+    let dir = tempdir().unwrap();
+    let backend = FilesystemBackend::new(dir.path()).unwrap();
+    for path in ["a", "b", "c", "d", "e"] {
+        backend
+            .update_state(&state_for("org/a", path))
+            .await
+            .unwrap();
+    }
+
+    let mut query = StateQuery::default().with_limit(2);
+    let mut pages = Vec::new();
+    loop {
+        let page = backend.list_states(&query).await.unwrap();
+        pages.push(
+            page.states
+                .iter()
+                .map(|s| s.template_path.clone())
+                .collect::<Vec<_>>(),
+        );
+        match page.next_page_token {
+            Some(token) => query = query.with_page_token(token),
+            None => break,
+        }
+    }
+
+    assert_eq!(pages, vec![vec!["a", "b"], vec!["c", "d"], vec!["e"]]);
+}
+
+#[tokio::test]
+async fn test_filesystem_backend_list_states_invalid_page_token() {
+    let dir = tempdir().unwrap();
+    let backend = FilesystemBackend::new(dir.path()).unwrap();
+
+    let result = backend
+        .list_states(&StateQuery::default().with_page_token("not-a-token"))
+        .await;
+
+    assert!(matches!(result, Err(CoreError::DatabaseError(msg)) if msg.contains("page token")));
+}
+
+#[tokio::test]
+async fn test_filesystem_backend_delete_state() {
+    let dir = tempdir().unwrap();
+    let backend = FilesystemBackend::new(dir.path()).unwrap();
+    let state = state_for("org/a", "README.md");
+    backend.update_state(&state).await.unwrap();
+
+    assert!(backend.delete_state(&state.key()).await.unwrap());
+    assert_eq!(backend.get_state(&state.key()).await.unwrap(), None);
+    assert!(!backend.delete_state(&state.key()).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_filesystem_backend_update_state_increments_version() {
+    let dir = tempdir().unwrap();
+    let backend = FilesystemBackend::new(dir.path()).unwrap();
+    let state = state_for("org/a", "README.md");
+
+    for expected in 1..=3 {
+        backend.update_state(&state).await.unwrap();
+        let stored = backend.get_state(&state.key()).await.unwrap().unwrap();
+        assert_eq!(stored.version, expected);
+    }
+}
+
+#[tokio::test]
+async fn test_filesystem_backend_update_state_if() {
+    let dir = tempdir().unwrap();
+    let backend = FilesystemBackend::new(dir.path()).unwrap();
+    let state = state_for("org/a", "README.md");
+
+    assert_eq!(backend.update_state_if(&state, None).await.unwrap(), 1);
+    assert_eq!(backend.update_state_if(&state, Some(1)).await.unwrap(), 2);
+    assert_eq!(
+        backend
+            .get_state(&state.key())
+            .await
+            .unwrap()
+            .unwrap()
+            .version,
+        2
+    );
+}
+
+#[tokio::test]
+async fn test_filesystem_backend_update_state_if_conflict() {
+    let dir = tempdir().unwrap();
+    let backend = FilesystemBackend::new(dir.path()).unwrap();
+    let mut state = state_for("org/a", "README.md");
+    backend.update_state_if(&state, None).await.unwrap();
+
+    // Creating a state which already exists conflicts
+    let result = backend.update_state_if(&state, None).await;
+    assert!(matches!(
+        result,
+        Err(CoreError::StateConflict {
+            expected: None,
+            actual: Some(1),
+            ..
+        })
+    ));
+
+    // Writing with a stale version conflicts and leaves the stored state untouched
+    state.master_checksum = "stale".to_string();
+    let result = backend.update_state_if(&state, Some(0)).await;
+    match result {
+        Err(CoreError::StateConflict {
+            key,
+            expected,
+            actual,
+        }) => {
+            assert_eq!(key, state.key());
+            assert_eq!((expected, actual), (Some(0), Some(1)));
+        }
+        r => panic!("Expected StateConflict, got {:?}", r),
+    }
+    let stored = backend.get_state(&state.key()).await.unwrap().unwrap();
+    assert_eq!(stored.master_checksum, "checksum");
+
+    // Updating a state which does not exist conflicts
+    let missing = state_for("org/a", "missing.md");
+    let result = backend.update_state_if(&missing, Some(1)).await;
+    assert!(matches!(
+        result,
+        Err(CoreError::StateConflict { actual: None, .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_filesystem_backend_concurrent_update_state_if_only_one_wins() {
+    let dir = tempdir().unwrap();
+    let backend = Arc::new(FilesystemBackend::new(dir.path()).unwrap());
+
+    let tasks = (0..10)
+        .map(|_| {
+            let backend = Arc::clone(&backend);
+            tokio::spawn(async move {
+                backend
+                    .update_state_if(&state_for("org/a", "README.md"), None)
+                    .await
+            })
+        })
+        .collect::<Vec<_>>();
+    let results = future::join_all(tasks).await;
+
+    let successes = results
+        .into_iter()
+        .filter(|result| result.as_ref().unwrap().is_ok())
+        .count();
+    assert_eq!(successes, 1);
+}
+
+#[tokio::test]
+async fn test_filesystem_backend_history_retention() {
+    let dir = tempdir().unwrap();
+    let backend = FilesystemBackend::new(dir.path())
+        .unwrap()
+        .with_history_retention(2);
+    let mut state = state_for("org/a", "README.md");
+    for checksum in ["one", "two", "three"] {
+        state.master_checksum = checksum.to_string();
+        backend.update_state(&state).await.unwrap();
+    }
+
+    let history = backend.get_state_history(&state.key()).await.unwrap();
+
+    let checksums: Vec<_> = history.iter().map(|s| s.master_checksum.as_str()).collect();
+    assert_eq!(checksums, ["three", "two"]);
+    assert_eq!(history[1].version, 2);
+}
+
+#[tokio::test]
+async fn test_filesystem_backend_reads_state_files_without_history() {
+    let dir = tempdir().unwrap();
+    let backend = FilesystemBackend::new(dir.path()).unwrap();
+    let state = TemplateState {
+        version: 4,
+        ..state_for("org/a", "README.md")
+    };
+    // State files written before history was recorded hold only the state
+    write_state_file(
+        &backend,
+        &state.key(),
+        &serde_json::to_string(&state).unwrap(),
+    );
+
+    assert_eq!(
+        backend.get_state_history(&state.key()).await.unwrap(),
+        vec![state.clone()]
+    );
+    backend.update_state(&state).await.unwrap();
+    let history = backend.get_state_history(&state.key()).await.unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].version, 5);
+    assert_eq!(history[1], state);
+}
+
+#[tokio::test]
+async fn test_filesystem_backend_conformance() {
+    let dir = tempdir().unwrap();
+    let mut count = 0;
+    crate::conformance::run_all(|| {
+        count += 1;
+        let backend = FilesystemBackend::new(dir.path().join(count.to_string())).unwrap();
+        async move { Arc::new(backend) as Arc<dyn StatePersistence> }
+    })
+    .await;
+}
+
+#[test]
+fn test_encode_name() {
+    assert_eq!(encode_name("readme.md"), "readme.md");
+    assert_eq!(encode_name("org/target"), "org%2Ftarget");
+    assert_eq!(encode_name("README.md"), "%52%45%41%44%4D%45.md");
+    assert_eq!(encode_name(".github"), "%2Egithub");
+    assert_eq!(encode_name(".."), "%2E%2E");
+    assert_eq!(encode_name("docs."), "docs%2E");
+    assert_eq!(encode_name("nul.txt"), "%6Eul.txt");
+    assert_eq!(encode_name("50%~"), "50%25%7E");
+    assert_eq!(encode_name(""), "%");
+
+    let long = "a".repeat(300);
+    let encoded = encode_name(&long);
+    assert_eq!(encoded.len(), MAX_NAME_LEN);
+    assert!(encoded.starts_with("aaa"));
+    assert_ne!(encoded, encode_name(&"a".repeat(301)));
+}
+
+#[tokio::test]
+async fn test_filesystem_backend_keeps_keys_apart() {
+    let dir = tempdir().unwrap();
+    let backend = FilesystemBackend::new(dir.path()).unwrap();
+    // Keys which the flat layout mapped to the same `org_a_docs_readme.md.json`, or which
+    // differ only in case or length
+    let keys = [
+        ("org/a", "docs/readme.md"),
+        ("org/a", "docs\\readme.md"),
+        ("org/a", "docs_readme.md"),
+        ("org/a_docs", "readme.md"),
+        ("org/a", "docs/README.md"),
+        ("org/a", "../../escape"),
+        ("..", "readme.md"),
+        ("org/a", &"long/".repeat(100)),
+        ("org/a", &"long/".repeat(101)),
+    ];
+    for (repo, path) in keys {
+        backend.update_state(&state_for(repo, path)).await.unwrap();
+    }
+
+    for (repo, path) in keys {
+        let state = backend
+            .get_state(&StateKey::new(repo, path))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((state.repo.as_str(), state.version), (repo, 1));
+    }
+    let listed = backend.list_states(&StateQuery::default()).await.unwrap();
+    assert_eq!(listed.states.len(), keys.len());
+    // Every file is written to a repository directory within the base directory
+    let mut names: Vec<_> = fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["%2E%2E", ".lock", "org%2Fa", "org%2Fa_docs"]);
+}
+
+#[tokio::test]
+async fn test_filesystem_backend_list_states_reads_only_queried_repo() {
+    let dir = tempdir().unwrap();
+    let backend = FilesystemBackend::new(dir.path()).unwrap();
+    backend
+        .update_state(&state_for("org/a", "README.md"))
+        .await
+        .unwrap();
+    // A corrupt file of another repository does not affect listing this one
+    write_state_file(&backend, &StateKey::new("org/b", "README.md"), "{");
+
+    let page = backend
+        .list_states(&StateQuery::default().with_repo("org/a"))
+        .await
+        .unwrap();
+
+    assert_eq!(page.states.len(), 1);
+}
+
+/// Returns the content of a state file in the flat layout as written before states were tracked
+/// per repository, keyed by a `templateId` in the source repository `org/master`.
+fn legacy_state_file(template_id: &str, checksum: &str) -> String {
+    format!(
+        r#"{{"templateId":"{}","sourceRepository":"org/master","currentChecksum":"{}","lastUpdatedUtc":"2025-03-02T09:20:00Z"}}"#,
+        template_id, checksum
+    )
+}
+
+#[tokio::test]
+async fn test_filesystem_backend_migrates_flat_layout() {
+    let dir = tempdir().unwrap();
+    let newer = TemplateState {
+        version: 5,
+        ..state_for("org/master", "README.md")
+    };
+    fs::write(
+        dir.path().join(".github_CODEOWNERS.json"),
+        legacy_state_file(".github/CODEOWNERS", "owners"),
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("README.md.json"),
+        legacy_state_file("README.md", "readme"),
+    )
+    .unwrap();
+    {
+        let backend = FilesystemBackend::new(dir.path()).unwrap();
+        write_state_file(
+            &backend,
+            &newer.key(),
+            &serde_json::to_string(&newer).unwrap(),
+        );
+    }
+
+    let backend = FilesystemBackend::new(dir.path()).unwrap();
+
+    let key = StateKey::new("org/master", ".github/CODEOWNERS");
+    assert_eq!(
+        backend.get_state(&key).await.unwrap(),
+        Some(TemplateState {
+            repo: "org/master".to_string(),
+            template_path: ".github/CODEOWNERS".to_string(),
+            source_repository: "org/master".to_string(),
+            master_checksum: "owners".to_string(),
+            deployed_checksum: None,
+            last_updated_utc: "2025-03-02T09:20:00Z".parse().unwrap(),
+            version: 0,
+        })
+    );
+    // The file written in the new layout is kept over the flat one
+    assert_eq!(backend.get_state(&newer.key()).await.unwrap(), Some(newer));
+    assert!(!dir.path().join(".github_CODEOWNERS.json").exists());
+    assert!(!dir.path().join("README.md.json").exists());
+    // Migrating again finds nothing to move
+    FilesystemBackend::new(dir.path()).unwrap();
+    let listed = backend.list_states(&StateQuery::default()).await.unwrap();
+    assert_eq!(listed.states.len(), 2);
+}
+
+#[tokio::test]
+async fn test_filesystem_backend_reports_unmigratable_flat_file() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("broken.json"), "{").unwrap();
+
+    let result = FilesystemBackend::new(dir.path());
+
+    assert!(
+        matches!(&result, Err(CoreError::DatabaseError(msg)) if msg.contains("broken.json")),
+        "{:?}",
+        result
+    );
+    assert!(dir.path().join("broken.json").exists());
+}
+
+#[tokio::test]
+async fn test_filesystem_backend_instances_on_one_directory_exclude_each_other() {
+    // Each instance has its own in-process lock, as a separate process would, so only the lock
+    // file keeps their read-modify-write cycles apart.
+    let dir = tempdir().unwrap();
+    let backends = [
+        Arc::new(FilesystemBackend::new(dir.path()).unwrap()),
+        Arc::new(FilesystemBackend::new(dir.path()).unwrap()),
+    ];
+    let state = state_for("org/a", "README.md");
+
+    let tasks = (0..20).map(|i| {
+        let backend = Arc::clone(&backends[i % 2]);
+        let state = state.clone();
+        tokio::spawn(async move { backend.update_state(&state).await })
+    });
+    for result in future::join_all(tasks).await {
+        result.unwrap().unwrap();
+    }
+
+    let stored = backends[0].get_state(&state.key()).await.unwrap().unwrap();
+    assert_eq!(stored.version, 20);
+}
+
+#[tokio::test]
+async fn test_filesystem_backend_waits_for_lock_file() {
+    let dir = tempdir().unwrap();
+    let backend = FilesystemBackend::new(dir.path()).unwrap();
+    let state = state_for("org/a", "README.md");
+    // Another process holds the lock file
+    let lock_file = fs::File::open(dir.path().join(LOCK_FILE_NAME)).unwrap();
+    lock_file.lock().unwrap();
+
+    // Reads wait as well, as the other process might be replacing files
+    let key = state.key();
+    let read = tokio::time::timeout(Duration::from_millis(10), backend.get_state(&key));
+    assert!(read.await.is_err());
+    let update = backend.update_state(&state);
+    tokio::pin!(update);
+    let waited = tokio::time::timeout(Duration::from_millis(100), &mut update).await;
+    assert!(waited.is_err());
+
+    lock_file.unlock().unwrap();
+    update.await.unwrap();
+    assert!(backend.get_state(&state.key()).await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn test_filesystem_backend_readers_share_lock_file() {
+    let dir = tempdir().unwrap();
+    let backend = FilesystemBackend::new(dir.path()).unwrap();
+    let state = state_for("org/a", "README.md");
+    backend.update_state(&state).await.unwrap();
+    // Another process reads the directory
+    let lock_file = fs::File::open(dir.path().join(LOCK_FILE_NAME)).unwrap();
+    lock_file.lock_shared().unwrap();
+
+    assert!(backend.get_state(&state.key()).await.unwrap().is_some());
+    let listed = backend.list_states(&StateQuery::default()).await.unwrap();
+    assert_eq!(listed.states.len(), 1);
+}
+
+#[tokio::test]
+async fn test_filesystem_backend_removes_stale_temp_files() {
+    let dir = tempdir().unwrap();
+    let key = StateKey::new("org/a", "README.md");
+    let temp_path = {
+        let backend = FilesystemBackend::new(dir.path()).unwrap();
+        backend.get_file_path(&key).with_extension("json.tmp")
+    };
+    // A crash left temporary files behind
+    fs::create_dir_all(temp_path.parent().unwrap()).unwrap();
+    fs::write(&temp_path, "{ partial").unwrap();
+    fs::write(dir.path().join("org_a_README.md.json.tmp"), "{").unwrap();
+
+    let backend = FilesystemBackend::new(dir.path()).unwrap();
+
+    assert!(!temp_path.exists());
+    assert!(!dir.path().join("org_a_README.md.json.tmp").exists());
+    assert_eq!(backend.get_state(&key).await.unwrap(), None);
 }
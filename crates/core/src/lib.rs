@@ -16,3 +16,21 @@ pub use updater::*;
 
 mod filesystem_backend; // Added for testing
 pub use filesystem_backend::*; // Added for testing
+
+mod memory_backend;
+pub use memory_backend::*;
+
+mod clock;
+pub use clock::*;
+
+mod caching_backend;
+pub use caching_backend::*;
+
+mod retrying_backend;
+pub use retrying_backend::*;
+
+mod state_migration;
+pub use state_migration::*;
+
+#[cfg(any(test, feature = "conformance"))]
+pub mod conformance;
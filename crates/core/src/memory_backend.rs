@@ -0,0 +1,418 @@
+//! Implements a `StatePersistence` backend which keeps all state in memory.
+//!
+//! The backend behaves like the persistent backends (versions, conditional updates, ordered and
+//! paginated listing), so it can stand in for them in tests and dry runs. For tests it can
+//! additionally snapshot and restore its content, inject faults into upcoming calls and report
+//! which calls were made.
+
+use crate::state_manager::{StatePersistence, DEFAULT_HISTORY_RETENTION};
+use crate::types::{CoreError, Result, StateKey, StatePage, StateQuery, TemplateState};
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+#[cfg(test)]
+#[path = "memory_backend_tests.rs"]
+mod tests;
+
+/// An operation of the `StatePersistence` trait, used to target injected faults and to inspect
+/// the calls made to an `InMemoryBackend`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StateOperation {
+    /// `StatePersistence::get_state`.
+    GetState,
+
+    /// `StatePersistence::update_state`.
+    UpdateState,
+
+    /// `StatePersistence::update_state_if`.
+    UpdateStateIf,
+
+    /// `StatePersistence::list_states`.
+    ListStates,
+
+    /// `StatePersistence::delete_state`.
+    DeleteState,
+
+    /// `StatePersistence::get_state_history`.
+    GetStateHistory,
+
+    /// `StatePersistence::get_states`.
+    GetStates,
+
+    /// `StatePersistence::update_states`.
+    UpdateStates,
+}
+
+/// A fault to inject into a call of an `InMemoryBackend`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InjectedFault {
+    /// The call fails with `CoreError::DatabaseError` carrying the given message, without
+    /// touching the stored state.
+    DatabaseError(String),
+
+    /// The call fails with `CoreError::TransientDatabaseError` carrying the given message,
+    /// without touching the stored state.
+    TransientDatabaseError(String),
+
+    /// Another writer updates the state of the call's key just before the call runs: an
+    /// existing state gets its version incremented, a missing state is created. A conditional
+    /// update made with a version read before the fault then fails with
+    /// `CoreError::StateConflict`, as it would against a real backend. Batch calls have no
+    /// single key, so the fault leaves their states untouched.
+    ConcurrentUpdate,
+}
+
+/// A copy of the content of an `InMemoryBackend`, taken with `InMemoryBackend::snapshot`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StateSnapshot {
+    states: BTreeMap<StateKey, TemplateState>,
+    history: BTreeMap<StateKey, Vec<TemplateState>>,
+}
+
+impl StateSnapshot {
+    /// Returns the states in the snapshot, in `StateKey` order.
+    pub fn states(&self) -> Vec<TemplateState> {
+        self.states.values().cloned().collect()
+    }
+}
+
+/// A fault waiting for the `remaining`-th matching call.
+#[derive(Debug)]
+struct PendingFault {
+    operation: Option<StateOperation>,
+    remaining: usize,
+    fault: InjectedFault,
+}
+
+#[derive(Debug)]
+struct Inner {
+    states: BTreeMap<StateKey, TemplateState>,
+    /// The previous versions of each state, newest first.
+    history: BTreeMap<StateKey, Vec<TemplateState>>,
+    history_retention: usize,
+    calls: Vec<StateOperation>,
+    faults: Vec<PendingFault>,
+}
+
+impl Default for Inner {
+    fn default() -> Self {
+        Self {
+            states: BTreeMap::new(),
+            history: BTreeMap::new(),
+            history_retention: DEFAULT_HISTORY_RETENTION,
+            calls: Vec::new(),
+            faults: Vec::new(),
+        }
+    }
+}
+
+impl Inner {
+    /// Stores a new version of a state, moving the version it replaces into the history.
+    fn store(&mut self, state: TemplateState) {
+        let key = state.key();
+        if let Some(previous) = self.states.insert(key.clone(), state) {
+            let history = self.history.entry(key).or_default();
+            history.insert(0, previous);
+            history.truncate(self.history_retention.saturating_sub(1));
+        }
+    }
+}
+
+/// A state persistence backend that keeps `TemplateState` in memory.
+///
+/// Clones share the same content, so a test can hand one clone to a `StateManager` and keep
+/// another to seed, inspect and manipulate the state.
+///
+/// # Example
+/// ```rust
+/// # use template_teleporter_core::{InMemoryBackend, InjectedFault, StateManager, StateOperation};
+/// let backend = InMemoryBackend::new();
+/// backend.fail_nth_call_of(
+///     StateOperation::UpdateStateIf,
+///     1,
+///     InjectedFault::DatabaseError("disk full".to_string()),
+/// );
+/// let state_manager = StateManager::new(Box::new(backend.clone()));
+/// assert!(backend.is_empty());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryBackend {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl InMemoryBackend {
+    /// Creates a new, empty `InMemoryBackend`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new `InMemoryBackend` containing the given states, with their versions as given.
+    pub fn with_states(states: impl IntoIterator<Item = TemplateState>) -> Self {
+        let backend = Self::new();
+        for state in states {
+            backend.insert(state);
+        }
+        backend
+    }
+
+    /// Sets how many versions of each state are kept in the history, including the current
+    /// version. Values below 1 are treated as 1.
+    pub fn with_history_retention(self, retention: usize) -> Self {
+        self.lock().history_retention = retention.max(1);
+        self
+    }
+
+    /// Stores a state as given, including its version, without counting as a call. The state it
+    /// replaces is recorded in the history.
+    pub fn insert(&self, state: TemplateState) {
+        self.lock().store(state);
+    }
+
+    /// Returns the stored state for a key without counting as a call.
+    pub fn state(&self, key: &StateKey) -> Option<TemplateState> {
+        self.lock().states.get(key).cloned()
+    }
+
+    /// Returns all stored states, in `StateKey` order.
+    pub fn states(&self) -> Vec<TemplateState> {
+        self.lock().states.values().cloned().collect()
+    }
+
+    /// Returns the number of stored states.
+    pub fn len(&self) -> usize {
+        self.lock().states.len()
+    }
+
+    /// Returns `true` if no states are stored.
+    pub fn is_empty(&self) -> bool {
+        self.lock().states.is_empty()
+    }
+
+    /// Removes all stored states and their history. Recorded calls and pending faults are kept.
+    pub fn clear(&self) {
+        let mut inner = self.lock();
+        inner.states.clear();
+        inner.history.clear();
+    }
+
+    /// Takes a copy of the stored states and their history.
+    pub fn snapshot(&self) -> StateSnapshot {
+        let inner = self.lock();
+        StateSnapshot {
+            states: inner.states.clone(),
+            history: inner.history.clone(),
+        }
+    }
+
+    /// Replaces the stored states and their history with those of a snapshot. Recorded calls
+    /// and pending faults are kept.
+    pub fn restore(&self, snapshot: &StateSnapshot) {
+        let mut inner = self.lock();
+        inner.states = snapshot.states.clone();
+        inner.history = snapshot.history.clone();
+    }
+
+    /// Returns the operations called so far, in call order.
+    pub fn calls(&self) -> Vec<StateOperation> {
+        self.lock().calls.clone()
+    }
+
+    /// Returns how many times the given operation has been called.
+    pub fn call_count(&self, operation: StateOperation) -> usize {
+        self.lock()
+            .calls
+            .iter()
+            .filter(|call| **call == operation)
+            .count()
+    }
+
+    /// Injects a fault into the `n`-th call from now (1-based) of any operation.
+    ///
+    /// # Panics
+    /// Panics if `n` is 0.
+    pub fn fail_nth_call(&self, n: usize, fault: InjectedFault) {
+        self.add_fault(None, n, fault);
+    }
+
+    /// Injects a fault into the `n`-th call from now (1-based) of the given operation.
+    ///
+    /// # Panics
+    /// Panics if `n` is 0.
+    pub fn fail_nth_call_of(&self, operation: StateOperation, n: usize, fault: InjectedFault) {
+        self.add_fault(Some(operation), n, fault);
+    }
+
+    /// Removes all pending faults.
+    pub fn clear_faults(&self) {
+        self.lock().faults.clear();
+    }
+
+    fn add_fault(&self, operation: Option<StateOperation>, n: usize, fault: InjectedFault) {
+        assert!(n > 0, "calls are counted from 1");
+        self.lock().faults.push(PendingFault {
+            operation,
+            remaining: n,
+            fault,
+        });
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        // A panicking test thread must not hide the state from the remaining assertions.
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Records a call and applies the fault injected into it, if any. Returns the locked state
+    /// for the call to operate on.
+    fn begin(
+        &self,
+        operation: StateOperation,
+        key: Option<&StateKey>,
+        incoming: Option<&TemplateState>,
+    ) -> Result<MutexGuard<'_, Inner>> {
+        let mut inner = self.lock();
+        inner.calls.push(operation);
+
+        let mut triggered = None;
+        inner.faults.retain_mut(|pending| {
+            if pending.operation.is_some_and(|op| op != operation) {
+                return true;
+            }
+            pending.remaining -= 1;
+            if pending.remaining == 0 && triggered.is_none() {
+                triggered = Some(pending.fault.clone());
+                return false;
+            }
+            // Two faults due on the same call: the later one fires on the next matching call.
+            pending.remaining = pending.remaining.max(1);
+            true
+        });
+
+        match triggered {
+            None => Ok(inner),
+            Some(InjectedFault::DatabaseError(message)) => Err(CoreError::DatabaseError(message)),
+            Some(InjectedFault::TransientDatabaseError(message)) => {
+                Err(CoreError::TransientDatabaseError(message))
+            }
+            Some(InjectedFault::ConcurrentUpdate) => {
+                if let Some(key) = key {
+                    match inner.states.get(key) {
+                        Some(state) => {
+                            let state = TemplateState {
+                                version: state.version + 1,
+                                ..state.clone()
+                            };
+                            inner.store(state);
+                        }
+                        None => {
+                            if let Some(incoming) = incoming {
+                                inner.store(TemplateState {
+                                    version: 1,
+                                    ..incoming.clone()
+                                });
+                            }
+                        }
+                    }
+                }
+                Ok(inner)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl StatePersistence for InMemoryBackend {
+    async fn get_state(&self, key: &StateKey) -> Result<Option<TemplateState>> {
+        let inner = self.begin(StateOperation::GetState, Some(key), None)?;
+        Ok(inner.states.get(key).cloned())
+    }
+
+    async fn update_state(&self, state: &TemplateState) -> Result<()> {
+        let key = state.key();
+        let mut inner = self.begin(StateOperation::UpdateState, Some(&key), Some(state))?;
+        let version = inner.states.get(&key).map_or(0, |current| current.version) + 1;
+        inner.store(TemplateState {
+            version,
+            ..state.clone()
+        });
+        Ok(())
+    }
+
+    async fn update_state_if(
+        &self,
+        state: &TemplateState,
+        expected_version: Option<u64>,
+    ) -> Result<u64> {
+        let key = state.key();
+        let mut inner = self.begin(StateOperation::UpdateStateIf, Some(&key), Some(state))?;
+        let actual_version = inner.states.get(&key).map(|current| current.version);
+        if actual_version != expected_version {
+            return Err(CoreError::StateConflict {
+                key,
+                expected: expected_version,
+                actual: actual_version,
+            });
+        }
+
+        let version = expected_version.map_or(1, |version| version + 1);
+        inner.store(TemplateState {
+            version,
+            ..state.clone()
+        });
+        Ok(version)
+    }
+
+    async fn list_states(&self, query: &StateQuery) -> Result<StatePage> {
+        let inner = self.begin(StateOperation::ListStates, None, None)?;
+        query.paginate(inner.states.values().cloned())
+    }
+
+    async fn delete_state(&self, key: &StateKey) -> Result<bool> {
+        let mut inner = self.begin(StateOperation::DeleteState, Some(key), None)?;
+        inner.history.remove(key);
+        Ok(inner.states.remove(key).is_some())
+    }
+
+    async fn get_state_history(&self, key: &StateKey) -> Result<Vec<TemplateState>> {
+        let inner = self.begin(StateOperation::GetStateHistory, Some(key), None)?;
+        let Some(current) = inner.states.get(key) else {
+            return Ok(Vec::new());
+        };
+        let mut history = vec![current.clone()];
+        history.extend(
+            inner
+                .history
+                .get(key)
+                .into_iter()
+                .flatten()
+                .take(inner.history_retention - 1)
+                .cloned(),
+        );
+        Ok(history)
+    }
+
+    async fn get_states(&self, keys: &[StateKey]) -> Result<Vec<Option<TemplateState>>> {
+        let inner = self.begin(StateOperation::GetStates, None, None)?;
+        Ok(keys
+            .iter()
+            .map(|key| inner.states.get(key).cloned())
+            .collect())
+    }
+
+    /// Writes all states under one lock, so the batch is atomic.
+    async fn update_states(&self, states: &[TemplateState]) -> Result<()> {
+        let mut inner = self.begin(StateOperation::UpdateStates, None, None)?;
+        for state in states {
+            let version = inner
+                .states
+                .get(&state.key())
+                .map_or(0, |current| current.version)
+                + 1;
+            inner.store(TemplateState {
+                version,
+                ..state.clone()
+            });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,265 @@
+//! Tests for the InMemoryBackend implementation.
+
+use super::*;
+use chrono::Utc;
+
+fn state_for(repo: &str, template_path: &str) -> TemplateState {
+    TemplateState {
+        repo: repo.to_string(),
+        template_path: template_path.to_string(),
+        source_repository: "owner/master".to_string(),
+        master_checksum: "checksum".to_string(),
+        deployed_checksum: None,
+        last_updated_utc: Utc::now(),
+        version: 0,
+    }
+}
+
+#[tokio::test]
+async fn test_in_memory_backend_update_and_get_state() {
+    let backend = InMemoryBackend::new();
+    let state = state_for("org/a", "README.md");
+
+    backend.update_state(&state).await.unwrap();
+    backend.update_state(&state).await.unwrap();
+
+    let stored = backend.get_state(&state.key()).await.unwrap().unwrap();
+    assert_eq!(
+        stored,
+        TemplateState {
+            version: 2,
+            ..state.clone()
+        }
+    );
+    assert_eq!(
+        backend
+            .get_state(&StateKey::new("org/a", "missing"))
+            .await
+            .unwrap(),
+        None
+    );
+}
+
+#[tokio::test]
+async fn test_in_memory_backend_update_state_if() {
+    let backend = InMemoryBackend::new();
+    let state = state_for("org/a", "README.md");
+
+    assert_eq!(backend.update_state_if(&state, None).await.unwrap(), 1);
+    assert_eq!(backend.update_state_if(&state, Some(1)).await.unwrap(), 2);
+    let result = backend.update_state_if(&state, Some(1)).await;
+    assert!(matches!(
+        result,
+        Err(CoreError::StateConflict {
+            expected: Some(1),
+            actual: Some(2),
+            ..
+        })
+    ));
+}
+
+#[tokio::test]
+async fn test_in_memory_backend_list_and_delete_states() {
+    let backend = InMemoryBackend::with_states(vec![
+        state_for("org/b", "README.md"),
+        state_for("org/a", "README.md"),
+        state_for("org/a", ".github/CODEOWNERS"),
+    ]);
+
+    let page = backend
+        .list_states(&StateQuery::default().with_limit(2))
+        .await
+        .unwrap();
+    let keys: Vec<_> = page.states.iter().map(|s| s.key().to_string()).collect();
+    assert_eq!(keys, vec!["org/a:.github/CODEOWNERS", "org/a:README.md"]);
+
+    let next = backend
+        .list_states(
+            &StateQuery::default()
+                .with_limit(2)
+                .with_page_token(page.next_page_token.unwrap()),
+        )
+        .await
+        .unwrap();
+    assert_eq!(next.states[0].repo, "org/b");
+    assert_eq!(next.next_page_token, None);
+
+    assert!(backend
+        .delete_state(&StateKey::new("org/b", "README.md"))
+        .await
+        .unwrap());
+    assert_eq!(backend.len(), 2);
+}
+
+#[tokio::test]
+async fn test_in_memory_backend_clones_share_content() {
+    let backend = InMemoryBackend::new();
+    let state_manager = crate::StateManager::new(Box::new(backend.clone()));
+
+    state_manager
+        .update_state(&state_for("org/a", "README.md"))
+        .await
+        .unwrap();
+
+    assert_eq!(backend.len(), 1);
+    assert_eq!(
+        backend
+            .state(&StateKey::new("org/a", "README.md"))
+            .unwrap()
+            .version,
+        1
+    );
+}
+
+#[tokio::test]
+async fn test_in_memory_backend_snapshot_and_restore() {
+    let backend = InMemoryBackend::with_states(vec![state_for("org/a", "README.md")]);
+    let snapshot = backend.snapshot();
+
+    backend
+        .update_state(&state_for("org/a", "CONTRIBUTING.md"))
+        .await
+        .unwrap();
+    backend
+        .delete_state(&StateKey::new("org/a", "README.md"))
+        .await
+        .unwrap();
+    assert_eq!(backend.states()[0].template_path, "CONTRIBUTING.md");
+
+    backend.restore(&snapshot);
+
+    assert_eq!(backend.states(), snapshot.states());
+    assert_eq!(backend.states()[0].template_path, "README.md");
+}
+
+#[tokio::test]
+async fn test_in_memory_backend_fail_nth_call() {
+    let backend = InMemoryBackend::new();
+    let state = state_for("org/a", "README.md");
+    backend.fail_nth_call(2, InjectedFault::DatabaseError("boom".to_string()));
+
+    backend.update_state(&state).await.unwrap();
+    let result = backend.get_state(&state.key()).await;
+    assert!(matches!(result, Err(CoreError::DatabaseError(msg)) if msg == "boom"));
+    // The fault only fires once
+    assert!(backend.get_state(&state.key()).await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn test_in_memory_backend_fail_nth_call_of_operation() {
+    let backend = InMemoryBackend::new();
+    let state = state_for("org/a", "README.md");
+    backend.fail_nth_call_of(
+        StateOperation::UpdateState,
+        2,
+        InjectedFault::DatabaseError("write failed".to_string()),
+    );
+
+    backend.update_state(&state).await.unwrap();
+    backend.get_state(&state.key()).await.unwrap();
+    assert!(backend.update_state(&state).await.is_err());
+
+    // The failed write left the state untouched
+    assert_eq!(backend.state(&state.key()).unwrap().version, 1);
+    assert_eq!(
+        backend.calls(),
+        vec![
+            StateOperation::UpdateState,
+            StateOperation::GetState,
+            StateOperation::UpdateState,
+        ]
+    );
+    assert_eq!(backend.call_count(StateOperation::UpdateState), 2);
+}
+
+#[tokio::test]
+async fn test_in_memory_backend_simulated_concurrent_update_conflicts() {
+    let backend = InMemoryBackend::with_states(vec![TemplateState {
+        version: 3,
+        ..state_for("org/a", "README.md")
+    }]);
+    let state = backend
+        .get_state(&StateKey::new("org/a", "README.md"))
+        .await
+        .unwrap()
+        .unwrap();
+    backend.fail_nth_call_of(
+        StateOperation::UpdateStateIf,
+        1,
+        InjectedFault::ConcurrentUpdate,
+    );
+
+    let result = backend.update_state_if(&state, Some(state.version)).await;
+
+    assert!(matches!(
+        result,
+        Err(CoreError::StateConflict {
+            expected: Some(3),
+            actual: Some(4),
+            ..
+        })
+    ));
+}
+
+#[tokio::test]
+async fn test_in_memory_backend_simulated_concurrent_create_conflicts() {
+    let backend = InMemoryBackend::new();
+    let state = state_for("org/a", "README.md");
+    backend.fail_nth_call(1, InjectedFault::ConcurrentUpdate);
+
+    let result = backend.update_state_if(&state, None).await;
+
+    assert!(matches!(
+        result,
+        Err(CoreError::StateConflict {
+            expected: None,
+            actual: Some(1),
+            ..
+        })
+    ));
+    assert_eq!(backend.len(), 1);
+}
+
+#[tokio::test]
+async fn test_in_memory_backend_clear_faults() {
+    let backend = InMemoryBackend::new();
+    backend.fail_nth_call(1, InjectedFault::DatabaseError("boom".to_string()));
+    backend.clear_faults();
+
+    assert!(backend
+        .list_states(&StateQuery::default())
+        .await
+        .unwrap()
+        .states
+        .is_empty());
+}
+
+#[tokio::test]
+async fn test_in_memory_backend_history_retention() {
+    let backend = InMemoryBackend::new().with_history_retention(2);
+    let mut state = state_for("org/a", "README.md");
+    for checksum in ["one", "two", "three"] {
+        state.master_checksum = checksum.to_string();
+        backend.update_state(&state).await.unwrap();
+    }
+
+    let history = backend.get_state_history(&state.key()).await.unwrap();
+
+    let checksums: Vec<_> = history.iter().map(|s| s.master_checksum.as_str()).collect();
+    assert_eq!(checksums, ["three", "two"]);
+    assert_eq!(history[0].version, 3);
+    assert!(backend.delete_state(&state.key()).await.unwrap());
+    assert!(backend
+        .get_state_history(&state.key())
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn test_in_memory_backend_conformance() {
+    crate::conformance::run_all(|| async {
+        Arc::new(InMemoryBackend::new()) as Arc<dyn StatePersistence>
+    })
+    .await;
+}
@@ -0,0 +1,315 @@
+//! Implements a `StatePersistence` decorator which retries failed calls of another backend.
+//!
+//! Database operations can fail transiently, e.g. when a connection drops or the service
+//! throttles requests. A `RetryingBackend` wraps any backend and repeats a failed call with
+//! exponentially growing, jittered delays until it succeeds, the error is classified as not
+//! retryable, the attempts are used up or the next delay would exceed the time budget. Time is
+//! read and waited for through a `Clock`, so the retry behaviour can be tested with a
+//! `SimulatedClock` without actually waiting.
+
+use crate::clock::{Clock, SystemClock};
+use crate::state_manager::StatePersistence;
+use crate::types::{CoreError, Result, StateKey, StatePage, StateQuery, TemplateState};
+use async_trait::async_trait;
+use std::fmt;
+use std::future::Future;
+use std::io::ErrorKind;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(test)]
+#[path = "retrying_backend_tests.rs"]
+mod tests;
+
+/// Returns whether an error is likely to go away when the call is repeated.
+///
+/// This is the default classification of a `RetryPolicy`:
+///   - `CoreError::TransientDatabaseError` is retryable, as backends report connection
+///     failures, timeouts and throttling with it. `CoreError::DatabaseError` is not: it reports
+///     failures which repeat, e.g. invalid configuration, rejected credentials or corrupt states.
+///   - `CoreError::IoError` is retryable if it is an interruption, a timeout or a broken
+///     connection.
+///   - Every other error, notably `CoreError::StateConflict`, is not: repeating a conditional
+///     update with the same expected version fails the same way.
+pub fn is_transient(error: &CoreError) -> bool {
+    match error {
+        CoreError::TransientDatabaseError(_) => true,
+        CoreError::IoError(e) => matches!(
+            e.kind(),
+            ErrorKind::Interrupted
+                | ErrorKind::TimedOut
+                | ErrorKind::WouldBlock
+                | ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::ConnectionRefused
+                | ErrorKind::NotConnected
+                | ErrorKind::BrokenPipe
+        ),
+        _ => false,
+    }
+}
+
+/// How a `RetryingBackend` retries failed calls.
+///
+/// The `n`-th retry (0-based) waits `initial_delay * multiplier^n`, capped at `max_delay` and
+/// then shortened by a random fraction of up to `jitter`, so that clients failing together do
+/// not retry together.
+///
+/// # Example
+/// ```rust
+/// use std::time::Duration;
+/// use template_teleporter_core::RetryPolicy;
+/// let policy = RetryPolicy::new()
+///     .with_max_attempts(3)
+///     .with_initial_delay(Duration::from_millis(50))
+///     .with_max_elapsed(Duration::from_secs(5));
+/// ```
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_delay: Duration,
+    max_delay: Duration,
+    multiplier: f64,
+    jitter: f64,
+    max_elapsed: Duration,
+    is_retryable: Arc<dyn Fn(&CoreError) -> bool + Send + Sync>,
+}
+
+impl RetryPolicy {
+    /// Creates the default policy: at most 5 attempts, starting with a delay of 100 ms which
+    /// doubles up to 5 s, with up to 50% jitter, within 30 s, retrying the errors for which
+    /// [`is_transient`] holds.
+    pub fn new() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(5),
+            multiplier: 2.0,
+            jitter: 0.5,
+            max_elapsed: Duration::from_secs(30),
+            is_retryable: Arc::new(is_transient),
+        }
+    }
+
+    /// Sets the number of attempts of a call, including the first one. Values below 1 are
+    /// treated as 1, which disables retries.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Sets the delay before the first retry.
+    pub fn with_initial_delay(mut self, delay: Duration) -> Self {
+        self.initial_delay = delay;
+        self
+    }
+
+    /// Sets the longest delay between two attempts, before jitter.
+    pub fn with_max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Sets the factor by which the delay grows with every retry. Values below 1 are treated as
+    /// 1, which keeps the delay constant.
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    /// Sets the largest fraction by which a delay is randomly shortened, between 0 (no jitter)
+    /// and 1 (delays anywhere between zero and the computed delay).
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets the time budget of a call: no retry is started whose delay would end later than
+    /// this after the first attempt started.
+    pub fn with_max_elapsed(mut self, max_elapsed: Duration) -> Self {
+        self.max_elapsed = max_elapsed;
+        self
+    }
+
+    /// Sets which errors are retried, replacing [`is_transient`].
+    pub fn with_retryable(
+        mut self,
+        is_retryable: impl Fn(&CoreError) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.is_retryable = Arc::new(is_retryable);
+        self
+    }
+
+    /// Returns whether the policy retries the given error.
+    pub fn is_retryable(&self, error: &CoreError) -> bool {
+        (self.is_retryable)(error)
+    }
+
+    /// Returns the delay before the `retry`-th retry (0-based), without jitter.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = self.multiplier.powi(retry.min(i32::MAX as u32) as i32);
+        let delay = self.initial_delay.as_secs_f64() * factor;
+        if delay >= self.max_delay.as_secs_f64() {
+            self.max_delay
+        } else {
+            Duration::from_secs_f64(delay)
+        }
+    }
+
+    /// Returns the delay before the `retry`-th retry (0-based), with jitter applied.
+    fn jittered_backoff(&self, retry: u32) -> Duration {
+        self.backoff(retry)
+            .mul_f64(1.0 - self.jitter * fastrand::f64())
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Manual Debug implementation because the classification function is not Debug.
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("initial_delay", &self.initial_delay)
+            .field("max_delay", &self.max_delay)
+            .field("multiplier", &self.multiplier)
+            .field("jitter", &self.jitter)
+            .field("max_elapsed", &self.max_elapsed)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A `StatePersistence` backend which retries the failed calls of another backend according to
+/// a `RetryPolicy`.
+///
+/// A call that failed may still have taken effect, e.g. when the connection dropped after the
+/// database committed a write. A retried `update_state` then writes the same content once more
+/// under a new version, and a retried `update_state_if` fails with `CoreError::StateConflict`.
+/// A retried `update_states` of a backend whose batches are not atomic writes the states written
+/// before the failure once more.
+///
+/// # Example
+/// ```rust
+/// use template_teleporter_core::{InMemoryBackend, RetryPolicy, RetryingBackend, StateManager};
+/// let backend = RetryingBackend::new(InMemoryBackend::new())
+///     .with_policy(RetryPolicy::new().with_max_attempts(3));
+/// let manager = StateManager::new(Box::new(backend));
+/// ```
+pub struct RetryingBackend<B> {
+    backend: B,
+    policy: RetryPolicy,
+    clock: Arc<dyn Clock>,
+}
+
+impl<B: StatePersistence> RetryingBackend<B> {
+    /// Wraps a backend with the default `RetryPolicy`, waiting on the `SystemClock`.
+    pub fn new(backend: B) -> Self {
+        Self {
+            backend,
+            policy: RetryPolicy::new(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Sets the retry policy.
+    pub fn with_policy(mut self, policy: RetryPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Sets the clock used to measure the time budget and to wait between attempts.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Returns the wrapped backend.
+    pub fn inner(&self) -> &B {
+        &self.backend
+    }
+
+    /// Runs an operation until it succeeds or the policy gives up, returning the last error in
+    /// that case.
+    async fn retry<'a, T, F, Fut>(&'a self, mut operation: F) -> Result<T>
+    where
+        F: FnMut() -> Fut + Send,
+        Fut: Future<Output = Result<T>> + Send + 'a,
+        T: Send,
+    {
+        let start = self.clock.now();
+        let mut attempt = 1;
+        loop {
+            let error = match operation().await {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+            if attempt >= self.policy.max_attempts || !self.policy.is_retryable(&error) {
+                return Err(error);
+            }
+            let delay = self.policy.jittered_backoff(attempt - 1);
+            let elapsed = self.clock.now().saturating_duration_since(start);
+            if elapsed + delay > self.policy.max_elapsed {
+                return Err(error);
+            }
+            self.clock.sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+impl<B> fmt::Debug for RetryingBackend<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryingBackend")
+            .field("policy", &self.policy)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl<B: StatePersistence> StatePersistence for RetryingBackend<B> {
+    async fn get_state(&self, key: &StateKey) -> Result<Option<TemplateState>> {
+        self.retry(|| self.backend.get_state(key)).await
+    }
+
+    async fn update_state(&self, state: &TemplateState) -> Result<()> {
+        self.retry(|| self.backend.update_state(state)).await
+    }
+
+    async fn update_state_if(
+        &self,
+        state: &TemplateState,
+        expected_version: Option<u64>,
+    ) -> Result<u64> {
+        self.retry(|| self.backend.update_state_if(state, expected_version))
+            .await
+    }
+
+    async fn list_states(&self, query: &StateQuery) -> Result<StatePage> {
+        self.retry(|| self.backend.list_states(query)).await
+    }
+
+    async fn scan_states(&self, page_token: Option<&str>, limit: usize) -> Result<StatePage> {
+        self.retry(|| self.backend.scan_states(page_token, limit))
+            .await
+    }
+
+    async fn delete_state(&self, key: &StateKey) -> Result<bool> {
+        self.retry(|| self.backend.delete_state(key)).await
+    }
+
+    async fn get_state_history(&self, key: &StateKey) -> Result<Vec<TemplateState>> {
+        self.retry(|| self.backend.get_state_history(key)).await
+    }
+
+    async fn get_states(&self, keys: &[StateKey]) -> Result<Vec<Option<TemplateState>>> {
+        self.retry(|| self.backend.get_states(keys)).await
+    }
+
+    async fn update_states(&self, states: &[TemplateState]) -> Result<()> {
+        self.retry(|| self.backend.update_states(states)).await
+    }
+}
@@ -0,0 +1,235 @@
+//! Tests for the retrying `StatePersistence` decorator.
+
+use super::*;
+use crate::clock::SimulatedClock;
+use crate::memory_backend::{InMemoryBackend, InjectedFault, StateOperation};
+use chrono::Utc;
+
+fn state_for(repo: &str, template_path: &str) -> TemplateState {
+    TemplateState {
+        repo: repo.to_string(),
+        template_path: template_path.to_string(),
+        source_repository: "owner/master".to_string(),
+        master_checksum: "checksum".to_string(),
+        deployed_checksum: None,
+        last_updated_utc: Utc::now(),
+        version: 0,
+    }
+}
+
+fn connection_lost() -> InjectedFault {
+    InjectedFault::TransientDatabaseError("connection lost".to_string())
+}
+
+/// Makes the next `count` calls of the backend fail with a transient error.
+fn fail_next_calls(backend: &InMemoryBackend, count: usize) {
+    for n in 1..=count {
+        backend.fail_nth_call(n, connection_lost());
+    }
+}
+
+/// A policy without jitter, so that the delays are predictable.
+fn policy() -> RetryPolicy {
+    RetryPolicy::new()
+        .with_initial_delay(Duration::from_millis(100))
+        .with_jitter(0.0)
+}
+
+fn retrying(
+    backend: &InMemoryBackend,
+    policy: RetryPolicy,
+) -> (RetryingBackend<InMemoryBackend>, SimulatedClock) {
+    let clock = SimulatedClock::new();
+    let retrying = RetryingBackend::new(backend.clone())
+        .with_policy(policy)
+        .with_clock(clock.clone());
+    (retrying, clock)
+}
+
+#[tokio::test]
+async fn test_retrying_backend_retries_transient_failures() {
+    let backend = InMemoryBackend::new();
+    let state = state_for("org/a", "README.md");
+    let (retrying, clock) = retrying(&backend, policy());
+    fail_next_calls(&backend, 2);
+
+    retrying.update_state(&state).await.unwrap();
+
+    assert_eq!(backend.call_count(StateOperation::UpdateState), 3);
+    assert_eq!(backend.state(&state.key()).unwrap().version, 1);
+    assert_eq!(
+        clock.sleeps(),
+        [Duration::from_millis(100), Duration::from_millis(200)]
+    );
+}
+
+#[tokio::test]
+async fn test_retrying_backend_does_not_retry_conflicts() {
+    let backend = InMemoryBackend::with_states([state_for("org/a", "README.md")]);
+    let (retrying, clock) = retrying(&backend, policy());
+
+    let result = retrying
+        .update_state_if(&state_for("org/a", "README.md"), Some(7))
+        .await;
+
+    assert!(matches!(result, Err(CoreError::StateConflict { .. })));
+    assert_eq!(backend.call_count(StateOperation::UpdateStateIf), 1);
+    assert!(clock.sleeps().is_empty());
+}
+
+#[tokio::test]
+async fn test_retrying_backend_does_not_retry_permanent_failures() {
+    let backend = InMemoryBackend::new();
+    let (retrying, clock) = retrying(&backend, policy());
+    backend.fail_nth_call(
+        1,
+        InjectedFault::DatabaseError("invalid state document".to_string()),
+    );
+
+    let result = retrying
+        .get_state(&StateKey::new("org/a", "README.md"))
+        .await;
+
+    assert!(matches!(result, Err(CoreError::DatabaseError(_))));
+    assert_eq!(backend.call_count(StateOperation::GetState), 1);
+    assert!(clock.sleeps().is_empty());
+}
+
+#[tokio::test]
+async fn test_retrying_backend_gives_up_after_max_attempts() {
+    let backend = InMemoryBackend::new();
+    let (retrying, clock) = retrying(&backend, policy().with_max_attempts(3));
+    fail_next_calls(&backend, 5);
+
+    let result = retrying
+        .get_state(&StateKey::new("org/a", "README.md"))
+        .await;
+
+    assert!(
+        matches!(result, Err(CoreError::TransientDatabaseError(msg)) if msg == "connection lost")
+    );
+    assert_eq!(backend.call_count(StateOperation::GetState), 3);
+    assert_eq!(clock.sleeps().len(), 2);
+}
+
+#[tokio::test]
+async fn test_retrying_backend_stops_at_max_elapsed() {
+    let backend = InMemoryBackend::new();
+    let policy = policy()
+        .with_initial_delay(Duration::from_secs(1))
+        .with_max_attempts(10)
+        .with_max_elapsed(Duration::from_millis(3500));
+    let (retrying, clock) = retrying(&backend, policy);
+    fail_next_calls(&backend, 10);
+
+    let result = retrying.list_states(&StateQuery::default()).await;
+
+    // After waiting 1 s and 2 s, another 4 s would exceed the budget of 3.5 s
+    assert!(matches!(result, Err(CoreError::TransientDatabaseError(_))));
+    assert_eq!(
+        clock.sleeps(),
+        [Duration::from_secs(1), Duration::from_secs(2)]
+    );
+    assert_eq!(backend.call_count(StateOperation::ListStates), 3);
+}
+
+#[tokio::test]
+async fn test_retrying_backend_custom_classification() {
+    let backend = InMemoryBackend::new();
+    let policy = policy().with_retryable(|error| {
+        !matches!(error, CoreError::TransientDatabaseError(msg) if msg == "connection lost")
+    });
+    let (retrying, clock) = retrying(&backend, policy);
+    fail_next_calls(&backend, 1);
+
+    let result = retrying
+        .get_state_history(&StateKey::new("org/a", "README.md"))
+        .await;
+
+    assert!(matches!(result, Err(CoreError::TransientDatabaseError(_))));
+    assert!(clock.sleeps().is_empty());
+}
+
+#[tokio::test]
+async fn test_retrying_backend_wraps_boxed_backends() {
+    let backend = InMemoryBackend::new();
+    let boxed: Box<dyn StatePersistence> = Box::new(backend.clone());
+    let clock = SimulatedClock::new();
+    let retrying = RetryingBackend::new(boxed)
+        .with_policy(policy())
+        .with_clock(clock.clone());
+    fail_next_calls(&backend, 1);
+
+    retrying
+        .update_state(&state_for("org/a", "README.md"))
+        .await
+        .unwrap();
+
+    assert_eq!(backend.len(), 1);
+    assert_eq!(clock.sleeps(), [Duration::from_millis(100)]);
+}
+
+#[test]
+fn test_retry_policy_backoff_grows_up_to_max_delay() {
+    let policy = policy()
+        .with_multiplier(3.0)
+        .with_max_delay(Duration::from_secs(1));
+
+    let delays: Vec<Duration> = (0..5).map(|retry| policy.backoff(retry)).collect();
+
+    assert_eq!(
+        delays,
+        [
+            Duration::from_millis(100),
+            Duration::from_millis(300),
+            Duration::from_millis(900),
+            Duration::from_secs(1),
+            Duration::from_secs(1),
+        ]
+    );
+    assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(1));
+}
+
+#[test]
+fn test_retry_policy_jitter_shortens_delays() {
+    let policy = policy().with_jitter(0.5);
+
+    for retry in 0..3 {
+        let full = policy.backoff(retry);
+        for _ in 0..100 {
+            let delay = policy.jittered_backoff(retry);
+            assert!(
+                delay <= full && delay >= full / 2,
+                "{:?} of {:?}",
+                delay,
+                full
+            );
+        }
+    }
+}
+
+#[test]
+fn test_is_transient() {
+    use std::io::Error;
+
+    assert!(is_transient(&CoreError::TransientDatabaseError(
+        "timeout".to_string()
+    )));
+    assert!(!is_transient(&CoreError::DatabaseError(
+        "Invalid Cosmos DB master key".to_string()
+    )));
+    assert!(is_transient(&CoreError::IoError(Error::from(
+        ErrorKind::TimedOut
+    ))));
+    assert!(!is_transient(&CoreError::IoError(Error::from(
+        ErrorKind::PermissionDenied
+    ))));
+    assert!(!is_transient(&CoreError::StateConflict {
+        key: StateKey::new("org/a", "README.md"),
+        expected: Some(1),
+        actual: Some(2),
+    }));
+    assert!(!is_transient(&CoreError::MissingConfiguration(
+        "tableName".to_string()
+    )));
+}
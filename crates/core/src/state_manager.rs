@@ -1,35 +1,49 @@
 //! Defines the `StatePersistence` trait for abstracting state storage
 //! and the `StateManager` struct which uses this trait.
 
-use crate::types::{Result, TemplateState}; // Removed AppConfig as it's not directly needed
+use crate::types::{CoreError, Result, StateKey, StatePage, StateQuery, TemplateState}; // Removed AppConfig as it's not directly needed
 use async_trait::async_trait;
+use chrono::Utc;
 use std::fmt; // Import fmt for custom Debug implementation
 
 #[cfg(test)]
 #[path = "state_manager_tests.rs"]
 mod tests;
 
+/// The number of versions of each state a backend keeps in its history, including the current
+/// version, unless configured otherwise.
+pub const DEFAULT_HISTORY_RETENTION: usize = 10;
+
+/// The maximum number of previous checksums `StateManager::old_checksums` returns, matching what
+/// a `TemplateChange` carries.
+pub const MAX_OLD_CHECKSUMS: usize = 10;
+
 /// Trait defining the interface for state persistence backends.
 ///
 /// This trait abstracts the underlying storage mechanism (e.g., DynamoDB, CosmosDB, filesystem)
 /// allowing the core logic to remain agnostic of the specific database implementation.
 /// Implementers must be `Send` and `Sync` to be usable in async contexts.
+///
+/// Failures which may go away when the call is repeated, such as timeouts, throttling or a lost
+/// connection, are reported as `CoreError::TransientDatabaseError`, so that a `RetryingBackend`
+/// retries them; other backend failures are reported as `CoreError::DatabaseError`.
 #[async_trait]
 pub trait StatePersistence: Send + Sync {
-    /// Retrieves the state for a given template ID from the backend.
+    /// Retrieves the state for a given repository and template path from the backend.
     ///
     /// # Arguments
-    /// * `template_id` - The unique identifier of the template state to retrieve.
+    /// * `key` - The key identifying the template state to retrieve.
     ///
     /// # Returns
     /// A `Result` containing `Some(TemplateState)` if found, `None` if not found,
     /// or a `CoreError::DatabaseError` if the backend operation fails.
-    async fn get_state(&self, template_id: &str) -> Result<Option<TemplateState>>;
+    async fn get_state(&self, key: &StateKey) -> Result<Option<TemplateState>>;
 
     /// Saves or updates the state for a template in the backend.
     ///
-    /// If the state for the given `template_id` already exists, it should be overwritten.
-    /// If it does not exist, it should be created.
+    /// If the state for the key of the given `state` already exists, it should be overwritten.
+    /// If it does not exist, it should be created. The stored version is incremented (or set to 1
+    /// for a new state) regardless of the version of `state`.
     ///
     /// # Arguments
     /// * `state` - The `TemplateState` object to save or update.
@@ -38,6 +52,123 @@ pub trait StatePersistence: Send + Sync {
     /// An empty `Result` on success, or a `CoreError::DatabaseError` if the backend operation fails.
     async fn update_state(&self, state: &TemplateState) -> Result<()>;
 
+    /// Saves or updates the state for a template only if the stored state still has the expected
+    /// version (compare-and-swap). The check and the write must be atomic with respect to every
+    /// other writer of the backend, including other processes.
+    ///
+    /// # Arguments
+    /// * `state` - The `TemplateState` object to save or update.
+    /// * `expected_version` - The version of the state as it was read, or `None` if no state
+    ///   should exist yet.
+    ///
+    /// # Returns
+    /// A `Result` containing the new version of the stored state on success,
+    /// `CoreError::StateConflict` if the stored version differs from `expected_version`, or a
+    /// `CoreError::DatabaseError` if the backend operation fails.
+    async fn update_state_if(
+        &self,
+        state: &TemplateState,
+        expected_version: Option<u64>,
+    ) -> Result<u64>;
+
+    /// Lists the states matching the repository and template path filters of `query`, in
+    /// `StateKey` order, one page at a time.
+    ///
+    /// # Arguments
+    /// * `query` - The filters, page size and page token of the listing.
+    ///
+    /// # Returns
+    /// A `Result` containing the requested `StatePage`, or a `CoreError::DatabaseError` if the
+    /// backend operation fails or the page token is invalid.
+    async fn list_states(&self, query: &StateQuery) -> Result<StatePage>;
+
+    /// Deletes the state for a template, e.g. after the template was removed from the master
+    /// repository.
+    ///
+    /// # Arguments
+    /// * `key` - The key identifying the template state to delete.
+    ///
+    /// # Returns
+    /// A `Result` containing `true` if the state existed and was deleted, `false` if there was
+    /// no state for the key, or a `CoreError::DatabaseError` if the backend operation fails.
+    async fn delete_state(&self, key: &StateKey) -> Result<bool>;
+
+    /// Retrieves the recorded versions of the state for a template, newest first, starting with
+    /// the current state.
+    ///
+    /// Every successful `update_state` and `update_state_if` records a version. Backends retain
+    /// the most recent versions of each state up to their history retention
+    /// ([`DEFAULT_HISTORY_RETENTION`] unless configured otherwise) and drop older ones. Deleting
+    /// a state deletes its history.
+    ///
+    /// # Arguments
+    /// * `key` - The key identifying the template state.
+    ///
+    /// # Returns
+    /// A `Result` containing the retained versions, which is empty if there is no state for the
+    /// key, or a `CoreError::DatabaseError` if the backend operation fails.
+    async fn get_state_history(&self, key: &StateKey) -> Result<Vec<TemplateState>>;
+
+    /// Retrieves the states for several keys at once.
+    ///
+    /// The default implementation calls `get_state` for each key in turn. Backends override it
+    /// to read all states in as few round trips as they can.
+    ///
+    /// # Arguments
+    /// * `keys` - The keys identifying the template states to retrieve.
+    ///
+    /// # Returns
+    /// A `Result` containing one entry per key, in the order of `keys`: `Some(TemplateState)` if
+    /// found, `None` if not found. A `CoreError::DatabaseError` is returned if any read fails.
+    async fn get_states(&self, keys: &[StateKey]) -> Result<Vec<Option<TemplateState>>> {
+        let mut states = Vec::with_capacity(keys.len());
+        for key in keys {
+            states.push(self.get_state(key).await?);
+        }
+        Ok(states)
+    }
+
+    /// Saves or updates several states at once, each as by `update_state`. A key may occur more
+    /// than once; its states are then written in order, each getting a new version.
+    ///
+    /// The default implementation calls `update_state` for each state in turn and stops at the
+    /// first failure, leaving the states before it written. Backends override it to write all
+    /// states in as few round trips as they can; they document whether the batch is atomic.
+    ///
+    /// # Arguments
+    /// * `states` - The `TemplateState` objects to save or update.
+    ///
+    /// # Returns
+    /// An empty `Result` on success, or a `CoreError::DatabaseError` if a write fails.
+    async fn update_states(&self, states: &[TemplateState]) -> Result<()> {
+        for state in states {
+            self.update_state(state).await?;
+        }
+        Ok(())
+    }
+
+    /// Reads all states one page at a time in the order the backend stores them, e.g. to copy
+    /// them to another backend. Unlike `list_states`, the pages are not in `StateKey` order and
+    /// cannot be filtered, but each page continues where the previous one ended, so reading all
+    /// states takes a single pass over the backend.
+    ///
+    /// The default implementation lists the states with `list_states`, which takes a single pass
+    /// for backends that list in key order natively. Backends which read and sort all states for
+    /// every `list_states` call override it.
+    ///
+    /// # Arguments
+    /// * `page_token` - The `next_page_token` of the previous page, or `None` for the first page.
+    /// * `limit` - The maximum number of states on the page.
+    ///
+    /// # Returns
+    /// A `Result` containing the requested `StatePage`, or a `CoreError::DatabaseError` if the
+    /// backend operation fails or the page token is invalid.
+    async fn scan_states(&self, page_token: Option<&str>, limit: usize) -> Result<StatePage> {
+        let mut query = StateQuery::default().with_limit(limit);
+        query.page_token = page_token.map(str::to_string);
+        self.list_states(&query).await
+    }
+
     // Potentially add methods for initialization or configuration if needed later
     // async fn initialize(&self) -> Result<()>;
 }
@@ -49,6 +180,51 @@ impl fmt::Debug for dyn StatePersistence {
     }
 }
 
+/// Lets boxed backends, e.g. those created from the configuration, be wrapped by decorators such
+/// as `RetryingBackend`.
+#[async_trait]
+impl<T: StatePersistence + ?Sized> StatePersistence for Box<T> {
+    async fn get_state(&self, key: &StateKey) -> Result<Option<TemplateState>> {
+        (**self).get_state(key).await
+    }
+
+    async fn update_state(&self, state: &TemplateState) -> Result<()> {
+        (**self).update_state(state).await
+    }
+
+    async fn update_state_if(
+        &self,
+        state: &TemplateState,
+        expected_version: Option<u64>,
+    ) -> Result<u64> {
+        (**self).update_state_if(state, expected_version).await
+    }
+
+    async fn list_states(&self, query: &StateQuery) -> Result<StatePage> {
+        (**self).list_states(query).await
+    }
+
+    async fn delete_state(&self, key: &StateKey) -> Result<bool> {
+        (**self).delete_state(key).await
+    }
+
+    async fn get_state_history(&self, key: &StateKey) -> Result<Vec<TemplateState>> {
+        (**self).get_state_history(key).await
+    }
+
+    async fn get_states(&self, keys: &[StateKey]) -> Result<Vec<Option<TemplateState>>> {
+        (**self).get_states(keys).await
+    }
+
+    async fn update_states(&self, states: &[TemplateState]) -> Result<()> {
+        (**self).update_states(states).await
+    }
+
+    async fn scan_states(&self, page_token: Option<&str>, limit: usize) -> Result<StatePage> {
+        (**self).scan_states(page_token, limit).await
+    }
+}
+
 /// Manages state persistence logic by delegating to a backend.
 ///
 /// This struct holds an instance of a type implementing the `StatePersistence` trait
@@ -83,16 +259,17 @@ impl StateManager {
         Self { backend }
     }
 
-    /// Retrieves the state for a given template ID by delegating to the configured backend.
+    /// Retrieves the state for a given repository and template path by delegating to the
+    /// configured backend.
     ///
     /// # Arguments
-    /// * `template_id` - The unique identifier of the template state to retrieve.
+    /// * `key` - The key identifying the template state to retrieve.
     ///
     /// # Returns
     /// A `Result` containing `Some(TemplateState)` if found, `None` if not found,
     /// or a `CoreError` if the backend operation fails.
-    pub async fn get_state(&self, template_id: &str) -> Result<Option<TemplateState>> {
-        self.backend.get_state(template_id).await
+    pub async fn get_state(&self, key: &StateKey) -> Result<Option<TemplateState>> {
+        self.backend.get_state(key).await
     }
 
     /// Saves or updates the state for a template by delegating to the configured backend.
@@ -105,4 +282,205 @@ impl StateManager {
     pub async fn update_state(&self, state: &TemplateState) -> Result<()> {
         self.backend.update_state(state).await
     }
+
+    /// Retrieves the states for several keys at once by delegating to the configured backend.
+    ///
+    /// # Arguments
+    /// * `keys` - The keys identifying the template states to retrieve.
+    ///
+    /// # Returns
+    /// A `Result` containing one entry per key, in the order of `keys`, or a `CoreError` if the
+    /// backend operation fails.
+    pub async fn get_states(&self, keys: &[StateKey]) -> Result<Vec<Option<TemplateState>>> {
+        self.backend.get_states(keys).await
+    }
+
+    /// Saves or updates several states at once by delegating to the configured backend.
+    ///
+    /// # Arguments
+    /// * `states` - The `TemplateState` objects to save or update.
+    ///
+    /// # Returns
+    /// An empty `Result` on success, or a `CoreError` if the backend operation fails.
+    pub async fn update_states(&self, states: &[TemplateState]) -> Result<()> {
+        self.backend.update_states(states).await
+    }
+
+    /// Saves or updates the state for a template, if the stored state still has the expected
+    /// version, by delegating to the configured backend.
+    ///
+    /// # Arguments
+    /// * `state` - The `TemplateState` object to save or update.
+    /// * `expected_version` - The version of the state as it was read, or `None` if no state
+    ///   should exist yet.
+    ///
+    /// # Returns
+    /// A `Result` containing the new version on success, or a `CoreError` (notably
+    /// `CoreError::StateConflict`) if the update was rejected or the backend operation fails.
+    pub async fn update_state_if(
+        &self,
+        state: &TemplateState,
+        expected_version: Option<u64>,
+    ) -> Result<u64> {
+        self.backend.update_state_if(state, expected_version).await
+    }
+
+    /// Lists one page of states by delegating to the configured backend.
+    ///
+    /// # Arguments
+    /// * `query` - The filters, page size and page token of the listing.
+    ///
+    /// # Returns
+    /// A `Result` containing the requested `StatePage`, or a `CoreError` if the backend
+    /// operation fails.
+    pub async fn list_states(&self, query: &StateQuery) -> Result<StatePage> {
+        self.backend.list_states(query).await
+    }
+
+    /// Lists all states matching the filters of `query`, fetching every page from the backend.
+    /// The page token of `query` is ignored; its limit is used as the page size.
+    ///
+    /// # Arguments
+    /// * `query` - The filters and page size of the listing.
+    ///
+    /// # Returns
+    /// A `Result` containing all matching states in `StateKey` order, or a `CoreError` if a
+    /// backend operation fails.
+    pub async fn list_all_states(&self, query: &StateQuery) -> Result<Vec<TemplateState>> {
+        let mut query = StateQuery {
+            page_token: None,
+            ..query.clone()
+        };
+        let mut states = Vec::new();
+        loop {
+            let page = self.backend.list_states(&query).await?;
+            states.extend(page.states);
+            match page.next_page_token {
+                Some(token) => query.page_token = Some(token),
+                None => return Ok(states),
+            }
+        }
+    }
+
+    /// Deletes the state for a template by delegating to the configured backend.
+    ///
+    /// # Arguments
+    /// * `key` - The key identifying the template state to delete.
+    ///
+    /// # Returns
+    /// A `Result` containing `true` if the state existed, `false` otherwise, or a `CoreError`
+    /// if the backend operation fails.
+    pub async fn delete_state(&self, key: &StateKey) -> Result<bool> {
+        self.backend.delete_state(key).await
+    }
+
+    /// Retrieves the retained versions of a state, newest first, by delegating to the configured
+    /// backend.
+    ///
+    /// # Arguments
+    /// * `key` - The key identifying the template state.
+    ///
+    /// # Returns
+    /// A `Result` containing the retained versions, starting with the current state, or a
+    /// `CoreError` if the backend operation fails.
+    pub async fn get_state_history(&self, key: &StateKey) -> Result<Vec<TemplateState>> {
+        self.backend.get_state_history(key).await
+    }
+
+    /// Returns the checksums a target repository may hold from earlier deployments of a
+    /// template, for the `old_checksum` list of a `TemplateChange`.
+    ///
+    /// The master and deployed checksums of the retained versions are returned newest first,
+    /// without duplicates and at most [`MAX_OLD_CHECKSUMS`] of them.
+    ///
+    /// # Arguments
+    /// * `key` - The key identifying the template state.
+    ///
+    /// # Returns
+    /// A `Result` containing the checksums, or a `CoreError` if the backend operation fails.
+    pub async fn old_checksums(&self, key: &StateKey) -> Result<Vec<String>> {
+        let mut checksums: Vec<String> = Vec::new();
+        for state in self.backend.get_state_history(key).await? {
+            let candidates = [Some(state.master_checksum), state.deployed_checksum];
+            for checksum in candidates.into_iter().flatten() {
+                if checksums.len() == MAX_OLD_CHECKSUMS {
+                    return Ok(checksums);
+                }
+                if !checksums.contains(&checksum) {
+                    checksums.push(checksum);
+                }
+            }
+        }
+        Ok(checksums)
+    }
+
+    /// Finds the version at which a target repository last received the template content with
+    /// the given checksum, answering "when did this repo last get version X".
+    ///
+    /// This is the oldest version of the most recent run of retained versions whose
+    /// `deployed_checksum` equals `checksum`; its `last_updated_utc` is when the content was
+    /// deployed.
+    ///
+    /// # Arguments
+    /// * `key` - The key identifying the template state.
+    /// * `checksum` - The checksum of the deployed template content.
+    ///
+    /// # Returns
+    /// A `Result` containing the version, `None` if the retained history has no version with that
+    /// deployed checksum, or a `CoreError` if the backend operation fails.
+    pub async fn last_deployment_of(
+        &self,
+        key: &StateKey,
+        checksum: &str,
+    ) -> Result<Option<TemplateState>> {
+        let mut deployment = None;
+        for state in self.backend.get_state_history(key).await? {
+            if state.deployed_checksum.as_deref() == Some(checksum) {
+                deployment = Some(state);
+            } else if deployment.is_some() {
+                break;
+            }
+        }
+        Ok(deployment)
+    }
+
+    /// Restores the content of an earlier version of a state by writing it as a new version.
+    ///
+    /// The write is conditional on the current version, so a concurrent update is never
+    /// overwritten. The restored state gets the current time as `last_updated_utc`.
+    ///
+    /// # Arguments
+    /// * `key` - The key identifying the template state.
+    /// * `version` - The version to restore.
+    ///
+    /// # Returns
+    /// A `Result` containing the state as stored after the rollback, or a `CoreError` if:
+    ///   - The version is not in the retained history (`CoreError::StateVersionNotFound`).
+    ///   - The state was updated concurrently (`CoreError::StateConflict`).
+    ///   - A backend operation fails.
+    pub async fn rollback_state(&self, key: &StateKey, version: u64) -> Result<TemplateState> {
+        let history = self.backend.get_state_history(key).await?;
+        let not_found = || CoreError::StateVersionNotFound {
+            key: key.clone(),
+            version,
+        };
+        let current_version = history.first().ok_or_else(not_found)?.version;
+        let restored = history
+            .into_iter()
+            .find(|state| state.version == version)
+            .ok_or_else(not_found)?;
+        let restored = TemplateState {
+            last_updated_utc: Utc::now(),
+            ..restored
+        };
+
+        let new_version = self
+            .backend
+            .update_state_if(&restored, Some(current_version))
+            .await?;
+        Ok(TemplateState {
+            version: new_version,
+            ..restored
+        })
+    }
 }
@@ -1,5 +1,6 @@
 use super::*; // Import items from state_manager.rs
-use crate::types::{CoreError, Result, TemplateState}; // Import necessary types
+use crate::memory_backend::{InMemoryBackend, InjectedFault, StateOperation};
+use crate::types::{CoreError, Result, StateKey, StatePage, StateQuery, TemplateState}; // Import necessary types
 use async_trait::async_trait;
 use chrono::Utc;
 use mockall::mock;
@@ -9,8 +10,16 @@ mock! {
     pub StatePersistenceBackend {} // Name it differently from the trait
     #[async_trait]
     impl StatePersistence for StatePersistenceBackend {
-        async fn get_state(&self, template_id: &str) -> Result<Option<TemplateState>>;
+        async fn get_state(&self, key: &StateKey) -> Result<Option<TemplateState>>;
         async fn update_state(&self, state: &TemplateState) -> Result<()>;
+        async fn update_state_if(
+            &self,
+            state: &TemplateState,
+            expected_version: Option<u64>,
+        ) -> Result<u64>;
+        async fn list_states(&self, query: &StateQuery) -> Result<StatePage>;
+        async fn delete_state(&self, key: &StateKey) -> Result<bool>;
+        async fn get_state_history(&self, key: &StateKey) -> Result<Vec<TemplateState>>;
     }
 }
 
@@ -23,12 +32,28 @@ fn test_state_manager_debug_impl() {
     struct DummyBackend;
     #[async_trait]
     impl StatePersistence for DummyBackend {
-        async fn get_state(&self, _template_id: &str) -> Result<Option<TemplateState>> {
+        async fn get_state(&self, _key: &StateKey) -> Result<Option<TemplateState>> {
             Ok(None)
         }
         async fn update_state(&self, _state: &TemplateState) -> Result<()> {
             Ok(())
         }
+        async fn update_state_if(
+            &self,
+            _state: &TemplateState,
+            _expected_version: Option<u64>,
+        ) -> Result<u64> {
+            Ok(1)
+        }
+        async fn list_states(&self, _query: &StateQuery) -> Result<StatePage> {
+            Ok(StatePage::default())
+        }
+        async fn delete_state(&self, _key: &StateKey) -> Result<bool> {
+            Ok(false)
+        }
+        async fn get_state_history(&self, _key: &StateKey) -> Result<Vec<TemplateState>> {
+            Ok(Vec::new())
+        }
     }
 
     let manager = StateManager::new(Box::new(DummyBackend));
@@ -44,11 +69,15 @@ fn test_state_manager_debug_impl() {
 #[tokio::test]
 async fn test_state_manager_get_state_found() {
     let template_id = "test-template";
+    let key = StateKey::new("org/target", template_id);
     let expected_state = TemplateState {
-        template_id: template_id.to_string(),
+        repo: "org/target".to_string(),
+        template_path: template_id.to_string(),
         source_repository: "test/repo".to_string(),
-        current_checksum: "checksum123".to_string(),
+        master_checksum: "checksum123".to_string(),
+        deployed_checksum: None,
         last_updated_utc: Utc::now(),
+        version: 0,
     };
 
     let mut mock_backend = MockStatePersistenceBackend::new();
@@ -57,7 +86,7 @@ async fn test_state_manager_get_state_found() {
     // Expect get_state to be called once with "test-template"
     mock_backend
         .expect_get_state()
-        .with(mockall::predicate::eq(template_id))
+        .with(mockall::predicate::eq(key.clone()))
         .times(1)
         .returning(move |_| Ok(Some(state_clone.clone()))); // Return the cloned state
 
@@ -65,7 +94,7 @@ async fn test_state_manager_get_state_found() {
     let state_manager = StateManager::new(Box::new(mock_backend));
 
     // Call the method under test
-    let result = state_manager.get_state(template_id).await;
+    let result = state_manager.get_state(&key).await;
 
     // Assertions
     assert!(result.is_ok());
@@ -74,18 +103,18 @@ async fn test_state_manager_get_state_found() {
 
 #[tokio::test]
 async fn test_state_manager_get_state_not_found() {
-    let template_id = "not-found-template";
+    let key = StateKey::new("org/target", "not-found-template");
     let mut mock_backend = MockStatePersistenceBackend::new();
 
     // Expect get_state to be called once and return Ok(None)
     mock_backend
         .expect_get_state()
-        .with(mockall::predicate::eq(template_id))
+        .with(mockall::predicate::eq(key.clone()))
         .times(1)
         .returning(|_| Ok(None));
 
     let state_manager = StateManager::new(Box::new(mock_backend));
-    let result = state_manager.get_state(template_id).await;
+    let result = state_manager.get_state(&key).await;
 
     assert!(result.is_ok());
     assert_eq!(result.unwrap(), None);
@@ -93,18 +122,18 @@ async fn test_state_manager_get_state_not_found() {
 
 #[tokio::test]
 async fn test_state_manager_get_state_error() {
-    let template_id = "error-template";
+    let key = StateKey::new("org/target", "error-template");
     let mut mock_backend = MockStatePersistenceBackend::new();
 
     // Expect get_state to be called once and return an error
     mock_backend
         .expect_get_state()
-        .with(mockall::predicate::eq(template_id))
+        .with(mockall::predicate::eq(key.clone()))
         .times(1)
         .returning(|_| Err(CoreError::DatabaseError("Simulated DB error".to_string())));
 
     let state_manager = StateManager::new(Box::new(mock_backend));
-    let result = state_manager.get_state(template_id).await;
+    let result = state_manager.get_state(&key).await;
 
     assert!(result.is_err());
     match result.err().unwrap() {
@@ -116,10 +145,13 @@ async fn test_state_manager_get_state_error() {
 #[tokio::test]
 async fn test_state_manager_update_state_success() {
     let state_to_update = TemplateState {
-        template_id: "update-template".to_string(),
+        repo: "org/target".to_string(),
+        template_path: "update-template".to_string(),
         source_repository: "test/repo".to_string(),
-        current_checksum: "new_checksum".to_string(),
+        master_checksum: "new_checksum".to_string(),
+        deployed_checksum: None,
         last_updated_utc: Utc::now(),
+        version: 0,
     };
 
     let mut mock_backend = MockStatePersistenceBackend::new();
@@ -141,10 +173,13 @@ async fn test_state_manager_update_state_success() {
 #[tokio::test]
 async fn test_state_manager_update_state_error() {
     let state_to_update = TemplateState {
-        template_id: "update-error-template".to_string(),
+        repo: "org/target".to_string(),
+        template_path: "update-error-template".to_string(),
         source_repository: "test/repo".to_string(),
-        current_checksum: "error_checksum".to_string(),
+        master_checksum: "error_checksum".to_string(),
+        deployed_checksum: None,
         last_updated_utc: Utc::now(),
+        version: 0,
     };
 
     let mut mock_backend = MockStatePersistenceBackend::new();
@@ -170,3 +205,190 @@ async fn test_state_manager_update_state_error() {
         _ => panic!("Expected DatabaseError"),
     }
 }
+
+#[tokio::test]
+async fn test_state_manager_list_all_states_follows_page_tokens() {
+    let state = |path: &str| TemplateState {
+        repo: "org/target".to_string(),
+        template_path: path.to_string(),
+        source_repository: "test/repo".to_string(),
+        master_checksum: "checksum".to_string(),
+        deployed_checksum: None,
+        last_updated_utc: Utc::now(),
+        version: 0,
+    };
+    let first_page = StatePage::new(vec![state("a"), state("b")], true);
+    let first_token = first_page.next_page_token.clone().unwrap();
+    let second_page = StatePage::new(vec![state("c")], false);
+
+    let mut mock_backend = MockStatePersistenceBackend::new();
+    mock_backend
+        .expect_list_states()
+        .withf(|query| query.page_token.is_none() && query.repo.as_deref() == Some("org/target"))
+        .times(1)
+        .returning(move |_| Ok(first_page.clone()));
+    mock_backend
+        .expect_list_states()
+        .withf(move |query| query.page_token.as_deref() == Some(first_token.as_str()))
+        .times(1)
+        .returning(move |_| Ok(second_page.clone()));
+
+    let state_manager = StateManager::new(Box::new(mock_backend));
+    let states = state_manager
+        .list_all_states(&StateQuery::default().with_repo("org/target").with_limit(2))
+        .await
+        .unwrap();
+
+    let paths: Vec<_> = states.iter().map(|s| s.template_path.as_str()).collect();
+    assert_eq!(paths, vec!["a", "b", "c"]);
+}
+
+#[tokio::test]
+async fn test_state_manager_delete_state() {
+    let key = StateKey::new("org/target", "removed-template");
+    let key_clone = key.clone();
+
+    let mut mock_backend = MockStatePersistenceBackend::new();
+    mock_backend
+        .expect_delete_state()
+        .withf(move |k| k == &key_clone)
+        .times(1)
+        .returning(|_| Ok(true));
+
+    let state_manager = StateManager::new(Box::new(mock_backend));
+    assert!(state_manager.delete_state(&key).await.unwrap());
+}
+
+fn deployment(master: &str, deployed: Option<&str>) -> TemplateState {
+    TemplateState {
+        repo: "org/target".to_string(),
+        template_path: "README.md".to_string(),
+        source_repository: "org/master".to_string(),
+        master_checksum: master.to_string(),
+        deployed_checksum: deployed.map(str::to_string),
+        last_updated_utc: Utc::now(),
+        version: 0,
+    }
+}
+
+/// Returns a state manager on an in-memory backend holding the given versions, oldest first.
+async fn manager_with_history(versions: &[TemplateState]) -> (StateManager, InMemoryBackend) {
+    let backend = InMemoryBackend::new();
+    for state in versions {
+        backend.update_state(state).await.unwrap();
+    }
+    (StateManager::new(Box::new(backend.clone())), backend)
+}
+
+#[tokio::test]
+async fn test_state_manager_old_checksums() {
+    let (state_manager, _) = manager_with_history(&[
+        deployment("a", Some("a")),
+        deployment("b", Some("a")),
+        deployment("c", Some("manual")),
+        deployment("c", Some("c")),
+    ])
+    .await;
+    let key = StateKey::new("org/target", "README.md");
+
+    let checksums = state_manager.old_checksums(&key).await.unwrap();
+
+    assert_eq!(checksums, vec!["c", "manual", "b", "a"]);
+    assert!(state_manager
+        .old_checksums(&StateKey::new("org/target", "missing"))
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn test_state_manager_old_checksums_are_limited() {
+    let versions: Vec<_> = (0..8)
+        .map(|i| deployment(&format!("m{}", i), Some(&format!("d{}", i))))
+        .collect();
+    let (state_manager, _) = manager_with_history(&versions).await;
+
+    let checksums = state_manager
+        .old_checksums(&StateKey::new("org/target", "README.md"))
+        .await
+        .unwrap();
+
+    assert_eq!(checksums.len(), MAX_OLD_CHECKSUMS);
+    assert_eq!(checksums[..3], ["m7", "d7", "m6"]);
+}
+
+#[tokio::test]
+async fn test_state_manager_last_deployment_of() {
+    let (state_manager, _) = manager_with_history(&[
+        deployment("a", Some("a")),
+        deployment("b", Some("b")),
+        deployment("b", Some("b")),
+        deployment("c", Some("b")),
+        deployment("c", Some("c")),
+    ])
+    .await;
+    let key = StateKey::new("org/target", "README.md");
+
+    // "b" was deployed by version 2 and kept until version 4
+    let last_b = state_manager.last_deployment_of(&key, "b").await.unwrap();
+    assert_eq!(last_b.map(|state| state.version), Some(2));
+    let last_a = state_manager.last_deployment_of(&key, "a").await.unwrap();
+    assert_eq!(last_a.map(|state| state.version), Some(1));
+    assert_eq!(
+        state_manager.last_deployment_of(&key, "x").await.unwrap(),
+        None
+    );
+}
+
+#[tokio::test]
+async fn test_state_manager_rollback_state() {
+    let (state_manager, backend) =
+        manager_with_history(&[deployment("a", Some("a")), deployment("b", Some("b"))]).await;
+    let key = StateKey::new("org/target", "README.md");
+
+    let restored = state_manager.rollback_state(&key, 1).await.unwrap();
+
+    assert_eq!(restored.version, 3);
+    assert_eq!(restored.master_checksum, "a");
+    assert_eq!(backend.state(&key), Some(restored));
+    let history = state_manager.get_state_history(&key).await.unwrap();
+    assert_eq!(history.len(), 3);
+}
+
+#[tokio::test]
+async fn test_state_manager_rollback_state_unknown_version() {
+    let (state_manager, _) = manager_with_history(&[deployment("a", Some("a"))]).await;
+
+    let result = state_manager
+        .rollback_state(&StateKey::new("org/target", "README.md"), 7)
+        .await;
+    let missing = state_manager
+        .rollback_state(&StateKey::new("org/target", "missing"), 1)
+        .await;
+
+    assert!(matches!(
+        result,
+        Err(CoreError::StateVersionNotFound { version: 7, .. })
+    ));
+    assert!(matches!(
+        missing,
+        Err(CoreError::StateVersionNotFound { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_state_manager_rollback_state_conflict() {
+    let (state_manager, backend) =
+        manager_with_history(&[deployment("a", Some("a")), deployment("b", Some("b"))]).await;
+    backend.fail_nth_call_of(
+        StateOperation::UpdateStateIf,
+        1,
+        InjectedFault::ConcurrentUpdate,
+    );
+
+    let result = state_manager
+        .rollback_state(&StateKey::new("org/target", "README.md"), 1)
+        .await;
+
+    assert!(matches!(result, Err(CoreError::StateConflict { .. })));
+}
@@ -0,0 +1,318 @@
+//! Copies every `TemplateState` from one `StatePersistence` backend to another.
+//!
+//! States are streamed page by page with `StatePersistence::scan_states` in a single pass over
+//! the source, so a migration never holds more than one page in memory. Each state is copied
+//! together with the previous versions the source retains in its history. After every page the
+//! progress can be recorded in a checkpoint file; a migration started with the same checkpoint
+//! file continues with the next page instead of starting over. Once all states are copied, both
+//! backends are read again and their state counts and content checksums compared.
+
+use crate::state_manager::StatePersistence;
+use crate::types::{CoreError, Result, StateKey, TemplateState};
+use chrono::SecondsFormat;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+#[path = "state_migration_tests.rs"]
+mod tests;
+
+/// The number of states read from the source per page, unless configured otherwise.
+pub const DEFAULT_MIGRATION_BATCH_SIZE: usize = 100;
+
+/// The number and combined checksum of the states in a backend.
+///
+/// The checksum covers every field of every state except `version`, which each backend assigns
+/// itself. Timestamps are included with microsecond precision, the finest precision all backends
+/// store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateSetSummary {
+    /// The number of states.
+    pub count: usize,
+
+    /// The lowercase hex-encoded SHA-256 checksum of the SHA-256 checksums of the states, sorted,
+    /// so it does not depend on the order in which a backend returns its states.
+    pub checksum: String,
+}
+
+impl fmt::Display for StateSetSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} states, checksum {}", self.count, self.checksum)
+    }
+}
+
+/// The outcome of a `StateMigration`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateMigrationReport {
+    /// The number of states copied, including those copied by interrupted earlier runs with the
+    /// same checkpoint file.
+    pub copied: usize,
+
+    /// The key of the last state copied before this run, if it resumed an interrupted migration.
+    pub resumed_after: Option<StateKey>,
+
+    /// The summaries of the source and the target after copying; `None` if verification was
+    /// disabled.
+    pub verified: Option<StateSetSummary>,
+}
+
+/// The progress of a migration, as recorded in its checkpoint file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct Checkpoint {
+    last_key: StateKey,
+    copied: usize,
+    /// The token of the next page to copy, or `None` if all states were copied.
+    next_page_token: Option<String>,
+}
+
+/// Copies all states from a source backend to a target backend.
+///
+/// States are written with `StatePersistence::update_states`, so the target assigns its own
+/// versions. The retained previous versions of each state are written before it, oldest first,
+/// so the target's history holds the same sequence of versions, up to its own retention. This
+/// reads the history of every state, one request per state. States the target already holds are
+/// overwritten; states only the target holds are kept, which makes verification fail.
+/// Verification compares the current states only.
+///
+/// # Example
+/// ```rust
+/// # use template_teleporter_core::{InMemoryBackend, StateMigration};
+/// # async fn run() -> template_teleporter_core::Result<()> {
+/// let source = InMemoryBackend::new();
+/// let target = InMemoryBackend::new();
+/// let report = StateMigration::new(&source, &target)
+///     .with_checkpoint("migration.checkpoint")
+///     .run()
+///     .await?;
+/// println!("Copied {} states", report.copied);
+/// # Ok(())
+/// # }
+/// ```
+pub struct StateMigration<'a> {
+    source: &'a dyn StatePersistence,
+    target: &'a dyn StatePersistence,
+    batch_size: usize,
+    checkpoint: Option<PathBuf>,
+    verify: bool,
+}
+
+impl<'a> StateMigration<'a> {
+    /// Creates a migration from `source` to `target` which verifies the result and keeps no
+    /// checkpoint.
+    pub fn new(source: &'a dyn StatePersistence, target: &'a dyn StatePersistence) -> Self {
+        Self {
+            source,
+            target,
+            batch_size: DEFAULT_MIGRATION_BATCH_SIZE,
+            checkpoint: None,
+            verify: true,
+        }
+    }
+
+    /// Sets the number of states read from the source per page. Values below 1 are treated as 1.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Records the progress in the file at `path` and resumes from it if it exists. The file is
+    /// removed once the migration completes.
+    pub fn with_checkpoint(mut self, path: impl Into<PathBuf>) -> Self {
+        self.checkpoint = Some(path.into());
+        self
+    }
+
+    /// Sets whether the states of source and target are compared after copying.
+    pub fn with_verification(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Runs the migration.
+    ///
+    /// # Returns
+    /// A `Result` containing the `StateMigrationReport`, or a `CoreError` if:
+    ///   - Reading the source or writing the target fails (the backend's error). Progress up to
+    ///     the last completed page is kept in the checkpoint file.
+    ///   - The checkpoint file cannot be read or written (`CoreError::IoError`) or is corrupt
+    ///     (`CoreError::DatabaseError`).
+    ///   - The states of source and target differ after copying
+    ///     (`CoreError::MigrationVerificationFailed`).
+    pub async fn run(&self) -> Result<StateMigrationReport> {
+        let checkpoint = match &self.checkpoint {
+            Some(path) => read_checkpoint(path).await?,
+            None => None,
+        };
+        let resumed_after = checkpoint.as_ref().map(|c| c.last_key.clone());
+        let mut copied = checkpoint.as_ref().map_or(0, |c| c.copied);
+        let mut page_token = checkpoint.as_ref().and_then(|c| c.next_page_token.clone());
+        let mut done = checkpoint.is_some() && page_token.is_none();
+
+        while !done {
+            let page = self
+                .source
+                .scan_states(page_token.as_deref(), self.batch_size)
+                .await?;
+            self.copy_states(&page.states).await?;
+            copied += page.states.len();
+            done = page.next_page_token.is_none();
+            if let (Some(path), Some(last)) = (&self.checkpoint, page.states.last()) {
+                write_checkpoint(
+                    path,
+                    &Checkpoint {
+                        last_key: last.key(),
+                        copied,
+                        next_page_token: page.next_page_token.clone(),
+                    },
+                )
+                .await?;
+            }
+            page_token = page.next_page_token;
+        }
+
+        let verified = if self.verify {
+            let source = summarize_states(self.source, self.batch_size).await?;
+            let target = summarize_states(self.target, self.batch_size).await?;
+            if source != target {
+                return Err(CoreError::MigrationVerificationFailed(format!(
+                    "source has {}, target has {}",
+                    source, target
+                )));
+            }
+            Some(source)
+        } else {
+            None
+        };
+
+        if let Some(path) = &self.checkpoint {
+            match tokio::fs::remove_file(path).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(StateMigrationReport {
+            copied,
+            resumed_after,
+            verified,
+        })
+    }
+
+    /// Writes the states to the target, each preceded by its previous versions retained by the
+    /// source, oldest first.
+    async fn copy_states(&self, states: &[TemplateState]) -> Result<()> {
+        let mut versions = Vec::new();
+        for state in states {
+            let mut history = self.source.get_state_history(&state.key()).await?;
+            // The history starts with the current version, which may even be newer than the
+            // listed state by now; only the versions before the listed state are copied.
+            history.retain(|version| version.version < state.version);
+            versions.extend(history.into_iter().rev());
+            versions.push(state.clone());
+        }
+        self.target.update_states(&versions).await
+    }
+}
+
+impl fmt::Debug for StateMigration<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StateMigration")
+            .field("batch_size", &self.batch_size)
+            .field("checkpoint", &self.checkpoint)
+            .field("verify", &self.verify)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Counts and checksums all states of a backend, scanning `batch_size` states per page. Only the
+/// 32-byte checksum of each state is kept in memory.
+///
+/// # Returns
+/// A `Result` containing the `StateSetSummary`, or the backend's error if scanning fails.
+pub async fn summarize_states(
+    backend: &dyn StatePersistence,
+    batch_size: usize,
+) -> Result<StateSetSummary> {
+    let mut checksums = Vec::new();
+    let mut page_token = None;
+    loop {
+        let page = backend
+            .scan_states(page_token.as_deref(), batch_size.max(1))
+            .await?;
+        for state in &page.states {
+            let mut hasher = Sha256::new();
+            hash_state(&mut hasher, state);
+            checksums.push(hasher.finalize());
+        }
+        match page.next_page_token {
+            Some(token) => page_token = Some(token),
+            None => break,
+        }
+    }
+    checksums.sort_unstable();
+    let mut hasher = Sha256::new();
+    for checksum in &checksums {
+        hasher.update(checksum);
+    }
+    Ok(StateSetSummary {
+        count: checksums.len(),
+        checksum: hex::encode(hasher.finalize()),
+    })
+}
+
+fn hash_state(hasher: &mut Sha256, state: &TemplateState) {
+    // Every field is terminated, so no two different states hash the same bytes.
+    for field in [
+        state.repo.as_str(),
+        state.template_path.as_str(),
+        state.source_repository.as_str(),
+        state.master_checksum.as_str(),
+    ] {
+        hasher.update(field.as_bytes());
+        hasher.update([0]);
+    }
+    match &state.deployed_checksum {
+        Some(deployed) => {
+            hasher.update([1]);
+            hasher.update(deployed.as_bytes());
+        }
+        None => hasher.update([0]),
+    }
+    hasher.update([0]);
+    hasher.update(
+        state
+            .last_updated_utc
+            .to_rfc3339_opts(SecondsFormat::Micros, true)
+            .as_bytes(),
+    );
+    hasher.update([b'\n']);
+}
+
+async fn read_checkpoint(path: &Path) -> Result<Option<Checkpoint>> {
+    match tokio::fs::read(path).await {
+        Ok(content) => serde_json::from_slice(&content).map(Some).map_err(|e| {
+            CoreError::DatabaseError(format!(
+                "Invalid migration checkpoint {}: {}",
+                path.display(),
+                e
+            ))
+        }),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Writes the checkpoint to a temporary file first, so an interruption never leaves a partially
+/// written checkpoint behind.
+async fn write_checkpoint(path: &Path, checkpoint: &Checkpoint) -> Result<()> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let content = serde_json::to_vec(checkpoint).map_err(|e| {
+        CoreError::DatabaseError(format!("Failed to serialize migration checkpoint: {}", e))
+    })?;
+    tokio::fs::write(&temp_path, content).await?;
+    tokio::fs::rename(&temp_path, path).await?;
+    Ok(())
+}
@@ -0,0 +1,224 @@
+//! Tests for the state migration between backends.
+
+use super::*;
+use crate::memory_backend::{InMemoryBackend, InjectedFault, StateOperation};
+use chrono::{DurationRound, TimeDelta, Utc};
+use tempfile::tempdir;
+
+fn state_for(repo: &str, template_path: &str) -> TemplateState {
+    TemplateState {
+        repo: repo.to_string(),
+        template_path: template_path.to_string(),
+        source_repository: "owner/master".to_string(),
+        master_checksum: format!("checksum of {}", template_path),
+        deployed_checksum: Some("deployed".to_string()),
+        last_updated_utc: Utc::now(),
+        version: 3,
+    }
+}
+
+fn source_with_states(count: usize) -> InMemoryBackend {
+    InMemoryBackend::with_states(
+        (0..count).map(|i| state_for(&format!("org/repo-{}", i % 3), &format!("file-{}", i))),
+    )
+}
+
+/// Returns the states of a backend without their versions.
+fn contents(backend: &InMemoryBackend) -> Vec<TemplateState> {
+    backend
+        .states()
+        .into_iter()
+        .map(|state| TemplateState {
+            version: 0,
+            ..state
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_state_migration_copies_all_states() {
+    let source = source_with_states(7);
+    let target = InMemoryBackend::new();
+
+    let report = StateMigration::new(&source, &target)
+        .with_batch_size(3)
+        .run()
+        .await
+        .unwrap();
+
+    assert_eq!(report.copied, 7);
+    assert_eq!(report.resumed_after, None);
+    assert_eq!(report.verified.unwrap().count, 7);
+    assert_eq!(contents(&target), contents(&source));
+    // The target assigns its own versions
+    assert!(target.states().iter().all(|state| state.version == 1));
+}
+
+#[tokio::test]
+async fn test_state_migration_resumes_from_checkpoint() {
+    let dir = tempdir().unwrap();
+    let checkpoint = dir.path().join("migration.checkpoint");
+    let source = source_with_states(7);
+    let target = InMemoryBackend::new();
+    target.fail_nth_call_of(
+        StateOperation::UpdateStates,
+        3,
+        InjectedFault::DatabaseError("connection lost".to_string()),
+    );
+    let migration = StateMigration::new(&source, &target)
+        .with_batch_size(2)
+        .with_checkpoint(&checkpoint);
+
+    let result = migration.run().await;
+    assert!(matches!(result, Err(CoreError::DatabaseError(_))));
+    assert!(checkpoint.exists());
+
+    let report = migration.run().await.unwrap();
+
+    // The first two pages were completed before the interruption and are not copied again
+    let keys: Vec<StateKey> = source.states().iter().map(TemplateState::key).collect();
+    assert_eq!(report.resumed_after, Some(keys[3].clone()));
+    assert_eq!(report.copied, 7);
+    assert_eq!(report.verified.unwrap().count, 7);
+    assert_eq!(contents(&target), contents(&source));
+    for key in &keys[..4] {
+        assert_eq!(target.state(key).unwrap().version, 1);
+    }
+    assert!(!checkpoint.exists());
+}
+
+#[tokio::test]
+async fn test_state_migration_copies_history() {
+    let source = InMemoryBackend::new();
+    let key = StateKey::new("org/a", "README.md");
+    for checksum in ["v1", "v2", "v3"] {
+        source
+            .update_state(&TemplateState {
+                master_checksum: checksum.to_string(),
+                ..state_for("org/a", "README.md")
+            })
+            .await
+            .unwrap();
+    }
+    source
+        .update_state(&state_for("org/b", "README.md"))
+        .await
+        .unwrap();
+    let target = InMemoryBackend::new();
+
+    let report = StateMigration::new(&source, &target).run().await.unwrap();
+
+    assert_eq!(report.copied, 2);
+    let checksums = |history: Vec<TemplateState>| -> Vec<(String, u64)> {
+        history
+            .into_iter()
+            .map(|state| (state.master_checksum, state.version))
+            .collect()
+    };
+    assert_eq!(
+        checksums(target.get_state_history(&key).await.unwrap()),
+        checksums(source.get_state_history(&key).await.unwrap())
+    );
+    assert_eq!(
+        target
+            .get_state_history(&StateKey::new("org/b", "README.md"))
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn test_state_migration_verification_detects_extra_target_states() {
+    let source = source_with_states(3);
+    let target = InMemoryBackend::with_states([state_for("org/other", "README.md")]);
+
+    let result = StateMigration::new(&source, &target).run().await;
+
+    assert!(
+        matches!(result, Err(CoreError::MigrationVerificationFailed(msg)) if msg.contains("3 states") && msg.contains("4 states"))
+    );
+}
+
+#[tokio::test]
+async fn test_state_migration_without_verification() {
+    let source = source_with_states(3);
+    let target = InMemoryBackend::with_states([state_for("org/other", "README.md")]);
+
+    let report = StateMigration::new(&source, &target)
+        .with_verification(false)
+        .run()
+        .await
+        .unwrap();
+
+    assert_eq!(report.copied, 3);
+    assert_eq!(report.verified, None);
+    assert_eq!(target.len(), 4);
+}
+
+#[tokio::test]
+async fn test_state_migration_rejects_corrupt_checkpoint() {
+    let dir = tempdir().unwrap();
+    let checkpoint = dir.path().join("migration.checkpoint");
+    std::fs::write(&checkpoint, "not json").unwrap();
+    let source = source_with_states(1);
+    let target = InMemoryBackend::new();
+
+    let result = StateMigration::new(&source, &target)
+        .with_checkpoint(&checkpoint)
+        .run()
+        .await;
+
+    assert!(matches!(result, Err(CoreError::DatabaseError(msg)) if msg.contains("checkpoint")));
+    assert!(target.is_empty());
+}
+
+#[tokio::test]
+async fn test_summarize_states() {
+    let state = state_for("org/a", "README.md");
+    let summary = |states: Vec<TemplateState>| async move {
+        summarize_states(&InMemoryBackend::with_states(states), 1)
+            .await
+            .unwrap()
+    };
+    let original = summary(vec![state.clone()]).await;
+    assert_eq!(original.count, 1);
+
+    // Versions and sub-microsecond precision are ignored
+    let mut same = state.clone();
+    same.version = 9;
+    same.last_updated_utc = state
+        .last_updated_utc
+        .duration_trunc(TimeDelta::microseconds(1))
+        .unwrap();
+    assert_eq!(summary(vec![same]).await, original);
+
+    // Any other field is covered
+    let mut deployed_empty = state.clone();
+    deployed_empty.deployed_checksum = Some(String::new());
+    let mut not_deployed = state.clone();
+    not_deployed.deployed_checksum = None;
+    let mut later = state.clone();
+    later.last_updated_utc += TimeDelta::seconds(1);
+    let mut other_source = state.clone();
+    other_source.source_repository = "owner/other".to_string();
+    for changed in [deployed_empty, not_deployed, later, other_source] {
+        assert_ne!(summary(vec![changed]).await, original);
+    }
+    assert_eq!(summary(vec![]).await.count, 0);
+
+    // Neither the order of the states nor the page size matters
+    let other = state_for("org/b", "README.md");
+    assert_eq!(
+        summarize_states(
+            &InMemoryBackend::with_states([state.clone(), other.clone()]),
+            1
+        )
+        .await
+        .unwrap(),
+        summarize_states(&InMemoryBackend::with_states([other, state]), 2)
+            .await
+            .unwrap()
+    );
+}
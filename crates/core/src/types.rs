@@ -2,8 +2,15 @@
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+use template_teleporter_developer_platforms::{MasterConfigError, RepoInfo, TemplatePath};
 use thiserror::Error;
 
+#[cfg(test)]
+#[path = "types_tests.rs"]
+mod tests;
+
 /// Custom error types encompassing potential failures within the core library.
 #[derive(Error, Debug)]
 pub enum CoreError {
@@ -11,6 +18,11 @@ pub enum CoreError {
     #[error("Database connection or operation error: {0}")]
     DatabaseError(String),
 
+    /// Errors of the state persistence backend which are likely to go away when the operation is
+    /// repeated, e.g. timeouts, throttling or a lost connection.
+    #[error("Transient database error: {0}")]
+    TransientDatabaseError(String),
+
     /// Error during SHA-256 checksum calculation.
     #[error("Checksum calculation failed: {0}")]
     ChecksumFailure(String),
@@ -30,10 +42,44 @@ pub enum CoreError {
     #[error("I/O error: {0}")]
     IoError(#[from] std::io::Error),
 
+    /// Error indicating the master configuration (`template-teleporter.toml`) failed to parse or
+    /// validate. Lists every problem found, with its location.
+    #[error("Invalid master configuration: {0}")]
+    InvalidMasterConfig(#[from] MasterConfigError),
+
+    /// A conditional state update was rejected because the stored state changed since it was
+    /// read, e.g. because another process updated it concurrently. Re-read the state and retry.
+    #[error("State conflict for {key}: expected version {expected:?}, found version {actual:?}")]
+    StateConflict {
+        /// The key of the state which was updated concurrently.
+        key: StateKey,
+        /// The version the caller expected, or `None` if it expected no state to exist.
+        expected: Option<u64>,
+        /// The version found in the backend, or `None` if no state exists.
+        actual: Option<u64>,
+    },
+
+    /// The requested version of a state is not in its retained history.
+    #[error("Version {version} of {key} is not in its history")]
+    StateVersionNotFound {
+        /// The key of the state.
+        key: StateKey,
+        /// The requested version.
+        version: u64,
+    },
+
     /// Error when a required configuration value is missing.
     #[error("Missing configuration value: {0}")]
     MissingConfiguration(String),
 
+    /// Error when a configuration value is present but not valid.
+    #[error("Invalid configuration value: {0}")]
+    InvalidConfiguration(String),
+
+    /// Error when the states copied by a state migration do not match the states of the source.
+    #[error("State migration verification failed: {0}")]
+    MigrationVerificationFailed(String),
+
     /// Errors originating from interactions with external developer platforms (e.g., GitHub API errors).
     #[error("Platform interaction error: {0}")]
     PlatformError(String),
@@ -42,36 +88,565 @@ pub enum CoreError {
 /// A specialized `Result` type for the core library, using `CoreError` as the error type.
 pub type Result<T> = std::result::Result<T, CoreError>;
 
-/// Represents the persisted state of a managed template, tracked by the application.
+/// Identifies the persisted state of one template in one target repository.
 ///
-/// This struct holds information about the template's source, its last known checksum,
-/// and when it was last updated by the system.
+/// State is tracked per (repository, template path) pair, because the same master template
+/// may have been deployed to, or manually modified in, each target repository independently.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StateKey {
+    /// Full name of the target repository (e.g., "org/repo-name").
+    pub repo: String,
+
+    /// Path of the template file within the target repository (e.g., ".github/PULL_REQUEST_TEMPLATE.md").
+    pub template_path: String,
+}
+
+impl StateKey {
+    /// Creates a new `StateKey` for the given repository and template path.
+    pub fn new(repo: impl Into<String>, template_path: impl Into<String>) -> Self {
+        Self {
+            repo: repo.into(),
+            template_path: template_path.into(),
+        }
+    }
+}
+
+impl fmt::Display for StateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.repo, self.template_path)
+    }
+}
+
+/// Represents the persisted state of a managed template in a target repository,
+/// tracked by the application.
+///
+/// This struct holds the checksum of the template in the master repository and the checksum of
+/// the template content this tool last deployed to the target repository. Together with the
+/// checksum of the content currently in the target repository these allow manual modifications
+/// in the target repository to be detected.
+///
+/// States serialized before states were tracked per repository, with a `templateId` and a
+/// `currentChecksum` instead of the key and the checksums, are read as well. Those states tracked
+/// the templates of the master repository: the `templateId` is the template path and the state
+/// belongs to the `sourceRepository`, unless the `templateId` has the form
+/// `<repo>:<template path>` in which a `StateKey` is displayed. The `currentChecksum` is the
+/// master checksum, and as nothing was deployed then, the deployed checksum is `None`.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "TemplateStateFields")]
 pub struct TemplateState {
-    /// Unique identifier for the template (e.g., file path within the master repo).
-    #[serde(rename = "templateId")]
-    pub template_id: String,
+    /// Full name of the target repository (e.g., "org/repo-name").
+    #[serde(rename = "repo")]
+    pub repo: String,
+
+    /// Path of the template file within the target repository.
+    #[serde(rename = "templatePath")]
+    pub template_path: String,
 
     /// The source repository where the master version of the template resides.
     #[serde(rename = "sourceRepository")]
     pub source_repository: String,
 
-    /// The SHA-256 checksum of the template content the last time it was successfully processed or updated.
-    #[serde(rename = "currentChecksum")]
-    pub current_checksum: String,
+    /// The SHA-256 checksum of the template in the master repository the last time it was processed.
+    #[serde(rename = "masterChecksum")]
+    pub master_checksum: String,
+
+    /// The SHA-256 checksum of the template content last written to the target repository by this tool,
+    /// or `None` if this tool has never deployed the template to the repository.
+    #[serde(rename = "deployedChecksum")]
+    pub deployed_checksum: Option<String>,
 
     /// Timestamp (UTC) when the template state was last updated in the persistence layer.
     #[serde(rename = "lastUpdatedUtc")]
     pub last_updated_utc: DateTime<Utc>,
+
+    /// The number of times the state has been written, used for optimistic concurrency.
+    ///
+    /// Backends assign the version on every write: a new state is stored with version 1 and each
+    /// later write increments it. The value of a state passed to a write is ignored. Pass the
+    /// version of a state read earlier to `StatePersistence::update_state_if` to only write if
+    /// nobody else has written the state since. States stored before versions were introduced
+    /// read as version 0.
+    #[serde(rename = "version", default)]
+    pub version: u64,
+}
+
+impl TemplateState {
+    /// Returns the `StateKey` identifying this state.
+    pub fn key(&self) -> StateKey {
+        StateKey::new(self.repo.clone(), self.template_path.clone())
+    }
+}
+
+/// The fields of a serialized `TemplateState`, in the current or the legacy format.
+#[derive(Deserialize)]
+struct TemplateStateFields {
+    repo: Option<String>,
+    #[serde(rename = "templatePath")]
+    template_path: Option<String>,
+    #[serde(rename = "templateId")]
+    template_id: Option<String>,
+    #[serde(rename = "sourceRepository")]
+    source_repository: String,
+    #[serde(rename = "masterChecksum", alias = "currentChecksum")]
+    master_checksum: String,
+    #[serde(rename = "deployedChecksum", default)]
+    deployed_checksum: Option<String>,
+    #[serde(rename = "lastUpdatedUtc")]
+    last_updated_utc: DateTime<Utc>,
+    #[serde(rename = "version", default)]
+    version: u64,
+}
+
+/// Returns the key of a state serialized in the legacy format, see `TemplateState`.
+fn legacy_key(template_id: &str, source_repository: &str) -> StateKey {
+    match template_id.split_once(':') {
+        Some((repo, template_path)) if !repo.is_empty() && !template_path.is_empty() => {
+            StateKey::new(repo, template_path)
+        }
+        _ => StateKey::new(source_repository, template_id),
+    }
+}
+
+impl TryFrom<TemplateStateFields> for TemplateState {
+    type Error = String;
+
+    fn try_from(fields: TemplateStateFields) -> std::result::Result<Self, Self::Error> {
+        let key = match (fields.repo, fields.template_path, fields.template_id) {
+            (Some(repo), Some(template_path), _) => StateKey::new(repo, template_path),
+            (None, None, Some(template_id)) => legacy_key(&template_id, &fields.source_repository),
+            (None, _, _) => return Err("missing field `repo`".to_string()),
+            (_, None, _) => return Err("missing field `templatePath`".to_string()),
+        };
+        Ok(TemplateState {
+            repo: key.repo,
+            template_path: key.template_path,
+            source_repository: fields.source_repository,
+            master_checksum: fields.master_checksum,
+            deployed_checksum: fields.deployed_checksum,
+            last_updated_utc: fields.last_updated_utc,
+            version: fields.version,
+        })
+    }
+}
+
+/// Selects which template states `StatePersistence::list_states` returns, and which page of them.
+///
+/// States are always listed in `StateKey` order, i.e. by repository and then by template path.
+///
+/// # Example
+/// ```rust
+/// use template_teleporter_core::StateQuery;
+/// let query = StateQuery::default()
+///     .with_repo("org/service")
+///     .with_template_path_prefix(".github/")
+///     .with_limit(50);
+/// assert_eq!(query.limit, Some(50));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateQuery {
+    /// Only return states of this repository (full name, e.g., "org/repo-name").
+    pub repo: Option<String>,
+
+    /// Only return states whose template path starts with this prefix.
+    pub template_path_prefix: Option<String>,
+
+    /// The maximum number of states to return in one page. `None` returns all matching states.
+    pub limit: Option<usize>,
+
+    /// The `next_page_token` of the previous page, to continue listing after it.
+    pub page_token: Option<String>,
+}
+
+impl StateQuery {
+    /// Only returns states of the given repository.
+    pub fn with_repo(mut self, repo: impl Into<String>) -> Self {
+        self.repo = Some(repo.into());
+        self
+    }
+
+    /// Only returns states whose template path starts with the given prefix.
+    pub fn with_template_path_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.template_path_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Returns at most `limit` states per page.
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Continues listing after the page which returned the given token.
+    pub fn with_page_token(mut self, page_token: impl Into<String>) -> Self {
+        self.page_token = Some(page_token.into());
+        self
+    }
+
+    /// Returns `true` if the state with the given key matches the repository and template path
+    /// filters of this query. Pagination is not taken into account.
+    pub fn matches(&self, key: &StateKey) -> bool {
+        self.repo.as_ref().is_none_or(|repo| &key.repo == repo)
+            && self
+                .template_path_prefix
+                .as_ref()
+                .is_none_or(|prefix| key.template_path.starts_with(prefix.as_str()))
+    }
+
+    /// Decodes the page token, returning the key of the last state of the previous page.
+    ///
+    /// # Errors
+    /// Returns `CoreError::DatabaseError` if the page token was not produced by `StatePage`.
+    pub fn start_after(&self) -> Result<Option<StateKey>> {
+        self.page_token
+            .as_ref()
+            .map(|token| {
+                hex::decode(token)
+                    .ok()
+                    .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+                    .ok_or_else(|| {
+                        CoreError::DatabaseError(format!("Invalid page token '{}'", token))
+                    })
+            })
+            .transpose()
+    }
+
+    /// Applies the filters and pagination of this query to states which are sorted by key.
+    ///
+    /// This is intended for backends which cannot filter or paginate natively.
+    pub fn paginate(
+        &self,
+        sorted_states: impl IntoIterator<Item = TemplateState>,
+    ) -> Result<StatePage> {
+        let start_after = self.start_after()?;
+        let mut matching = sorted_states
+            .into_iter()
+            .filter(|state| {
+                let key = state.key();
+                self.matches(&key) && start_after.as_ref().is_none_or(|after| &key > after)
+            })
+            .peekable();
+
+        let mut states = Vec::new();
+        while self.limit.is_none_or(|limit| states.len() < limit) {
+            match matching.next() {
+                Some(state) => states.push(state),
+                None => break,
+            }
+        }
+        let has_more = matching.peek().is_some();
+        Ok(StatePage::new(states, has_more))
+    }
+}
+
+/// One page of template states returned by `StatePersistence::list_states` or
+/// `StatePersistence::scan_states`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StatePage {
+    /// The states on this page, in `StateKey` order unless returned by `scan_states`.
+    pub states: Vec<TemplateState>,
+
+    /// The token to pass as `StateQuery::page_token`, or to `scan_states`, to fetch the next page,
+    /// or `None` if this is the last page.
+    pub next_page_token: Option<String>,
+}
+
+impl StatePage {
+    /// Creates a page from states in `StateKey` order. If `has_more` is `true`, the next page
+    /// token points after the last state of the page.
+    pub fn new(states: Vec<TemplateState>, has_more: bool) -> Self {
+        let next_page_token = match states.last() {
+            Some(last) if has_more => Some(Self::token_after(&last.key())),
+            _ => None,
+        };
+        Self {
+            states,
+            next_page_token,
+        }
+    }
+
+    /// Returns the page token which continues listing after the given key.
+    pub fn token_after(key: &StateKey) -> String {
+        // Serializing a StateKey to JSON cannot fail.
+        hex::encode(serde_json::to_vec(key).unwrap_or_default())
+    }
 }
 
 /// Represents the application's configuration settings, typically loaded from a file.
+///
+/// `database_type` selects the `StatePersistence` backend and defaults to the filesystem, so
+/// configurations written before it existed still load. Settings that only apply to one backend
+/// live in the section named after it, e.g. `sqlite.path`; the sections of the backends not
+/// selected are ignored.
+///
+/// # Example
+/// ```yaml
+/// databaseType: sqlite
+/// tableName: TemplateState
+/// sqlite:
+///   path: /var/lib/template-teleporter/state.db
+/// ```
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")] // Consistent config naming
 pub struct AppConfig {
-    /// Optional endpoint override for the database (e.g., for local testing).
+    /// The backend storing `TemplateState`. Defaults to `DatabaseType::Filesystem`.
+    #[serde(default)]
+    pub database_type: DatabaseType,
+
+    /// Optional endpoint override for the database (e.g., for local testing). Required for
+    /// Cosmos DB, where it is the account endpoint, and PostgreSQL, where it is the connection
+    /// string.
     pub database_endpoint: Option<String>,
 
     /// The name of the table or container used for storing `TemplateState`.
     pub table_name: String,
+
+    /// How many versions of each state the backend keeps in its history, including the current
+    /// version. Defaults to `DEFAULT_HISTORY_RETENTION`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history_retention: Option<usize>,
+
+    /// Settings of the filesystem backend.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filesystem: Option<FilesystemSettings>,
+
+    /// Settings of the SQLite backend.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sqlite: Option<SqliteSettings>,
+
+    /// Settings of the DynamoDB backend.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dynamodb: Option<DynamoDbSettings>,
+
+    /// Settings of the Cosmos DB backend.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cosmosdb: Option<CosmosDbSettings>,
+
+    /// Settings of the PostgreSQL backend.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub postgres: Option<PostgresSettings>,
+}
+
+impl AppConfig {
+    /// Creates a configuration for the given backend without endpoint or backend settings.
+    pub fn new(database_type: DatabaseType, table_name: impl Into<String>) -> Self {
+        Self {
+            database_type,
+            database_endpoint: None,
+            table_name: table_name.into(),
+            history_retention: None,
+            filesystem: None,
+            sqlite: None,
+            dynamodb: None,
+            cosmosdb: None,
+            postgres: None,
+        }
+    }
+
+    /// Sets the database endpoint.
+    pub fn with_database_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.database_endpoint = Some(endpoint.into());
+        self
+    }
+
+    /// Returns the configured history retention, or `DEFAULT_HISTORY_RETENTION`.
+    pub fn history_retention(&self) -> usize {
+        self.history_retention
+            .unwrap_or(crate::state_manager::DEFAULT_HISTORY_RETENTION)
+    }
+
+    /// Checks that the configuration holds everything the selected backend needs.
+    ///
+    /// # Errors
+    /// Returns `CoreError::MissingConfiguration` if `table_name` is empty or a value required by
+    /// the selected backend is missing, and `CoreError::InvalidConfiguration` if
+    /// `history_retention` or a value of the selected backend is out of range.
+    pub fn validate(&self) -> Result<()> {
+        if self.table_name.is_empty() {
+            return Err(CoreError::MissingConfiguration(
+                "table_name cannot be empty".to_string(),
+            ));
+        }
+        if self.history_retention == Some(0) {
+            return Err(CoreError::InvalidConfiguration(
+                "historyRetention must be at least 1".to_string(),
+            ));
+        }
+
+        match self.database_type {
+            // Configurations from before `database_type` have no filesystem section; their callers
+            // construct `FilesystemBackend` with a path of their own.
+            DatabaseType::Filesystem | DatabaseType::Memory | DatabaseType::Dynamodb => {}
+            DatabaseType::Sqlite => {
+                if self.sqlite.is_none() {
+                    return Err(missing_setting(self.database_type, "sqlite.path"));
+                }
+            }
+            DatabaseType::Cosmosdb => {
+                if self.database_endpoint.is_none() {
+                    return Err(missing_setting(self.database_type, "databaseEndpoint"));
+                }
+            }
+            DatabaseType::Postgres => {
+                if self.database_endpoint.is_none() {
+                    return Err(missing_setting(self.database_type, "databaseEndpoint"));
+                }
+                if self
+                    .postgres
+                    .as_ref()
+                    .and_then(|settings| settings.pool_size)
+                    == Some(0)
+                {
+                    return Err(CoreError::InvalidConfiguration(
+                        "postgres.poolSize must be at least 1".to_string(),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn missing_setting(database_type: DatabaseType, setting: &str) -> CoreError {
+    CoreError::MissingConfiguration(format!(
+        "{} (required for databaseType {})",
+        setting, database_type
+    ))
+}
+
+/// The kinds of `StatePersistence` backends that can be selected in `AppConfig`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum DatabaseType {
+    /// One JSON file per state in a local directory (`FilesystemBackend`). The default.
+    #[default]
+    Filesystem,
+    /// An embedded SQLite database file.
+    Sqlite,
+    /// A process-local store that is lost on exit (`InMemoryBackend`), for tests and dry runs.
+    Memory,
+    /// An Amazon DynamoDB table.
+    Dynamodb,
+    /// An Azure Cosmos DB SQL API container.
+    Cosmosdb,
+    /// A PostgreSQL table.
+    Postgres,
+}
+
+impl fmt::Display for DatabaseType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DatabaseType::Filesystem => "filesystem",
+            DatabaseType::Sqlite => "sqlite",
+            DatabaseType::Memory => "memory",
+            DatabaseType::Dynamodb => "dynamodb",
+            DatabaseType::Cosmosdb => "cosmosdb",
+            DatabaseType::Postgres => "postgres",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The `filesystem` section of `AppConfig`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FilesystemSettings {
+    /// The directory holding the state files. It is created if it does not exist.
+    pub path: PathBuf,
+}
+
+/// The `sqlite` section of `AppConfig`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SqliteSettings {
+    /// The database file. It is created if it does not exist.
+    pub path: PathBuf,
+}
+
+/// The `dynamodb` section of `AppConfig`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DynamoDbSettings {
+    /// The AWS region of the table. Defaults to the region configured in the environment.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+}
+
+/// The `cosmosdb` section of `AppConfig`.
+///
+/// The master key is never part of the configuration file; it is read from the environment
+/// variable named by `master_key_env`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CosmosDbSettings {
+    /// The database holding the container. Defaults to `TemplateTeleporter`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub database: Option<String>,
+
+    /// The environment variable holding the account's master key. Defaults to `COSMOSDB_KEY`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub master_key_env: Option<String>,
+}
+
+/// The `postgres` section of `AppConfig`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PostgresSettings {
+    /// The maximum number of pooled connections.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pool_size: Option<usize>,
+}
+
+/// The result of synchronising a single template into a single target repository.
+///
+/// A `TemplateUpdater` produces one outcome per target repository it visits, so callers can
+/// report on partial failures without the whole update being aborted.
+#[derive(Debug, Clone)]
+pub struct RepoUpdateOutcome {
+    /// The target repository the template was synchronised into.
+    pub repo: RepoInfo,
+
+    /// The path of the template within the target repository.
+    pub template_path: TemplatePath,
+
+    /// What happened to the template in the target repository.
+    pub status: RepoUpdateStatus,
+}
+
+/// Classification of a template file in a target repository relative to the master template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetFileStatus {
+    /// The target file already matches the master template.
+    UpToDate,
+
+    /// The target file is missing or still matches the version last deployed by this tool,
+    /// so it can be replaced with the master template.
+    SafeToUpdate,
+
+    /// The target file differs from both the master template and the version last deployed
+    /// by this tool, so it must not be overwritten.
+    ManuallyModified,
+}
+
+/// Describes what happened when synchronising a template into a target repository.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RepoUpdateStatus {
+    /// The target repository already contains the current master version of the template.
+    UpToDate,
+
+    /// The template changes were pushed to the target repository for review.
+    Updated {
+        /// The URL of the pull request containing the template changes.
+        pr_url: String,
+
+        /// The number of the pull request containing the template changes, or `None` if the
+        /// platform does not number its pull requests.
+        pr_number: Option<u64>,
+    },
+
+    /// The template in the target repository differs from both the master template and the
+    /// version last deployed by this tool, so it was changed by hand and has been left alone.
+    ManuallyModified,
+
+    /// The template could not be synchronised, e.g. because a platform call failed.
+    Failed(String),
 }
@@ -0,0 +1,81 @@
+//! Tests for the serialization of the core data types.
+
+use super::*;
+
+fn state() -> TemplateState {
+    TemplateState {
+        repo: "org/service".to_string(),
+        template_path: ".github/PULL_REQUEST_TEMPLATE.md".to_string(),
+        source_repository: "org/template-master".to_string(),
+        master_checksum: "master".to_string(),
+        deployed_checksum: Some("deployed".to_string()),
+        last_updated_utc: "2025-03-02T09:20:00Z".parse().unwrap(),
+        version: 3,
+    }
+}
+
+#[test]
+fn test_template_state_round_trips() {
+    let json = serde_json::to_string(&state()).unwrap();
+
+    assert!(json.contains(r#""templatePath":".github/PULL_REQUEST_TEMPLATE.md""#));
+    assert!(json.contains(r#""masterChecksum":"master""#));
+    assert_eq!(
+        serde_json::from_str::<TemplateState>(&json).unwrap(),
+        state()
+    );
+}
+
+#[test]
+fn test_template_state_reads_legacy_format() {
+    // A state as written before states were tracked per repository.
+    let json = r#"{
+        "templateId": ".github/PULL_REQUEST_TEMPLATE.md",
+        "sourceRepository": "org/template-master",
+        "currentChecksum": "master",
+        "lastUpdatedUtc": "2025-03-02T09:20:00Z"
+    }"#;
+
+    let state: TemplateState = serde_json::from_str(json).unwrap();
+
+    assert_eq!(
+        state,
+        TemplateState {
+            repo: "org/template-master".to_string(),
+            deployed_checksum: None,
+            version: 0,
+            ..self::state()
+        }
+    );
+}
+
+#[test]
+fn test_template_state_splits_legacy_template_id_with_repo() {
+    let json = r#"{
+        "templateId": "org/service:.github/PULL_REQUEST_TEMPLATE.md",
+        "sourceRepository": "org/template-master",
+        "currentChecksum": "master",
+        "lastUpdatedUtc": "2025-03-02T09:20:00Z"
+    }"#;
+
+    let state: TemplateState = serde_json::from_str(json).unwrap();
+
+    assert_eq!(
+        state.key(),
+        StateKey::new("org/service", ".github/PULL_REQUEST_TEMPLATE.md")
+    );
+}
+
+#[test]
+fn test_template_state_requires_a_key() {
+    let json = r#"{
+        "repo": "org/service",
+        "sourceRepository": "org/template-master",
+        "masterChecksum": "master",
+        "lastUpdatedUtc": "2025-03-02T09:20:00Z"
+    }"#;
+
+    let error = serde_json::from_str::<TemplateState>(json).unwrap_err();
+
+    assert!(error.to_string().contains("templatePath"), "{}", error);
+}
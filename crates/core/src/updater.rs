@@ -2,11 +2,17 @@
 //! template synchronization workflow.
 
 use crate::state_manager::StateManager;
-use crate::types::{Result, TemplateState};
+use crate::types::{
+    CoreError, RepoUpdateOutcome, RepoUpdateStatus, Result, StateKey, TargetFileStatus,
+    TemplateState,
+};
 use crate::utils::calculate_checksum;
 use chrono::Utc;
 use std::fmt; // Import fmt for custom Debug
 use std::sync::Arc;
+use template_teleporter_developer_platforms::{
+    DeveloperPlatform, RepoInfo, TemplateCategory, TemplateChange, TemplatePath,
+};
 
 #[cfg(test)]
 #[path = "updater_tests.rs"]
@@ -15,16 +21,27 @@ mod tests;
 /// Handles the core logic for processing template updates.
 ///
 /// This struct uses a `StateManager` (configured with a specific `StatePersistence` backend)
-/// to check the current state of a template, compares checksums, and updates the state
-/// if the template content has changed. It is designed to be agnostic of the specific
-/// platform (like GitHub) and the specific database backend. Platform interaction logic
-/// (e.g., creating Pull Requests) will be handled separately, potentially by injecting
-/// platform-specific clients in the future.
+/// to track the state of a template in each target repository, compares the master, deployed
+/// and target checksums, and pushes changed templates to every target repository of the
+/// template's category through the configured `DeveloperPlatform` implementations. Templates
+/// that were modified by hand in a target repository are never overwritten. It is agnostic of
+/// the specific platform (like GitHub) and the specific database backend.
 pub struct TemplateUpdater {
     /// Shared access to the state manager, which handles persistence.
     state_manager: Arc<StateManager>,
-    // TODO: Inject platform clients later
-    // platform_clients: Vec<Box<dyn PlatformUpdater>>,
+
+    /// The developer platforms hosting the target repositories. Each platform is asked for
+    /// the repositories it hosts for a given category.
+    platforms: Vec<Arc<dyn DeveloperPlatform>>,
+}
+
+/// The new version of a template, as pushed to every target repository.
+#[derive(Clone, Copy)]
+struct PushedTemplate<'a> {
+    path: &'a TemplatePath,
+    source_repository: &'a str,
+    checksum: &'a str,
+    data: &'a [u8],
 }
 
 // Manual Debug implementation because StateManager is not Debug (due to Box<dyn Trait>)
@@ -32,7 +49,7 @@ impl fmt::Debug for TemplateUpdater {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("TemplateUpdater")
             .field("state_manager", &"Arc<StateManager>") // Don't print the actual StateManager
-            // Add other fields here if they are added later
+            .field("platforms", &self.platforms.len())
             .finish()
     }
 }
@@ -45,103 +62,273 @@ impl TemplateUpdater {
     ///   persistence backend implementing `StatePersistence`. The `Arc` allows the
     ///   `StateManager` to be shared if the `TemplateUpdater` needs to be cloned or shared
     ///   across threads.
+    /// * `platforms` - The developer platforms hosting the target repositories.
     ///
     /// # Returns
     /// A new `TemplateUpdater` instance.
-    pub fn new(state_manager: Arc<StateManager>) -> Self {
-        // TODO: Initialize platform clients based on config later if needed
-        Self { state_manager }
+    pub fn new(
+        state_manager: Arc<StateManager>,
+        platforms: Vec<Arc<dyn DeveloperPlatform>>,
+    ) -> Self {
+        Self {
+            state_manager,
+            platforms,
+        }
     }
 
     /// Processes a potential template update based on new content.
     ///
     /// This is the core workflow method. It performs the following steps:
     /// 1. Calculates the checksum of the `new_template_data`.
-    /// 2. Retrieves the current `TemplateState` for the given `template_id` using the `StateManager`.
-    /// 3. Compares the new checksum with the `current_checksum` in the retrieved state (if any).
-    /// 4. If the checksums differ or no previous state exists (`needs_update` is true):
-    ///    a. **(Placeholder)** In a full implementation, this is where interaction with platform clients
-    ///    would occur to fetch target repository content, check for manual overrides, and potentially
-    ///    create a Pull Request. This logic is currently omitted.
-    ///    b. Creates a new `TemplateState` struct with the updated information (new checksum, current timestamp).
-    ///    c. Saves the new state back to the persistence layer via the `StateManager`.
-    /// 5. If the checksums match, it logs that no update is needed.
+    /// 2. Asks every platform for the target repositories of `category`.
+    /// 3. Retrieves the `TemplateState` of the template in all target repositories with a single
+    ///    `StateManager::get_states` call.
+    /// 4. For each target repository:
+    ///    a. Skips the repository if the new master version was already deployed to it.
+    ///    b. Fetches the template from the target repository and classifies it with
+    ///    [`classify_target_file`] as up-to-date, safe-to-update, or manually-modified, taking the
+    ///    earlier versions from the state history into account.
+    ///    c. Applies a `TemplateChange` to repositories that are safe to update. Manually modified
+    ///    templates are never overwritten; they are reported instead.
+    ///    d. Saves a new `TemplateState` recording the new master checksum and, if the target now
+    ///    holds (or will hold, once the pull request is merged) the master version, the new
+    ///    deployed checksum. If the platform update failed the state is left untouched so that
+    ///    the next run retries. The state is only saved if nobody else changed it since step 3;
+    ///    otherwise the repository is reported as failed. States are therefore saved one by one
+    ///    with `StateManager::update_state_if`, which detects such changes.
     ///
     /// # Arguments
-    /// * `template_id` - The unique identifier for the template being processed (e.g., its path).
+    /// * `category` - The template category the template belongs to.
+    /// * `template_path` - The path of the template, relative to the category directory. This
+    ///   is also the path of the template in the target repositories.
     /// * `source_repository` - The identifier of the source repository (used for recording in `TemplateState`).
     /// * `new_template_data` - The raw byte content of the new or current template version from the source.
     ///
     /// # Returns
-    /// An empty `Result` on success, or a `CoreError` if any step (checksum calculation, state retrieval, state update) fails.
+    /// A `Result` containing one `RepoUpdateOutcome` per visited target repository, or a
+    /// `CoreError` if checksum calculation, repository discovery or reading the states fails.
+    /// Failures of platform calls and state operations for individual repositories are reported
+    /// as `RepoUpdateStatus::Failed` outcomes rather than errors, so the remaining repositories
+    /// are still visited.
     ///
     /// # Errors
-    /// Can return `CoreError::ChecksumFailure`, `CoreError::DatabaseError`.
+    /// Can return `CoreError::ChecksumFailure`, `CoreError::DatabaseError`, `CoreError::PlatformError`.
     pub async fn process_update(
         &self,
-        template_id: &str,
+        category: &TemplateCategory,
+        template_path: &TemplatePath,
         source_repository: &str,
         new_template_data: &[u8],
-    ) -> Result<()> {
-        // Using tracing::info! or similar would be better than println! for real applications
-        println!("Processing update for template: {}", template_id);
-
+    ) -> Result<Vec<RepoUpdateOutcome>> {
         // 1. Calculate checksum
         let new_checksum = calculate_checksum(new_template_data)?;
-        println!("  New checksum: {}", new_checksum);
 
-        // 2. Get current state
-        let current_state_opt = self.state_manager.get_state(template_id).await?;
+        // 2. Find every target repository of the category
+        let mut targets = Vec::new();
+        for platform in &self.platforms {
+            let repos = platform
+                .list_repos_by_category(category)
+                .await
+                .map_err(|e| CoreError::PlatformError(e.to_string()))?;
+            targets.extend(repos.into_iter().map(|repo| (platform, repo)));
+        }
+
+        // 3. Get the current states of all target repositories at once
+        let keys: Vec<StateKey> = targets
+            .iter()
+            .map(|(_, repo)| state_key(repo, template_path))
+            .collect();
+        let current_states = self.state_manager.get_states(&keys).await?;
+
+        // 4. Visit every target repository
+        let template = PushedTemplate {
+            path: template_path,
+            source_repository,
+            checksum: &new_checksum,
+            data: new_template_data,
+        };
+        let mut outcomes = Vec::with_capacity(targets.len());
+        for ((platform, repo), current_state) in targets.into_iter().zip(current_states) {
+            let status = self
+                .sync_repo(platform.as_ref(), &repo, current_state, template)
+                .await
+                .unwrap_or_else(|e| RepoUpdateStatus::Failed(e.to_string()));
+            outcomes.push(RepoUpdateOutcome {
+                repo,
+                template_path: template_path.clone(),
+                status,
+            });
+        }
+
+        Ok(outcomes)
+    }
+
+    /// Synchronises a single template into a single target repository, given the state of the
+    /// template in the repository as read before.
+    ///
+    /// Platform failures and failures to save the new state are captured in the returned status.
+    /// Other failures are returned as errors, which end the visit before the repository is
+    /// changed, because continuing without state would risk overwriting manual changes.
+    async fn sync_repo(
+        &self,
+        platform: &dyn DeveloperPlatform,
+        repo: &RepoInfo,
+        current_state_opt: Option<TemplateState>,
+        template: PushedTemplate<'_>,
+    ) -> Result<RepoUpdateStatus> {
+        let PushedTemplate {
+            path: template_path,
+            source_repository,
+            checksum: new_checksum,
+            data: new_template_data,
+        } = template;
+        let key = state_key(repo, template_path);
+        let repo_name = key.repo.clone();
+
+        // 4a. Skip the repository if it already has the current version
+        let deployed_checksum = current_state_opt
+            .as_ref()
+            .and_then(|state| state.deployed_checksum.clone());
+        if let Some(current_state) = &current_state_opt {
+            // The pull request for this master version may not have been merged yet, in which case
+            // the target still holds the old content. Re-checking it would misreport a manual change.
+            if current_state.master_checksum == new_checksum
+                && deployed_checksum.as_deref() == Some(new_checksum)
+            {
+                return Ok(RepoUpdateStatus::UpToDate);
+            }
+        }
+
+        // 4b. Classify the template in the target repository
+        let target_checksum = match platform.get_repo_file(repo, template_path).await {
+            Ok(Some(content)) => Some(calculate_checksum(&content)?),
+            Ok(None) => None,
+            Err(e) => return Ok(RepoUpdateStatus::Failed(e.to_string())),
+        };
+        // Earlier versions of the template the target may still hold, from its history.
+        let old_checksums = if current_state_opt.is_some() {
+            self.state_manager.old_checksums(&key).await?
+        } else {
+            Vec::new()
+        };
+        let classification = classify_target_file(
+            target_checksum.as_deref(),
+            new_checksum,
+            deployed_checksum.as_deref(),
+            &old_checksums,
+        );
 
-        // 3. Compare checksums
-        let needs_update = match &current_state_opt {
-            Some(current_state) => {
-                println!("  Current checksum: {}", current_state.current_checksum);
-                current_state.current_checksum != new_checksum
+        // 4c. Apply the change where it is safe to do so
+        let (status, new_deployed_checksum) = match classification {
+            TargetFileStatus::UpToDate => {
+                (RepoUpdateStatus::UpToDate, Some(new_checksum.to_string()))
             }
-            None => {
-                println!("  No current state found.");
-                true // New template
+            TargetFileStatus::ManuallyModified => {
+                (RepoUpdateStatus::ManuallyModified, deployed_checksum)
+            }
+            TargetFileStatus::SafeToUpdate => {
+                let change = TemplateChange::new(
+                    template_path.clone(),
+                    old_checksums,
+                    new_checksum.to_string(),
+                    new_template_data.to_vec(),
+                );
+
+                match platform
+                    .update_repo(repo, std::slice::from_ref(&change))
+                    .await
+                {
+                    Ok(result) => (
+                        RepoUpdateStatus::Updated {
+                            pr_url: result.pr_url().to_string(),
+                            pr_number: result.pr_number(),
+                        },
+                        Some(new_checksum.to_string()),
+                    ),
+                    Err(e) => return Ok(RepoUpdateStatus::Failed(e.to_string())),
+                }
             }
         };
 
-        if needs_update {
-            println!("  Checksum mismatch or new template. Update required.");
-
-            // 4. Platform Interaction (Placeholder)
-            // TODO: Implement platform interaction logic here or in a separate method.
-            // This would involve:
-            // - Fetching target repo content via a platform client.
-            // - Comparing target content checksum with current_state.current_checksum to detect manual changes.
-            // - If no manual changes, creating a PR via the platform client.
-            // - Handling potential CoreError::PlatformError from the client.
-            // self.create_update_pr(template_id, new_template_data).await?;
-
-            // 5. Create new state data
-            let new_state = TemplateState {
-                template_id: template_id.to_string(),
-                source_repository: source_repository.to_string(),
-                current_checksum: new_checksum,
-                last_updated_utc: Utc::now(),
-            };
-
-            // 6. Save new state
-            self.state_manager.update_state(&new_state).await?;
-            println!("  State successfully updated for template: {}", template_id);
-        } else {
-            println!(
-                "  No state change detected for template: {}. No update needed.",
-                template_id
-            );
+        // 4d. Save new state, unless another run changed it since it was read above
+        let new_state = TemplateState {
+            repo: repo_name,
+            template_path: template_path.clone(),
+            source_repository: source_repository.to_string(),
+            master_checksum: new_checksum.to_string(),
+            deployed_checksum: new_deployed_checksum,
+            last_updated_utc: Utc::now(),
+            version: 0,
+        };
+        let expected_version = current_state_opt.map(|state| state.version);
+        match self
+            .state_manager
+            .update_state_if(&new_state, expected_version)
+            .await
+        {
+            Ok(_) => Ok(status),
+            // After a conflict the concurrent run has recorded its own outcome; either way the
+            // next run re-checks the target.
+            Err(e) => Ok(RepoUpdateStatus::Failed(match status {
+                RepoUpdateStatus::Updated { pr_url, .. } => {
+                    format!("{} (pull request {} was created)", e, pr_url)
+                }
+                _ => e.to_string(),
+            })),
         }
-
-        Ok(())
     }
+}
+
+/// Returns the key of the state of a template in a target repository.
+fn state_key(repo: &RepoInfo, template_path: &TemplatePath) -> StateKey {
+    StateKey::new(
+        format!("{}/{}", repo.org(), repo.name()),
+        template_path.clone(),
+    )
+}
 
-    // Placeholder for future platform interaction logic
-    // async fn create_update_pr(&self, template_id: &str, data: &[u8]) -> Result<()> {
-    //     println!("  (Placeholder) Would create PR for template: {}", template_id);
-    //     // Logic to interact with platform clients would go here
-    //     Ok(())
-    // }
+/// Classifies a template file in a target repository relative to the master template.
+///
+/// Implements the three-way check from the specification: a target file that differs from both
+/// the master template and the version last deployed by this tool has been modified by hand.
+/// A target file for which no deployment has been recorded, and which differs from the master
+/// template, is treated as manually modified as well, because this tool did not write it.
+///
+/// The deployed checksum is recorded as soon as the pull request deploying it is opened, so a
+/// target still holding an earlier version of the template, e.g. because that pull request has
+/// not been merged yet when the master template changes again, is safe to update as well.
+///
+/// # Arguments
+/// * `target_checksum` - The checksum of the file in the target repository, or `None` if the
+///   file does not exist.
+/// * `master_checksum` - The checksum of the template in the master repository.
+/// * `deployed_checksum` - The checksum of the content last deployed by this tool, if any.
+/// * `old_checksums` - The checksums of earlier versions of the template, from the state history.
+///
+/// # Examples
+/// ```
+/// # use template_teleporter_core::{classify_target_file, TargetFileStatus};
+/// assert_eq!(classify_target_file(Some("a"), "a", None, &[]), TargetFileStatus::UpToDate);
+/// assert_eq!(classify_target_file(Some("a"), "b", Some("a"), &[]), TargetFileStatus::SafeToUpdate);
+/// assert_eq!(
+///     classify_target_file(Some("c"), "b", Some("a"), &["c".to_string()]),
+///     TargetFileStatus::SafeToUpdate
+/// );
+/// assert_eq!(classify_target_file(Some("c"), "b", Some("a"), &[]), TargetFileStatus::ManuallyModified);
+/// ```
+pub fn classify_target_file(
+    target_checksum: Option<&str>,
+    master_checksum: &str,
+    deployed_checksum: Option<&str>,
+    old_checksums: &[String],
+) -> TargetFileStatus {
+    match target_checksum {
+        None => TargetFileStatus::SafeToUpdate,
+        Some(target) if target == master_checksum => TargetFileStatus::UpToDate,
+        Some(target) if Some(target) == deployed_checksum => TargetFileStatus::SafeToUpdate,
+        Some(target) if old_checksums.iter().any(|old| old == target) => {
+            TargetFileStatus::SafeToUpdate
+        }
+        Some(_) => TargetFileStatus::ManuallyModified,
+    }
 }
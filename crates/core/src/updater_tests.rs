@@ -2,11 +2,14 @@
 
 use super::*;
 use crate::state_manager::{StateManager, StatePersistence};
-use crate::types::{CoreError, Result, TemplateState};
+use crate::types::{
+    CoreError, Result, StateKey, StatePage, StateQuery, TargetFileStatus, TemplateState,
+};
 use async_trait::async_trait;
 use chrono::Utc;
 use mockall::mock;
 use std::sync::Arc;
+use template_teleporter_developer_platforms::{PlatformError, TemplateMetadata, UpdateResult};
 
 // Mock StatePersistence using mockall
 mock! {
@@ -14,146 +17,598 @@ mock! {
 
     #[async_trait]
     impl StatePersistence for StatePersistence {
-        async fn get_state(&self, template_id: &str) -> Result<Option<TemplateState>>;
+        async fn get_state(&self, key: &StateKey) -> Result<Option<TemplateState>>;
         async fn update_state(&self, state: &TemplateState) -> Result<()>;
+        async fn update_state_if(
+            &self,
+            state: &TemplateState,
+            expected_version: Option<u64>,
+        ) -> Result<u64>;
+        async fn list_states(&self, query: &StateQuery) -> Result<StatePage>;
+        async fn delete_state(&self, key: &StateKey) -> Result<bool>;
+        async fn get_state_history(&self, key: &StateKey) -> Result<Vec<TemplateState>>;
     }
 }
 
+// Mock DeveloperPlatform using mockall
+mock! {
+    pub Platform {}
+
+    #[async_trait]
+    impl DeveloperPlatform for Platform {
+        async fn list_categories(&self) -> std::result::Result<Vec<TemplateCategory>, PlatformError>;
+        async fn get_template(
+            &self,
+            category: &TemplateCategory,
+            path: &TemplatePath,
+        ) -> std::result::Result<Vec<u8>, PlatformError>;
+        async fn list_templates(
+            &self,
+            category: &TemplateCategory,
+        ) -> std::result::Result<Vec<TemplateMetadata>, PlatformError>;
+        async fn list_repos_by_category(
+            &self,
+            category: &TemplateCategory,
+        ) -> std::result::Result<Vec<RepoInfo>, PlatformError>;
+        async fn get_repo_file(
+            &self,
+            repo: &RepoInfo,
+            path: &TemplatePath,
+        ) -> std::result::Result<Option<Vec<u8>>, PlatformError>;
+        async fn get_updated_templates(
+            &self,
+            category: &TemplateCategory,
+            since_commit: &str,
+        ) -> std::result::Result<Vec<TemplateChange>, PlatformError>;
+        async fn update_repo(
+            &self,
+            repo: &RepoInfo,
+            changes: &[TemplateChange],
+        ) -> std::result::Result<UpdateResult, PlatformError>;
+    }
+}
+
+fn category() -> TemplateCategory {
+    TemplateCategory::new("saas_rust".to_string())
+}
+
+fn repo(name: &str) -> RepoInfo {
+    RepoInfo::new("org".to_string(), name.to_string(), "main".to_string())
+}
+
+fn checksum(data: &[u8]) -> String {
+    crate::utils::calculate_checksum(data).unwrap()
+}
+
+fn state(template_path: &str, master: &[u8], deployed: Option<&[u8]>) -> TemplateState {
+    TemplateState {
+        repo: "org/service".to_string(),
+        template_path: template_path.to_string(),
+        source_repository: "master".to_string(),
+        master_checksum: checksum(master),
+        deployed_checksum: deployed.map(checksum),
+        last_updated_utc: Utc::now(),
+        version: 4,
+    }
+}
+
+/// Creates a platform that hosts the `org/service` repository, containing `target` at any path.
+fn service_platform(target: Option<&'static [u8]>) -> MockPlatform {
+    let mut platform = MockPlatform::new();
+    platform
+        .expect_list_repos_by_category()
+        .returning(|_| Ok(vec![repo("service")]));
+    platform
+        .expect_get_repo_file()
+        .returning(move |_, _| Ok(target.map(|t| t.to_vec())));
+    platform
+}
+
+fn pr_result(number: u64) -> UpdateResult {
+    UpdateResult::new(
+        format!("https://example.com/org/service/pull/{}", number),
+        number,
+        Vec::new(),
+    )
+}
+
+fn updater(backend: MockStatePersistence, platforms: Vec<MockPlatform>) -> TemplateUpdater {
+    let state_manager = StateManager::new(Box::new(backend));
+    TemplateUpdater::new(
+        Arc::new(state_manager),
+        platforms
+            .into_iter()
+            .map(|p| Arc::new(p) as Arc<dyn DeveloperPlatform>)
+            .collect(),
+    )
+}
+
 #[tokio::test]
 async fn test_process_update_new_template() {
-    let template_id = "template1";
-    let source_repository = "repo1";
+    let template_path = "template1";
     let new_template_data = b"template content";
-    let checksum = crate::utils::calculate_checksum(new_template_data).unwrap();
+    let new_checksum = checksum(new_template_data);
 
     let mut mock_backend = MockStatePersistence::new();
     // get_state returns Ok(None) to simulate new template
     mock_backend
         .expect_get_state()
-        .withf(move |id| id == template_id)
+        .withf(move |key| key == &StateKey::new("org/service", template_path))
         .times(1)
         .returning(|_| Ok(None));
 
     // update_state expects to be called with the new TemplateState
+    let expected_checksum = new_checksum.clone();
     mock_backend
-        .expect_update_state()
-        .withf(move |state| {
-            state.template_id == template_id
-                && state.source_repository == source_repository
-                && state.current_checksum == checksum
+        .expect_update_state_if()
+        .withf(move |state, _| {
+            state.repo == "org/service"
+                && state.template_path == template_path
+                && state.source_repository == "repo1"
+                && state.master_checksum == expected_checksum
+                && state.deployed_checksum.as_ref() == Some(&expected_checksum)
         })
         .times(1)
-        .returning(|_| Ok(()));
+        .returning(|_, _| Ok(1));
 
-    let state_manager = StateManager::new(Box::new(mock_backend));
-    let updater = TemplateUpdater::new(Arc::new(state_manager));
-    let result = updater
-        .process_update(template_id, source_repository, new_template_data)
-        .await;
+    // The template does not exist in the target yet, so it is safe to create it
+    let mut platform = service_platform(None);
+    platform
+        .expect_update_repo()
+        .withf(move |repo, changes| {
+            repo.name() == "service"
+                && changes.len() == 1
+                && changes[0].path() == template_path
+                && changes[0].new_checksum() == new_checksum
+                && changes[0].old_checksum_count() == 0
+                && changes[0].content() == &b"template content".to_vec()
+        })
+        .times(1)
+        .returning(|_, _| Ok(pr_result(7)));
 
-    assert!(result.is_ok());
+    let updater = updater(mock_backend, vec![platform]);
+    let outcomes = updater
+        .process_update(
+            &category(),
+            &template_path.to_string(),
+            "repo1",
+            new_template_data,
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(outcomes.len(), 1);
+    assert_eq!(outcomes[0].repo.name(), "service");
+    assert_eq!(outcomes[0].template_path, template_path);
+    assert_eq!(
+        outcomes[0].status,
+        RepoUpdateStatus::Updated {
+            pr_url: "https://example.com/org/service/pull/7".to_string(),
+            pr_number: Some(7),
+        }
+    );
 }
 
 #[tokio::test]
-async fn test_process_update_unchanged_template() {
-    let template_id = "template2";
-    let source_repository = "repo2";
+async fn test_process_update_already_deployed_template() {
+    let template_path = "template2";
     let new_template_data = b"unchanged content";
-    let checksum = crate::utils::calculate_checksum(new_template_data).unwrap();
 
-    let state = TemplateState {
-        template_id: template_id.to_string(),
-        source_repository: source_repository.to_string(),
-        current_checksum: checksum.clone(),
-        last_updated_utc: Utc::now(),
-    };
+    let current = state(template_path, new_template_data, Some(new_template_data));
 
     let mut mock_backend = MockStatePersistence::new();
-    // get_state returns Ok(Some(state)) with matching checksum
+    // get_state returns a state which already records the deployment of this version
     mock_backend
         .expect_get_state()
-        .withf(move |id| id == template_id)
         .times(1)
-        .returning(move |_| Ok(Some(state.clone())));
+        .returning(move |_| Ok(Some(current.clone())));
 
     // update_state should NOT be called
-    mock_backend.expect_update_state().times(0);
+    mock_backend.expect_update_state_if().times(0);
 
-    let state_manager = StateManager::new(Box::new(mock_backend));
-    let updater = TemplateUpdater::new(Arc::new(state_manager));
-    let result = updater
-        .process_update(template_id, source_repository, new_template_data)
-        .await;
+    // The target should not be fetched, it may still hold an unmerged pull request
+    let mut platform = MockPlatform::new();
+    platform
+        .expect_list_repos_by_category()
+        .returning(|_| Ok(vec![repo("service")]));
+    platform.expect_get_repo_file().times(0);
+    platform.expect_update_repo().times(0);
+
+    let updater = updater(mock_backend, vec![platform]);
+    let outcomes = updater
+        .process_update(
+            &category(),
+            &template_path.to_string(),
+            "repo2",
+            new_template_data,
+        )
+        .await
+        .unwrap();
 
-    assert!(result.is_ok());
+    assert_eq!(outcomes.len(), 1);
+    assert_eq!(outcomes[0].status, RepoUpdateStatus::UpToDate);
 }
 
 #[tokio::test]
 async fn test_process_update_changed_template() {
-    let template_id = "template3";
-    let source_repository = "repo3";
+    let template_path = "template3";
     let old_template_data = b"old content";
     let new_template_data = b"new content";
-    let old_checksum = crate::utils::calculate_checksum(old_template_data).unwrap();
-    let new_checksum = crate::utils::calculate_checksum(new_template_data).unwrap();
+    let old_checksum = checksum(old_template_data);
+    let older_checksum = checksum(b"older content");
+    let new_checksum = checksum(new_template_data);
 
-    let state = TemplateState {
-        template_id: template_id.to_string(),
-        source_repository: source_repository.to_string(),
-        current_checksum: old_checksum.clone(),
-        last_updated_utc: Utc::now(),
-    };
+    let current = state(template_path, old_template_data, Some(old_template_data));
+    let mut older = state(template_path, b"older content", Some(b"older content"));
+    older.version = current.version - 1;
 
     let mut mock_backend = MockStatePersistence::new();
-    // get_state returns Ok(Some(state)) with non-matching checksum
+    let history = vec![current.clone(), older];
     mock_backend
         .expect_get_state()
-        .withf(move |id| id == template_id)
         .times(1)
-        .returning(move |_| Ok(Some(state.clone())));
+        .returning(move |_| Ok(Some(current.clone())));
+    mock_backend
+        .expect_get_state_history()
+        .times(1)
+        .returning(move |_| Ok(history.clone()));
 
     // update_state expects to be called with the new TemplateState
+    let expected_checksum = new_checksum.clone();
+    mock_backend
+        .expect_update_state_if()
+        .withf(move |state, expected_version| {
+            state.master_checksum == expected_checksum
+                && state.deployed_checksum.as_ref() == Some(&expected_checksum)
+                && expected_version == &Some(4)
+        })
+        .times(1)
+        .returning(|_, _| Ok(1));
+
+    // The target still holds the version deployed earlier, so it is safe to update
+    let mut platform = service_platform(Some(b"old content"));
+    platform
+        .expect_update_repo()
+        .withf(move |_, changes| {
+            changes[0].new_checksum() == new_checksum
+                && changes[0].old_checksums().collect::<Vec<_>>()
+                    == vec![&old_checksum, &older_checksum]
+        })
+        .times(1)
+        .returning(|_, _| Ok(pr_result(8)));
+
+    let updater = updater(mock_backend, vec![platform]);
+    let outcomes = updater
+        .process_update(
+            &category(),
+            &template_path.to_string(),
+            "repo3",
+            new_template_data,
+        )
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        outcomes[0].status,
+        RepoUpdateStatus::Updated {
+            pr_number: Some(8),
+            ..
+        }
+    ));
+}
+
+#[tokio::test]
+async fn test_process_update_target_already_up_to_date() {
+    let template_path = "template6";
+    let new_template_data = b"same content";
+    let new_checksum = checksum(new_template_data);
+
+    let mut mock_backend = MockStatePersistence::new();
+    mock_backend.expect_get_state().returning(|_| Ok(None));
+    // The target already matches, so it is recorded as deployed
+    mock_backend
+        .expect_update_state_if()
+        .withf(move |state, _| state.deployed_checksum.as_ref() == Some(&new_checksum))
+        .times(1)
+        .returning(|_, _| Ok(1));
+
+    let mut platform = service_platform(Some(b"same content"));
+    // The target already has the content, so no pull request should be created
+    platform.expect_update_repo().times(0);
+
+    let updater = updater(mock_backend, vec![platform]);
+    let outcomes = updater
+        .process_update(
+            &category(),
+            &template_path.to_string(),
+            "source",
+            new_template_data,
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(outcomes.len(), 1);
+    assert_eq!(outcomes[0].status, RepoUpdateStatus::UpToDate);
+}
+
+#[tokio::test]
+async fn test_process_update_manually_modified_template_is_skipped() {
+    let template_path = "template11";
+    let current = state(template_path, b"v1", Some(b"v1"));
+    let deployed = current.deployed_checksum.clone();
+    let new_checksum = checksum(b"v2");
+
+    let mut mock_backend = MockStatePersistence::new();
+    let history = vec![current.clone()];
+    mock_backend
+        .expect_get_state()
+        .returning(move |_| Ok(Some(current.clone())));
+    mock_backend
+        .expect_get_state_history()
+        .returning(move |_| Ok(history.clone()));
+    // The new master version is recorded, but the deployed version is left as it was
+    mock_backend
+        .expect_update_state_if()
+        .withf(move |state, _| {
+            state.master_checksum == new_checksum && state.deployed_checksum == deployed
+        })
+        .times(1)
+        .returning(|_, _| Ok(1));
+
+    // The target differs from both the old and the new master version
+    let mut platform = service_platform(Some(b"hand edited"));
+    platform.expect_update_repo().times(0);
+
+    let updater = updater(mock_backend, vec![platform]);
+    let outcomes = updater
+        .process_update(&category(), &template_path.to_string(), "source", b"v2")
+        .await
+        .unwrap();
+
+    assert_eq!(outcomes[0].status, RepoUpdateStatus::ManuallyModified);
+}
+
+#[tokio::test]
+async fn test_process_update_while_previous_pull_request_is_open() {
+    let template_path = "template13";
+    let v1_checksum = checksum(b"v1");
+    let v2_checksum = checksum(b"v2");
+    let v3_checksum = checksum(b"v3");
+
+    // The pull request deploying v2 was opened but not merged, so the target still holds v1
+    let current = state(template_path, b"v2", Some(b"v2"));
+    let mut deployed = state(template_path, b"v1", Some(b"v1"));
+    deployed.version = current.version - 1;
+
+    let mut mock_backend = MockStatePersistence::new();
+    let history = vec![current.clone(), deployed];
+    mock_backend
+        .expect_get_state()
+        .returning(move |_| Ok(Some(current.clone())));
+    mock_backend
+        .expect_get_state_history()
+        .returning(move |_| Ok(history.clone()));
+    let expected_checksum = v3_checksum.clone();
+    mock_backend
+        .expect_update_state_if()
+        .withf(move |state, expected_version| {
+            state.master_checksum == expected_checksum
+                && state.deployed_checksum.as_ref() == Some(&expected_checksum)
+                && expected_version == &Some(4)
+        })
+        .times(1)
+        .returning(|_, _| Ok(5));
+
+    let mut platform = service_platform(Some(b"v1"));
+    platform
+        .expect_update_repo()
+        .withf(move |_, changes| {
+            changes[0].new_checksum() == v3_checksum
+                && changes[0].old_checksums().collect::<Vec<_>>()
+                    == vec![&v2_checksum, &v1_checksum]
+        })
+        .times(1)
+        .returning(|_, _| Ok(pr_result(10)));
+
+    let updater = updater(mock_backend, vec![platform]);
+    let outcomes = updater
+        .process_update(&category(), &template_path.to_string(), "source", b"v3")
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        outcomes[0].status,
+        RepoUpdateStatus::Updated {
+            pr_number: Some(10),
+            ..
+        }
+    ));
+}
+
+#[tokio::test]
+async fn test_process_update_untracked_existing_template_is_skipped() {
+    let mut mock_backend = MockStatePersistence::new();
+    mock_backend.expect_get_state().returning(|_| Ok(None));
+    mock_backend
+        .expect_update_state_if()
+        .withf(|state, _| state.deployed_checksum.is_none())
+        .times(1)
+        .returning(|_, _| Ok(1));
+
+    // The target has its own version of a template this tool never deployed
+    let mut platform = service_platform(Some(b"team specific"));
+    platform.expect_update_repo().times(0);
+
+    let updater = updater(mock_backend, vec![platform]);
+    let outcomes = updater
+        .process_update(&category(), &"template12".to_string(), "source", b"master")
+        .await
+        .unwrap();
+
+    assert_eq!(outcomes[0].status, RepoUpdateStatus::ManuallyModified);
+}
+
+#[tokio::test]
+async fn test_process_update_visits_all_platforms() {
+    let template_path = "template7";
+
+    let mut mock_backend = MockStatePersistence::new();
+    mock_backend.expect_get_state().returning(|_| Ok(None));
     mock_backend
-        .expect_update_state()
-        .withf(move |state| {
-            state.template_id == template_id
-                && state.source_repository == source_repository
-                && state.current_checksum == new_checksum
+        .expect_update_state_if()
+        .times(2)
+        .returning(|_, _| Ok(1));
+
+    let platforms = (0..2)
+        .map(|i| {
+            let mut platform = MockPlatform::new();
+            platform
+                .expect_list_repos_by_category()
+                .times(1)
+                .returning(move |_| Ok(vec![repo(&format!("repo-{}", i))]));
+            platform.expect_get_repo_file().returning(|_, _| Ok(None));
+            platform
+                .expect_update_repo()
+                .times(1)
+                .returning(move |_, _| Ok(pr_result(i)));
+            platform
         })
+        .collect();
+
+    let updater = updater(mock_backend, platforms);
+    let outcomes = updater
+        .process_update(&category(), &template_path.to_string(), "source", b"data")
+        .await
+        .unwrap();
+
+    let names: Vec<_> = outcomes.iter().map(|o| o.repo.name().to_string()).collect();
+    assert_eq!(names, vec!["repo-0", "repo-1"]);
+}
+
+#[tokio::test]
+async fn test_process_update_repo_failure_keeps_state() {
+    let template_path = "template8";
+
+    let mut mock_backend = MockStatePersistence::new();
+    mock_backend.expect_get_state().returning(|_| Ok(None));
+    // A failed repository must leave its state untouched so that the next run retries
+    mock_backend
+        .expect_update_state_if()
+        .withf(|state, _| state.repo == "org/working")
         .times(1)
-        .returning(|_| Ok(()));
+        .returning(|_, _| Ok(1));
+
+    let mut platform = MockPlatform::new();
+    platform
+        .expect_list_repos_by_category()
+        .returning(|_| Ok(vec![repo("broken"), repo("working")]));
+    platform.expect_get_repo_file().returning(|_, _| Ok(None));
+    platform.expect_update_repo().returning(|repo, _| {
+        if repo.name() == "broken" {
+            Err(PlatformError::RateLimitExceeded)
+        } else {
+            Ok(pr_result(1))
+        }
+    });
 
-    let state_manager = StateManager::new(Box::new(mock_backend));
-    let updater = TemplateUpdater::new(Arc::new(state_manager));
+    let updater = updater(mock_backend, vec![platform]);
+    let outcomes = updater
+        .process_update(&category(), &template_path.to_string(), "source", b"data")
+        .await
+        .unwrap();
+
+    assert_eq!(outcomes.len(), 2);
+    assert_eq!(
+        outcomes[0].status,
+        RepoUpdateStatus::Failed("API rate limit exceeded".to_string())
+    );
+    assert!(matches!(
+        outcomes[1].status,
+        RepoUpdateStatus::Updated {
+            pr_number: Some(1),
+            ..
+        }
+    ));
+}
+
+#[tokio::test]
+async fn test_process_update_get_repo_file_failure() {
+    let mut mock_backend = MockStatePersistence::new();
+    mock_backend.expect_get_state().returning(|_| Ok(None));
+    mock_backend.expect_update_state_if().times(0);
+
+    let mut platform = MockPlatform::new();
+    platform
+        .expect_list_repos_by_category()
+        .returning(|_| Ok(vec![repo("missing")]));
+    platform.expect_get_repo_file().returning(|repo, _| {
+        Err(PlatformError::RepoNotFound {
+            org: repo.org().to_string(),
+            name: repo.name().to_string(),
+        })
+    });
+    platform.expect_update_repo().times(0);
+
+    let updater = updater(mock_backend, vec![platform]);
+    let outcomes = updater
+        .process_update(&category(), &"template9".to_string(), "source", b"data")
+        .await
+        .unwrap();
+
+    assert_eq!(
+        outcomes[0].status,
+        RepoUpdateStatus::Failed("Repository not found: org/missing".to_string())
+    );
+}
+
+#[tokio::test]
+async fn test_process_update_list_repos_error() {
+    let mut mock_backend = MockStatePersistence::new();
+    mock_backend.expect_get_state().times(0);
+    mock_backend.expect_update_state_if().times(0);
+
+    let mut platform = MockPlatform::new();
+    platform
+        .expect_list_repos_by_category()
+        .returning(|category| Err(PlatformError::CategoryNotFound(category.name().to_string())));
+
+    let updater = updater(mock_backend, vec![platform]);
     let result = updater
-        .process_update(template_id, source_repository, new_template_data)
+        .process_update(&category(), &"template10".to_string(), "source", b"data")
         .await;
 
-    assert!(result.is_ok());
+    match result.err().unwrap() {
+        CoreError::PlatformError(msg) => assert!(msg.contains("saas_rust")),
+        _ => panic!("Expected PlatformError"),
+    }
 }
 
 #[tokio::test]
 async fn test_process_update_get_state_error() {
-    let template_id = "template4";
-    let source_repository = "repo4";
-    let new_template_data = b"irrelevant";
+    let template_path = "template4";
     let error_msg = "simulated get_state error";
 
     let mut mock_backend = MockStatePersistence::new();
     // get_state returns an error
     mock_backend
         .expect_get_state()
-        .withf(move |id| id == template_id)
         .times(1)
         .returning(move |_| Err(CoreError::DatabaseError(error_msg.to_string())));
 
     // update_state should NOT be called
-    mock_backend.expect_update_state().times(0);
+    mock_backend.expect_update_state_if().times(0);
+
+    let mut platform = service_platform(None);
+    platform.expect_update_repo().times(0);
 
-    let state_manager = StateManager::new(Box::new(mock_backend));
-    let updater = TemplateUpdater::new(Arc::new(state_manager));
+    let updater = updater(mock_backend, vec![platform]);
     let result = updater
-        .process_update(template_id, source_repository, new_template_data)
+        .process_update(
+            &category(),
+            &template_path.to_string(),
+            "repo4",
+            b"irrelevant",
+        )
         .await;
 
     assert!(result.is_err());
@@ -165,50 +620,270 @@ async fn test_process_update_get_state_error() {
 
 #[tokio::test]
 async fn test_process_update_update_state_error() {
-    let template_id = "template5";
-    let source_repository = "repo5";
-    let new_template_data = b"template data";
-    let checksum = crate::utils::calculate_checksum(new_template_data).unwrap();
+    let template_path = "template5";
     let error_msg = "simulated update_state error";
 
     let mut mock_backend = MockStatePersistence::new();
     // get_state returns Ok(None) to simulate new template
     mock_backend
         .expect_get_state()
-        .withf(move |id| id == template_id)
         .times(1)
         .returning(|_| Ok(None));
 
     // update_state returns an error
     mock_backend
-        .expect_update_state()
-        .withf(move |state| {
-            state.template_id == template_id
-                && state.source_repository == source_repository
-                && state.current_checksum == checksum
-        })
+        .expect_update_state_if()
         .times(1)
-        .returning(move |_| Err(CoreError::DatabaseError(error_msg.to_string())));
+        .returning(move |_, _| Err(CoreError::DatabaseError(error_msg.to_string())));
 
-    let state_manager = StateManager::new(Box::new(mock_backend));
-    let updater = TemplateUpdater::new(Arc::new(state_manager));
-    let result = updater
-        .process_update(template_id, source_repository, new_template_data)
-        .await;
+    let mut platform = service_platform(Some(b"template data"));
+    platform.expect_update_repo().times(0);
 
-    assert!(result.is_err());
-    match result.err().unwrap() {
-        CoreError::DatabaseError(msg) => assert_eq!(msg, error_msg),
-        _ => panic!("Expected DatabaseError"),
-    }
+    let updater = updater(mock_backend, vec![platform]);
+    let outcomes = updater
+        .process_update(
+            &category(),
+            &template_path.to_string(),
+            "repo5",
+            b"template data",
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(outcomes.len(), 1);
+    assert_eq!(
+        outcomes[0].status,
+        RepoUpdateStatus::Failed(CoreError::DatabaseError(error_msg.to_string()).to_string())
+    );
+}
+
+#[tokio::test]
+async fn test_process_update_state_error_does_not_stop_other_repos() {
+    let mut mock_backend = MockStatePersistence::new();
+    mock_backend.expect_get_state().returning(|_| Ok(None));
+    mock_backend
+        .expect_update_state_if()
+        .times(2)
+        .returning(|state, _| {
+            if state.repo == "org/service" {
+                Err(CoreError::DatabaseError("connection lost".to_string()))
+            } else {
+                Ok(1)
+            }
+        });
+
+    let mut platform = MockPlatform::new();
+    platform
+        .expect_list_repos_by_category()
+        .returning(|_| Ok(vec![repo("service"), repo("other")]));
+    platform.expect_get_repo_file().returning(|_, _| Ok(None));
+    platform
+        .expect_update_repo()
+        .times(2)
+        .returning(|_, _| Ok(pr_result(3)));
+
+    let updater = updater(mock_backend, vec![platform]);
+    let outcomes = updater
+        .process_update(&category(), &"template8".to_string(), "repo8", b"content")
+        .await
+        .unwrap();
+
+    assert_eq!(outcomes.len(), 2);
+    // The pull request was created, so the failure names it
+    assert!(
+        matches!(&outcomes[0].status, RepoUpdateStatus::Failed(msg) if msg.contains("connection lost") && msg.contains("/pull/3"))
+    );
+    assert_eq!(outcomes[1].repo.name(), "other");
+    assert!(matches!(
+        outcomes[1].status,
+        RepoUpdateStatus::Updated { .. }
+    ));
+}
+
+#[tokio::test]
+async fn test_process_update_new_template_expects_no_state() {
+    let mut mock_backend = MockStatePersistence::new();
+    mock_backend.expect_get_state().returning(|_| Ok(None));
+    mock_backend
+        .expect_update_state_if()
+        .withf(|_, expected_version| expected_version.is_none())
+        .times(1)
+        .returning(|_, _| Ok(1));
+
+    let mut platform = service_platform(None);
+    platform
+        .expect_update_repo()
+        .returning(|_, _| Ok(pr_result(1)));
+
+    let updater = updater(mock_backend, vec![platform]);
+    updater
+        .process_update(&category(), &"template6".to_string(), "repo6", b"content")
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_process_update_state_conflict_reports_failure() {
+    let template_path = "template7";
+    let current = state(template_path, b"old", Some(b"old"));
+
+    let mut mock_backend = MockStatePersistence::new();
+    let history = vec![current.clone()];
+    mock_backend
+        .expect_get_state()
+        .returning(move |_| Ok(Some(current.clone())));
+    mock_backend
+        .expect_get_state_history()
+        .returning(move |_| Ok(history.clone()));
+    // Another run updated the state between reading and writing it
+    mock_backend
+        .expect_update_state_if()
+        .times(1)
+        .returning(move |state, expected| {
+            Err(CoreError::StateConflict {
+                key: state.key(),
+                expected,
+                actual: Some(5),
+            })
+        });
+
+    let mut platform = service_platform(Some(b"old"));
+    platform
+        .expect_update_repo()
+        .returning(|_, _| Ok(pr_result(9)));
+
+    let updater = updater(mock_backend, vec![platform]);
+    let outcomes = updater
+        .process_update(&category(), &template_path.to_string(), "repo7", b"new")
+        .await
+        .unwrap();
+
+    assert_eq!(outcomes.len(), 1);
+    assert!(
+        matches!(&outcomes[0].status, RepoUpdateStatus::Failed(msg) if msg.contains("State conflict"))
+    );
+}
+
+#[test]
+fn test_classify_target_file() {
+    // Missing targets can always be created
+    assert_eq!(
+        classify_target_file(None, "master", Some("deployed"), &[]),
+        TargetFileStatus::SafeToUpdate
+    );
+    assert_eq!(
+        classify_target_file(None, "master", None, &[]),
+        TargetFileStatus::SafeToUpdate
+    );
+    // Targets matching master are up to date, whatever was deployed before
+    assert_eq!(
+        classify_target_file(Some("master"), "master", Some("deployed"), &[]),
+        TargetFileStatus::UpToDate
+    );
+    assert_eq!(
+        classify_target_file(Some("master"), "master", None, &[]),
+        TargetFileStatus::UpToDate
+    );
+    // Targets still holding the deployed version can be replaced
+    assert_eq!(
+        classify_target_file(Some("deployed"), "master", Some("deployed"), &[]),
+        TargetFileStatus::SafeToUpdate
+    );
+    // Targets still holding an earlier version, e.g. behind an unmerged pull request, too
+    assert_eq!(
+        classify_target_file(
+            Some("earlier"),
+            "master",
+            Some("deployed"),
+            &["deployed".to_string(), "earlier".to_string()]
+        ),
+        TargetFileStatus::SafeToUpdate
+    );
+    // Anything else was changed by hand
+    assert_eq!(
+        classify_target_file(Some("other"), "master", Some("deployed"), &[]),
+        TargetFileStatus::ManuallyModified
+    );
+    assert_eq!(
+        classify_target_file(Some("other"), "master", None, &[]),
+        TargetFileStatus::ManuallyModified
+    );
 }
 
 #[tokio::test]
 async fn test_template_updater_new_and_debug() {
     // Test TemplateUpdater::new and Debug implementation
-    let mock_backend = Box::new(MockStatePersistence::new());
-    let state_manager = Arc::new(StateManager::new(mock_backend));
-    let updater = TemplateUpdater::new(state_manager);
+    let updater = updater(MockStatePersistence::new(), vec![MockPlatform::new()]);
     let debug_str = format!("{:?}", updater);
     assert!(debug_str.contains("TemplateUpdater"));
+    assert!(debug_str.contains("platforms: 1"));
+}
+
+#[tokio::test]
+async fn test_process_update_with_in_memory_backend() {
+    let backend = crate::InMemoryBackend::new();
+    let mut platform = service_platform(None);
+    platform
+        .expect_update_repo()
+        .times(1)
+        .returning(|_, _| Ok(pr_result(3)));
+    let state_manager = StateManager::new(Box::new(backend.clone()));
+    let updater = TemplateUpdater::new(
+        Arc::new(state_manager),
+        vec![Arc::new(platform) as Arc<dyn DeveloperPlatform>],
+    );
+
+    let first = updater
+        .process_update(&category(), &"template8".to_string(), "repo8", b"v1")
+        .await
+        .unwrap();
+    // The second run finds the deployment recorded and skips the repository
+    let second = updater
+        .process_update(&category(), &"template8".to_string(), "repo8", b"v1")
+        .await
+        .unwrap();
+
+    assert!(matches!(first[0].status, RepoUpdateStatus::Updated { .. }));
+    assert_eq!(second[0].status, RepoUpdateStatus::UpToDate);
+    let stored = backend
+        .state(&StateKey::new("org/service", "template8"))
+        .unwrap();
+    assert_eq!(stored.deployed_checksum, Some(checksum(b"v1")));
+    assert_eq!(stored.version, 1);
+}
+
+#[tokio::test]
+async fn test_process_update_reads_states_in_one_batch() {
+    let backend = crate::InMemoryBackend::new();
+    let platforms = (0..2)
+        .map(|i| {
+            let mut platform = MockPlatform::new();
+            platform
+                .expect_list_repos_by_category()
+                .returning(move |_| {
+                    Ok(vec![
+                        repo(&format!("repo-{}-a", i)),
+                        repo(&format!("repo-{}-b", i)),
+                    ])
+                });
+            platform.expect_get_repo_file().returning(|_, _| Ok(None));
+            platform
+                .expect_update_repo()
+                .times(2)
+                .returning(|_, _| Ok(pr_result(1)));
+            Arc::new(platform) as Arc<dyn DeveloperPlatform>
+        })
+        .collect();
+    let state_manager = StateManager::new(Box::new(backend.clone()));
+    let updater = TemplateUpdater::new(Arc::new(state_manager), platforms);
+
+    let outcomes = updater
+        .process_update(&category(), &"template9".to_string(), "source", b"data")
+        .await
+        .unwrap();
+
+    assert_eq!(outcomes.len(), 4);
+    assert_eq!(backend.call_count(crate::StateOperation::GetStates), 1);
+    assert_eq!(backend.call_count(crate::StateOperation::GetState), 0);
+    assert_eq!(backend.len(), 4);
 }
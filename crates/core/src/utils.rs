@@ -4,6 +4,7 @@
 use crate::types::{AppConfig, CoreError, Result};
 use sha2::{Digest, Sha256};
 use std::path::Path;
+use template_teleporter_developer_platforms::MasterConfig;
 
 // Test module declaration for lib.rs itself
 #[cfg(test)]
@@ -53,12 +54,15 @@ pub fn calculate_checksum(data: &[u8]) -> Result<String> {
 /// A `Result` containing the parsed `AppConfig` on success, or a `CoreError` if:
 ///   - The file cannot be opened (`CoreError::IoError`).
 ///   - The file content is not valid YAML or doesn't match the `AppConfig` structure (`CoreError::ConfigParseError`).
-///   - A required configuration value is missing or invalid (`CoreError::MissingConfiguration`).
+///   - A required configuration value is missing (`CoreError::MissingConfiguration`) or invalid
+///     (`CoreError::InvalidConfiguration`).
 ///
 /// # Errors
 /// Returns `CoreError::IoError` if the file cannot be opened.
 /// Returns `CoreError::ConfigParseError` if YAML parsing fails.
-/// Returns `CoreError::MissingConfiguration` if `table_name` is empty.
+/// Returns `CoreError::MissingConfiguration` if `table_name` is empty or a setting required by the
+/// selected `database_type` is missing (see `AppConfig::validate`).
+/// Returns `CoreError::InvalidConfiguration` if a setting of the selected backend is invalid.
 pub fn parse_config(config_path: &Path) -> Result<AppConfig> {
     // Open the file, propagating IO errors.
     let file = std::fs::File::open(config_path)?;
@@ -66,12 +70,34 @@ pub fn parse_config(config_path: &Path) -> Result<AppConfig> {
     let config: AppConfig =
         serde_yaml::from_reader(file).map_err(|e| CoreError::ConfigParseError { source: e })?;
 
-    // Basic validation: Ensure table_name is not empty
-    if config.table_name.is_empty() {
-        return Err(CoreError::MissingConfiguration(
-            "table_name cannot be empty".to_string(),
-        ));
-    }
+    // Ensure the selected backend has everything it needs.
+    config.validate()?;
 
     Ok(config)
 }
+
+/// Parses and validates the master configuration (`template-teleporter.toml`) from a file path.
+///
+/// Delegates to `MasterConfig::parse`, which validates the configuration beyond its TOML
+/// structure (e.g., missing `config_version`, duplicate file paths, repositories referencing
+/// unknown categories) and reports every problem found with its line and column.
+///
+/// # Arguments
+/// * `config_path` - Path to the `template-teleporter.toml` file.
+///
+/// # Returns
+/// A `Result` containing the parsed `MasterConfig` on success, or a `CoreError` if:
+///   - The file cannot be read (`CoreError::IoError`).
+///   - The file is not a valid master configuration (`CoreError::InvalidMasterConfig`).
+///
+/// # Errors
+/// Returns `CoreError::IoError` if the file cannot be read.
+/// Returns `CoreError::InvalidMasterConfig` listing every problem, located as
+/// `<config_path>:<line>:<column>`, if parsing or validation fails.
+pub fn parse_master_config(config_path: &Path) -> Result<MasterConfig> {
+    let content = std::fs::read_to_string(config_path)?;
+    Ok(MasterConfig::parse(
+        &content,
+        &config_path.display().to_string(),
+    )?)
+}
@@ -1,4 +1,6 @@
 use super::*;
+use crate::state_manager::DEFAULT_HISTORY_RETENTION;
+use crate::types::{DatabaseType, SqliteSettings};
 use std::fs::File;
 use std::io::Write;
 use tempfile::tempdir; // Added import for Sha256::new()
@@ -20,11 +22,12 @@ fn test_parse_config_with_enum() {
     let mut file = File::create(&file_path).unwrap();
     writeln!(
         file,
-        "---\ndatabaseEndpoint: http://localhost:8000\ntableName: TemplateState"
+        "---\ndatabaseType: dynamodb\ndatabaseEndpoint: http://localhost:8000\ntableName: TemplateState"
     )
     .unwrap();
 
     let config = parse_config(&file_path).unwrap();
+    assert_eq!(config.database_type, DatabaseType::Dynamodb);
     assert_eq!(
         config.database_endpoint.as_deref(),
         Some("http://localhost:8000")
@@ -66,7 +69,7 @@ fn test_parse_config_empty_table_name() {
     let mut file = File::create(&file_path).unwrap();
     writeln!(
         file,
-        "---\ndatabaseEndpoint: http://localhost:8000\ntableName: \"\""
+        "---\ndatabaseType: dynamodb\ndatabaseEndpoint: http://localhost:8000\ntableName: \"\""
     )
     .unwrap();
 
@@ -85,7 +88,11 @@ fn test_parse_config_missing_table_name() {
     let dir = tempdir().unwrap();
     let file_path = dir.path().join("missing_table.yaml");
     let mut file = File::create(&file_path).unwrap();
-    writeln!(file, "---\ndatabaseEndpoint: http://localhost:8000").unwrap();
+    writeln!(
+        file,
+        "---\ndatabaseType: dynamodb\ndatabaseEndpoint: http://localhost:8000"
+    )
+    .unwrap();
 
     let result = parse_config(&file_path);
     assert!(result.is_err());
@@ -97,11 +104,10 @@ fn test_parse_config_missing_table_name() {
 
 #[test]
 fn test_app_config_serialization() {
-    let config = AppConfig {
-        database_endpoint: Some("https://cosmos.example.com".to_string()),
-        table_name: "StateTable".to_string(),
-    };
+    let config = AppConfig::new(DatabaseType::Cosmosdb, "StateTable")
+        .with_database_endpoint("https://cosmos.example.com");
     let yaml = serde_yaml::to_string(&config).unwrap();
+    assert!(yaml.contains("databaseType: cosmosdb"));
     assert!(yaml.contains("databaseEndpoint: https://cosmos.example.com"));
     assert!(yaml.contains("tableName: StateTable"));
 
@@ -113,9 +119,170 @@ fn test_app_config_serialization() {
     assert_eq!(deserialized.table_name, "StateTable");
 }
 
+fn parse_config_str(yaml: &str) -> Result<AppConfig> {
+    let dir = tempdir().unwrap();
+    let file_path = dir.path().join("config.yaml");
+    std::fs::write(&file_path, yaml).unwrap();
+    parse_config(&file_path)
+}
+
+#[test]
+fn test_parse_config_backend_sections() {
+    let config = parse_config_str(
+        "---\ndatabaseType: sqlite\ntableName: TemplateState\nsqlite:\n  path: /var/lib/state.db\npostgres:\n  poolSize: 4\n",
+    )
+    .unwrap();
+
+    assert_eq!(config.database_type, DatabaseType::Sqlite);
+    assert_eq!(
+        config.sqlite,
+        Some(SqliteSettings {
+            path: "/var/lib/state.db".into()
+        })
+    );
+    // Sections of other backends are kept but not validated
+    assert_eq!(config.postgres.unwrap().pool_size, Some(4));
+    assert_eq!(config.filesystem, None);
+}
+
+#[test]
+fn test_parse_config_without_database_type_selects_filesystem() {
+    // A configuration written before `databaseType` existed
+    let config = parse_config_str(
+        "---\ndatabaseEndpoint: http://localhost:8000\ntableName: TemplateState\n",
+    )
+    .unwrap();
+
+    assert_eq!(config.database_type, DatabaseType::Filesystem);
+    assert_eq!(
+        config.database_endpoint.as_deref(),
+        Some("http://localhost:8000")
+    );
+    assert_eq!(config.table_name, "TemplateState");
+    assert_eq!(config.filesystem, None);
+}
+
+#[test]
+fn test_parse_config_unknown_database_type() {
+    let result = parse_config_str("---\ndatabaseType: mongodb\ntableName: TemplateState\n");
+
+    assert!(matches!(result, Err(CoreError::ConfigParseError { .. })));
+}
+
+#[test]
+fn test_parse_config_missing_backend_settings() {
+    let cases = [
+        ("sqlite", "sqlite.path"),
+        ("cosmosdb", "databaseEndpoint"),
+        ("postgres", "databaseEndpoint"),
+    ];
+    for (database_type, setting) in cases {
+        let result = parse_config_str(&format!(
+            "---\ndatabaseType: {}\ntableName: TemplateState\n",
+            database_type
+        ));
+
+        match result {
+            Err(CoreError::MissingConfiguration(msg)) => {
+                assert!(msg.contains(setting), "{}: {}", database_type, msg);
+                assert!(msg.contains(database_type), "{}: {}", database_type, msg);
+            }
+            other => panic!(
+                "Expected MissingConfiguration for {}, got {:?}",
+                database_type, other
+            ),
+        }
+    }
+}
+
+#[test]
+fn test_parse_config_backends_without_required_settings() {
+    for database_type in ["filesystem", "memory", "dynamodb"] {
+        let config = parse_config_str(&format!(
+            "---\ndatabaseType: {}\ntableName: TemplateState\n",
+            database_type
+        ))
+        .unwrap();
+
+        assert_eq!(config.database_type.to_string(), database_type);
+    }
+}
+
+#[test]
+fn test_parse_config_invalid_pool_size() {
+    let result = parse_config_str(
+        "---\ndatabaseType: postgres\ndatabaseEndpoint: postgres://localhost/state\ntableName: TemplateState\npostgres:\n  poolSize: 0\n",
+    );
+
+    assert!(
+        matches!(result, Err(CoreError::InvalidConfiguration(msg)) if msg.contains("poolSize"))
+    );
+}
+
+#[test]
+fn test_parse_config_history_retention() {
+    let config = parse_config_str(
+        "---\ndatabaseType: memory\ntableName: TemplateState\nhistoryRetention: 3\n",
+    )
+    .unwrap();
+    assert_eq!(config.history_retention(), 3);
+
+    let default =
+        parse_config_str("---\ndatabaseType: memory\ntableName: TemplateState\n").unwrap();
+    assert_eq!(default.history_retention(), DEFAULT_HISTORY_RETENTION);
+
+    let result = parse_config_str(
+        "---\ndatabaseType: memory\ntableName: TemplateState\nhistoryRetention: 0\n",
+    );
+    assert!(
+        matches!(result, Err(CoreError::InvalidConfiguration(msg)) if msg.contains("historyRetention"))
+    );
+}
+
 #[test]
 fn test_core_error_platform_error() {
     let err = CoreError::PlatformError("platform failed".to_string());
     let msg = format!("{}", err);
     assert!(msg.contains("platform failed"));
 }
+
+#[test]
+fn test_parse_master_config() {
+    let dir = tempdir().unwrap();
+    let file_path = dir.path().join("template-teleporter.toml");
+    std::fs::write(
+        &file_path,
+        "[meta]\nconfig_version = \"1.0\"\n\n[categories.saas_rust]\nfiles = [\"README.md\"]\n\n[repositories]\n\"org/service\" = { category = \"saas_rust\" }\n",
+    )
+    .unwrap();
+
+    let config = parse_master_config(&file_path).unwrap();
+    assert_eq!(config.meta.config_version, "1.0");
+    assert_eq!(config.repositories["org/service"].category, "saas_rust");
+}
+
+#[test]
+fn test_parse_master_config_reports_all_problems() {
+    let dir = tempdir().unwrap();
+    let file_path = dir.path().join("template-teleporter.toml");
+    std::fs::write(
+        &file_path,
+        "[meta]\n\n[categories.saas_rust]\nfiles = [\"README.md\", \"README.md\"]\n\n[repositories]\n\"org/service\" = { category = \"unknown\" }\n",
+    )
+    .unwrap();
+
+    match parse_master_config(&file_path).err().unwrap() {
+        CoreError::InvalidMasterConfig(e) => {
+            assert_eq!(e.file, file_path.display().to_string());
+            let lines: Vec<_> = e.issues.iter().map(|issue| issue.line).collect();
+            assert_eq!(lines, vec![1, 4, 7]);
+        }
+        e => panic!("Expected InvalidMasterConfig, got {:?}", e),
+    }
+}
+
+#[test]
+fn test_parse_master_config_file_not_found() {
+    let result = parse_master_config(Path::new("this_file_should_not_exist.toml"));
+    assert!(matches!(result, Err(CoreError::IoError(_))));
+}
@@ -0,0 +1,846 @@
+//! Implements a `StatePersistence` backend on the Azure Cosmos DB SQL API.
+//!
+//! States are stored as documents in the container named by `AppConfig::table_name`, partitioned
+//! by `/repo` as provisioned by the deployment's Terraform configuration. The backend talks to
+//! the Cosmos DB REST API directly and authenticates with the account's master key.
+//!
+//! Cosmos DB document IDs may not contain `/` and are at most 255 characters long, so the ID of
+//! a document is the URL-safe base64 encoding of the template path, shortened with a hash if
+//! necessary. Writes of existing documents are conditioned on the document's
+//! etag, so concurrent writers never overwrite each other's changes unnoticed. The previous
+//! versions of a state are kept in the `history` array of its document, newest first.
+//!
+//! Batches of states are read with one query per chunk of keys, which fans out across the
+//! partitions of the requested repositories. Batches of writes read the stored documents in the
+//! same way, but each state is still written with its own conditional request.
+
+use async_trait::async_trait;
+use base64::engine::general_purpose::{STANDARD as BASE64, URL_SAFE_NO_PAD as BASE64_URL};
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use template_teleporter_core::{
+    AppConfig, CoreError, Result, StateKey, StatePage, StatePersistence, StateQuery, TemplateState,
+    DEFAULT_HISTORY_RETENTION,
+};
+
+#[cfg(test)]
+#[path = "lib_tests.rs"]
+mod tests;
+
+#[cfg(test)]
+mod stand_in;
+
+/// The name of the database holding the state container, as provisioned by Terraform.
+pub const DEFAULT_DATABASE_NAME: &str = "TemplateTeleporter";
+
+/// The version of the Cosmos DB REST API used by the backend.
+const API_VERSION: &str = "2018-12-31";
+
+/// The number of conditional writes `update_state` and `update_states` make for a state that
+/// keeps being modified concurrently before giving up.
+const MAX_WRITE_ATTEMPTS: usize = 10;
+
+/// The maximum number of keys read by one query of `get_states`, which keeps the query well
+/// below the size limits of Cosmos DB.
+const MAX_KEYS_PER_QUERY: usize = 1_000;
+
+/// The maximum length of a Cosmos DB document ID.
+const MAX_ID_LEN: usize = 255;
+
+/// The configuration of a `CosmosDbBackend`.
+///
+/// # Example
+/// ```rust
+/// use template_teleporter_core::{AppConfig, DatabaseType};
+/// use template_teleporter_cosmosdb_backend::{CosmosDbConfig, DEFAULT_DATABASE_NAME};
+/// let app_config = AppConfig::new(DatabaseType::Cosmosdb, "TemplateState")
+///     .with_database_endpoint("https://my-account.documents.azure.com");
+/// let config = CosmosDbConfig::from_app_config(&app_config, "bWFzdGVyLWtleQ==").unwrap();
+/// assert_eq!(config.database, DEFAULT_DATABASE_NAME);
+/// assert_eq!(config.container, "TemplateState");
+/// ```
+#[derive(Clone)]
+pub struct CosmosDbConfig {
+    /// The account endpoint, e.g. `https://my-account.documents.azure.com`.
+    pub endpoint: String,
+
+    /// The base64 encoded master key of the account.
+    pub master_key: String,
+
+    /// The name of the database holding the container.
+    pub database: String,
+
+    /// The name of the container storing the states.
+    pub container: String,
+}
+
+impl CosmosDbConfig {
+    /// Creates a new `CosmosDbConfig`.
+    pub fn new(
+        endpoint: impl Into<String>,
+        master_key: impl Into<String>,
+        database: impl Into<String>,
+        container: impl Into<String>,
+    ) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            master_key: master_key.into(),
+            database: database.into(),
+            container: container.into(),
+        }
+    }
+
+    /// Creates a `CosmosDbConfig` for the container named by `AppConfig::table_name`, at the
+    /// endpoint given by `AppConfig::database_endpoint`. The database is taken from the
+    /// `cosmosdb` section of the configuration, falling back to [`DEFAULT_DATABASE_NAME`].
+    ///
+    /// # Errors
+    /// Returns `CoreError::MissingConfiguration` if the application configuration has no
+    /// database endpoint.
+    pub fn from_app_config(config: &AppConfig, master_key: impl Into<String>) -> Result<Self> {
+        let endpoint = config.database_endpoint.as_ref().ok_or_else(|| {
+            CoreError::MissingConfiguration("databaseEndpoint (required for Cosmos DB)".to_string())
+        })?;
+        let database = config
+            .cosmosdb
+            .as_ref()
+            .and_then(|settings| settings.database.as_deref())
+            .unwrap_or(DEFAULT_DATABASE_NAME);
+        Ok(Self::new(
+            endpoint.clone(),
+            master_key,
+            database,
+            config.table_name.clone(),
+        ))
+    }
+
+    /// Sets the name of the database holding the container.
+    pub fn with_database(mut self, database: impl Into<String>) -> Self {
+        self.database = database.into();
+        self
+    }
+}
+
+// Manual Debug implementation so that the master key never ends up in logs.
+impl fmt::Debug for CosmosDbConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CosmosDbConfig")
+            .field("endpoint", &self.endpoint)
+            .field("master_key", &"<redacted>")
+            .field("database", &self.database)
+            .field("container", &self.container)
+            .finish()
+    }
+}
+
+/// A `TemplateState` as stored in Cosmos DB, using the attribute names of the specification.
+#[derive(Debug, Serialize, Deserialize)]
+struct StateDocument {
+    id: String,
+    repo: String,
+    template_path: String,
+    source_repository: String,
+    master_checksum: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    deployed_checksum: Option<String>,
+    last_updated: DateTime<Utc>,
+    // Documents written before versions were introduced read as version 0.
+    #[serde(default)]
+    version: u64,
+    // Documents written before history was recorded have none.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    history: Vec<StateVersion>,
+    #[serde(rename = "_etag", default, skip_serializing)]
+    etag: String,
+}
+
+/// A previous version of a state, as kept in the `history` of its document.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StateVersion {
+    source_repository: String,
+    master_checksum: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    deployed_checksum: Option<String>,
+    last_updated: DateTime<Utc>,
+    #[serde(default)]
+    version: u64,
+}
+
+impl StateDocument {
+    /// Creates the document replacing `current`, with the next version and the current document
+    /// moved into the history, keeping at most `retention` versions including the new one.
+    fn next(state: &TemplateState, current: Option<&StateDocument>, retention: usize) -> Self {
+        let mut history = Vec::new();
+        if let Some(current) = current {
+            history.push(StateVersion {
+                source_repository: current.source_repository.clone(),
+                master_checksum: current.master_checksum.clone(),
+                deployed_checksum: current.deployed_checksum.clone(),
+                last_updated: current.last_updated,
+                version: current.version,
+            });
+            history.extend(current.history.iter().cloned());
+            history.truncate(retention - 1);
+        }
+        Self {
+            id: document_id(&state.template_path),
+            repo: state.repo.clone(),
+            template_path: state.template_path.clone(),
+            source_repository: state.source_repository.clone(),
+            master_checksum: state.master_checksum.clone(),
+            deployed_checksum: state.deployed_checksum.clone(),
+            last_updated: state.last_updated_utc,
+            version: current.map_or(1, |current| current.version + 1),
+            history,
+            etag: String::new(),
+        }
+    }
+
+    /// Returns the current state followed by the previous versions, newest first.
+    fn into_history(self, retention: usize) -> Vec<TemplateState> {
+        let key = StateKey::new(&self.repo, &self.template_path);
+        let previous: Vec<_> = self
+            .history
+            .iter()
+            .take(retention - 1)
+            .map(|version| TemplateState {
+                repo: key.repo.clone(),
+                template_path: key.template_path.clone(),
+                source_repository: version.source_repository.clone(),
+                master_checksum: version.master_checksum.clone(),
+                deployed_checksum: version.deployed_checksum.clone(),
+                last_updated_utc: version.last_updated,
+                version: version.version,
+            })
+            .collect();
+        let mut history = vec![self.into_state()];
+        history.extend(previous);
+        history
+    }
+
+    fn into_state(self) -> TemplateState {
+        TemplateState {
+            repo: self.repo,
+            template_path: self.template_path,
+            source_repository: self.source_repository,
+            master_checksum: self.master_checksum,
+            deployed_checksum: self.deployed_checksum,
+            last_updated_utc: self.last_updated,
+            version: self.version,
+        }
+    }
+}
+
+/// A state persistence backend that stores `TemplateState` in a Cosmos DB container.
+pub struct CosmosDbBackend {
+    config: CosmosDbConfig,
+    key: Vec<u8>,
+    http: reqwest::Client,
+    history_retention: usize,
+}
+
+// Manual Debug implementation so that the decoded master key never ends up in logs.
+impl fmt::Debug for CosmosDbBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CosmosDbBackend")
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CosmosDbBackend {
+    /// Creates a new `CosmosDbBackend`.
+    ///
+    /// # Errors
+    /// Returns `CoreError::DatabaseError` if the master key is not valid base64.
+    pub fn new(config: CosmosDbConfig) -> Result<Self> {
+        let key = BASE64.decode(config.master_key.trim()).map_err(|e| {
+            CoreError::DatabaseError(format!("Invalid Cosmos DB master key: {}", e))
+        })?;
+        Ok(Self {
+            config,
+            key,
+            http: reqwest::Client::new(),
+            history_retention: DEFAULT_HISTORY_RETENTION,
+        })
+    }
+
+    /// Sets how many versions of each state are kept in the history, including the current
+    /// version. Values below 1 are treated as 1.
+    pub fn with_history_retention(mut self, retention: usize) -> Self {
+        self.history_retention = retention.max(1);
+        self
+    }
+
+    /// Returns the configuration of the backend.
+    pub fn config(&self) -> &CosmosDbConfig {
+        &self.config
+    }
+
+    /// Creates the database and the state container partitioned by `/repo`, unless they exist.
+    ///
+    /// Production containers are provisioned with Terraform; this is intended for the Cosmos DB
+    /// emulator and other development setups.
+    ///
+    /// # Returns
+    /// An empty `Result` on success, or a `CoreError::DatabaseError` if the database or the
+    /// container cannot be created.
+    pub async fn create_container(&self) -> Result<()> {
+        let database = self
+            .request(Method::POST, "dbs", "dbs", "")
+            .json(&json!({ "id": self.config.database }));
+        let response = send(database, "create database").await?;
+        if !response.status().is_success() && response.status() != StatusCode::CONFLICT {
+            return Err(unexpected_response("create database", response).await);
+        }
+
+        let database_link = format!("dbs/{}", self.config.database);
+        let container = self
+            .request(
+                Method::POST,
+                &format!("{}/colls", database_link),
+                "colls",
+                &database_link,
+            )
+            .json(&json!({
+                "id": self.config.container,
+                "partitionKey": { "paths": ["/repo"], "kind": "Hash" },
+            }));
+        let response = send(container, "create container").await?;
+        if !response.status().is_success() && response.status() != StatusCode::CONFLICT {
+            return Err(unexpected_response("create container", response).await);
+        }
+        Ok(())
+    }
+
+    fn container_link(&self) -> String {
+        format!(
+            "dbs/{}/colls/{}",
+            self.config.database, self.config.container
+        )
+    }
+
+    fn document_link(&self, key: &StateKey) -> String {
+        format!(
+            "{}/docs/{}",
+            self.container_link(),
+            document_id(&key.template_path)
+        )
+    }
+
+    /// Builds an authorized request for the resource at `path`. The signature covers the
+    /// resource type and the link of the resource (or, for feeds, of the parent resource).
+    fn request(
+        &self,
+        method: Method,
+        path: &str,
+        resource_type: &str,
+        resource_link: &str,
+    ) -> RequestBuilder {
+        let date = Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        let authorization = authorization_token(
+            &self.key,
+            method.as_str(),
+            resource_type,
+            resource_link,
+            &date,
+        );
+        let url = format!("{}/{}", self.config.endpoint.trim_end_matches('/'), path);
+        self.http
+            .request(method, url)
+            .header("authorization", authorization)
+            .header("x-ms-date", date)
+            .header("x-ms-version", API_VERSION)
+    }
+
+    fn document_request(&self, method: Method, key: &StateKey) -> RequestBuilder {
+        let link = self.document_link(key);
+        self.request(method, &link, "docs", &link)
+            .header("x-ms-documentdb-partitionkey", partition_key(&key.repo))
+    }
+
+    async fn read_document(&self, key: &StateKey) -> Result<Option<StateDocument>> {
+        let response = send(self.document_request(Method::GET, key), "read document").await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => Ok(Some(parse_json(response, "read document").await?)),
+            _ => Err(unexpected_response("read document", response).await),
+        }
+    }
+
+    /// Creates a document. Returns `false` if a document with the same key already exists.
+    async fn create_document(&self, document: &StateDocument) -> Result<bool> {
+        let link = self.container_link();
+        let request = self
+            .request(Method::POST, &format!("{}/docs", link), "docs", &link)
+            .header(
+                "x-ms-documentdb-partitionkey",
+                partition_key(&document.repo),
+            )
+            .json(document);
+        let response = send(request, "create document").await?;
+        match response.status() {
+            StatusCode::CONFLICT => Ok(false),
+            status if status.is_success() => Ok(true),
+            _ => Err(unexpected_response("create document", response).await),
+        }
+    }
+
+    /// Replaces a document if it still has the given etag. Returns `false` if the document was
+    /// modified or deleted since.
+    async fn replace_document(&self, document: &StateDocument, etag: &str) -> Result<bool> {
+        let key = StateKey::new(&document.repo, &document.template_path);
+        let request = self
+            .document_request(Method::PUT, &key)
+            .header("if-match", etag)
+            .json(document);
+        let response = send(request, "replace document").await?;
+        match response.status() {
+            StatusCode::PRECONDITION_FAILED | StatusCode::NOT_FOUND => Ok(false),
+            status if status.is_success() => Ok(true),
+            _ => Err(unexpected_response("replace document", response).await),
+        }
+    }
+
+    /// Reads the documents of several states, with one query per chunk of keys. The query
+    /// selects the cross product of the requested repositories and template paths, so documents
+    /// of keys that were not requested are dropped.
+    ///
+    /// # Returns
+    /// A `Result` containing the stored documents by key, without entries for keys that have no
+    /// document, or a `CoreError::DatabaseError` if a query fails.
+    async fn read_documents(&self, keys: &[StateKey]) -> Result<HashMap<StateKey, StateDocument>> {
+        let unique: Vec<&StateKey> = keys.iter().collect::<BTreeSet<_>>().into_iter().collect();
+        let mut documents = HashMap::new();
+        for chunk in unique.chunks(MAX_KEYS_PER_QUERY) {
+            let repos: BTreeSet<&str> = chunk.iter().map(|key| key.repo.as_str()).collect();
+            let template_paths: BTreeSet<&str> =
+                chunk.iter().map(|key| key.template_path.as_str()).collect();
+            let parameters = vec![
+                json!({ "name": "@repos", "value": repos }),
+                json!({ "name": "@template_paths", "value": template_paths }),
+            ];
+            // A single repository is served by its partition alone.
+            let partition = match repos.len() {
+                1 => repos.first().copied(),
+                _ => None,
+            };
+            let found = self
+                .query_documents(
+                    "SELECT * FROM c WHERE ARRAY_CONTAINS(@repos, c.repo) \
+                     AND ARRAY_CONTAINS(@template_paths, c.template_path)",
+                    parameters,
+                    partition,
+                )
+                .await?;
+            for document in found {
+                let key = StateKey::new(&document.repo, &document.template_path);
+                if chunk.contains(&&key) {
+                    documents.insert(key, document);
+                }
+            }
+        }
+        Ok(documents)
+    }
+
+    /// Writes a state with the version after that of the stored document, re-reading the stored
+    /// document if another writer modifies it in between.
+    ///
+    /// # Arguments
+    /// * `state` - The state to write.
+    /// * `current` - The stored document of the state as last read, or `None` if there was none.
+    async fn put_state(
+        &self,
+        state: &TemplateState,
+        mut current: Option<StateDocument>,
+    ) -> Result<()> {
+        for attempt in 1..=MAX_WRITE_ATTEMPTS {
+            let document = StateDocument::next(state, current.as_ref(), self.history_retention);
+            let written = match &current {
+                None => self.create_document(&document).await?,
+                Some(current) => self.replace_document(&document, &current.etag).await?,
+            };
+            if written {
+                return Ok(());
+            }
+            if attempt < MAX_WRITE_ATTEMPTS {
+                current = self.read_document(&state.key()).await?;
+            }
+        }
+        Err(CoreError::DatabaseError(format!(
+            "Failed to update state for {}: modified concurrently {} times",
+            state.key(),
+            MAX_WRITE_ATTEMPTS
+        )))
+    }
+
+    /// Runs a query, following continuation tokens until all results are read. Queries with a
+    /// partition key are served by that partition; other queries fan out across partitions.
+    async fn query_documents(
+        &self,
+        query: &str,
+        parameters: Vec<Value>,
+        repo: Option<&str>,
+    ) -> Result<Vec<StateDocument>> {
+        let mut documents = Vec::new();
+        let mut continuation: Option<String> = None;
+        loop {
+            let (page, next) = self
+                .query_page(query, &parameters, repo, continuation.as_deref(), None)
+                .await?;
+            documents.extend(page);
+            continuation = next;
+            if continuation.is_none() {
+                return Ok(documents);
+            }
+        }
+    }
+
+    /// Reads one page of the results of a query, starting at a continuation token and with at
+    /// most `max_items` documents if given.
+    ///
+    /// # Returns
+    /// A `Result` containing the documents and the continuation token of the next page, or `None`
+    /// if this is the last page.
+    async fn query_page(
+        &self,
+        query: &str,
+        parameters: &[Value],
+        repo: Option<&str>,
+        continuation: Option<&str>,
+        max_items: Option<usize>,
+    ) -> Result<(Vec<StateDocument>, Option<String>)> {
+        #[derive(Deserialize)]
+        struct QueryResponse {
+            #[serde(rename = "Documents")]
+            documents: Vec<StateDocument>,
+        }
+
+        let link = self.container_link();
+        let body = json!({ "query": query, "parameters": parameters }).to_string();
+        let mut request = self
+            .request(Method::POST, &format!("{}/docs", link), "docs", &link)
+            .header("content-type", "application/query+json")
+            .header("x-ms-documentdb-isquery", "True")
+            .body(body);
+        request = match repo {
+            Some(repo) => request.header("x-ms-documentdb-partitionkey", partition_key(repo)),
+            None => request.header("x-ms-documentdb-query-enablecrosspartition", "True"),
+        };
+        if let Some(continuation) = continuation {
+            request = request.header("x-ms-continuation", continuation);
+        }
+        if let Some(max_items) = max_items {
+            request = request.header("x-ms-max-item-count", max_items.max(1).to_string());
+        }
+
+        let response = send(request, "query documents").await?;
+        if !response.status().is_success() {
+            return Err(unexpected_response("query documents", response).await);
+        }
+        let continuation = response
+            .headers()
+            .get("x-ms-continuation")
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let page: QueryResponse = parse_json(response, "query documents").await?;
+        Ok((page.documents, continuation))
+    }
+
+    /// Lists the states of one repository with an ordered query served by its partition.
+    async fn query_repo(
+        &self,
+        repo: &str,
+        query: &StateQuery,
+        start_after: Option<StateKey>,
+    ) -> Result<StatePage> {
+        let mut conditions = vec!["c.repo = @repo".to_string()];
+        let mut parameters = vec![json!({ "name": "@repo", "value": repo })];
+        match start_after {
+            Some(after) if after.repo.as_str() > repo => return Ok(StatePage::default()),
+            Some(after) if after.repo == repo => {
+                conditions.push("c.template_path > @after".to_string());
+                parameters.push(json!({ "name": "@after", "value": after.template_path }));
+            }
+            _ => {}
+        }
+        if let Some(prefix) = &query.template_path_prefix {
+            conditions.push("STARTSWITH(c.template_path, @prefix)".to_string());
+            parameters.push(json!({ "name": "@prefix", "value": prefix }));
+        }
+        // Fetch one state more than requested to find out whether there is another page.
+        let top = query
+            .limit
+            .map(|limit| format!("TOP {} ", limit.saturating_add(1)))
+            .unwrap_or_default();
+        let sql = format!(
+            "SELECT {}* FROM c WHERE {} ORDER BY c.template_path",
+            top,
+            conditions.join(" AND ")
+        );
+
+        let mut states: Vec<_> = self
+            .query_documents(&sql, parameters, Some(repo))
+            .await?
+            .into_iter()
+            .map(StateDocument::into_state)
+            .collect();
+        let has_more = query.limit.is_some_and(|limit| states.len() > limit);
+        if let Some(limit) = query.limit {
+            states.truncate(limit);
+        }
+        Ok(StatePage::new(states, has_more))
+    }
+
+    /// Lists states across repositories. The Cosmos DB gateway cannot order cross-partition
+    /// queries, so this reads every matching state and sorts them before paginating. Intended for
+    /// cleanup jobs and reports.
+    async fn query_all(&self, query: &StateQuery) -> Result<StatePage> {
+        let (sql, parameters) = match &query.template_path_prefix {
+            Some(prefix) => (
+                "SELECT * FROM c WHERE STARTSWITH(c.template_path, @prefix)",
+                vec![json!({ "name": "@prefix", "value": prefix })],
+            ),
+            None => ("SELECT * FROM c", Vec::new()),
+        };
+        let mut states: Vec<_> = self
+            .query_documents(sql, parameters, None)
+            .await?
+            .into_iter()
+            .map(StateDocument::into_state)
+            .collect();
+        states.sort_by_key(|state| state.key());
+        query.paginate(states)
+    }
+}
+
+#[async_trait]
+impl StatePersistence for CosmosDbBackend {
+    async fn get_state(&self, key: &StateKey) -> Result<Option<TemplateState>> {
+        Ok(self
+            .read_document(key)
+            .await?
+            .map(StateDocument::into_state))
+    }
+
+    /// Writes the state with the version after the stored one, re-reading the stored state if
+    /// another writer modifies it in between.
+    async fn update_state(&self, state: &TemplateState) -> Result<()> {
+        let current = self.read_document(&state.key()).await?;
+        self.put_state(state, current).await
+    }
+
+    /// Compares the stored version, then writes the state conditioned on the etag of the
+    /// compared document, so a write in between is detected as a conflict.
+    async fn update_state_if(
+        &self,
+        state: &TemplateState,
+        expected_version: Option<u64>,
+    ) -> Result<u64> {
+        let key = state.key();
+        let current = self.read_document(&key).await?;
+        let mut actual = current.as_ref().map(|document| document.version);
+        if actual == expected_version {
+            let document = StateDocument::next(state, current.as_ref(), self.history_retention);
+            let new_version = document.version;
+            let written = match &current {
+                None => self.create_document(&document).await?,
+                Some(current) => self.replace_document(&document, &current.etag).await?,
+            };
+            if written {
+                return Ok(new_version);
+            }
+            actual = self
+                .read_document(&key)
+                .await?
+                .map(|document| document.version);
+        }
+        Err(CoreError::StateConflict {
+            key,
+            expected: expected_version,
+            actual,
+        })
+    }
+
+    /// Uses an ordered single-partition query when the query selects a repository, and a
+    /// cross-partition query otherwise.
+    async fn list_states(&self, query: &StateQuery) -> Result<StatePage> {
+        let start_after = query.start_after()?;
+        match &query.repo {
+            Some(repo) => self.query_repo(repo, query, start_after).await,
+            None => self.query_all(query).await,
+        }
+    }
+
+    /// Reads the container with a cross-partition query, one page of the gateway per call. The
+    /// page token is the continuation token of the gateway.
+    async fn scan_states(&self, page_token: Option<&str>, limit: usize) -> Result<StatePage> {
+        let (documents, next_page_token) = self
+            .query_page("SELECT * FROM c", &[], None, page_token, Some(limit))
+            .await?;
+        Ok(StatePage {
+            states: documents
+                .into_iter()
+                .map(StateDocument::into_state)
+                .collect(),
+            next_page_token,
+        })
+    }
+
+    async fn delete_state(&self, key: &StateKey) -> Result<bool> {
+        let response = send(
+            self.document_request(Method::DELETE, key),
+            "delete document",
+        )
+        .await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(false),
+            status if status.is_success() => Ok(true),
+            _ => Err(unexpected_response("delete document", response).await),
+        }
+    }
+
+    /// Reads the document of the state, which holds its previous versions.
+    async fn get_state_history(&self, key: &StateKey) -> Result<Vec<TemplateState>> {
+        Ok(self
+            .read_document(key)
+            .await?
+            .map(|document| document.into_history(self.history_retention))
+            .unwrap_or_default())
+    }
+
+    /// Reads the documents of all states with one query per chunk of keys.
+    async fn get_states(&self, keys: &[StateKey]) -> Result<Vec<Option<TemplateState>>> {
+        let states: HashMap<StateKey, TemplateState> = self
+            .read_documents(keys)
+            .await?
+            .into_iter()
+            .map(|(key, document)| (key, document.into_state()))
+            .collect();
+        Ok(keys.iter().map(|key| states.get(key).cloned()).collect())
+    }
+
+    /// Reads the stored documents of all states in batches, then writes each state with its own
+    /// conditional request. The batch is not atomic: if a write fails, the states before it
+    /// remain written.
+    async fn update_states(&self, states: &[TemplateState]) -> Result<()> {
+        let keys: Vec<StateKey> = states.iter().map(TemplateState::key).collect();
+        let mut prefetched: HashMap<StateKey, Option<StateDocument>> =
+            keys.iter().map(|key| (key.clone(), None)).collect();
+        for (key, document) in self.read_documents(&keys).await? {
+            prefetched.insert(key, Some(document));
+        }
+
+        for (state, key) in states.iter().zip(&keys) {
+            // The prefetched document is only current for the first state of each key.
+            let current = match prefetched.remove(key) {
+                Some(current) => current,
+                None => self.read_document(key).await?,
+            };
+            self.put_state(state, current).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the Cosmos DB document ID for a template path, its URL-safe base64 encoding.
+///
+/// IDs longer than [`MAX_ID_LEN`] are shortened and suffixed with `~` and the encoded SHA-256 of
+/// the path. `~` is not part of the encoding otherwise, so shortened IDs cannot collide with
+/// others. They cannot be decoded, but every document also holds its template path.
+fn document_id(template_path: &str) -> String {
+    let mut id = BASE64_URL.encode(template_path);
+    if id.len() > MAX_ID_LEN {
+        let digest = BASE64_URL.encode(Sha256::digest(template_path));
+        id.truncate(MAX_ID_LEN - digest.len() - 1);
+        id.push('~');
+        id.push_str(&digest);
+    }
+    id
+}
+
+/// Formats the value of the `x-ms-documentdb-partitionkey` header, a JSON array.
+fn partition_key(repo: &str) -> String {
+    json!([repo]).to_string()
+}
+
+/// Computes the master key authorization token for a request, as described in
+/// <https://learn.microsoft.com/rest/api/cosmos-db/access-control-on-cosmosdb-resources>.
+fn authorization_token(
+    key: &[u8],
+    method: &str,
+    resource_type: &str,
+    resource_link: &str,
+    date: &str,
+) -> String {
+    let payload = format!(
+        "{}\n{}\n{}\n{}\n\n",
+        method.to_lowercase(),
+        resource_type.to_lowercase(),
+        resource_link,
+        date.to_lowercase()
+    );
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC can take a key of any size");
+    mac.update(payload.as_bytes());
+    let signature = BASE64.encode(mac.finalize().into_bytes());
+    utf8_percent_encode(
+        &format!("type=master&ver=1.0&sig={}", signature),
+        NON_ALPHANUMERIC,
+    )
+    .to_string()
+}
+
+async fn send(request: RequestBuilder, operation: &str) -> Result<Response> {
+    request.send().await.map_err(|e| {
+        let message = format!("Cosmos DB {} failed: {}", operation, e);
+        // Failures to connect or to complete the exchange; anything else is a malformed request.
+        if e.is_timeout() || e.is_connect() || e.is_request() {
+            CoreError::TransientDatabaseError(message)
+        } else {
+            CoreError::DatabaseError(message)
+        }
+    })
+}
+
+async fn parse_json<T: serde::de::DeserializeOwned>(
+    response: Response,
+    operation: &str,
+) -> Result<T> {
+    response.json().await.map_err(|e| {
+        CoreError::DatabaseError(format!(
+            "Cosmos DB {} returned an invalid response: {}",
+            operation, e
+        ))
+    })
+}
+
+async fn unexpected_response(operation: &str, response: Response) -> CoreError {
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    let message = serde_json::from_str::<Value>(&body)
+        .ok()
+        .and_then(|value| value["message"].as_str().map(str::to_string))
+        .unwrap_or(body);
+    let message = format!(
+        "Cosmos DB {} failed with status {}: {}",
+        operation, status, message
+    );
+    // Timeouts, throttling, "retry with" and service failures may succeed when repeated.
+    if status == StatusCode::REQUEST_TIMEOUT
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || status.as_u16() == 449
+        || status.is_server_error()
+    {
+        CoreError::TransientDatabaseError(message)
+    } else {
+        CoreError::DatabaseError(message)
+    }
+}
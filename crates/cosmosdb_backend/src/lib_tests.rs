@@ -0,0 +1,611 @@
+//! Integration tests for `CosmosDbBackend`.
+//!
+//! The tests run against the Cosmos DB account in `COSMOSDB_ENDPOINT`, authenticating with the
+//! master key in `COSMOSDB_KEY` (the emulator's well-known key by default). When running against
+//! the Cosmos DB emulator, its TLS certificate must be trusted by the system. Without
+//! `COSMOSDB_ENDPOINT` the tests run against an in-process stand-in. Each test creates its own
+//! container.
+
+use super::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use template_teleporter_core::{CosmosDbSettings, DatabaseType};
+use wiremock::matchers::method;
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// A backend on a fresh container, keeping the stand-in server alive while in use.
+struct TestBackend {
+    backend: CosmosDbBackend,
+    _server: Option<MockServer>,
+}
+
+fn unique_container_name() -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    format!(
+        "TemplateState-{}-{}",
+        Utc::now().timestamp_nanos_opt().unwrap_or_default(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+async fn test_config() -> (CosmosDbConfig, Option<MockServer>) {
+    let (endpoint, server) = match std::env::var("COSMOSDB_ENDPOINT") {
+        Ok(endpoint) => (endpoint, None),
+        Err(_) => {
+            let server = stand_in::start().await;
+            (server.uri(), Some(server))
+        }
+    };
+    let key = std::env::var("COSMOSDB_KEY").unwrap_or_else(|_| stand_in::MASTER_KEY.to_string());
+    let config = CosmosDbConfig::new(
+        endpoint,
+        key,
+        DEFAULT_DATABASE_NAME,
+        unique_container_name(),
+    );
+    (config, server)
+}
+
+async fn test_backend() -> TestBackend {
+    let (config, server) = test_config().await;
+    let backend = CosmosDbBackend::new(config).unwrap();
+    backend.create_container().await.unwrap();
+    TestBackend {
+        backend,
+        _server: server,
+    }
+}
+
+fn state_for(repo: &str, template_path: &str) -> TemplateState {
+    TemplateState {
+        repo: repo.to_string(),
+        template_path: template_path.to_string(),
+        source_repository: "owner/master".to_string(),
+        master_checksum: "checksum".to_string(),
+        deployed_checksum: Some("deployed".to_string()),
+        last_updated_utc: Utc::now(),
+        version: 0,
+    }
+}
+
+fn keys(page: &StatePage) -> Vec<String> {
+    page.states.iter().map(|s| s.key().to_string()).collect()
+}
+
+#[test]
+fn test_authorization_token_matches_documented_example() {
+    // The example from the Cosmos DB access control documentation.
+    let key = BASE64
+        .decode("dsZQi3KtZmCv1ljt3VNWNm7sQUF1y5rJfC6kv5JiwvW0EndXdDku/dkKBp8/ufDToSxLzR4y+O/0H/t4bQtVNw==")
+        .unwrap();
+
+    let token = authorization_token(
+        &key,
+        "GET",
+        "dbs",
+        "dbs/ToDoList",
+        "Thu, 27 Apr 2017 00:51:12 GMT",
+    );
+
+    assert_eq!(
+        token.to_lowercase(),
+        "type%3dmaster%26ver%3d1%2e0%26sig%3dc09pevjrgp2uqrkr934kfbtqhbyc7tvr3ohyqlu%2bc%2bc%3d"
+    );
+}
+
+#[test]
+fn test_cosmosdb_config_from_app_config() {
+    let app_config = AppConfig::new(DatabaseType::Cosmosdb, "TemplateState")
+        .with_database_endpoint("https://account.documents.azure.com");
+
+    let config = CosmosDbConfig::from_app_config(&app_config, "a2V5")
+        .unwrap()
+        .with_database("Other");
+
+    assert_eq!(config.endpoint, "https://account.documents.azure.com");
+    assert_eq!(config.database, "Other");
+    assert_eq!(config.container, "TemplateState");
+    assert!(!format!("{:?}", config).contains("a2V5"));
+}
+
+#[test]
+fn test_cosmosdb_config_from_app_config_database() {
+    let mut app_config = AppConfig::new(DatabaseType::Cosmosdb, "TemplateState")
+        .with_database_endpoint("https://account.documents.azure.com");
+    assert_eq!(
+        CosmosDbConfig::from_app_config(&app_config, "a2V5")
+            .unwrap()
+            .database,
+        DEFAULT_DATABASE_NAME
+    );
+
+    app_config.cosmosdb = Some(CosmosDbSettings {
+        database: Some("Configured".to_string()),
+        master_key_env: None,
+    });
+    let config = CosmosDbConfig::from_app_config(&app_config, "a2V5").unwrap();
+
+    assert_eq!(config.database, "Configured");
+}
+
+#[test]
+fn test_cosmosdb_config_from_app_config_requires_endpoint() {
+    let app_config = AppConfig::new(DatabaseType::Cosmosdb, "TemplateState");
+
+    let result = CosmosDbConfig::from_app_config(&app_config, "a2V5");
+
+    assert!(matches!(result, Err(CoreError::MissingConfiguration(_))));
+}
+
+#[test]
+fn test_cosmosdb_backend_new_rejects_invalid_master_key() {
+    let config = CosmosDbConfig::new("https://localhost:8081", "not base64!", "db", "container");
+
+    let result = CosmosDbBackend::new(config);
+
+    assert!(matches!(result, Err(CoreError::DatabaseError(msg)) if msg.contains("master key")));
+}
+
+#[tokio::test]
+async fn test_cosmosdb_backend_wrong_master_key_is_database_error() {
+    let test = test_backend().await;
+    let mut config = test.backend.config().clone();
+    config.master_key = BASE64.encode("wrong key");
+    let backend = CosmosDbBackend::new(config).unwrap();
+
+    let result = backend
+        .get_state(&StateKey::new("org/a", "README.md"))
+        .await;
+
+    assert!(matches!(result, Err(CoreError::DatabaseError(msg)) if msg.contains("401")));
+}
+
+#[tokio::test]
+async fn test_cosmosdb_backend_throttling_is_transient_database_error() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(429).set_body_json(json!({
+            "code": "TooManyRequests",
+            "message": "Request rate is large.",
+        })))
+        .mount(&server)
+        .await;
+    let config = CosmosDbConfig::new(
+        server.uri(),
+        stand_in::MASTER_KEY,
+        DEFAULT_DATABASE_NAME,
+        unique_container_name(),
+    );
+    let backend = CosmosDbBackend::new(config).unwrap();
+
+    let result = backend
+        .get_state(&StateKey::new("org/a", "README.md"))
+        .await;
+
+    assert!(matches!(result, Err(CoreError::TransientDatabaseError(msg)) if msg.contains("429")));
+}
+
+#[tokio::test]
+async fn test_cosmosdb_backend_create_container_is_idempotent() {
+    let test = test_backend().await;
+
+    test.backend.create_container().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_cosmosdb_backend_update_and_get_state() {
+    let test = test_backend().await;
+    let state = state_for("org/a", ".github/PULL_REQUEST_TEMPLATE.md");
+
+    test.backend.update_state(&state).await.unwrap();
+    let stored = test.backend.get_state(&state.key()).await.unwrap().unwrap();
+
+    assert_eq!(
+        stored,
+        TemplateState {
+            version: 1,
+            ..state
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_cosmosdb_backend_long_template_path() {
+    let test = test_backend().await;
+    let long_path = format!("{}/README.md", "docs".repeat(60));
+    let states = [
+        state_for("org/a", &long_path),
+        // Shares the shortened prefix of the ID, but not its hash
+        state_for("org/a", &format!("{}/LICENSE", "docs".repeat(60))),
+    ];
+
+    test.backend.update_states(&states).await.unwrap();
+
+    assert!(document_id(&long_path).len() <= MAX_ID_LEN);
+    let stored = test
+        .backend
+        .get_state(&StateKey::new("org/a", &long_path))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.template_path, long_path);
+    let page = test
+        .backend
+        .list_states(&StateQuery::default())
+        .await
+        .unwrap();
+    assert_eq!(page.states.len(), 2);
+}
+
+#[tokio::test]
+async fn test_cosmosdb_backend_get_state_not_found() {
+    let test = test_backend().await;
+
+    let result = test
+        .backend
+        .get_state(&StateKey::new("org/a", "missing"))
+        .await
+        .unwrap();
+
+    assert_eq!(result, None);
+}
+
+#[tokio::test]
+async fn test_cosmosdb_backend_update_state_overwrites_and_increments_version() {
+    let test = test_backend().await;
+    let mut state = state_for("org/a", "README.md");
+    test.backend.update_state(&state).await.unwrap();
+
+    state.master_checksum = "updated".to_string();
+    state.deployed_checksum = None;
+    test.backend.update_state(&state).await.unwrap();
+
+    let stored = test.backend.get_state(&state.key()).await.unwrap().unwrap();
+    assert_eq!(stored.master_checksum, "updated");
+    assert_eq!(stored.deployed_checksum, None);
+    assert_eq!(stored.version, 2);
+}
+
+#[tokio::test]
+async fn test_cosmosdb_backend_update_state_if() {
+    let test = test_backend().await;
+    let backend = &test.backend;
+    let state = state_for("org/a", "README.md");
+
+    assert_eq!(backend.update_state_if(&state, None).await.unwrap(), 1);
+    assert_eq!(backend.update_state_if(&state, Some(1)).await.unwrap(), 2);
+
+    let result = backend.update_state_if(&state, Some(1)).await;
+    assert!(matches!(
+        result,
+        Err(CoreError::StateConflict {
+            expected: Some(1),
+            actual: Some(2),
+            ..
+        })
+    ));
+    let result = backend.update_state_if(&state, None).await;
+    assert!(matches!(
+        result,
+        Err(CoreError::StateConflict {
+            expected: None,
+            actual: Some(2),
+            ..
+        })
+    ));
+    let missing = state_for("org/a", "missing");
+    let result = backend.update_state_if(&missing, Some(1)).await;
+    assert!(matches!(
+        result,
+        Err(CoreError::StateConflict { actual: None, .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_cosmosdb_backend_update_state_if_allows_one_concurrent_writer() {
+    let test = test_backend().await;
+    let backend = Arc::new(test.backend);
+    backend
+        .update_state(&state_for("org/a", "README.md"))
+        .await
+        .unwrap();
+
+    // Writers reading version 1 after another writer replaced it fail the version comparison,
+    // those reading it before fail the etag check.
+    let tasks = (0..5)
+        .map(|_| {
+            let backend = Arc::clone(&backend);
+            tokio::spawn(async move {
+                backend
+                    .update_state_if(&state_for("org/a", "README.md"), Some(1))
+                    .await
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut successes = 0;
+    for task in tasks {
+        match task.await.unwrap() {
+            Ok(version) => {
+                assert_eq!(version, 2);
+                successes += 1;
+            }
+            Err(CoreError::StateConflict { .. }) => {}
+            Err(e) => panic!("Unexpected error: {:?}", e),
+        }
+    }
+    assert_eq!(successes, 1);
+}
+
+#[tokio::test]
+async fn test_cosmosdb_backend_update_state_retries_concurrent_writes() {
+    let test = test_backend().await;
+    let backend = Arc::new(test.backend);
+
+    let tasks = (0..5)
+        .map(|_| {
+            let backend = Arc::clone(&backend);
+            tokio::spawn(
+                async move { backend.update_state(&state_for("org/a", "README.md")).await },
+            )
+        })
+        .collect::<Vec<_>>();
+    for task in tasks {
+        task.await.unwrap().unwrap();
+    }
+
+    let stored = backend
+        .get_state(&StateKey::new("org/a", "README.md"))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.version, 5);
+}
+
+#[tokio::test]
+async fn test_cosmosdb_backend_list_states_filters_and_sorts() {
+    let test = test_backend().await;
+    let backend = &test.backend;
+    for (repo, path) in [
+        ("org/b", ".github/CODEOWNERS"),
+        ("org/a", ".github/PULL_REQUEST_TEMPLATE.md"),
+        ("org/a", "README.md"),
+        ("org/a", ".github/CODEOWNERS"),
+        ("org/a", ".githubx"),
+    ] {
+        backend.update_state(&state_for(repo, path)).await.unwrap();
+    }
+
+    let all = backend.list_states(&StateQuery::default()).await.unwrap();
+    assert_eq!(
+        keys(&all),
+        vec![
+            "org/a:.github/CODEOWNERS",
+            "org/a:.github/PULL_REQUEST_TEMPLATE.md",
+            "org/a:.githubx",
+            "org/a:README.md",
+            "org/b:.github/CODEOWNERS",
+        ]
+    );
+    assert_eq!(all.next_page_token, None);
+
+    let by_prefix = backend
+        .list_states(&StateQuery::default().with_template_path_prefix(".github/"))
+        .await
+        .unwrap();
+    assert_eq!(
+        keys(&by_prefix),
+        vec![
+            "org/a:.github/CODEOWNERS",
+            "org/a:.github/PULL_REQUEST_TEMPLATE.md",
+            "org/b:.github/CODEOWNERS",
+        ]
+    );
+
+    let by_repo = backend
+        .list_states(
+            &StateQuery::default()
+                .with_repo("org/a")
+                .with_template_path_prefix(".github/"),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        keys(&by_repo),
+        vec![
+            "org/a:.github/CODEOWNERS",
+            "org/a:.github/PULL_REQUEST_TEMPLATE.md",
+        ]
+    );
+}
+
+async fn list_pages(backend: &CosmosDbBackend, mut query: StateQuery) -> Vec<Vec<String>> {
+    let mut pages = Vec::new();
+    loop {
+        let page = backend.list_states(&query).await.unwrap();
+        pages.push(keys(&page));
+        match page.next_page_token {
+            Some(token) => query = query.with_page_token(token),
+            None => break,
+        }
+    }
+    pages
+}
+
+#[tokio::test]
+async fn test_cosmosdb_backend_list_states_paginates() {
+    let test = test_backend().await;
+    for (repo, path) in [
+        ("org/a", "x"),
+        ("org/a", "y"),
+        ("org/a", "z"),
+        ("org/a", "zz"),
+        ("org/b", "x"),
+        ("org/c", "x"),
+    ] {
+        test.backend
+            .update_state(&state_for(repo, path))
+            .await
+            .unwrap();
+    }
+
+    let across_repos = list_pages(&test.backend, StateQuery::default().with_limit(4)).await;
+    assert_eq!(
+        across_repos,
+        vec![
+            vec!["org/a:x", "org/a:y", "org/a:z", "org/a:zz"],
+            vec!["org/b:x", "org/c:x"],
+        ]
+    );
+
+    let one_repo = list_pages(
+        &test.backend,
+        StateQuery::default().with_repo("org/a").with_limit(3),
+    )
+    .await;
+    assert_eq!(
+        one_repo,
+        vec![vec!["org/a:x", "org/a:y", "org/a:z"], vec!["org/a:zz"]]
+    );
+}
+
+#[tokio::test]
+async fn test_cosmosdb_backend_list_states_with_token_from_other_repo() {
+    let test = test_backend().await;
+    test.backend
+        .update_state(&state_for("org/b", "x"))
+        .await
+        .unwrap();
+
+    let before = StateQuery::default()
+        .with_repo("org/b")
+        .with_page_token(StatePage::token_after(&StateKey::new("org/a", "z")));
+    let after = StateQuery::default()
+        .with_repo("org/b")
+        .with_page_token(StatePage::token_after(&StateKey::new("org/c", "a")));
+
+    assert_eq!(
+        keys(&test.backend.list_states(&before).await.unwrap()),
+        vec!["org/b:x"]
+    );
+    assert!(test
+        .backend
+        .list_states(&after)
+        .await
+        .unwrap()
+        .states
+        .is_empty());
+}
+
+#[tokio::test]
+async fn test_cosmosdb_backend_scan_states_reads_every_state_once() {
+    let test = test_backend().await;
+    for (repo, path) in [
+        ("org/a", "x"),
+        ("org/a", "y"),
+        ("org/b", "x"),
+        ("org/c", "x"),
+        ("org/c", "y"),
+    ] {
+        test.backend
+            .update_state(&state_for(repo, path))
+            .await
+            .unwrap();
+    }
+
+    let mut scanned = Vec::new();
+    let mut page_token = None;
+    loop {
+        let page = test
+            .backend
+            .scan_states(page_token.as_deref(), 1)
+            .await
+            .unwrap();
+        assert!(page.states.len() <= 1);
+        scanned.extend(keys(&page));
+        page_token = page.next_page_token;
+        if page_token.is_none() {
+            break;
+        }
+    }
+
+    scanned.sort();
+    assert_eq!(
+        scanned,
+        vec!["org/a:x", "org/a:y", "org/b:x", "org/c:x", "org/c:y"]
+    );
+}
+
+#[tokio::test]
+async fn test_cosmosdb_backend_delete_state() {
+    let test = test_backend().await;
+    let state = state_for("org/a", "README.md");
+    test.backend.update_state(&state).await.unwrap();
+
+    assert!(test.backend.delete_state(&state.key()).await.unwrap());
+    assert_eq!(test.backend.get_state(&state.key()).await.unwrap(), None);
+    assert!(!test.backend.delete_state(&state.key()).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_cosmosdb_backend_history_retention() {
+    let test = test_backend().await;
+    let backend = CosmosDbBackend::new(test.backend.config().clone())
+        .unwrap()
+        .with_history_retention(2);
+    let mut state = state_for("org/a", "README.md");
+    for checksum in ["one", "two", "three"] {
+        state.master_checksum = checksum.to_string();
+        backend.update_state(&state).await.unwrap();
+    }
+
+    let history = backend.get_state_history(&state.key()).await.unwrap();
+
+    let checksums: Vec<_> = history.iter().map(|s| s.master_checksum.as_str()).collect();
+    assert_eq!(checksums, ["three", "two"]);
+    assert_eq!(history[1].version, 2);
+    assert_eq!(history[1].key(), state.key());
+}
+
+#[tokio::test]
+async fn test_cosmosdb_backend_get_states_returns_only_requested_keys() {
+    let test = test_backend().await;
+    for (repo, path) in [
+        ("org/a", "x.md"),
+        ("org/a", "y.md"),
+        ("org/b", "x.md"),
+        ("org/b", "y.md"),
+    ] {
+        test.backend
+            .update_state(&state_for(repo, path))
+            .await
+            .unwrap();
+    }
+    let keys = [
+        StateKey::new("org/b", "y.md"),
+        StateKey::new("org/a", "x.md"),
+        StateKey::new("org/c", "x.md"),
+    ];
+
+    // The query also matches org/a:y.md and org/b:x.md, which were not requested
+    let states = test.backend.get_states(&keys).await.unwrap();
+
+    let found: Vec<Option<StateKey>> = states
+        .iter()
+        .map(|state| state.as_ref().map(TemplateState::key))
+        .collect();
+    assert_eq!(found, [Some(keys[0].clone()), Some(keys[1].clone()), None]);
+}
+
+#[tokio::test]
+async fn test_cosmosdb_backend_conformance() {
+    let test_backends = std::sync::Mutex::new(Vec::new());
+    template_teleporter_core::conformance::run_all(|| async {
+        let test = test_backend().await;
+        let backend = CosmosDbBackend::new(test.backend.config().clone()).unwrap();
+        // Keeps the stand-in servers running until the suite has finished.
+        test_backends.lock().unwrap().push(test);
+        Arc::new(backend) as Arc<dyn StatePersistence>
+    })
+    .await;
+}
@@ -0,0 +1,360 @@
+//! A minimal in-process stand-in for the Cosmos DB emulator, used by the tests when no
+//! `COSMOSDB_ENDPOINT` is configured.
+//!
+//! It serves the REST operations used by `CosmosDbBackend`, checks the master key signature of
+//! every request and understands the shapes of the SQL queries the backend sends. Query results
+//! are returned at most `PAGE_SIZE` documents at a time, or fewer if the request sets
+//! `x-ms-max-item-count`, and cross-partition queries return documents in reverse order, so the
+//! backend's continuation handling and sorting are exercised as they would be against a large
+//! container.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use percent_encoding::percent_decode_str;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use wiremock::matchers::any;
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+use crate::MAX_ID_LEN;
+
+/// The master key accepted by the stand-in: the well-known key of the Cosmos DB emulator.
+pub(crate) const MASTER_KEY: &str =
+    "C2y6yDjf5/R+ob0N8A7Cgv30VRDJIWEHLM+4QDU5DE2nQ9nDuVTqobD4b8mGGyPMbIZnqyMsEcaGQy67XIw/Jw==";
+
+const PAGE_SIZE: usize = 2;
+
+/// Documents of one container, keyed by partition key and ID.
+type Container = BTreeMap<(String, String), Value>;
+
+/// Starts a stand-in server and returns it; its `uri()` is the endpoint to use.
+pub(crate) async fn start() -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(any())
+        .respond_with(StandIn::default())
+        .mount(&server)
+        .await;
+    server
+}
+
+#[derive(Default)]
+struct Store {
+    databases: HashSet<String>,
+    containers: HashMap<String, Container>,
+    last_etag: u64,
+}
+
+#[derive(Default)]
+struct StandIn {
+    store: Arc<Mutex<Store>>,
+}
+
+type Outcome = (u16, Value, Option<String>);
+
+impl Respond for StandIn {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let path = request.url.path().trim_start_matches('/').to_string();
+        let segments: Vec<&str> = path.split('/').collect();
+        let method = request.method.as_str();
+        let body: Value = serde_json::from_slice(&request.body).unwrap_or_default();
+        let mut store = self.store.lock().unwrap();
+
+        let (status, body, continuation) = match (method, segments.as_slice()) {
+            ("POST", ["dbs"]) => {
+                authorized(request, "dbs", "", || create(&mut store.databases, &body))
+            }
+            ("POST", ["dbs", database, "colls"]) => {
+                let link = format!("dbs/{}", database);
+                authorized(request, "colls", &link, || {
+                    if !store.databases.contains(*database) {
+                        return error(404, "NotFound", "Database not found");
+                    }
+                    let container =
+                        format!("{}/colls/{}", link, body["id"].as_str().unwrap_or_default());
+                    if store.containers.contains_key(&container) {
+                        return error(409, "Conflict", "Container already exists");
+                    }
+                    store.containers.insert(container, Container::new());
+                    (201, body.clone(), None)
+                })
+            }
+            ("POST", ["dbs", database, "colls", container, "docs"]) => {
+                let link = format!("dbs/{}/colls/{}", database, container);
+                authorized(request, "docs", &link, || {
+                    let Store {
+                        containers,
+                        last_etag,
+                        ..
+                    } = &mut *store;
+                    match containers.get_mut(&link) {
+                        None => error(404, "NotFound", "Container not found"),
+                        Some(documents) if header(request, "x-ms-documentdb-isquery").is_some() => {
+                            query(request, documents, &body)
+                        }
+                        Some(documents) => create_document(request, documents, last_etag, body),
+                    }
+                })
+            }
+            (_, ["dbs", database, "colls", container, "docs", id]) => {
+                let link = format!("dbs/{}/colls/{}", database, container);
+                let document_link = format!("{}/docs/{}", link, id);
+                authorized(request, "docs", &document_link, || {
+                    let Store {
+                        containers,
+                        last_etag,
+                        ..
+                    } = &mut *store;
+                    match containers.get_mut(&link) {
+                        None => error(404, "NotFound", "Container not found"),
+                        Some(documents) => {
+                            let key = (partition_key(request).unwrap_or_default(), id.to_string());
+                            document_operation(request, documents, last_etag, key, body)
+                        }
+                    }
+                })
+            }
+            _ => error(400, "BadRequest", "Unsupported request"),
+        };
+
+        let mut response = ResponseTemplate::new(status).set_body_json(body);
+        if let Some(continuation) = continuation {
+            response = response.insert_header("x-ms-continuation", continuation);
+        }
+        response
+    }
+}
+
+/// Runs `operation` if the request carries a valid master key signature for the resource.
+fn authorized(
+    request: &Request,
+    resource_type: &str,
+    resource_link: &str,
+    operation: impl FnOnce() -> Outcome,
+) -> Outcome {
+    let date = header(request, "x-ms-date").unwrap_or_default();
+    let payload = format!(
+        "{}\n{}\n{}\n{}\n\n",
+        request.method.as_str().to_lowercase(),
+        resource_type,
+        resource_link,
+        date.to_lowercase()
+    );
+    let mut mac = Hmac::<Sha256>::new_from_slice(&BASE64.decode(MASTER_KEY).unwrap()).unwrap();
+    mac.update(payload.as_bytes());
+    let expected = format!(
+        "type=master&ver=1.0&sig={}",
+        BASE64.encode(mac.finalize().into_bytes())
+    );
+
+    let authorization = header(request, "authorization").unwrap_or_default();
+    let decoded = percent_decode_str(&authorization).decode_utf8_lossy();
+    if decoded != expected || header(request, "x-ms-version").is_none() {
+        return error(
+            401,
+            "Unauthorized",
+            "The input authorization token is not valid",
+        );
+    }
+    operation()
+}
+
+fn create(databases: &mut HashSet<String>, body: &Value) -> Outcome {
+    let id = body["id"].as_str().unwrap_or_default().to_string();
+    if !databases.insert(id) {
+        return error(409, "Conflict", "Database already exists");
+    }
+    (201, body.clone(), None)
+}
+
+fn create_document(
+    request: &Request,
+    documents: &mut Container,
+    last_etag: &mut u64,
+    mut document: Value,
+) -> Outcome {
+    let partition = partition_key(request).unwrap_or_default();
+    if document["repo"].as_str() != Some(partition.as_str()) {
+        return error(400, "BadRequest", "Partition key mismatch");
+    }
+    if document["id"].as_str().unwrap_or_default().len() > MAX_ID_LEN {
+        return error(400, "BadRequest", "The size of the id exceeds the limit");
+    }
+    let key = (
+        partition,
+        document["id"].as_str().unwrap_or_default().to_string(),
+    );
+    if documents.contains_key(&key) {
+        return error(409, "Conflict", "Document already exists");
+    }
+    *last_etag += 1;
+    document["_etag"] = json!(format!("\"{}\"", last_etag));
+    documents.insert(key, document.clone());
+    (201, document, None)
+}
+
+fn document_operation(
+    request: &Request,
+    documents: &mut Container,
+    last_etag: &mut u64,
+    key: (String, String),
+    mut document: Value,
+) -> Outcome {
+    let Some(current) = documents.get(&key) else {
+        return error(404, "NotFound", "Document not found");
+    };
+    match request.method.as_str() {
+        "GET" => (200, current.clone(), None),
+        "DELETE" => {
+            documents.remove(&key);
+            (204, Value::Null, None)
+        }
+        "PUT" => {
+            if header(request, "if-match").is_some_and(|etag| current["_etag"] != json!(etag)) {
+                return error(412, "PreconditionFailed", "Operation cannot be performed");
+            }
+            *last_etag += 1;
+            document["_etag"] = json!(format!("\"{}\"", last_etag));
+            documents.insert(key, document.clone());
+            (200, document, None)
+        }
+        _ => error(405, "MethodNotAllowed", "Unsupported method"),
+    }
+}
+
+/// Evaluates queries of the shape
+/// `SELECT [TOP n] * FROM c [WHERE <term> AND ...] [ORDER BY c.template_path]`.
+fn query(request: &Request, documents: &Container, body: &Value) -> Outcome {
+    let partition = partition_key(request);
+    if partition.is_none()
+        && header(request, "x-ms-documentdb-query-enablecrosspartition").as_deref() != Some("True")
+    {
+        return error(
+            400,
+            "BadRequest",
+            "Cross partition query is required but disabled",
+        );
+    }
+    let parameters: HashMap<&str, &Value> = body["parameters"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|p| Some((p["name"].as_str()?, &p["value"])))
+        .collect();
+    let sql = body["query"].as_str().unwrap_or_default();
+
+    let rest = sql.strip_prefix("SELECT ").unwrap_or_default();
+    let (top, rest) = match rest.strip_prefix("TOP ") {
+        Some(rest) => {
+            let (top, rest) = rest.split_once(' ').unwrap_or_default();
+            (top.parse::<usize>().ok(), rest)
+        }
+        None => (None, rest),
+    };
+    let rest = rest.strip_prefix("* FROM c").unwrap_or_default();
+    let (filter, ordered) = match rest.strip_suffix(" ORDER BY c.template_path") {
+        Some(filter) => (filter, true),
+        None => (rest, false),
+    };
+    let terms: Vec<&str> = filter
+        .strip_prefix(" WHERE ")
+        .map(|conditions| conditions.split(" AND ").collect())
+        .unwrap_or_default();
+    if ordered && partition.is_none() {
+        return error(
+            400,
+            "BadRequest",
+            "Cross partition ORDER BY cannot be served",
+        );
+    }
+
+    let mut matching: Vec<&Value> = documents
+        .iter()
+        .filter(|((repo, _), _)| partition.as_ref().is_none_or(|p| p == repo))
+        .map(|(_, document)| document)
+        .filter(|document| {
+            terms
+                .iter()
+                .all(|term| matches(document, term, &parameters))
+        })
+        .collect();
+    if ordered {
+        matching.sort_by_key(|document| document["template_path"].as_str().map(str::to_string));
+    } else {
+        matching.reverse();
+    }
+    if let Some(top) = top {
+        matching.truncate(top);
+    }
+
+    let offset: usize = header(request, "x-ms-continuation")
+        .and_then(|c| c.parse().ok())
+        .unwrap_or_default();
+    let page_size = header(request, "x-ms-max-item-count")
+        .and_then(|count| count.parse().ok())
+        .map_or(PAGE_SIZE, |count: usize| count.min(PAGE_SIZE));
+    let page: Vec<_> = matching.iter().skip(offset).take(page_size).collect();
+    let continuation =
+        (offset + page_size < matching.len()).then(|| (offset + page_size).to_string());
+    (
+        200,
+        json!({ "Documents": page, "_count": page.len() }),
+        continuation,
+    )
+}
+
+fn matches(document: &Value, term: &str, parameters: &HashMap<&str, &Value>) -> bool {
+    let text = |field: &str| document[field].as_str().unwrap_or_default().to_string();
+    let parameter = |name: &str| {
+        parameters
+            .get(name)
+            .and_then(|value| value.as_str())
+            .unwrap_or_default()
+            .to_string()
+    };
+    if let Some(name) = term.strip_prefix("c.repo = ") {
+        text("repo") == parameter(name)
+    } else if let Some(name) = term.strip_prefix("c.template_path > ") {
+        text("template_path") > parameter(name)
+    } else if let Some((name, field)) = term
+        .strip_prefix("ARRAY_CONTAINS(")
+        .and_then(|rest| rest.strip_suffix(')'))
+        .and_then(|args| args.split_once(", c."))
+    {
+        parameters
+            .get(name)
+            .and_then(|value| value.as_array())
+            .is_some_and(|values| {
+                values
+                    .iter()
+                    .any(|value| value.as_str() == Some(&text(field)))
+            })
+    } else if let Some(name) = term
+        .strip_prefix("STARTSWITH(c.template_path, ")
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        text("template_path").starts_with(&parameter(name))
+    } else {
+        false
+    }
+}
+
+fn header(request: &Request, name: &str) -> Option<String> {
+    request
+        .headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+fn partition_key(request: &Request) -> Option<String> {
+    let value: Value =
+        serde_json::from_str(&header(request, "x-ms-documentdb-partitionkey")?).ok()?;
+    value[0].as_str().map(str::to_string)
+}
+
+fn error(status: u16, code: &str, message: &str) -> Outcome {
+    (status, json!({ "code": code, "message": message }), None)
+}
@@ -0,0 +1,830 @@
+//! Implements `DeveloperPlatform` for Azure Repos, authenticating with a personal access token
+//! or a managed identity.
+//!
+//! The organization of a repository holds both the Azure DevOps organization and the project,
+//! e.g. `contoso/platform` for the repository `contoso/platform/service`. Templates, the master
+//! configuration and target repository files are read through the items API. Updates are
+//! committed with the pushes API onto a dedicated branch, for which a pull request is opened.
+//!
+//! All requests go to a configurable base URL, so the client can be pointed at an Azure DevOps
+//! Server collection or at a local stub server in tests.
+
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use reqwest::{Method, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fmt;
+use tokio::sync::Mutex;
+
+use crate::checksum::calculate_checksum;
+use crate::master_config::{repo_full_name, split_repo_name, MasterConfig, MASTER_CONFIG_FILE};
+use crate::update_branch::{
+    update_branch_name, update_commit_message, update_description, update_title,
+};
+use crate::{
+    DeveloperPlatform, PlatformError, RepoInfo, TemplateCategory, TemplateChange, TemplateMetadata,
+    TemplatePath, UpdateResult,
+};
+
+#[cfg(test)]
+#[path = "azure_devops_tests.rs"]
+mod tests;
+
+/// The base URL of Azure DevOps Services.
+pub const AZURE_DEVOPS_URL: &str = "https://dev.azure.com";
+
+/// The endpoint of the Azure Instance Metadata Service issuing managed identity tokens.
+pub const AZURE_IDENTITY_ENDPOINT: &str = "http://169.254.169.254/metadata/identity/oauth2/token";
+
+/// The Microsoft Entra ID resource of Azure DevOps, which managed identity tokens are requested
+/// for.
+const AZURE_DEVOPS_RESOURCE: &str = "499b84ac-1321-427f-aa17-267ca6a5a798";
+
+const API_VERSION: &str = "7.1";
+
+/// Managed identity tokens are refreshed when they expire within this many seconds.
+const TOKEN_REFRESH_MARGIN_SECONDS: i64 = 300;
+
+/// The number of changes requested per page from the diffs API.
+const DIFF_PAGE_SIZE: usize = 100;
+
+/// The object ID git uses for refs that do not exist.
+const ZERO_OBJECT_ID: &str = "0000000000000000000000000000000000000000";
+
+const USER_AGENT: &str = "template-teleporter";
+
+/// How an `AzureDevOpsClient` authenticates.
+#[derive(Clone)]
+pub enum AzureDevOpsAuth {
+    /// A personal access token with the `Code (Read & write)` scope.
+    PersonalAccessToken(String),
+
+    /// The managed identity of the Azure resource the client runs on, which has to be added as
+    /// a user of the organization.
+    ManagedIdentity {
+        /// The client ID of a user-assigned identity, or `None` for the system-assigned one.
+        client_id: Option<String>,
+    },
+}
+
+// Manual Debug implementation so that the token never ends up in logs.
+impl fmt::Debug for AzureDevOpsAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PersonalAccessToken(_) => f
+                .debug_tuple("PersonalAccessToken")
+                .field(&"<redacted>")
+                .finish(),
+            Self::ManagedIdentity { client_id } => f
+                .debug_struct("ManagedIdentity")
+                .field("client_id", client_id)
+                .finish(),
+        }
+    }
+}
+
+/// The configuration of an `AzureDevOpsClient`.
+///
+/// # Example
+/// ```rust
+/// use template_teleporter_developer_platforms::{AzureDevOpsAuth, AzureDevOpsConfig, RepoInfo};
+/// let config = AzureDevOpsConfig::new(
+///     AzureDevOpsAuth::ManagedIdentity { client_id: None },
+///     RepoInfo::new("contoso/platform".to_string(), "template-master".to_string(), "main".to_string()),
+/// )
+/// .with_api_base_url("https://devops.example.com/tfs");
+/// assert_eq!(config.api_base_url, "https://devops.example.com/tfs");
+/// ```
+#[derive(Debug, Clone)]
+pub struct AzureDevOpsConfig {
+    /// How the client authenticates.
+    pub auth: AzureDevOpsAuth,
+
+    /// The master template repository, whose organization is `<organization>/<project>`.
+    pub master_repo: RepoInfo,
+
+    /// The base URL the organization is appended to, e.g. the URL of an Azure DevOps Server
+    /// instance.
+    pub api_base_url: String,
+
+    /// The endpoint managed identity tokens are requested from.
+    pub identity_endpoint: String,
+}
+
+impl AzureDevOpsConfig {
+    /// Creates a new `AzureDevOpsConfig` for Azure DevOps Services.
+    pub fn new(auth: AzureDevOpsAuth, master_repo: RepoInfo) -> Self {
+        Self {
+            auth,
+            master_repo,
+            api_base_url: AZURE_DEVOPS_URL.to_string(),
+            identity_endpoint: AZURE_IDENTITY_ENDPOINT.to_string(),
+        }
+    }
+
+    /// Sets the base URL, e.g. for an Azure DevOps Server instance.
+    pub fn with_api_base_url(mut self, api_base_url: impl Into<String>) -> Self {
+        self.api_base_url = api_base_url.into();
+        self
+    }
+
+    /// Sets the endpoint managed identity tokens are requested from.
+    pub fn with_identity_endpoint(mut self, identity_endpoint: impl Into<String>) -> Self {
+        self.identity_endpoint = identity_endpoint.into();
+        self
+    }
+}
+
+/// A `DeveloperPlatform` implementation for Azure Repos.
+pub struct AzureDevOpsClient {
+    auth: AzureDevOpsAuth,
+    master_repo: RepoInfo,
+    api_base_url: Url,
+    identity_endpoint: Url,
+    http: reqwest::Client,
+
+    /// The cached managed identity token.
+    token: Mutex<Option<AccessToken>>,
+}
+
+// Manual Debug implementation so that the tokens never end up in logs.
+impl fmt::Debug for AzureDevOpsClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AzureDevOpsClient")
+            .field("auth", &self.auth)
+            .field("master_repo", &self.master_repo)
+            .field("api_base_url", &self.api_base_url.as_str())
+            .finish_non_exhaustive()
+    }
+}
+
+impl AzureDevOpsClient {
+    /// Creates a new `AzureDevOpsClient`.
+    ///
+    /// # Errors
+    /// Returns `PlatformError::ConfigError` if the base URL or the identity endpoint is invalid.
+    pub fn new(config: AzureDevOpsConfig) -> Result<Self, PlatformError> {
+        let parse = |name: &str, url: &str| {
+            Url::parse(url).map_err(|e| {
+                PlatformError::ConfigError(format!(
+                    "Invalid Azure DevOps {} '{}': {}",
+                    name, url, e
+                ))
+            })
+        };
+        let api_base_url = parse("URL", &config.api_base_url)?;
+        let identity_endpoint = parse("identity endpoint", &config.identity_endpoint)?;
+        let http = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .build()
+            .map_err(|e| PlatformError::ConfigError(e.to_string()))?;
+
+        Ok(Self {
+            auth: config.auth,
+            master_repo: config.master_repo,
+            api_base_url,
+            identity_endpoint,
+            http,
+            token: Mutex::new(None),
+        })
+    }
+
+    /// Returns the value of the `Authorization` header, requesting a new managed identity token
+    /// if there is no cached token or it is about to expire.
+    async fn authorization(&self) -> Result<String, PlatformError> {
+        let client_id = match &self.auth {
+            AzureDevOpsAuth::PersonalAccessToken(pat) => {
+                return Ok(format!("Basic {}", BASE64.encode(format!(":{}", pat))));
+            }
+            AzureDevOpsAuth::ManagedIdentity { client_id } => client_id,
+        };
+
+        // Hold the lock while refreshing so concurrent callers do not request tokens twice.
+        let mut token = self.token.lock().await;
+        if let Some(token) = token.as_ref() {
+            if token.expires_at - Duration::seconds(TOKEN_REFRESH_MARGIN_SECONDS) > Utc::now() {
+                return Ok(format!("Bearer {}", token.access_token));
+            }
+        }
+
+        let mut url = self.identity_endpoint.clone();
+        url.query_pairs_mut()
+            .append_pair("api-version", "2018-02-01")
+            .append_pair("resource", AZURE_DEVOPS_RESOURCE);
+        if let Some(client_id) = client_id {
+            url.query_pairs_mut().append_pair("client_id", client_id);
+        }
+        let response = self
+            .http
+            .get(url)
+            .header("Metadata", "true")
+            .send()
+            .await
+            .map_err(|e| {
+                PlatformError::AuthError(format!("Failed to request managed identity token: {}", e))
+            })?;
+        if !response.status().is_success() {
+            return Err(match map_error(response).await {
+                PlatformError::ApiError(msg) => PlatformError::AuthError(format!(
+                    "Failed to request managed identity token: {}",
+                    msg
+                )),
+                e => e,
+            });
+        }
+        let response: IdentityToken = parse_json(response).await?;
+        let expires_at = response
+            .expires_on
+            .parse()
+            .ok()
+            .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+            .ok_or_else(|| {
+                PlatformError::InvalidContent(format!(
+                    "Invalid managed identity token expiry '{}'",
+                    response.expires_on
+                ))
+            })?;
+        let header = format!("Bearer {}", response.access_token);
+        *token = Some(AccessToken {
+            access_token: response.access_token,
+            expires_at,
+        });
+        Ok(header)
+    }
+
+    /// Builds an API URL for the given repository from the base URL and the given path
+    /// segments, with the API version set.
+    ///
+    /// # Errors
+    /// Returns `PlatformError::ConfigError` if the organization of the repository is not of the
+    /// form `<organization>/<project>`.
+    fn url(&self, repo: &RepoInfo, segments: &[&str]) -> Result<Url, PlatformError> {
+        let (organization, project) = match repo.org().split_once('/') {
+            Some((organization, project))
+                if !organization.is_empty() && !project.is_empty() && !project.contains('/') =>
+            {
+                (organization, project)
+            }
+            _ => {
+                return Err(PlatformError::ConfigError(format!(
+                    "Azure DevOps repository '{}' is not of the form \
+                     '<organization>/<project>/<name>'",
+                    repo_full_name(repo)
+                )))
+            }
+        };
+
+        let mut url = self.api_base_url.clone();
+        if let Ok(mut path) = url.path_segments_mut() {
+            path.pop_if_empty()
+                .extend([organization, project, "_apis", "git", "repositories"])
+                .push(repo.name())
+                .extend(segments);
+        }
+        url.query_pairs_mut()
+            .append_pair("api-version", API_VERSION);
+        Ok(url)
+    }
+
+    /// Sends an authenticated API request.
+    ///
+    /// # Returns
+    /// `None` if the API responded with 404 Not Found, otherwise the successful response.
+    async fn send(
+        &self,
+        method: Method,
+        url: Url,
+        body: Option<Value>,
+    ) -> Result<Option<reqwest::Response>, PlatformError> {
+        let mut request = self
+            .http
+            .request(method, url)
+            .header("Authorization", self.authorization().await?);
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| PlatformError::ApiError(e.to_string()))?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success()
+            || response.status() == StatusCode::NON_AUTHORITATIVE_INFORMATION
+        {
+            return Err(map_error(response).await);
+        }
+        Ok(Some(response))
+    }
+
+    /// Calls the API.
+    ///
+    /// # Returns
+    /// `None` if the API responded with 404 Not Found, otherwise the deserialized response body.
+    async fn api<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        url: Url,
+        body: Option<Value>,
+    ) -> Result<Option<T>, PlatformError> {
+        match self.send(method, url, body).await? {
+            Some(response) => parse_json(response).await.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Calls the API for the given repository, mapping 404 Not Found to `RepoNotFound`.
+    async fn repo_api<T: DeserializeOwned>(
+        &self,
+        repo: &RepoInfo,
+        method: Method,
+        url: Url,
+        body: Option<Value>,
+    ) -> Result<T, PlatformError> {
+        self.api(method, url, body)
+            .await?
+            .ok_or_else(|| PlatformError::RepoNotFound {
+                org: repo.org().to_string(),
+                name: repo.name().to_string(),
+            })
+    }
+
+    /// Reads a file through the items API at the given branch, or commit if `commit` is set.
+    ///
+    /// # Returns
+    /// `None` if the repository or the file does not exist at the given version.
+    async fn get_item(
+        &self,
+        repo: &RepoInfo,
+        path: &str,
+        version: &str,
+        commit: bool,
+    ) -> Result<Option<Vec<u8>>, PlatformError> {
+        let mut url = self.url(repo, &["items"])?;
+        url.query_pairs_mut()
+            .append_pair("path", &format!("/{}", path))
+            .append_pair("versionDescriptor.version", version)
+            .append_pair(
+                "versionDescriptor.versionType",
+                if commit { "commit" } else { "branch" },
+            )
+            .append_pair("$format", "octetStream");
+        match self.send(Method::GET, url, None).await? {
+            Some(response) => response
+                .bytes()
+                .await
+                .map(|bytes| Some(bytes.to_vec()))
+                .map_err(|e| PlatformError::ApiError(e.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads and parses the master configuration from the master repository.
+    async fn load_config(&self) -> Result<MasterConfig, PlatformError> {
+        let content = self
+            .get_item(
+                &self.master_repo,
+                MASTER_CONFIG_FILE,
+                self.master_repo.default_branch(),
+                false,
+            )
+            .await?
+            .ok_or_else(|| {
+                PlatformError::ConfigError(format!(
+                    "{} not found in {}",
+                    MASTER_CONFIG_FILE,
+                    repo_full_name(&self.master_repo)
+                ))
+            })?;
+        let content =
+            String::from_utf8(content).map_err(|e| PlatformError::InvalidContent(e.to_string()))?;
+        MasterConfig::from_toml_str(&content)
+    }
+
+    /// Returns the commit a branch points to, or `None` if the branch does not exist.
+    async fn branch_head(
+        &self,
+        repo: &RepoInfo,
+        branch: &str,
+    ) -> Result<Option<String>, PlatformError> {
+        let mut url = self.url(repo, &["refs"])?;
+        url.query_pairs_mut()
+            .append_pair("filter", &format!("heads/{}", branch));
+        let refs: List<GitRef> = self.repo_api(repo, Method::GET, url, None).await?;
+        // The filter matches refs by prefix.
+        let name = format!("refs/heads/{}", branch);
+        Ok(refs
+            .value
+            .into_iter()
+            .find(|r| r.name == name)
+            .map(|r| r.object_id))
+    }
+
+    /// Returns the active pull request from `branch` into the default branch, if there is one.
+    async fn find_pull_request(
+        &self,
+        repo: &RepoInfo,
+        branch: &str,
+    ) -> Result<Option<PullRequest>, PlatformError> {
+        let mut url = self.url(repo, &["pullrequests"])?;
+        url.query_pairs_mut()
+            .append_pair(
+                "searchCriteria.sourceRefName",
+                &format!("refs/heads/{}", branch),
+            )
+            .append_pair(
+                "searchCriteria.targetRefName",
+                &format!("refs/heads/{}", repo.default_branch()),
+            )
+            .append_pair("searchCriteria.status", "active");
+        let pull_requests: List<PullRequest> = self.repo_api(repo, Method::GET, url, None).await?;
+        Ok(pull_requests.value.into_iter().next())
+    }
+}
+
+#[async_trait]
+impl DeveloperPlatform for AzureDevOpsClient {
+    async fn list_categories(&self) -> Result<Vec<TemplateCategory>, PlatformError> {
+        Ok(self.load_config().await?.categories())
+    }
+
+    async fn get_template(
+        &self,
+        category: &TemplateCategory,
+        path: &TemplatePath,
+    ) -> Result<Vec<u8>, PlatformError> {
+        self.load_config().await?.category(category)?;
+        self.get_item(
+            &self.master_repo,
+            &MasterConfig::master_path(category, path),
+            self.master_repo.default_branch(),
+            false,
+        )
+        .await?
+        .ok_or_else(|| PlatformError::TemplateNotFound(path.clone()))
+    }
+
+    async fn list_templates(
+        &self,
+        category: &TemplateCategory,
+    ) -> Result<Vec<TemplateMetadata>, PlatformError> {
+        let config = self.load_config().await?;
+        let master = &self.master_repo;
+        let mut templates = Vec::new();
+        for path in &config.category(category)?.files {
+            let master_path = MasterConfig::master_path(category, path);
+            let content = self
+                .get_item(master, &master_path, master.default_branch(), false)
+                .await?
+                .ok_or_else(|| PlatformError::TemplateNotFound(path.clone()))?;
+
+            let mut url = self.url(master, &["commits"])?;
+            url.query_pairs_mut()
+                .append_pair("searchCriteria.itemPath", &format!("/{}", master_path))
+                .append_pair(
+                    "searchCriteria.itemVersion.version",
+                    master.default_branch(),
+                )
+                .append_pair("searchCriteria.$top", "1");
+            let commits: List<Commit> = self.repo_api(master, Method::GET, url, None).await?;
+            let last_updated = commits
+                .value
+                .first()
+                .map(|c| c.committer.date)
+                .ok_or_else(|| PlatformError::TemplateNotFound(path.clone()))?;
+
+            templates.push(TemplateMetadata::new(
+                path.clone(),
+                calculate_checksum(&content),
+                last_updated,
+            ));
+        }
+        Ok(templates)
+    }
+
+    async fn list_repos_by_category(
+        &self,
+        category: &TemplateCategory,
+    ) -> Result<Vec<RepoInfo>, PlatformError> {
+        let config = self.load_config().await?;
+        let mut repos = Vec::new();
+        for full_name in config.repositories_for(category)? {
+            let (org, name) = split_repo_name(full_name)?;
+            let repo = RepoInfo::new(org, name, String::new());
+            let url = self.url(&repo, &[])?;
+            let repository: Repository = self.repo_api(&repo, Method::GET, url, None).await?;
+            let default_branch = repository
+                .default_branch
+                .as_deref()
+                .map(|b| b.strip_prefix("refs/heads/").unwrap_or(b).to_string())
+                .ok_or_else(|| {
+                    PlatformError::OperationFailed(format!(
+                        "Repository {} has no default branch",
+                        full_name
+                    ))
+                })?;
+            repos.push(RepoInfo::new(
+                repo.org().to_string(),
+                repo.name().to_string(),
+                default_branch,
+            ));
+        }
+        Ok(repos)
+    }
+
+    async fn get_repo_file(
+        &self,
+        repo: &RepoInfo,
+        path: &TemplatePath,
+    ) -> Result<Option<Vec<u8>>, PlatformError> {
+        match self
+            .get_item(repo, path, repo.default_branch(), false)
+            .await?
+        {
+            Some(content) => Ok(Some(content)),
+            None => {
+                // The items API reports missing files and missing repositories alike.
+                let url = self.url(repo, &[])?;
+                let _: Repository = self.repo_api(repo, Method::GET, url, None).await?;
+                Ok(None)
+            }
+        }
+    }
+
+    async fn get_updated_templates(
+        &self,
+        category: &TemplateCategory,
+        since_commit: &str,
+    ) -> Result<Vec<TemplateChange>, PlatformError> {
+        let config = self.load_config().await?;
+        let category_config = config.category(category)?;
+        let category_dir = format!("/{}", MasterConfig::master_path(category, &String::new()));
+        let master = &self.master_repo;
+
+        // Compares from the common commit, like GitHub's three-dot comparison.
+        let mut diffs = Vec::new();
+        loop {
+            let mut url = self.url(master, &["diffs", "commits"])?;
+            url.query_pairs_mut()
+                .append_pair("baseVersion", since_commit)
+                .append_pair("baseVersionType", "commit")
+                .append_pair("targetVersion", master.default_branch())
+                .append_pair("targetVersionType", "branch")
+                .append_pair("$top", &DIFF_PAGE_SIZE.to_string())
+                .append_pair("$skip", &diffs.len().to_string());
+            let page: Diffs = self.repo_api(master, Method::GET, url, None).await?;
+            let done = page.all_changes_included || page.changes.is_empty();
+            diffs.extend(page.changes);
+            if done {
+                break;
+            }
+        }
+
+        let mut changes = Vec::new();
+        for diff in diffs {
+            // Templates removed from the master repository are not changes to apply.
+            if diff.change_type.contains("delete") {
+                continue;
+            }
+            let Some(path) = diff.item.path.strip_prefix(&category_dir) else {
+                continue;
+            };
+            if !category_config.files.iter().any(|f| f == path) {
+                continue;
+            }
+
+            let content = self
+                .get_item(
+                    master,
+                    diff.item.path.trim_start_matches('/'),
+                    master.default_branch(),
+                    false,
+                )
+                .await?
+                .ok_or_else(|| PlatformError::TemplateNotFound(path.to_string()))?;
+            let old_path = diff.original_path.as_deref().unwrap_or(&diff.item.path);
+            let old_checksum = self
+                .get_item(master, old_path.trim_start_matches('/'), since_commit, true)
+                .await?
+                .map(|old| calculate_checksum(&old));
+            changes.push(TemplateChange::new(
+                path.to_string(),
+                old_checksum.into_iter().collect(),
+                calculate_checksum(&content),
+                content,
+            ));
+        }
+        Ok(changes)
+    }
+
+    async fn update_repo(
+        &self,
+        repo: &RepoInfo,
+        changes: &[TemplateChange],
+    ) -> Result<UpdateResult, PlatformError> {
+        if changes.is_empty() {
+            return Err(PlatformError::OperationFailed(format!(
+                "No changes to apply to {}",
+                repo_full_name(repo)
+            )));
+        }
+
+        // 1. Resolve the default branch, which the commit is based on, and the update branch,
+        //    which the push replaces if it exists.
+        let base = self
+            .branch_head(repo, repo.default_branch())
+            .await?
+            .ok_or_else(|| {
+                PlatformError::OperationFailed(format!(
+                    "Default branch '{}' of {} not found",
+                    repo.default_branch(),
+                    repo_full_name(repo)
+                ))
+            })?;
+        let branch = update_branch_name(changes);
+        let old_object_id = self
+            .branch_head(repo, &branch)
+            .await?
+            .unwrap_or_else(|| ZERO_OBJECT_ID.to_string());
+
+        // 2. Build the changes. Files are added or edited depending on whether they exist on
+        //    the default branch.
+        let mut commit_changes = Vec::new();
+        for change in changes {
+            let exists = self
+                .get_item(repo, change.path(), repo.default_branch(), false)
+                .await?
+                .is_some();
+            commit_changes.push(json!({
+                "changeType": if exists { "edit" } else { "add" },
+                "item": { "path": format!("/{}", change.path()) },
+                "newContent": {
+                    "content": BASE64.encode(change.content()),
+                    "contentType": "base64encoded",
+                },
+            }));
+        }
+
+        // 3. Push a single commit onto the update branch.
+        let updated_files: Vec<TemplatePath> =
+            changes.iter().map(|change| change.path().clone()).collect();
+        let url = self.url(repo, &["pushes"])?;
+        let _: Value = self
+            .repo_api(
+                repo,
+                Method::POST,
+                url,
+                Some(json!({
+                    "refUpdates": [{
+                        "name": format!("refs/heads/{}", branch),
+                        "oldObjectId": old_object_id,
+                    }],
+                    "commits": [{
+                        "comment": update_commit_message(&updated_files),
+                        "parents": [base],
+                        "changes": commit_changes,
+                    }],
+                })),
+            )
+            .await?;
+
+        // 4. Open a pull request, unless one is already active for the branch.
+        let pull_request = match self.find_pull_request(repo, &branch).await? {
+            Some(pull_request) => pull_request,
+            None => {
+                let url = self.url(repo, &["pullrequests"])?;
+                self.repo_api(
+                    repo,
+                    Method::POST,
+                    url,
+                    Some(json!({
+                        "title": update_title(),
+                        "sourceRefName": format!("refs/heads/{}", branch),
+                        "targetRefName": format!("refs/heads/{}", repo.default_branch()),
+                        "description": update_description(&updated_files),
+                    })),
+                )
+                .await?
+            }
+        };
+
+        let mut pr_url = self.api_base_url.clone();
+        if let Ok(mut path) = pr_url.path_segments_mut() {
+            path.pop_if_empty()
+                .extend(repo.org().split('/'))
+                .extend(["_git", repo.name(), "pullrequest"])
+                .push(&pull_request.pull_request_id.to_string());
+        }
+        Ok(UpdateResult::new(
+            pr_url.to_string(),
+            pull_request.pull_request_id,
+            updated_files,
+        ))
+    }
+}
+
+/// Maps an unsuccessful API response onto a `PlatformError`.
+async fn map_error(response: reqwest::Response) -> PlatformError {
+    let status = response.status();
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        return PlatformError::RateLimitExceeded;
+    }
+
+    let url = response.url().path().to_string();
+    let body = response.text().await.unwrap_or_default();
+    match status {
+        // Azure DevOps answers requests with an invalid or expired personal access token with
+        // 203 and a sign-in page.
+        StatusCode::NON_AUTHORITATIVE_INFORMATION => PlatformError::AuthError(format!(
+            "{} for {}: the credentials were not accepted",
+            status, url
+        )),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            PlatformError::AuthError(format!("{} for {}: {}", status, url, body))
+        }
+        _ => PlatformError::ApiError(format!("{} for {}: {}", status, url, body)),
+    }
+}
+
+async fn parse_json<T: DeserializeOwned>(response: reqwest::Response) -> Result<T, PlatformError> {
+    response.json().await.map_err(|e| {
+        PlatformError::InvalidContent(format!("Unexpected Azure DevOps response: {}", e))
+    })
+}
+
+#[derive(Debug)]
+struct AccessToken {
+    access_token: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Change {
+    item: Item,
+    #[serde(rename = "changeType")]
+    change_type: String,
+    #[serde(rename = "originalPath")]
+    original_path: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Commit {
+    committer: GitUserDate,
+}
+
+#[derive(Debug, Deserialize)]
+struct Diffs {
+    #[serde(rename = "allChangesIncluded", default)]
+    all_changes_included: bool,
+    #[serde(default)]
+    changes: Vec<Change>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitRef {
+    name: String,
+    #[serde(rename = "objectId")]
+    object_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitUserDate {
+    date: DateTime<Utc>,
+}
+
+/// A token issued by the managed identity endpoint.
+#[derive(Debug, Deserialize)]
+struct IdentityToken {
+    access_token: String,
+    /// The expiry, in seconds since the epoch.
+    expires_on: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Item {
+    path: String,
+}
+
+/// The envelope Azure DevOps wraps collections in.
+#[derive(Debug, Deserialize)]
+struct List<T> {
+    value: Vec<T>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PullRequest {
+    #[serde(rename = "pullRequestId")]
+    pull_request_id: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Repository {
+    #[serde(rename = "defaultBranch")]
+    default_branch: Option<String>,
+}
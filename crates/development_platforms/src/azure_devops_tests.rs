@@ -0,0 +1,506 @@
+use super::*;
+use crate::conformance::{self, category, mount_config, replay, StubPlatform};
+use wiremock::matchers::{
+    body_partial_json, header, method, path, query_param, HeaderExactMatcher,
+};
+use wiremock::{Mock, MockBuilder, MockServer, ResponseTemplate};
+
+// Responses recorded from the Azure DevOps API, trimmed to the fields of interest.
+const REPOSITORY: &str = include_str!("../test_data/azure_devops/repository.json");
+const COMMITS: &str = include_str!("../test_data/azure_devops/commits.json");
+const DIFFS: &str = include_str!("../test_data/azure_devops/diffs.json");
+const REFS: &str = include_str!("../test_data/azure_devops/refs.json");
+const PUSH: &str = include_str!("../test_data/azure_devops/push.json");
+const PULL_REQUEST: &str = include_str!("../test_data/azure_devops/pull_request.json");
+const IDENTITY_TOKEN: &str = include_str!("../test_data/azure_devops/identity_token.json");
+
+const PAT: &str = "azure-devops-pat";
+
+const MASTER_REPO: &str = "/contoso/platform/_apis/git/repositories/template-master";
+const SERVICE_REPO: &str = "/contoso/platform/_apis/git/repositories/service";
+
+struct AzureDevOps;
+
+impl StubPlatform for AzureDevOps {
+    type Client = AzureDevOpsClient;
+
+    const ORG: &'static str = "contoso/platform";
+    const MISSING_FILE: &'static str = "TF401174: The item '/README.md' could not be found";
+    const MISSING_REPO: &'static str = "TF401019: The Git repository does not exist";
+    const REJECTED_TOKEN: &'static str =
+        "TF400813: The user is not authorized to access this resource.";
+
+    fn client(server: &MockServer) -> AzureDevOpsClient {
+        client_with(
+            server,
+            AzureDevOpsAuth::PersonalAccessToken(PAT.to_string()),
+        )
+    }
+
+    fn get_file(repo: &RepoInfo, file: &str) -> MockBuilder {
+        get_item(&repo_path(repo), file)
+    }
+
+    fn file(content: &str) -> ResponseTemplate {
+        item(content)
+    }
+
+    fn get_repo(repo: &RepoInfo) -> MockBuilder {
+        Mock::given(method("GET")).and(path(repo_path(repo)))
+    }
+
+    fn repo() -> ResponseTemplate {
+        replay(200, REPOSITORY)
+    }
+
+    fn authorization() -> HeaderExactMatcher {
+        header(
+            "Authorization",
+            format!("Basic {}", BASE64.encode(format!(":{}", PAT))).as_str(),
+        )
+    }
+
+    async fn mount_last_commit(server: &MockServer, expected: u64) {
+        Mock::given(method("GET"))
+            .and(path(format!("{}/commits", MASTER_REPO)))
+            .and(query_param("searchCriteria.itemVersion.version", "main"))
+            .and(query_param("searchCriteria.$top", "1"))
+            .respond_with(replay(200, COMMITS))
+            .expect(expected)
+            .mount(server)
+            .await;
+    }
+
+    fn error(status: u16, message: &str) -> ResponseTemplate {
+        ResponseTemplate::new(status).set_body_json(json!({ "message": message }))
+    }
+}
+
+fn client_with(server: &MockServer, auth: AzureDevOpsAuth) -> AzureDevOpsClient {
+    let config = AzureDevOpsConfig::new(auth, AzureDevOps::master())
+        .with_api_base_url(server.uri())
+        .with_identity_endpoint(format!("{}/metadata/identity/oauth2/token", server.uri()));
+    AzureDevOpsClient::new(config).unwrap()
+}
+
+/// The path of a repository in the Git API.
+fn repo_path(repo: &RepoInfo) -> String {
+    format!("/{}/_apis/git/repositories/{}", repo.org(), repo.name())
+}
+
+fn item(content: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_raw(content, "application/octet-stream")
+}
+
+/// Returns a request to the items API of `repo` for `file` on the `main` branch.
+fn get_item(repo: &str, file: &str) -> MockBuilder {
+    Mock::given(method("GET"))
+        .and(path(format!("{}/items", repo)))
+        .and(query_param("path", format!("/{}", file)))
+        .and(query_param("versionDescriptor.version", "main"))
+        .and(query_param("versionDescriptor.versionType", "branch"))
+}
+
+/// Mounts `response` for the items API of `repo` at `file` on the `main` branch.
+async fn mount_item(server: &MockServer, repo: &str, file: &str, response: ResponseTemplate) {
+    get_item(repo, file)
+        .respond_with(response)
+        .mount(server)
+        .await;
+}
+
+#[test]
+fn test_new_rejects_invalid_api_url() {
+    let config = AzureDevOpsConfig::new(
+        AzureDevOpsAuth::PersonalAccessToken(PAT.into()),
+        AzureDevOps::master(),
+    )
+    .with_api_base_url("::");
+
+    let result = AzureDevOpsClient::new(config);
+
+    assert!(matches!(result, Err(PlatformError::ConfigError(_))));
+}
+
+#[test]
+fn test_config_debug_redacts_personal_access_token() {
+    let config = AzureDevOpsConfig::new(
+        AzureDevOpsAuth::PersonalAccessToken(PAT.into()),
+        AzureDevOps::master(),
+    );
+
+    let debug = format!("{:?}", config);
+
+    assert!(!debug.contains(PAT));
+    assert!(debug.contains("<redacted>"));
+}
+
+#[tokio::test]
+async fn test_azure_devops_client_conformance() {
+    conformance::run_all::<AzureDevOps>().await;
+}
+
+#[tokio::test]
+async fn test_list_categories_authenticates_with_personal_access_token() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(format!("{}/items", MASTER_REPO)))
+        .and(query_param("api-version", "7.1"))
+        .and(query_param("$format", "octetStream"))
+        .and(header(
+            "Authorization",
+            format!("Basic {}", BASE64.encode(format!(":{}", PAT))).as_str(),
+        ))
+        .respond_with(item(&AzureDevOps::master_config()))
+        .mount(&server)
+        .await;
+    let client = AzureDevOps::client(&server);
+
+    let categories = client.list_categories().await.unwrap();
+
+    assert_eq!(categories, vec![category()]);
+}
+
+#[tokio::test]
+async fn test_managed_identity_token_is_requested_once() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/metadata/identity/oauth2/token"))
+        .and(header("Metadata", "true"))
+        .and(query_param(
+            "resource",
+            "499b84ac-1321-427f-aa17-267ca6a5a798",
+        ))
+        .and(query_param("client_id", "user-assigned"))
+        .respond_with(replay(200, IDENTITY_TOKEN))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("{}/items", MASTER_REPO)))
+        .and(header(
+            "Authorization",
+            "Bearer eyJ0eXAiOiJKV1QiLCJhbGciOiJSUzI1NiJ9.managed-identity",
+        ))
+        .respond_with(item(&AzureDevOps::master_config()))
+        .expect(2)
+        .mount(&server)
+        .await;
+    let client = client_with(
+        &server,
+        AzureDevOpsAuth::ManagedIdentity {
+            client_id: Some("user-assigned".to_string()),
+        },
+    );
+
+    client.list_categories().await.unwrap();
+    client.list_categories().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_managed_identity_failure_maps_to_auth_error() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/metadata/identity/oauth2/token"))
+        .respond_with(ResponseTemplate::new(400).set_body_string("Identity not found"))
+        .mount(&server)
+        .await;
+    let client = client_with(
+        &server,
+        AzureDevOpsAuth::ManagedIdentity { client_id: None },
+    );
+
+    let result = client.list_categories().await;
+
+    assert!(
+        matches!(result, Err(PlatformError::AuthError(msg)) if msg.contains("Identity not found"))
+    );
+}
+
+#[tokio::test]
+async fn test_list_repos_by_category_maps_organization_and_project() {
+    let server = MockServer::start().await;
+    mount_config::<AzureDevOps>(&server).await;
+    Mock::given(method("GET"))
+        .and(path(SERVICE_REPO))
+        .respond_with(replay(200, REPOSITORY))
+        .mount(&server)
+        .await;
+    let client = AzureDevOps::client(&server);
+
+    let repos = client.list_repos_by_category(&category()).await.unwrap();
+
+    assert_eq!(repos.len(), 1);
+    assert_eq!(repos[0].org(), "contoso/platform");
+    assert_eq!(repos[0].name(), "service");
+    assert_eq!(repos[0].default_branch(), "develop");
+}
+
+#[tokio::test]
+async fn test_repo_without_project_is_rejected() {
+    let server = MockServer::start().await;
+    let client = AzureDevOps::client(&server);
+    let repo = RepoInfo::new(
+        "contoso".to_string(),
+        "service".to_string(),
+        "main".to_string(),
+    );
+
+    let result = client.get_repo_file(&repo, &"README.md".to_string()).await;
+
+    assert!(matches!(result, Err(PlatformError::ConfigError(_))));
+}
+
+#[tokio::test]
+async fn test_sign_in_redirect_maps_to_auth_error() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(203).set_body_raw("<html>Sign In</html>", "text/html"))
+        .mount(&server)
+        .await;
+    let client = AzureDevOps::client(&server);
+
+    let result = client.list_categories().await;
+
+    assert!(matches!(result, Err(PlatformError::AuthError(_))));
+}
+
+#[tokio::test]
+async fn test_rate_limit_maps_to_rate_limit_exceeded() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "30"))
+        .mount(&server)
+        .await;
+    let client = AzureDevOps::client(&server);
+
+    let result = client.list_categories().await;
+
+    assert!(matches!(result, Err(PlatformError::RateLimitExceeded)));
+}
+
+#[tokio::test]
+async fn test_get_updated_templates_follows_renames_and_skips_deletions() {
+    let server = MockServer::start().await;
+    mount_config::<AzureDevOps>(&server).await;
+    Mock::given(method("GET"))
+        .and(path(format!("{}/diffs/commits", MASTER_REPO)))
+        .and(query_param("baseVersion", "23d0bc5b"))
+        .and(query_param("baseVersionType", "commit"))
+        .and(query_param("targetVersion", "main"))
+        .and(query_param("$skip", "0"))
+        .respond_with(replay(200, DIFFS))
+        .expect(1)
+        .mount(&server)
+        .await;
+    mount_item(
+        &server,
+        MASTER_REPO,
+        "templates/saas_rust/.github/PULL_REQUEST_TEMPLATE.md",
+        item("template v2"),
+    )
+    .await;
+    Mock::given(method("GET"))
+        .and(path(format!("{}/items", MASTER_REPO)))
+        .and(query_param(
+            "path",
+            "/templates/saas_rust/PULL_REQUEST_TEMPLATE.md",
+        ))
+        .and(query_param("versionDescriptor.version", "23d0bc5b"))
+        .and(query_param("versionDescriptor.versionType", "commit"))
+        .respond_with(item("template v1"))
+        .mount(&server)
+        .await;
+    let client = AzureDevOps::client(&server);
+
+    let changes = client
+        .get_updated_templates(&category(), "23d0bc5b")
+        .await
+        .unwrap();
+
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].path(), ".github/PULL_REQUEST_TEMPLATE.md");
+    assert_eq!(changes[0].content(), b"template v2");
+    assert_eq!(
+        changes[0].old_checksum_at(0),
+        Some(&calculate_checksum(b"template v1"))
+    );
+}
+
+#[tokio::test]
+async fn test_update_repo_pushes_new_branch_and_opens_pull_request() {
+    let server = MockServer::start().await;
+    let changes = [
+        TemplateChange::new(
+            ".github/PULL_REQUEST_TEMPLATE.md".to_string(),
+            vec![calculate_checksum(b"v1")],
+            calculate_checksum(b"v2"),
+            b"v2".to_vec(),
+        ),
+        TemplateChange::new(
+            "CODEOWNERS".to_string(),
+            Vec::new(),
+            calculate_checksum(b"owners"),
+            b"owners".to_vec(),
+        ),
+    ];
+    let branch = update_branch_name(&changes);
+
+    Mock::given(method("GET"))
+        .and(path(format!("{}/refs", SERVICE_REPO)))
+        .and(query_param("filter", "heads/main"))
+        .respond_with(replay(200, REFS))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("{}/refs", SERVICE_REPO)))
+        .and(query_param("filter", format!("heads/{}", branch)))
+        .respond_with(replay(200, r#"{ "value": [], "count": 0 }"#))
+        .mount(&server)
+        .await;
+    mount_item(
+        &server,
+        SERVICE_REPO,
+        ".github/PULL_REQUEST_TEMPLATE.md",
+        item("v1"),
+    )
+    .await;
+    mount_item(
+        &server,
+        SERVICE_REPO,
+        "CODEOWNERS",
+        AzureDevOps::error(404, "TF401174"),
+    )
+    .await;
+    Mock::given(method("POST"))
+        .and(path(format!("{}/pushes", SERVICE_REPO)))
+        .and(body_partial_json(json!({
+            "refUpdates": [{
+                "name": format!("refs/heads/{}", branch),
+                "oldObjectId": "0000000000000000000000000000000000000000",
+            }],
+            "commits": [{
+                "parents": ["be67f8871a4d2c75f13a51c1d3c30ac0d74d4ef4"],
+                "changes": [
+                    {
+                        "changeType": "edit",
+                        "item": { "path": "/.github/PULL_REQUEST_TEMPLATE.md" },
+                        "newContent": {
+                            "content": BASE64.encode("v2"),
+                            "contentType": "base64encoded",
+                        },
+                    },
+                    {
+                        "changeType": "add",
+                        "item": { "path": "/CODEOWNERS" },
+                    },
+                ],
+            }],
+        })))
+        .respond_with(replay(201, PUSH))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("{}/pullrequests", SERVICE_REPO)))
+        .and(query_param("searchCriteria.status", "active"))
+        .respond_with(replay(200, r#"{ "value": [], "count": 0 }"#))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path(format!("{}/pullrequests", SERVICE_REPO)))
+        .and(body_partial_json(json!({
+            "sourceRefName": format!("refs/heads/{}", branch),
+            "targetRefName": "refs/heads/main",
+        })))
+        .respond_with(replay(201, PULL_REQUEST))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let client = AzureDevOps::client(&server);
+
+    let result = client
+        .update_repo(&AzureDevOps::service(), &changes)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        result.pr_url(),
+        format!(
+            "{}/contoso/platform/_git/service/pullrequest/42",
+            server.uri()
+        )
+    );
+    assert_eq!(result.pr_number(), Some(42));
+    assert_eq!(result.updated_files().len(), 2);
+}
+
+#[tokio::test]
+async fn test_update_repo_replaces_existing_branch_and_reuses_pull_request() {
+    let server = MockServer::start().await;
+    let change = TemplateChange::new(
+        "CODEOWNERS".to_string(),
+        Vec::new(),
+        calculate_checksum(b"owners"),
+        b"owners".to_vec(),
+    );
+    let branch = update_branch_name(std::slice::from_ref(&change));
+
+    Mock::given(method("GET"))
+        .and(path(format!("{}/refs", SERVICE_REPO)))
+        .and(query_param("filter", "heads/main"))
+        .respond_with(replay(200, REFS))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("{}/refs", SERVICE_REPO)))
+        .and(query_param("filter", format!("heads/{}", branch)))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "value": [{ "name": format!("refs/heads/{}", branch), "objectId": "5e1f0c9a" }],
+        })))
+        .mount(&server)
+        .await;
+    mount_item(&server, SERVICE_REPO, "CODEOWNERS", item("old owners")).await;
+    Mock::given(method("POST"))
+        .and(path(format!("{}/pushes", SERVICE_REPO)))
+        .and(body_partial_json(json!({
+            "refUpdates": [{ "oldObjectId": "5e1f0c9a" }],
+            "commits": [{ "changes": [{ "changeType": "edit" }] }],
+        })))
+        .respond_with(replay(201, PUSH))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("{}/pullrequests", SERVICE_REPO)))
+        .respond_with(replay(
+            200,
+            &format!(r#"{{ "value": [{}] }}"#, PULL_REQUEST),
+        ))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path(format!("{}/pullrequests", SERVICE_REPO)))
+        .respond_with(ResponseTemplate::new(201))
+        .expect(0)
+        .mount(&server)
+        .await;
+    let client = AzureDevOps::client(&server);
+
+    let result = client
+        .update_repo(&AzureDevOps::service(), &[change])
+        .await
+        .unwrap();
+
+    assert_eq!(result.pr_number(), Some(42));
+}
+
+#[tokio::test]
+async fn test_update_repo_without_changes_fails() {
+    let server = MockServer::start().await;
+    let client = AzureDevOps::client(&server);
+
+    let result = client.update_repo(&AzureDevOps::service(), &[]).await;
+
+    assert!(matches!(result, Err(PlatformError::OperationFailed(_))));
+}
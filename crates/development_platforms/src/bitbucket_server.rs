@@ -0,0 +1,684 @@
+//! Implements `DeveloperPlatform` for Bitbucket Server and Data Center, authenticating with an
+//! HTTP access token.
+//!
+//! The organization of a repository is the key of its Bitbucket project (or `~<user>` for
+//! personal repositories), and its name is the repository slug. Templates, the master
+//! configuration and target repository files are read through the raw API. Updates are written
+//! onto a dedicated branch, created from the default branch by the first edit, with one commit
+//! per file through the file edit API of the browse endpoint, and a pull request is opened for
+//! the branch.
+//!
+//! Bitbucket Data Center has no public instance, so the API base URL (e.g.
+//! `https://bitbucket.example.com/rest/api/1.0`) is always configured.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use reqwest::{Method, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fmt;
+
+use crate::checksum::calculate_checksum;
+use crate::master_config::{repo_full_name, split_repo_name, MasterConfig, MASTER_CONFIG_FILE};
+use crate::update_branch::{
+    update_branch_name, update_commit_message, update_description, update_title,
+};
+use crate::{
+    DeveloperPlatform, PlatformError, RepoInfo, TemplateCategory, TemplateChange, TemplateMetadata,
+    TemplatePath, UpdateResult,
+};
+
+#[cfg(test)]
+#[path = "bitbucket_server_tests.rs"]
+mod tests;
+
+/// The number of items requested per page from paged APIs.
+const PAGE_SIZE: usize = 100;
+
+const USER_AGENT: &str = "template-teleporter";
+
+/// The configuration of a `BitbucketServerClient`.
+///
+/// # Example
+/// ```rust
+/// use template_teleporter_developer_platforms::{BitbucketServerConfig, RepoInfo};
+/// let config = BitbucketServerConfig::new(
+///     "https://bitbucket.example.com/rest/api/1.0".to_string(),
+///     "http-access-token".to_string(),
+///     RepoInfo::new("PLAT".to_string(), "template-master".to_string(), "main".to_string()),
+/// );
+/// assert_eq!(config.api_base_url, "https://bitbucket.example.com/rest/api/1.0");
+/// ```
+#[derive(Clone)]
+pub struct BitbucketServerConfig {
+    /// The base URL of the Bitbucket REST API, including the `/rest/api/1.0` path.
+    pub api_base_url: String,
+
+    /// An HTTP access token with write permission on the repositories, e.g. a project token.
+    pub token: String,
+
+    /// The master template repository, named by project key and repository slug.
+    pub master_repo: RepoInfo,
+}
+
+impl BitbucketServerConfig {
+    /// Creates a new `BitbucketServerConfig`.
+    pub fn new(api_base_url: String, token: String, master_repo: RepoInfo) -> Self {
+        Self {
+            api_base_url,
+            token,
+            master_repo,
+        }
+    }
+}
+
+// Manual Debug implementation so that the token never ends up in logs.
+impl fmt::Debug for BitbucketServerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BitbucketServerConfig")
+            .field("api_base_url", &self.api_base_url)
+            .field("token", &"<redacted>")
+            .field("master_repo", &self.master_repo)
+            .finish()
+    }
+}
+
+/// A `DeveloperPlatform` implementation for Bitbucket Server and Data Center.
+pub struct BitbucketServerClient {
+    token: String,
+    master_repo: RepoInfo,
+    api_base_url: Url,
+    http: reqwest::Client,
+}
+
+// Manual Debug implementation so that the token never ends up in logs.
+impl fmt::Debug for BitbucketServerClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BitbucketServerClient")
+            .field("master_repo", &self.master_repo)
+            .field("api_base_url", &self.api_base_url.as_str())
+            .finish_non_exhaustive()
+    }
+}
+
+/// The body of a request, either JSON or an encoded multipart form with its content type.
+enum Body {
+    Json(Value),
+    Form { content_type: String, data: Vec<u8> },
+}
+
+impl BitbucketServerClient {
+    /// Creates a new `BitbucketServerClient`.
+    ///
+    /// # Errors
+    /// Returns `PlatformError::ConfigError` if the API base URL is invalid.
+    pub fn new(config: BitbucketServerConfig) -> Result<Self, PlatformError> {
+        let api_base_url = Url::parse(&config.api_base_url).map_err(|e| {
+            PlatformError::ConfigError(format!(
+                "Invalid Bitbucket API URL '{}': {}",
+                config.api_base_url, e
+            ))
+        })?;
+        let http = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .build()
+            .map_err(|e| PlatformError::ConfigError(e.to_string()))?;
+
+        Ok(Self {
+            token: config.token,
+            master_repo: config.master_repo,
+            api_base_url,
+            http,
+        })
+    }
+
+    /// Builds an API URL for the given repository from the base URL and the given path
+    /// segments. Segments containing `/`, like file paths, are split into several segments.
+    fn url(&self, repo: &RepoInfo, segments: &[&str]) -> Url {
+        let mut url = self.api_base_url.clone();
+        if let Ok(mut path) = url.path_segments_mut() {
+            path.pop_if_empty()
+                .extend(["projects", repo.org(), "repos", repo.name()]);
+            for segment in segments {
+                path.extend(segment.split('/'));
+            }
+        }
+        url
+    }
+
+    /// Sends an API request, authenticated with the access token.
+    ///
+    /// # Returns
+    /// `None` if the API responded with 404 Not Found, otherwise the successful response.
+    async fn send(
+        &self,
+        method: Method,
+        url: Url,
+        body: Option<Body>,
+    ) -> Result<Option<reqwest::Response>, PlatformError> {
+        let mut request = self
+            .http
+            .request(method, url)
+            .bearer_auth(&self.token)
+            .header("Accept", "application/json")
+            // Bitbucket rejects multipart requests without this header as possible XSRF.
+            .header("X-Atlassian-Token", "no-check");
+        request = match body {
+            Some(Body::Json(body)) => request.json(&body),
+            Some(Body::Form { content_type, data }) => {
+                request.header("Content-Type", content_type).body(data)
+            }
+            None => request,
+        };
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| PlatformError::ApiError(e.to_string()))?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(map_error(response).await);
+        }
+        Ok(Some(response))
+    }
+
+    /// Calls the API for the given repository, mapping 404 Not Found to `RepoNotFound`.
+    async fn repo_api<T: DeserializeOwned>(
+        &self,
+        repo: &RepoInfo,
+        method: Method,
+        url: Url,
+        body: Option<Body>,
+    ) -> Result<T, PlatformError> {
+        match self.send(method, url, body).await? {
+            Some(response) => parse_json(response).await,
+            None => Err(PlatformError::RepoNotFound {
+                org: repo.org().to_string(),
+                name: repo.name().to_string(),
+            }),
+        }
+    }
+
+    /// Reads all pages of a paged API for the given repository.
+    async fn paged<T: DeserializeOwned>(
+        &self,
+        repo: &RepoInfo,
+        url: Url,
+    ) -> Result<Vec<T>, PlatformError> {
+        let mut values = Vec::new();
+        let mut start = 0;
+        loop {
+            let mut page_url = url.clone();
+            page_url
+                .query_pairs_mut()
+                .append_pair("start", &start.to_string())
+                .append_pair("limit", &PAGE_SIZE.to_string());
+            let page: Page<T> = self.repo_api(repo, Method::GET, page_url, None).await?;
+            values.extend(page.values);
+            match page.next_page_start {
+                Some(next) if !page.is_last_page => start = next,
+                _ => return Ok(values),
+            }
+        }
+    }
+
+    /// Reads a file through the raw API.
+    ///
+    /// # Returns
+    /// `None` if the repository or the file does not exist at the given reference.
+    async fn get_file(
+        &self,
+        repo: &RepoInfo,
+        path: &str,
+        reference: &str,
+    ) -> Result<Option<Vec<u8>>, PlatformError> {
+        let mut url = self.url(repo, &["raw", path]);
+        url.query_pairs_mut().append_pair("at", reference);
+        match self.send(Method::GET, url, None).await? {
+            Some(response) => response
+                .bytes()
+                .await
+                .map(|bytes| Some(bytes.to_vec()))
+                .map_err(|e| PlatformError::ApiError(e.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads and parses the master configuration from the master repository.
+    async fn load_config(&self) -> Result<MasterConfig, PlatformError> {
+        let content = self
+            .get_file(
+                &self.master_repo,
+                MASTER_CONFIG_FILE,
+                self.master_repo.default_branch(),
+            )
+            .await?
+            .ok_or_else(|| {
+                PlatformError::ConfigError(format!(
+                    "{} not found in {}",
+                    MASTER_CONFIG_FILE,
+                    repo_full_name(&self.master_repo)
+                ))
+            })?;
+        let content =
+            String::from_utf8(content).map_err(|e| PlatformError::InvalidContent(e.to_string()))?;
+        MasterConfig::from_toml_str(&content)
+    }
+
+    /// Returns the default branch of the given repository.
+    async fn default_branch(&self, repo: &RepoInfo) -> Result<Branch, PlatformError> {
+        let url = self.url(repo, &["branches", "default"]);
+        self.repo_api(repo, Method::GET, url, None).await
+    }
+
+    /// Returns the branch with the given name, if it exists.
+    async fn find_branch(
+        &self,
+        repo: &RepoInfo,
+        name: &str,
+    ) -> Result<Option<Branch>, PlatformError> {
+        let mut url = self.url(repo, &["branches"]);
+        url.query_pairs_mut().append_pair("filterText", name);
+        // The filter matches branches containing the text.
+        let branches: Vec<Branch> = self.paged(repo, url).await?;
+        Ok(branches.into_iter().find(|b| b.display_id == name))
+    }
+
+    /// Returns the open pull request from `branch` into the default branch, if there is one.
+    async fn find_pull_request(
+        &self,
+        repo: &RepoInfo,
+        branch: &str,
+    ) -> Result<Option<PullRequest>, PlatformError> {
+        let mut url = self.url(repo, &["pull-requests"]);
+        url.query_pairs_mut()
+            .append_pair("direction", "OUTGOING")
+            .append_pair("at", &format!("refs/heads/{}", branch))
+            .append_pair("state", "OPEN");
+        let target = format!("refs/heads/{}", repo.default_branch());
+        let pull_requests: Vec<PullRequest> = self.paged(repo, url).await?;
+        Ok(pull_requests.into_iter().find(|pr| pr.to_ref.id == target))
+    }
+
+    /// Commits the new content of a template onto `branch` through the file edit API.
+    ///
+    /// # Parameters
+    /// - `source_branch`: The branch to create `branch` from, if it does not exist yet.
+    /// - `source_commit`: The commit the file is edited at, or `None` if the file is new.
+    ///
+    /// # Returns
+    /// The ID of the new commit.
+    async fn edit_file(
+        &self,
+        repo: &RepoInfo,
+        branch: &str,
+        source_branch: Option<&str>,
+        source_commit: Option<&str>,
+        change: &TemplateChange,
+    ) -> Result<String, PlatformError> {
+        let message = update_commit_message(std::slice::from_ref(change.path()));
+        let mut fields = vec![("branch", branch), ("message", message.as_str())];
+        if let Some(source_branch) = source_branch {
+            fields.push(("sourceBranch", source_branch));
+        }
+        if let Some(source_commit) = source_commit {
+            fields.push(("sourceCommitId", source_commit));
+        }
+        let url = self.url(repo, &["browse", change.path()]);
+        let form = multipart_form(&fields, change.path(), change.content());
+        let commit: Commit = self.repo_api(repo, Method::PUT, url, Some(form)).await?;
+        Ok(commit.id)
+    }
+}
+
+#[async_trait]
+impl DeveloperPlatform for BitbucketServerClient {
+    async fn list_categories(&self) -> Result<Vec<TemplateCategory>, PlatformError> {
+        Ok(self.load_config().await?.categories())
+    }
+
+    async fn get_template(
+        &self,
+        category: &TemplateCategory,
+        path: &TemplatePath,
+    ) -> Result<Vec<u8>, PlatformError> {
+        self.load_config().await?.category(category)?;
+        self.get_file(
+            &self.master_repo,
+            &MasterConfig::master_path(category, path),
+            self.master_repo.default_branch(),
+        )
+        .await?
+        .ok_or_else(|| PlatformError::TemplateNotFound(path.clone()))
+    }
+
+    async fn list_templates(
+        &self,
+        category: &TemplateCategory,
+    ) -> Result<Vec<TemplateMetadata>, PlatformError> {
+        let config = self.load_config().await?;
+        let master = &self.master_repo;
+        let mut templates = Vec::new();
+        for path in &config.category(category)?.files {
+            let master_path = MasterConfig::master_path(category, path);
+            let content = self
+                .get_file(master, &master_path, master.default_branch())
+                .await?
+                .ok_or_else(|| PlatformError::TemplateNotFound(path.clone()))?;
+
+            let mut url = self.url(master, &["commits"]);
+            url.query_pairs_mut()
+                .append_pair("path", &master_path)
+                .append_pair("until", master.default_branch())
+                .append_pair("limit", "1");
+            let commits: Page<Commit> = self.repo_api(master, Method::GET, url, None).await?;
+            let last_updated = commits
+                .values
+                .first()
+                .and_then(|c| c.committer_timestamp)
+                .and_then(DateTime::<Utc>::from_timestamp_millis)
+                .ok_or_else(|| PlatformError::TemplateNotFound(path.clone()))?;
+
+            templates.push(TemplateMetadata::new(
+                path.clone(),
+                calculate_checksum(&content),
+                last_updated,
+            ));
+        }
+        Ok(templates)
+    }
+
+    async fn list_repos_by_category(
+        &self,
+        category: &TemplateCategory,
+    ) -> Result<Vec<RepoInfo>, PlatformError> {
+        let config = self.load_config().await?;
+        let mut repos = Vec::new();
+        for full_name in config.repositories_for(category)? {
+            let (org, name) = split_repo_name(full_name)?;
+            let repo = RepoInfo::new(org, name, String::new());
+            let branch = self.default_branch(&repo).await?;
+            repos.push(RepoInfo::new(
+                repo.org().to_string(),
+                repo.name().to_string(),
+                branch.display_id,
+            ));
+        }
+        Ok(repos)
+    }
+
+    async fn get_repo_file(
+        &self,
+        repo: &RepoInfo,
+        path: &TemplatePath,
+    ) -> Result<Option<Vec<u8>>, PlatformError> {
+        match self.get_file(repo, path, repo.default_branch()).await? {
+            Some(content) => Ok(Some(content)),
+            None => {
+                // The raw API reports missing files and missing repositories alike.
+                let _: Value = self
+                    .repo_api(repo, Method::GET, self.url(repo, &[]), None)
+                    .await?;
+                Ok(None)
+            }
+        }
+    }
+
+    async fn get_updated_templates(
+        &self,
+        category: &TemplateCategory,
+        since_commit: &str,
+    ) -> Result<Vec<TemplateChange>, PlatformError> {
+        let config = self.load_config().await?;
+        let category_config = config.category(category)?;
+        let category_dir = MasterConfig::master_path(category, &String::new());
+        let master = &self.master_repo;
+
+        // Lists the changes reachable from the default branch but not from `since_commit`,
+        // like GitHub's three-dot comparison.
+        let mut url = self.url(master, &["compare", "changes"]);
+        url.query_pairs_mut()
+            .append_pair("from", master.default_branch())
+            .append_pair("to", since_commit);
+        let diffs: Vec<Change> = self.paged(master, url).await?;
+
+        let mut changes = Vec::new();
+        for diff in diffs {
+            // Templates removed from the master repository are not changes to apply.
+            if diff.change_type == "DELETE" {
+                continue;
+            }
+            let Some(path) = diff.path.to_string.strip_prefix(&category_dir) else {
+                continue;
+            };
+            if !category_config.files.iter().any(|f| f == path) {
+                continue;
+            }
+
+            let content = self
+                .get_file(master, &diff.path.to_string, master.default_branch())
+                .await?
+                .ok_or_else(|| PlatformError::TemplateNotFound(path.to_string()))?;
+            let old_path = diff.src_path.as_ref().unwrap_or(&diff.path);
+            let old_checksum = self
+                .get_file(master, &old_path.to_string, since_commit)
+                .await?
+                .map(|old| calculate_checksum(&old));
+            changes.push(TemplateChange::new(
+                path.to_string(),
+                old_checksum.into_iter().collect(),
+                calculate_checksum(&content),
+                content,
+            ));
+        }
+        Ok(changes)
+    }
+
+    async fn update_repo(
+        &self,
+        repo: &RepoInfo,
+        changes: &[TemplateChange],
+    ) -> Result<UpdateResult, PlatformError> {
+        if changes.is_empty() {
+            return Err(PlatformError::OperationFailed(format!(
+                "No changes to apply to {}",
+                repo_full_name(repo)
+            )));
+        }
+
+        // 1. Start from the update branch if it exists already, otherwise from the default
+        //    branch, from which the first edit creates the update branch.
+        let branch = update_branch_name(changes);
+        let (mut head, mut source_branch) = match self.find_branch(repo, &branch).await? {
+            Some(existing) => (existing.latest_commit, None),
+            None => {
+                let default = self.default_branch(repo).await?;
+                (default.latest_commit, Some(repo.default_branch()))
+            }
+        };
+
+        // 2. Commit the files which differ, one commit per file. The branch name is derived
+        //    from the new content, so an existing branch normally holds all of it already.
+        let updated_files: Vec<TemplatePath> =
+            changes.iter().map(|change| change.path().clone()).collect();
+        for change in changes {
+            let current = self.get_file(repo, change.path(), &head).await?;
+            if current.as_ref() == Some(change.content()) {
+                continue;
+            }
+            head = self
+                .edit_file(
+                    repo,
+                    &branch,
+                    source_branch.take(),
+                    current.is_some().then_some(head.as_str()),
+                    change,
+                )
+                .await?;
+        }
+        if source_branch.is_some() {
+            return Err(PlatformError::OperationFailed(format!(
+                "The changes are already applied to the default branch of {}",
+                repo_full_name(repo)
+            )));
+        }
+
+        // 3. Open a pull request, unless one is already open for the branch.
+        let pull_request = match self.find_pull_request(repo, &branch).await? {
+            Some(pull_request) => pull_request,
+            None => {
+                let url = self.url(repo, &["pull-requests"]);
+                self.repo_api(
+                    repo,
+                    Method::POST,
+                    url,
+                    Some(Body::Json(json!({
+                        "title": update_title(),
+                        "description": update_description(&updated_files),
+                        "fromRef": { "id": format!("refs/heads/{}", branch) },
+                        "toRef": { "id": format!("refs/heads/{}", repo.default_branch()) },
+                    }))),
+                )
+                .await?
+            }
+        };
+
+        let pr_url = pull_request
+            .links
+            .self_links
+            .into_iter()
+            .next()
+            .map(|link| link.href)
+            .ok_or_else(|| {
+                PlatformError::InvalidContent("Pull request without a link".to_string())
+            })?;
+        Ok(UpdateResult::new(pr_url, pull_request.id, updated_files))
+    }
+}
+
+/// Encodes a `multipart/form-data` body of text fields followed by the `content` file part.
+fn multipart_form(fields: &[(&str, &str)], path: &str, content: &[u8]) -> Body {
+    // The boundary must not occur in any part; the checksum of the content cannot.
+    let boundary = format!("template-teleporter-{}", calculate_checksum(content));
+    let mut data = Vec::new();
+    for (name, value) in fields {
+        data.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                boundary, name, value
+            )
+            .as_bytes(),
+        );
+    }
+    let file_name = path.rsplit('/').next().unwrap_or(path).replace('"', "%22");
+    data.extend_from_slice(
+        format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"content\"; filename=\"{}\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n",
+            boundary, file_name
+        )
+        .as_bytes(),
+    );
+    data.extend_from_slice(content);
+    data.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+    Body::Form {
+        content_type: format!("multipart/form-data; boundary={}", boundary),
+        data,
+    }
+}
+
+/// Maps an unsuccessful API response onto a `PlatformError`.
+async fn map_error(response: reqwest::Response) -> PlatformError {
+    let status = response.status();
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        return PlatformError::RateLimitExceeded;
+    }
+
+    let url = response.url().path().to_string();
+    let body = response.text().await.unwrap_or_default();
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            PlatformError::AuthError(format!("{} for {}: {}", status, url, body))
+        }
+        _ => PlatformError::ApiError(format!("{} for {}: {}", status, url, body)),
+    }
+}
+
+async fn parse_json<T: DeserializeOwned>(response: reqwest::Response) -> Result<T, PlatformError> {
+    response
+        .json()
+        .await
+        .map_err(|e| PlatformError::InvalidContent(format!("Unexpected Bitbucket response: {}", e)))
+}
+
+#[derive(Debug, Deserialize)]
+struct Branch {
+    #[serde(rename = "displayId")]
+    display_id: String,
+    #[serde(rename = "latestCommit")]
+    latest_commit: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Change {
+    path: ChangePath,
+    #[serde(rename = "srcPath")]
+    src_path: Option<ChangePath>,
+    #[serde(rename = "type")]
+    change_type: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChangePath {
+    #[serde(rename = "toString")]
+    to_string: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Commit {
+    id: String,
+    /// The commit time, in milliseconds since the epoch.
+    #[serde(rename = "committerTimestamp")]
+    committer_timestamp: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Link {
+    href: String,
+}
+
+/// A page of a paged API.
+#[derive(Debug, Deserialize)]
+struct Page<T> {
+    values: Vec<T>,
+    #[serde(rename = "isLastPage", default = "default_true")]
+    is_last_page: bool,
+    #[serde(rename = "nextPageStart")]
+    next_page_start: Option<usize>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+struct PullRequest {
+    id: u64,
+    #[serde(rename = "toRef")]
+    to_ref: Ref,
+    links: PullRequestLinks,
+}
+
+#[derive(Debug, Deserialize)]
+struct PullRequestLinks {
+    #[serde(rename = "self", default)]
+    self_links: Vec<Link>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Ref {
+    id: String,
+}
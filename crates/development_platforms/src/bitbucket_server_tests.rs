@@ -0,0 +1,489 @@
+use super::*;
+use crate::conformance::{self, category, mount_config, replay, StubPlatform};
+use wiremock::matchers::{
+    body_partial_json, body_string_contains, header, method, path, query_param, HeaderExactMatcher,
+};
+use wiremock::{Mock, MockBuilder, MockServer, Request, ResponseTemplate};
+
+// Responses recorded from the Bitbucket Data Center API, trimmed to the fields of interest.
+const DEFAULT_BRANCH: &str = include_str!("../test_data/bitbucket_server/default_branch.json");
+const COMMITS: &str = include_str!("../test_data/bitbucket_server/commits.json");
+const CHANGES: &str = include_str!("../test_data/bitbucket_server/changes.json");
+const COMMIT: &str = include_str!("../test_data/bitbucket_server/commit.json");
+const PULL_REQUEST: &str = include_str!("../test_data/bitbucket_server/pull_request.json");
+
+const TOKEN: &str = "bitbucket-token";
+
+const MASTER_REPO: &str = "/rest/api/1.0/projects/PLAT/repos/template-master";
+const SERVICE_REPO: &str = "/rest/api/1.0/projects/PLAT/repos/service";
+
+/// The latest commit of the default branch in `default_branch.json`.
+const DEFAULT_HEAD: &str = "8d51122def5632836d1cb1026e879069e10a1e13";
+
+struct BitbucketServer;
+
+impl StubPlatform for BitbucketServer {
+    type Client = BitbucketServerClient;
+
+    const ORG: &'static str = "PLAT";
+    const MISSING_FILE: &'static str = "The path \"README.md\" does not exist at revision \"main\"";
+    const MISSING_REPO: &'static str = "Repository PLAT/service does not exist.";
+    const REJECTED_TOKEN: &'static str = "Authentication failed. Please check your credentials.";
+
+    fn client(server: &MockServer) -> BitbucketServerClient {
+        BitbucketServerClient::new(BitbucketServerConfig::new(
+            format!("{}/rest/api/1.0", server.uri()),
+            TOKEN.to_string(),
+            Self::master(),
+        ))
+        .unwrap()
+    }
+
+    fn get_file(repo: &RepoInfo, file: &str) -> MockBuilder {
+        Mock::given(method("GET"))
+            .and(path(format!("{}/raw/{}", repo_path(repo), file)))
+            .and(query_param("at", "main"))
+    }
+
+    fn file(content: &str) -> ResponseTemplate {
+        raw(content)
+    }
+
+    fn get_repo(repo: &RepoInfo) -> MockBuilder {
+        Mock::given(method("GET")).and(path(repo_path(repo)))
+    }
+
+    fn repo() -> ResponseTemplate {
+        ResponseTemplate::new(200).set_body_json(json!({ "slug": "service" }))
+    }
+
+    fn authorization() -> HeaderExactMatcher {
+        header("Authorization", format!("Bearer {}", TOKEN).as_str())
+    }
+
+    async fn mount_last_commit(server: &MockServer, expected: u64) {
+        Mock::given(method("GET"))
+            .and(path(format!("{}/commits", MASTER_REPO)))
+            .and(query_param("until", "main"))
+            .and(query_param("limit", "1"))
+            .respond_with(replay(200, COMMITS))
+            .expect(expected)
+            .mount(server)
+            .await;
+    }
+
+    fn error(status: u16, message: &str) -> ResponseTemplate {
+        ResponseTemplate::new(status).set_body_json(json!({ "errors": [{ "message": message }] }))
+    }
+}
+
+/// The path of a repository in the REST API.
+fn repo_path(repo: &RepoInfo) -> String {
+    format!(
+        "/rest/api/1.0/projects/{}/repos/{}",
+        repo.org(),
+        repo.name()
+    )
+}
+
+fn raw(content: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_raw(content, "text/plain")
+}
+
+fn last_page(values: Value) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({
+        "size": values.as_array().map_or(0, |v| v.len()),
+        "isLastPage": true,
+        "start": 0,
+        "values": values,
+    }))
+}
+
+#[test]
+fn test_new_rejects_invalid_api_url() {
+    let config = BitbucketServerConfig::new(
+        "::".to_string(),
+        TOKEN.to_string(),
+        BitbucketServer::service(),
+    );
+
+    let result = BitbucketServerClient::new(config);
+
+    assert!(matches!(result, Err(PlatformError::ConfigError(_))));
+}
+
+#[test]
+fn test_config_debug_redacts_token() {
+    let config = BitbucketServerConfig::new(
+        "https://bitbucket.example.com/rest/api/1.0".to_string(),
+        TOKEN.to_string(),
+        BitbucketServer::service(),
+    );
+
+    let debug = format!("{:?}", config);
+
+    assert!(!debug.contains(TOKEN));
+    assert!(debug.contains("<redacted>"));
+}
+
+#[test]
+fn test_multipart_form_encodes_fields_and_content() {
+    let Body::Form { content_type, data } =
+        multipart_form(&[("branch", "update")], "dir/file.txt", b"content")
+    else {
+        panic!("Expected a form body");
+    };
+
+    let boundary = content_type
+        .strip_prefix("multipart/form-data; boundary=")
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(data).unwrap(),
+        format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"branch\"\r\n\r\nupdate\r\n\
+             --{b}\r\nContent-Disposition: form-data; name=\"content\"; filename=\"file.txt\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\ncontent\r\n--{b}--\r\n",
+            b = boundary
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_bitbucket_server_client_conformance() {
+    conformance::run_all::<BitbucketServer>().await;
+}
+
+#[tokio::test]
+async fn test_list_repos_by_category_uses_default_branch() {
+    let server = MockServer::start().await;
+    mount_config::<BitbucketServer>(&server).await;
+    Mock::given(method("GET"))
+        .and(path(format!("{}/branches/default", SERVICE_REPO)))
+        .respond_with(replay(200, DEFAULT_BRANCH))
+        .mount(&server)
+        .await;
+    let client = BitbucketServer::client(&server);
+
+    let repos = client.list_repos_by_category(&category()).await.unwrap();
+
+    assert_eq!(repos.len(), 1);
+    assert_eq!(repos[0].org(), "PLAT");
+    assert_eq!(repos[0].default_branch(), "develop");
+}
+
+#[tokio::test]
+async fn test_rate_limit_maps_to_rate_limit_exceeded() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "5"))
+        .mount(&server)
+        .await;
+    let client = BitbucketServer::client(&server);
+
+    let result = client.list_categories().await;
+
+    assert!(matches!(result, Err(PlatformError::RateLimitExceeded)));
+}
+
+#[tokio::test]
+async fn test_get_updated_templates_follows_moves_and_skips_deletions() {
+    let server = MockServer::start().await;
+    mount_config::<BitbucketServer>(&server).await;
+    Mock::given(method("GET"))
+        .and(path(format!("{}/compare/changes", MASTER_REPO)))
+        .and(query_param("from", "main"))
+        .and(query_param("to", "abcdef0"))
+        .respond_with(replay(200, CHANGES))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!(
+            "{}/raw/templates/saas_rust/.github/PULL_REQUEST_TEMPLATE.md",
+            MASTER_REPO
+        )))
+        .and(query_param("at", "main"))
+        .respond_with(raw("template v2"))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!(
+            "{}/raw/templates/saas_rust/PULL_REQUEST_TEMPLATE.md",
+            MASTER_REPO
+        )))
+        .and(query_param("at", "abcdef0"))
+        .respond_with(raw("template v1"))
+        .mount(&server)
+        .await;
+    let client = BitbucketServer::client(&server);
+
+    let changes = client
+        .get_updated_templates(&category(), "abcdef0")
+        .await
+        .unwrap();
+
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].path(), ".github/PULL_REQUEST_TEMPLATE.md");
+    assert_eq!(changes[0].content(), b"template v2");
+    assert_eq!(
+        changes[0].old_checksum_at(0),
+        Some(&calculate_checksum(b"template v1"))
+    );
+}
+
+#[tokio::test]
+async fn test_get_updated_templates_reads_all_pages() {
+    let server = MockServer::start().await;
+    mount_config::<BitbucketServer>(&server).await;
+    Mock::given(method("GET"))
+        .and(path(format!("{}/compare/changes", MASTER_REPO)))
+        .and(query_param("start", "0"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "isLastPage": false,
+            "nextPageStart": 1,
+            "values": [{ "path": { "toString": "README.md" }, "type": "MODIFY" }],
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("{}/compare/changes", MASTER_REPO)))
+        .and(query_param("start", "1"))
+        .respond_with(last_page(json!([
+            { "path": { "toString": "templates/saas_rust/CODEOWNERS" }, "type": "ADD" },
+        ])))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!(
+            "{}/raw/templates/saas_rust/CODEOWNERS",
+            MASTER_REPO
+        )))
+        .and(query_param("at", "main"))
+        .respond_with(raw("* @plat/owners"))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!(
+            "{}/raw/templates/saas_rust/CODEOWNERS",
+            MASTER_REPO
+        )))
+        .and(query_param("at", "abcdef0"))
+        .respond_with(BitbucketServer::error(404, "The path does not exist"))
+        .mount(&server)
+        .await;
+    let client = BitbucketServer::client(&server);
+
+    let changes = client
+        .get_updated_templates(&category(), "abcdef0")
+        .await
+        .unwrap();
+
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].path(), "CODEOWNERS");
+    assert_eq!(changes[0].old_checksum_at(0), None);
+}
+
+#[tokio::test]
+async fn test_update_repo_creates_branch_commits_files_and_opens_pull_request() {
+    let server = MockServer::start().await;
+    let changes = [
+        TemplateChange::new(
+            ".github/PULL_REQUEST_TEMPLATE.md".to_string(),
+            vec![calculate_checksum(b"v1")],
+            calculate_checksum(b"v2"),
+            b"v2".to_vec(),
+        ),
+        TemplateChange::new(
+            "CODEOWNERS".to_string(),
+            Vec::new(),
+            calculate_checksum(b"owners"),
+            b"owners".to_vec(),
+        ),
+    ];
+    let branch = update_branch_name(&changes);
+
+    Mock::given(method("GET"))
+        .and(path(format!("{}/branches", SERVICE_REPO)))
+        .and(query_param("filterText", branch.as_str()))
+        .respond_with(last_page(json!([{
+            "displayId": format!("{}-old", branch),
+            "latestCommit": "5e1f0c9a",
+        }])))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("{}/branches/default", SERVICE_REPO)))
+        .respond_with(replay(200, DEFAULT_BRANCH))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!(
+            "{}/raw/.github/PULL_REQUEST_TEMPLATE.md",
+            SERVICE_REPO
+        )))
+        .and(query_param("at", DEFAULT_HEAD))
+        .respond_with(raw("v1"))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("{}/raw/CODEOWNERS", SERVICE_REPO)))
+        .respond_with(BitbucketServer::error(
+            404,
+            "The path \"CODEOWNERS\" does not exist",
+        ))
+        .mount(&server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path(format!(
+            "{}/browse/.github/PULL_REQUEST_TEMPLATE.md",
+            SERVICE_REPO
+        )))
+        .and(header("X-Atlassian-Token", "no-check"))
+        .and(body_string_contains(format!(
+            "name=\"branch\"\r\n\r\n{}\r\n",
+            branch
+        )))
+        .and(body_string_contains(
+            "name=\"sourceBranch\"\r\n\r\nmain\r\n",
+        ))
+        .and(body_string_contains(format!(
+            "name=\"sourceCommitId\"\r\n\r\n{}\r\n",
+            DEFAULT_HEAD
+        )))
+        .and(body_string_contains("\r\n\r\nv2\r\n"))
+        .respond_with(replay(200, COMMIT))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path(format!("{}/browse/CODEOWNERS", SERVICE_REPO)))
+        .and(body_string_contains(format!(
+            "name=\"branch\"\r\n\r\n{}\r\n",
+            branch
+        )))
+        .and(|request: &Request| {
+            let body = String::from_utf8_lossy(&request.body);
+            !body.contains("sourceBranch") && !body.contains("sourceCommitId")
+        })
+        .respond_with(replay(200, COMMIT))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("{}/pull-requests", SERVICE_REPO)))
+        .and(query_param("direction", "OUTGOING"))
+        .and(query_param("at", format!("refs/heads/{}", branch)))
+        .and(query_param("state", "OPEN"))
+        .respond_with(last_page(json!([])))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path(format!("{}/pull-requests", SERVICE_REPO)))
+        .and(body_partial_json(json!({
+            "fromRef": { "id": format!("refs/heads/{}", branch) },
+            "toRef": { "id": "refs/heads/main" },
+        })))
+        .respond_with(replay(201, PULL_REQUEST))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let client = BitbucketServer::client(&server);
+
+    let result = client
+        .update_repo(&BitbucketServer::service(), &changes)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        result.pr_url(),
+        "https://bitbucket.example.com/projects/PLAT/repos/service/pull-requests/42"
+    );
+    assert_eq!(result.pr_number(), Some(42));
+    assert_eq!(result.updated_files().len(), 2);
+}
+
+#[tokio::test]
+async fn test_update_repo_reuses_up_to_date_branch_and_pull_request() {
+    let server = MockServer::start().await;
+    let change = TemplateChange::new(
+        "CODEOWNERS".to_string(),
+        Vec::new(),
+        calculate_checksum(b"owners"),
+        b"owners".to_vec(),
+    );
+    let branch = update_branch_name(std::slice::from_ref(&change));
+
+    Mock::given(method("GET"))
+        .and(path(format!("{}/branches", SERVICE_REPO)))
+        .respond_with(last_page(json!([{
+            "displayId": branch,
+            "latestCommit": "5e1f0c9a",
+        }])))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("{}/raw/CODEOWNERS", SERVICE_REPO)))
+        .and(query_param("at", "5e1f0c9a"))
+        .respond_with(raw("owners"))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("{}/pull-requests", SERVICE_REPO)))
+        .respond_with(replay(
+            200,
+            &format!(r#"{{ "isLastPage": true, "values": [{}] }}"#, PULL_REQUEST),
+        ))
+        .mount(&server)
+        .await;
+    Mock::given(method("PUT"))
+        .respond_with(replay(200, COMMIT))
+        .expect(0)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .respond_with(replay(201, PULL_REQUEST))
+        .expect(0)
+        .mount(&server)
+        .await;
+    let client = BitbucketServer::client(&server);
+
+    let result = client
+        .update_repo(&BitbucketServer::service(), &[change])
+        .await
+        .unwrap();
+
+    assert_eq!(result.pr_number(), Some(42));
+}
+
+#[tokio::test]
+async fn test_update_repo_fails_when_default_branch_is_up_to_date() {
+    let server = MockServer::start().await;
+    let change = TemplateChange::new(
+        "CODEOWNERS".to_string(),
+        Vec::new(),
+        calculate_checksum(b"owners"),
+        b"owners".to_vec(),
+    );
+    Mock::given(method("GET"))
+        .and(path(format!("{}/branches", SERVICE_REPO)))
+        .respond_with(last_page(json!([])))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("{}/branches/default", SERVICE_REPO)))
+        .respond_with(replay(200, DEFAULT_BRANCH))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("{}/raw/CODEOWNERS", SERVICE_REPO)))
+        .respond_with(raw("owners"))
+        .mount(&server)
+        .await;
+    let client = BitbucketServer::client(&server);
+
+    let result = client
+        .update_repo(&BitbucketServer::service(), &[change])
+        .await;
+
+    assert!(matches!(result, Err(PlatformError::OperationFailed(_))));
+}
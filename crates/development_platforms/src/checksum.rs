@@ -0,0 +1,11 @@
+//! Checksum helper shared by the platform implementations.
+
+use sha2::{Digest, Sha256};
+
+/// Calculates the lowercase hex-encoded SHA-256 checksum of the given data.
+///
+/// This matches the checksums calculated by the core library, so that checksums reported by
+/// platforms can be compared with the persisted template state.
+pub(crate) fn calculate_checksum(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
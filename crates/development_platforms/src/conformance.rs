@@ -0,0 +1,249 @@
+//! Fixtures and checks shared by the tests of the platform clients.
+//!
+//! The client of every platform must behave the same towards the `TemplateUpdater`: templates are
+//! read from the master repository named in the master config, missing files are reported as
+//! `None` and missing repositories and rejected credentials map to the same `PlatformError`s.
+//! Each client's tests implement [`StubPlatform`] with the requests and responses of its API and
+//! run the suite against a stub server:
+//!
+//! ```rust,ignore
+//! #[tokio::test]
+//! async fn test_my_platform_conformance() {
+//!     conformance::run_all::<MyPlatform>().await;
+//! }
+//! ```
+
+use crate::checksum::calculate_checksum;
+use crate::master_config::MASTER_CONFIG_FILE;
+use crate::{DeveloperPlatform, PlatformError, RepoInfo, TemplateCategory};
+use wiremock::matchers::{method, HeaderExactMatcher};
+use wiremock::{Mock, MockBuilder, MockServer, ResponseTemplate};
+
+/// The content of the CODEOWNERS template in the master repository.
+const OWNERS: &str = "* @org/owners";
+
+/// The requests and responses of a platform's API, as served by a stub server.
+pub(crate) trait StubPlatform {
+    type Client: DeveloperPlatform;
+
+    /// The organization owning the master and target repositories.
+    const ORG: &'static str = "org";
+    /// The error message of the response to a request for a file which does not exist.
+    const MISSING_FILE: &'static str = "Not Found";
+    /// The error message of the responses to requests to a repository which does not exist.
+    const MISSING_REPO: &'static str = "Not Found";
+    /// The error message of the responses to requests with rejected credentials.
+    const REJECTED_TOKEN: &'static str;
+
+    /// Returns a client of the master repository [`StubPlatform::master`], using `server` as API.
+    fn client(server: &MockServer) -> Self::Client;
+
+    /// Returns a request for `file` on the `main` branch of `repo`.
+    fn get_file(repo: &RepoInfo, file: &str) -> MockBuilder;
+
+    /// Returns a response to [`StubPlatform::get_file`] with the given content.
+    fn file(content: &str) -> ResponseTemplate;
+
+    /// Returns a request for the metadata of `repo`.
+    fn get_repo(repo: &RepoInfo) -> MockBuilder;
+
+    /// Returns a response to [`StubPlatform::get_repo`] for an existing repository.
+    fn repo() -> ResponseTemplate;
+
+    /// Returns the header authenticating requests to the master repository.
+    fn authorization() -> HeaderExactMatcher;
+
+    /// Mounts the latest commit of the `main` branch of the master repository, committed at
+    /// 2025-03-02T09:20:00Z, expecting it to be requested `expected` times.
+    async fn mount_last_commit(server: &MockServer, expected: u64);
+
+    /// Returns an error response in the format of the platform.
+    fn error(status: u16, message: &str) -> ResponseTemplate {
+        ResponseTemplate::new(status).set_body_string(message)
+    }
+
+    /// Starts a stub server with the responses every request needs, e.g. to authenticate.
+    async fn start() -> MockServer {
+        MockServer::start().await
+    }
+
+    /// Returns the master repository.
+    fn master() -> RepoInfo {
+        RepoInfo::new(
+            Self::ORG.to_string(),
+            "template-master".to_string(),
+            "main".to_string(),
+        )
+    }
+
+    /// Returns the repository targeted by the `saas_rust` category.
+    fn service() -> RepoInfo {
+        RepoInfo::new(
+            Self::ORG.to_string(),
+            "service".to_string(),
+            "main".to_string(),
+        )
+    }
+
+    /// Returns the master config, with the `saas_rust` category targeting
+    /// [`StubPlatform::service`].
+    fn master_config() -> String {
+        format!(
+            r#"
+[meta]
+config_version = "1.0"
+
+[categories.saas_rust]
+files = [".github/PULL_REQUEST_TEMPLATE.md", "CODEOWNERS"]
+
+[repositories]
+"{}/service" = {{ category = "saas_rust" }}
+"#,
+            Self::ORG
+        )
+    }
+}
+
+pub(crate) fn category() -> TemplateCategory {
+    TemplateCategory::new("saas_rust".to_string())
+}
+
+/// Replays a recorded response.
+pub(crate) fn replay(status: u16, body: &str) -> ResponseTemplate {
+    ResponseTemplate::new(status).set_body_raw(body, "application/json")
+}
+
+/// Mounts the master config, served only to requests authenticated for the master repository.
+pub(crate) async fn mount_config<P: StubPlatform>(server: &MockServer) {
+    P::get_file(&P::master(), MASTER_CONFIG_FILE)
+        .and(P::authorization())
+        .respond_with(P::file(&P::master_config()))
+        .mount(server)
+        .await;
+}
+
+/// Runs every check of the suite, each against a new stub server.
+pub(crate) async fn run_all<P: StubPlatform>() {
+    list_categories_reads_master_config::<P>().await;
+    get_template_returns_master_content::<P>().await;
+    list_templates_uses_last_commit_date::<P>().await;
+    get_repo_file_returns_none_for_missing_file::<P>().await;
+    get_repo_file_missing_repo_maps_to_repo_not_found::<P>().await;
+    rejected_token_maps_to_auth_error::<P>().await;
+}
+
+pub(crate) async fn list_categories_reads_master_config<P: StubPlatform>() {
+    let server = P::start().await;
+    mount_config::<P>(&server).await;
+    let client = P::client(&server);
+
+    let categories = client.list_categories().await.unwrap();
+
+    assert_eq!(categories, vec![category()]);
+}
+
+pub(crate) async fn get_template_returns_master_content<P: StubPlatform>() {
+    let server = P::start().await;
+    mount_config::<P>(&server).await;
+    P::get_file(
+        &P::master(),
+        "templates/saas_rust/.github/PULL_REQUEST_TEMPLATE.md",
+    )
+    .respond_with(P::file("template v2"))
+    .mount(&server)
+    .await;
+    let client = P::client(&server);
+
+    let content = client
+        .get_template(&category(), &".github/PULL_REQUEST_TEMPLATE.md".to_string())
+        .await
+        .unwrap();
+
+    assert_eq!(content, b"template v2");
+}
+
+pub(crate) async fn list_templates_uses_last_commit_date<P: StubPlatform>() {
+    let server = P::start().await;
+    mount_config::<P>(&server).await;
+    P::get_file(
+        &P::master(),
+        "templates/saas_rust/.github/PULL_REQUEST_TEMPLATE.md",
+    )
+    .respond_with(P::file("template v2"))
+    .mount(&server)
+    .await;
+    P::get_file(&P::master(), "templates/saas_rust/CODEOWNERS")
+        .respond_with(P::file(OWNERS))
+        .mount(&server)
+        .await;
+    P::mount_last_commit(&server, 2).await;
+    let client = P::client(&server);
+
+    let templates = client.list_templates(&category()).await.unwrap();
+
+    assert_eq!(templates.len(), 2);
+    assert_eq!(templates[0].path(), ".github/PULL_REQUEST_TEMPLATE.md");
+    assert_eq!(templates[1].path(), "CODEOWNERS");
+    assert_eq!(
+        templates[1].checksum(),
+        calculate_checksum(OWNERS.as_bytes())
+    );
+    assert_eq!(
+        templates[1].last_updated().to_rfc3339(),
+        "2025-03-02T09:20:00+00:00"
+    );
+}
+
+pub(crate) async fn get_repo_file_returns_none_for_missing_file<P: StubPlatform>() {
+    let server = P::start().await;
+    P::get_file(&P::service(), "README.md")
+        .respond_with(P::error(404, P::MISSING_FILE))
+        .mount(&server)
+        .await;
+    P::get_repo(&P::service())
+        .respond_with(P::repo())
+        .mount(&server)
+        .await;
+    let client = P::client(&server);
+
+    let content = client
+        .get_repo_file(&P::service(), &"README.md".to_string())
+        .await
+        .unwrap();
+
+    assert_eq!(content, None);
+}
+
+pub(crate) async fn get_repo_file_missing_repo_maps_to_repo_not_found<P: StubPlatform>() {
+    let server = P::start().await;
+    Mock::given(method("GET"))
+        .respond_with(P::error(404, P::MISSING_REPO))
+        .mount(&server)
+        .await;
+    let client = P::client(&server);
+
+    let result = client
+        .get_repo_file(&P::service(), &"README.md".to_string())
+        .await;
+
+    assert!(
+        matches!(result, Err(PlatformError::RepoNotFound { org, name }) if org == P::ORG && name == "service")
+    );
+}
+
+pub(crate) async fn rejected_token_maps_to_auth_error<P: StubPlatform>() {
+    let server = P::start().await;
+    Mock::given(method("GET"))
+        .respond_with(P::error(401, P::REJECTED_TOKEN))
+        .mount(&server)
+        .await;
+    let client = P::client(&server);
+
+    let result = client
+        .get_repo_file(&P::service(), &"README.md".to_string())
+        .await;
+
+    assert!(
+        matches!(result, Err(PlatformError::AuthError(msg)) if msg.contains(P::REJECTED_TOKEN))
+    );
+}
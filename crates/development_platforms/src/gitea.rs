@@ -0,0 +1,606 @@
+//! Implements `DeveloperPlatform` for Gitea and Forgejo, authenticating with an access token.
+//!
+//! Templates, the master configuration and target repository files are read through the
+//! contents API. Updates are written onto a dedicated branch, created from the default branch
+//! when it does not exist yet, with a single commit of the files API, and a pull request is
+//! opened for the branch.
+//!
+//! Gitea has no public instance, so the API base URL (e.g. `https://gitea.example.com/api/v1`)
+//! is always configured. Forgejo serves the same API.
+
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use reqwest::{Method, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::checksum::calculate_checksum;
+use crate::master_config::{repo_full_name, split_repo_name, MasterConfig, MASTER_CONFIG_FILE};
+use crate::update_branch::{
+    update_branch_name, update_commit_message, update_description, update_title,
+};
+use crate::{
+    DeveloperPlatform, PlatformError, RepoInfo, TemplateCategory, TemplateChange, TemplateMetadata,
+    TemplatePath, UpdateResult,
+};
+
+#[cfg(test)]
+#[path = "gitea_tests.rs"]
+mod tests;
+
+/// The number of pull requests requested per page when looking for an open pull request.
+const PULLS_PAGE_SIZE: usize = 50;
+
+const USER_AGENT: &str = "template-teleporter";
+
+/// The configuration of a `GiteaClient`.
+///
+/// # Example
+/// ```rust
+/// use template_teleporter_developer_platforms::{GiteaConfig, RepoInfo};
+/// let config = GiteaConfig::new(
+///     "https://gitea.example.com/api/v1".to_string(),
+///     "access-token".to_string(),
+///     RepoInfo::new("my-org".to_string(), "template-master".to_string(), "main".to_string()),
+/// );
+/// assert_eq!(config.api_base_url, "https://gitea.example.com/api/v1");
+/// ```
+#[derive(Clone)]
+pub struct GiteaConfig {
+    /// The base URL of the Gitea REST API, including the `/api/v1` path.
+    pub api_base_url: String,
+
+    /// An access token with read and write access to the repositories.
+    pub token: String,
+
+    /// The master template repository.
+    pub master_repo: RepoInfo,
+}
+
+impl GiteaConfig {
+    /// Creates a new `GiteaConfig`.
+    pub fn new(api_base_url: String, token: String, master_repo: RepoInfo) -> Self {
+        Self {
+            api_base_url,
+            token,
+            master_repo,
+        }
+    }
+}
+
+// Manual Debug implementation so that the token never ends up in logs.
+impl fmt::Debug for GiteaConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GiteaConfig")
+            .field("api_base_url", &self.api_base_url)
+            .field("token", &"<redacted>")
+            .field("master_repo", &self.master_repo)
+            .finish()
+    }
+}
+
+/// A `DeveloperPlatform` implementation for Gitea and Forgejo.
+pub struct GiteaClient {
+    token: String,
+    master_repo: RepoInfo,
+    api_base_url: Url,
+    http: reqwest::Client,
+}
+
+// Manual Debug implementation so that the token never ends up in logs.
+impl fmt::Debug for GiteaClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GiteaClient")
+            .field("master_repo", &self.master_repo)
+            .field("api_base_url", &self.api_base_url.as_str())
+            .finish_non_exhaustive()
+    }
+}
+
+impl GiteaClient {
+    /// Creates a new `GiteaClient`.
+    ///
+    /// # Errors
+    /// Returns `PlatformError::ConfigError` if the API base URL is invalid.
+    pub fn new(config: GiteaConfig) -> Result<Self, PlatformError> {
+        let api_base_url = Url::parse(&config.api_base_url).map_err(|e| {
+            PlatformError::ConfigError(format!(
+                "Invalid Gitea API URL '{}': {}",
+                config.api_base_url, e
+            ))
+        })?;
+        let http = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .build()
+            .map_err(|e| PlatformError::ConfigError(e.to_string()))?;
+
+        Ok(Self {
+            token: config.token,
+            master_repo: config.master_repo,
+            api_base_url,
+            http,
+        })
+    }
+
+    /// Builds an API URL for the given repository from the base URL and the given path
+    /// segments. Segments containing `/`, like file paths, are split into several segments.
+    fn url(&self, repo: &RepoInfo, segments: &[&str]) -> Url {
+        let mut url = self.api_base_url.clone();
+        if let Ok(mut path) = url.path_segments_mut() {
+            path.pop_if_empty()
+                .extend(["repos", repo.org(), repo.name()]);
+            for segment in segments {
+                path.extend(segment.split('/'));
+            }
+        }
+        url
+    }
+
+    /// Calls the API, authenticated with the access token.
+    ///
+    /// # Returns
+    /// `None` if the API responded with 404 Not Found, otherwise the deserialized response body.
+    async fn api<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        url: Url,
+        body: Option<Value>,
+    ) -> Result<Option<T>, PlatformError> {
+        let mut request = self
+            .http
+            .request(method, url)
+            .header("Authorization", format!("token {}", self.token))
+            .header("Accept", "application/json");
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| PlatformError::ApiError(e.to_string()))?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(map_error(response).await);
+        }
+        parse_json(response).await.map(Some)
+    }
+
+    /// Calls the API for the given repository, mapping 404 Not Found to `RepoNotFound`.
+    async fn repo_api<T: DeserializeOwned>(
+        &self,
+        repo: &RepoInfo,
+        method: Method,
+        segments: &[&str],
+        body: Option<Value>,
+    ) -> Result<T, PlatformError> {
+        let url = self.url(repo, segments);
+        self.api(method, url, body)
+            .await?
+            .ok_or_else(|| PlatformError::RepoNotFound {
+                org: repo.org().to_string(),
+                name: repo.name().to_string(),
+            })
+    }
+
+    /// Reads a file through the contents API.
+    ///
+    /// # Returns
+    /// `None` if the repository or the file does not exist at the given reference.
+    async fn get_contents(
+        &self,
+        repo: &RepoInfo,
+        path: &str,
+        reference: &str,
+    ) -> Result<Option<Contents>, PlatformError> {
+        let mut url = self.url(repo, &["contents", path]);
+        url.query_pairs_mut().append_pair("ref", reference);
+        self.api(Method::GET, url, None).await
+    }
+
+    /// Reads and decodes a file through the contents API.
+    async fn get_file(
+        &self,
+        repo: &RepoInfo,
+        path: &str,
+        reference: &str,
+    ) -> Result<Option<Vec<u8>>, PlatformError> {
+        self.get_contents(repo, path, reference)
+            .await?
+            .map(|c| c.decode())
+            .transpose()
+    }
+
+    /// Reads and parses the master configuration from the master repository.
+    async fn load_config(&self) -> Result<MasterConfig, PlatformError> {
+        let content = self
+            .get_file(
+                &self.master_repo,
+                MASTER_CONFIG_FILE,
+                self.master_repo.default_branch(),
+            )
+            .await?
+            .ok_or_else(|| {
+                PlatformError::ConfigError(format!(
+                    "{} not found in {}",
+                    MASTER_CONFIG_FILE,
+                    repo_full_name(&self.master_repo)
+                ))
+            })?;
+        let content =
+            String::from_utf8(content).map_err(|e| PlatformError::InvalidContent(e.to_string()))?;
+        MasterConfig::from_toml_str(&content)
+    }
+
+    /// Returns the open pull request from `branch` into the default branch, if there is one.
+    async fn find_pull_request(
+        &self,
+        repo: &RepoInfo,
+        branch: &str,
+    ) -> Result<Option<PullRequest>, PlatformError> {
+        // Gitea cannot filter pull requests by head branch, so the open ones are paged through.
+        for page in 1.. {
+            let mut url = self.url(repo, &["pulls"]);
+            url.query_pairs_mut()
+                .append_pair("state", "open")
+                .append_pair("page", &page.to_string())
+                .append_pair("limit", &PULLS_PAGE_SIZE.to_string());
+            let pulls: Vec<PullRequest> =
+                self.api(Method::GET, url, None).await?.unwrap_or_default();
+            let count = pulls.len();
+            if let Some(pull) = pulls
+                .into_iter()
+                .find(|pull| pull.head.name == branch && pull.base.name == repo.default_branch())
+            {
+                return Ok(Some(pull));
+            }
+            if count < PULLS_PAGE_SIZE {
+                break;
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[async_trait]
+impl DeveloperPlatform for GiteaClient {
+    async fn list_categories(&self) -> Result<Vec<TemplateCategory>, PlatformError> {
+        Ok(self.load_config().await?.categories())
+    }
+
+    async fn get_template(
+        &self,
+        category: &TemplateCategory,
+        path: &TemplatePath,
+    ) -> Result<Vec<u8>, PlatformError> {
+        self.load_config().await?.category(category)?;
+        self.get_file(
+            &self.master_repo,
+            &MasterConfig::master_path(category, path),
+            self.master_repo.default_branch(),
+        )
+        .await?
+        .ok_or_else(|| PlatformError::TemplateNotFound(path.clone()))
+    }
+
+    async fn list_templates(
+        &self,
+        category: &TemplateCategory,
+    ) -> Result<Vec<TemplateMetadata>, PlatformError> {
+        let config = self.load_config().await?;
+        let master = &self.master_repo;
+        let mut templates = Vec::new();
+        for path in &config.category(category)?.files {
+            let master_path = MasterConfig::master_path(category, path);
+            let content = self
+                .get_file(master, &master_path, master.default_branch())
+                .await?
+                .ok_or_else(|| PlatformError::TemplateNotFound(path.clone()))?;
+
+            let mut url = self.url(master, &["commits"]);
+            url.query_pairs_mut()
+                .append_pair("sha", master.default_branch())
+                .append_pair("path", &master_path)
+                .append_pair("limit", "1")
+                .append_pair("stat", "false")
+                .append_pair("files", "false");
+            let commits: Vec<CommitSummary> =
+                self.api(Method::GET, url, None).await?.unwrap_or_default();
+            let last_updated = commits
+                .first()
+                .map(|c| c.commit.committer.date)
+                .ok_or_else(|| PlatformError::TemplateNotFound(path.clone()))?;
+
+            templates.push(TemplateMetadata::new(
+                path.clone(),
+                calculate_checksum(&content),
+                last_updated,
+            ));
+        }
+        Ok(templates)
+    }
+
+    async fn list_repos_by_category(
+        &self,
+        category: &TemplateCategory,
+    ) -> Result<Vec<RepoInfo>, PlatformError> {
+        let config = self.load_config().await?;
+        let mut repos = Vec::new();
+        for full_name in config.repositories_for(category)? {
+            let (org, name) = split_repo_name(full_name)?;
+            let repo = RepoInfo::new(org, name, String::new());
+            let details: Repository = self.repo_api(&repo, Method::GET, &[], None).await?;
+            repos.push(RepoInfo::new(
+                repo.org().to_string(),
+                repo.name().to_string(),
+                details.default_branch,
+            ));
+        }
+        Ok(repos)
+    }
+
+    async fn get_repo_file(
+        &self,
+        repo: &RepoInfo,
+        path: &TemplatePath,
+    ) -> Result<Option<Vec<u8>>, PlatformError> {
+        match self.get_file(repo, path, repo.default_branch()).await? {
+            Some(content) => Ok(Some(content)),
+            None => {
+                // The contents API reports missing files and missing repositories alike.
+                let _: Repository = self.repo_api(repo, Method::GET, &[], None).await?;
+                Ok(None)
+            }
+        }
+    }
+
+    async fn get_updated_templates(
+        &self,
+        category: &TemplateCategory,
+        since_commit: &str,
+    ) -> Result<Vec<TemplateChange>, PlatformError> {
+        let config = self.load_config().await?;
+        let category_config = config.category(category)?;
+        let category_dir = MasterConfig::master_path(category, &String::new());
+        let master = &self.master_repo;
+
+        let range = format!("{}...{}", since_commit, master.default_branch());
+        let comparison: Comparison = self
+            .repo_api(master, Method::GET, &["compare", &range], None)
+            .await?;
+        // Gitea lists the files affected by each commit, without renames, so every template
+        // touched by any commit is read again at both ends of the range.
+        let touched: BTreeSet<&str> = comparison
+            .commits
+            .iter()
+            .flat_map(|commit| commit.files.iter())
+            .filter_map(|file| file.filename.strip_prefix(&category_dir))
+            .collect();
+
+        let mut changes = Vec::new();
+        for path in touched {
+            if !category_config.files.iter().any(|f| f == path) {
+                continue;
+            }
+            let master_path = MasterConfig::master_path(category, &path.to_string());
+            // Templates removed from the master repository are not changes to apply.
+            let Some(content) = self
+                .get_file(master, &master_path, master.default_branch())
+                .await?
+            else {
+                continue;
+            };
+            let old_checksum = self
+                .get_file(master, &master_path, since_commit)
+                .await?
+                .map(|old| calculate_checksum(&old));
+            let new_checksum = calculate_checksum(&content);
+            // Changes reverted within the range leave the template as it was.
+            if old_checksum.as_ref() == Some(&new_checksum) {
+                continue;
+            }
+            changes.push(TemplateChange::new(
+                path.to_string(),
+                old_checksum.into_iter().collect(),
+                new_checksum,
+                content,
+            ));
+        }
+        Ok(changes)
+    }
+
+    async fn update_repo(
+        &self,
+        repo: &RepoInfo,
+        changes: &[TemplateChange],
+    ) -> Result<UpdateResult, PlatformError> {
+        if changes.is_empty() {
+            return Err(PlatformError::OperationFailed(format!(
+                "No changes to apply to {}",
+                repo_full_name(repo)
+            )));
+        }
+
+        // 1. Create the update branch from the default branch, unless it exists already.
+        let branch = update_branch_name(changes);
+        let existing: Option<Value> = self
+            .api(Method::GET, self.url(repo, &["branches", &branch]), None)
+            .await?;
+        if existing.is_none() {
+            let _: Value = self
+                .repo_api(
+                    repo,
+                    Method::POST,
+                    &["branches"],
+                    Some(json!({
+                        "new_branch_name": branch,
+                        "old_branch_name": repo.default_branch(),
+                    })),
+                )
+                .await?;
+        }
+
+        // 2. Commit the files which differ on the branch. Updating a file requires the SHA of
+        //    the blob it replaces. The branch name is derived from the new content, so an
+        //    existing branch normally holds all of it already.
+        let mut files = Vec::new();
+        for change in changes {
+            let current = self.get_contents(repo, change.path(), &branch).await?;
+            let (operation, sha) = match current {
+                Some(current) if current.decode()? == *change.content() => continue,
+                Some(current) => ("update", Some(current.sha)),
+                None => ("create", None),
+            };
+            files.push(json!({
+                "operation": operation,
+                "path": change.path(),
+                "content": BASE64.encode(change.content()),
+                "sha": sha,
+            }));
+        }
+
+        let updated_files: Vec<TemplatePath> =
+            changes.iter().map(|change| change.path().clone()).collect();
+        if !files.is_empty() {
+            let _: Value = self
+                .repo_api(
+                    repo,
+                    Method::POST,
+                    &["contents"],
+                    Some(json!({
+                        "branch": branch,
+                        "message": update_commit_message(&updated_files),
+                        "files": files,
+                    })),
+                )
+                .await?;
+        }
+
+        // 3. Open a pull request, unless one is already open for the branch.
+        let pull = match self.find_pull_request(repo, &branch).await? {
+            Some(pull) => pull,
+            None => {
+                self.repo_api(
+                    repo,
+                    Method::POST,
+                    &["pulls"],
+                    Some(json!({
+                        "title": update_title(),
+                        "head": branch,
+                        "base": repo.default_branch(),
+                        "body": update_description(&updated_files),
+                    })),
+                )
+                .await?
+            }
+        };
+
+        Ok(UpdateResult::new(pull.html_url, pull.number, updated_files))
+    }
+}
+
+/// Maps an unsuccessful API response onto a `PlatformError`.
+async fn map_error(response: reqwest::Response) -> PlatformError {
+    let status = response.status();
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        return PlatformError::RateLimitExceeded;
+    }
+
+    let url = response.url().path().to_string();
+    let body = response.text().await.unwrap_or_default();
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            PlatformError::AuthError(format!("{} for {}: {}", status, url, body))
+        }
+        _ => PlatformError::ApiError(format!("{} for {}: {}", status, url, body)),
+    }
+}
+
+async fn parse_json<T: DeserializeOwned>(response: reqwest::Response) -> Result<T, PlatformError> {
+    response
+        .json()
+        .await
+        .map_err(|e| PlatformError::InvalidContent(format!("Unexpected Gitea response: {}", e)))
+}
+
+#[derive(Debug, Deserialize)]
+struct BranchRef {
+    #[serde(rename = "ref")]
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommitSummary {
+    commit: CommitDetails,
+    #[serde(default)]
+    files: Vec<CommitFile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommitDetails {
+    committer: CommitSignature,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommitSignature {
+    date: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommitFile {
+    filename: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Comparison {
+    #[serde(default)]
+    commits: Vec<CommitSummary>,
+}
+
+/// A file returned by the contents API.
+#[derive(Debug, Deserialize)]
+struct Contents {
+    sha: String,
+    encoding: Option<String>,
+    content: Option<String>,
+}
+
+impl Contents {
+    fn decode(&self) -> Result<Vec<u8>, PlatformError> {
+        // Directories and submodules have no content.
+        let (Some(encoding), Some(content)) = (&self.encoding, &self.content) else {
+            return Err(PlatformError::InvalidContent(
+                "Path is not a file".to_string(),
+            ));
+        };
+        if encoding != "base64" {
+            return Err(PlatformError::InvalidContent(format!(
+                "Unsupported content encoding '{}'",
+                encoding
+            )));
+        }
+        BASE64
+            .decode(content)
+            .map_err(|e| PlatformError::InvalidContent(e.to_string()))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PullRequest {
+    html_url: String,
+    number: u64,
+    head: BranchRef,
+    base: BranchRef,
+}
+
+#[derive(Debug, Deserialize)]
+struct Repository {
+    default_branch: String,
+}
@@ -0,0 +1,466 @@
+//! Most tests run against a stub server. `test_sync_against_gitea_server` runs against a real
+//! Gitea or Forgejo server when `GITEA_URL` (e.g. `http://localhost:3000/api/v1`) and
+//! `GITEA_TOKEN`, an access token with the `write:repository` and `write:user` scopes, are set,
+//! e.g. for a container started with `docker run -p 3000:3000 gitea/gitea`; without them it is
+//! skipped. It creates two repositories owned by the token's user and deletes them when it passes.
+
+use super::*;
+use crate::conformance::{self, category, mount_config, StubPlatform};
+use wiremock::matchers::{
+    body_partial_json, header, method, path, query_param, HeaderExactMatcher,
+};
+use wiremock::{Mock, MockBuilder, MockServer, ResponseTemplate};
+
+const TOKEN: &str = "gitea-token";
+
+struct Gitea;
+
+impl StubPlatform for Gitea {
+    type Client = GiteaClient;
+
+    const REJECTED_TOKEN: &'static str = "token is required";
+
+    fn client(server: &MockServer) -> GiteaClient {
+        GiteaClient::new(GiteaConfig::new(
+            format!("{}/api/v1", server.uri()),
+            TOKEN.to_string(),
+            Self::master(),
+        ))
+        .unwrap()
+    }
+
+    fn get_file(repo: &RepoInfo, file: &str) -> MockBuilder {
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/api/v1/repos/{}/{}/contents/{}",
+                repo.org(),
+                repo.name(),
+                file
+            )))
+            .and(query_param("ref", "main"))
+    }
+
+    fn file(content: &str) -> ResponseTemplate {
+        contents(content, "blob-sha")
+    }
+
+    fn get_repo(repo: &RepoInfo) -> MockBuilder {
+        Mock::given(method("GET")).and(path(format!(
+            "/api/v1/repos/{}/{}",
+            repo.org(),
+            repo.name()
+        )))
+    }
+
+    fn repo() -> ResponseTemplate {
+        ResponseTemplate::new(200).set_body_json(json!({ "default_branch": "main" }))
+    }
+
+    fn authorization() -> HeaderExactMatcher {
+        header("Authorization", format!("token {}", TOKEN).as_str())
+    }
+
+    async fn mount_last_commit(server: &MockServer, expected: u64) {
+        Mock::given(method("GET"))
+            .and(path("/api/v1/repos/org/template-master/commits"))
+            .and(query_param("sha", "main"))
+            .and(query_param("limit", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([{
+                "sha": "570e7b2a",
+                "commit": { "committer": { "date": "2025-03-02T10:20:00+01:00" } },
+            }])))
+            .expect(expected)
+            .mount(server)
+            .await;
+    }
+}
+
+fn contents(content: &str, sha: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({
+        "type": "file",
+        "sha": sha,
+        "encoding": "base64",
+        "content": BASE64.encode(content),
+    }))
+}
+
+fn pull_request(number: u64, head: &str) -> Value {
+    json!({
+        "number": number,
+        "html_url": format!("https://gitea.example.com/org/service/pulls/{}", number),
+        "state": "open",
+        "head": { "ref": head },
+        "base": { "ref": "main" },
+    })
+}
+
+#[test]
+fn test_config_debug_redacts_token() {
+    let config = GiteaConfig::new(
+        "https://gitea.example.com/api/v1".to_string(),
+        TOKEN.to_string(),
+        Gitea::service(),
+    );
+
+    let debug = format!("{:?}", config);
+
+    assert!(!debug.contains(TOKEN));
+    assert!(debug.contains("<redacted>"));
+}
+
+#[tokio::test]
+async fn test_gitea_client_conformance() {
+    conformance::run_all::<Gitea>().await;
+}
+
+#[tokio::test]
+async fn test_list_repos_by_category_uses_default_branch() {
+    let server = MockServer::start().await;
+    mount_config::<Gitea>(&server).await;
+    Mock::given(method("GET"))
+        .and(path("/api/v1/repos/org/service"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!({ "default_branch": "develop" })),
+        )
+        .mount(&server)
+        .await;
+    let client = Gitea::client(&server);
+
+    let repos = client.list_repos_by_category(&category()).await.unwrap();
+
+    assert_eq!(repos.len(), 1);
+    assert_eq!(repos[0].default_branch(), "develop");
+}
+
+#[tokio::test]
+async fn test_get_updated_templates_reads_templates_touched_in_range() {
+    let server = MockServer::start().await;
+    mount_config::<Gitea>(&server).await;
+    Mock::given(method("GET"))
+        .and(path(
+            "/api/v1/repos/org/template-master/compare/base-sha...main",
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "total_commits": 2,
+            "commits": [
+                { "commit": { "committer": { "date": "2025-03-02T10:20:00Z" } }, "files": [
+                    { "filename": "templates/saas_rust/.github/PULL_REQUEST_TEMPLATE.md", "status": "modified" },
+                    { "filename": "README.md", "status": "modified" },
+                ] },
+                { "commit": { "committer": { "date": "2025-03-01T10:20:00Z" } }, "files": [
+                    { "filename": "templates/saas_rust/CODEOWNERS", "status": "removed" },
+                    { "filename": "templates/saas_rust/.github/PULL_REQUEST_TEMPLATE.md", "status": "modified" },
+                ] },
+            ],
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(
+            "/api/v1/repos/org/template-master/contents/templates/saas_rust/.github/PULL_REQUEST_TEMPLATE.md",
+        ))
+        .and(query_param("ref", "main"))
+        .respond_with(contents("template v2", "new-sha"))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(
+            "/api/v1/repos/org/template-master/contents/templates/saas_rust/.github/PULL_REQUEST_TEMPLATE.md",
+        ))
+        .and(query_param("ref", "base-sha"))
+        .respond_with(contents("template v1", "old-sha"))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(
+            "/api/v1/repos/org/template-master/contents/templates/saas_rust/CODEOWNERS",
+        ))
+        .and(query_param("ref", "main"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&server)
+        .await;
+    let client = Gitea::client(&server);
+
+    let changes = client
+        .get_updated_templates(&category(), "base-sha")
+        .await
+        .unwrap();
+
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].path(), ".github/PULL_REQUEST_TEMPLATE.md");
+    assert_eq!(changes[0].content(), b"template v2");
+    assert_eq!(
+        changes[0].old_checksum_at(0),
+        Some(&calculate_checksum(b"template v1"))
+    );
+}
+
+#[tokio::test]
+async fn test_update_repo_creates_branch_commits_and_opens_pull_request() {
+    let server = MockServer::start().await;
+    let changes = [
+        TemplateChange::new(
+            ".github/PULL_REQUEST_TEMPLATE.md".to_string(),
+            vec![calculate_checksum(b"v1")],
+            calculate_checksum(b"v2"),
+            b"v2".to_vec(),
+        ),
+        TemplateChange::new(
+            "README.md".to_string(),
+            Vec::new(),
+            calculate_checksum(b"readme"),
+            b"readme".to_vec(),
+        ),
+    ];
+    let branch = update_branch_name(&changes);
+
+    Mock::given(method("GET"))
+        .and(path(format!(
+            "/api/v1/repos/org/service/branches/{}",
+            branch
+        )))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/repos/org/service/branches"))
+        .and(body_partial_json(
+            json!({ "new_branch_name": branch, "old_branch_name": "main" }),
+        ))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({ "name": branch })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(
+            "/api/v1/repos/org/service/contents/.github/PULL_REQUEST_TEMPLATE.md",
+        ))
+        .and(query_param("ref", branch.as_str()))
+        .respond_with(contents("v1", "old-blob"))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/api/v1/repos/org/service/contents/README.md"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/repos/org/service/contents"))
+        .and(body_partial_json(json!({
+            "branch": branch,
+            "files": [
+                {
+                    "operation": "update",
+                    "path": ".github/PULL_REQUEST_TEMPLATE.md",
+                    "content": BASE64.encode("v2"),
+                    "sha": "old-blob",
+                },
+                {
+                    "operation": "create",
+                    "path": "README.md",
+                    "content": BASE64.encode("readme"),
+                },
+            ],
+        })))
+        .respond_with(
+            ResponseTemplate::new(201).set_body_json(json!({ "commit": { "sha": "new-commit" } })),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/api/v1/repos/org/service/pulls"))
+        .and(query_param("state", "open"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!([pull_request(3, "other-branch")])),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/repos/org/service/pulls"))
+        .and(body_partial_json(json!({ "head": branch, "base": "main" })))
+        .respond_with(ResponseTemplate::new(201).set_body_json(pull_request(42, &branch)))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let client = Gitea::client(&server);
+
+    let result = client
+        .update_repo(&Gitea::service(), &changes)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        result.pr_url(),
+        "https://gitea.example.com/org/service/pulls/42"
+    );
+    assert_eq!(result.pr_number(), Some(42));
+    assert_eq!(result.updated_files().len(), 2);
+}
+
+#[tokio::test]
+async fn test_update_repo_reuses_up_to_date_branch_and_pull_request() {
+    let server = MockServer::start().await;
+    let change = TemplateChange::new(
+        "README.md".to_string(),
+        Vec::new(),
+        calculate_checksum(b"readme"),
+        b"readme".to_vec(),
+    );
+    let branch = update_branch_name(std::slice::from_ref(&change));
+
+    Mock::given(method("GET"))
+        .and(path(format!(
+            "/api/v1/repos/org/service/branches/{}",
+            branch
+        )))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "name": branch })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/api/v1/repos/org/service/contents/README.md"))
+        .respond_with(contents("readme", "readme-blob"))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/api/v1/repos/org/service/pulls"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([pull_request(7, &branch)])))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(201))
+        .expect(0)
+        .mount(&server)
+        .await;
+    let client = Gitea::client(&server);
+
+    let result = client
+        .update_repo(&Gitea::service(), &[change])
+        .await
+        .unwrap();
+
+    assert_eq!(result.pr_number(), Some(7));
+}
+
+/// A repository created on a real Gitea server for `test_sync_against_gitea_server`.
+struct ServerRepo {
+    http: reqwest::Client,
+    api_url: String,
+    token: String,
+    repo: RepoInfo,
+}
+
+impl ServerRepo {
+    /// Creates a repository owned by the token's user, with the given files on `main`.
+    async fn create(api_url: &str, token: &str, name: &str, files: &[(&str, &str)]) -> Self {
+        let http = reqwest::Client::new();
+        let created: Value = http
+            .post(format!("{}/user/repos", api_url))
+            .header("Authorization", format!("token {}", token))
+            .json(&json!({ "name": name, "auto_init": true, "default_branch": "main" }))
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let owner = created["owner"]["login"].as_str().unwrap().to_string();
+        let files: Vec<Value> = files
+            .iter()
+            .map(|(path, content)| {
+                json!({ "operation": "create", "path": path, "content": BASE64.encode(content) })
+            })
+            .collect();
+        http.post(format!("{}/repos/{}/{}/contents", api_url, owner, name))
+            .header("Authorization", format!("token {}", token))
+            .json(&json!({ "branch": "main", "message": "Add files", "files": files }))
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+
+        Self {
+            http,
+            api_url: api_url.to_string(),
+            token: token.to_string(),
+            repo: RepoInfo::new(owner, name.to_string(), "main".to_string()),
+        }
+    }
+
+    async fn delete(self) {
+        self.http
+            .delete(format!(
+                "{}/repos/{}/{}",
+                self.api_url,
+                self.repo.org(),
+                self.repo.name()
+            ))
+            .header("Authorization", format!("token {}", self.token))
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_sync_against_gitea_server() {
+    let (Ok(api_url), Ok(token)) = (std::env::var("GITEA_URL"), std::env::var("GITEA_TOKEN"))
+    else {
+        eprintln!("GITEA_URL or GITEA_TOKEN is not set, skipping Gitea server test");
+        return;
+    };
+    let suffix = Utc::now().timestamp_micros();
+    let target = ServerRepo::create(&api_url, &token, &format!("tt-target-{}", suffix), &[]).await;
+    let config = format!(
+        "[meta]\nconfig_version = \"1.0\"\n\n[categories.saas_rust]\nfiles = [\"CODEOWNERS\"]\n\n\
+         [repositories]\n\"{}/{}\" = {{ category = \"saas_rust\" }}\n",
+        target.repo.org(),
+        target.repo.name()
+    );
+    let master = ServerRepo::create(
+        &api_url,
+        &token,
+        &format!("tt-master-{}", suffix),
+        &[
+            (MASTER_CONFIG_FILE, &config),
+            ("templates/saas_rust/CODEOWNERS", "* @org/owners\n"),
+        ],
+    )
+    .await;
+    let client = GiteaClient::new(GiteaConfig::new(
+        api_url.clone(),
+        token.clone(),
+        master.repo.clone(),
+    ))
+    .unwrap();
+
+    let repos = client.list_repos_by_category(&category()).await.unwrap();
+    assert_eq!(repos.len(), 1);
+    let templates = client.list_templates(&category()).await.unwrap();
+    assert_eq!(templates.len(), 1);
+    let path = templates[0].path().clone();
+    assert_eq!(client.get_repo_file(&repos[0], &path).await.unwrap(), None);
+    let content = client.get_template(&category(), &path).await.unwrap();
+    let change = TemplateChange::new(
+        path.clone(),
+        Vec::new(),
+        templates[0].checksum().to_string(),
+        content,
+    );
+
+    let first = client
+        .update_repo(&repos[0], std::slice::from_ref(&change))
+        .await
+        .unwrap();
+    let second = client.update_repo(&repos[0], &[change]).await.unwrap();
+
+    assert_eq!(second.pr_number(), first.pr_number());
+    master.delete().await;
+    target.delete().await;
+}
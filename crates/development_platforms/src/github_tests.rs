@@ -0,0 +1,429 @@
+use super::*;
+use crate::conformance::{self, category, mount_config, StubPlatform};
+use jsonwebtoken::{DecodingKey, Validation};
+use wiremock::matchers::{
+    body_partial_json, header, method, path, path_regex, query_param, HeaderExactMatcher,
+};
+use wiremock::{Mock, MockBuilder, MockServer, ResponseTemplate};
+
+const PRIVATE_KEY: &str = include_str!("../test_data/github_app_private_key.pem");
+const PUBLIC_KEY: &str = include_str!("../test_data/github_app_public_key.pem");
+
+const APP_ID: u64 = 12345;
+const TOKEN: &str = "ghs_installation_token";
+
+struct GitHub;
+
+impl StubPlatform for GitHub {
+    type Client = GitHubClient;
+
+    const REJECTED_TOKEN: &'static str = "Bad credentials";
+
+    fn client(server: &MockServer) -> GitHubClient {
+        let config = GitHubAppConfig::new(APP_ID, PRIVATE_KEY.to_string(), Self::master())
+            .with_api_base_url(server.uri());
+        GitHubClient::new(config).unwrap()
+    }
+
+    fn get_file(repo: &RepoInfo, file: &str) -> MockBuilder {
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/repos/{}/{}/contents/{}",
+                repo.org(),
+                repo.name(),
+                file
+            )))
+            .and(query_param("ref", "main"))
+    }
+
+    fn file(content: &str) -> ResponseTemplate {
+        contents(content)
+    }
+
+    fn get_repo(repo: &RepoInfo) -> MockBuilder {
+        Mock::given(method("GET")).and(path(format!("/repos/{}/{}", repo.org(), repo.name())))
+    }
+
+    fn repo() -> ResponseTemplate {
+        ResponseTemplate::new(200).set_body_json(json!({ "default_branch": "main" }))
+    }
+
+    fn authorization() -> HeaderExactMatcher {
+        header("Authorization", format!("Bearer {}", TOKEN).as_str())
+    }
+
+    async fn mount_last_commit(server: &MockServer, expected: u64) {
+        Mock::given(method("GET"))
+            .and(path("/repos/org/template-master/commits"))
+            .and(query_param("sha", "main"))
+            .and(query_param("per_page", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([{
+                "commit": { "committer": { "date": "2025-03-02T10:20:00+01:00" } },
+            }])))
+            .expect(expected)
+            .mount(server)
+            .await;
+    }
+
+    async fn start() -> MockServer {
+        let server = MockServer::start().await;
+        mount_auth(&server, 3600, 1).await;
+        server
+    }
+}
+
+fn contents(content: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({
+        "type": "file",
+        "encoding": "base64",
+        "content": format!("{}\n", BASE64.encode(content)),
+    }))
+}
+
+/// Mounts the installation lookup and a token exchange returning a token which expires in
+/// `expires_in` seconds.
+async fn mount_auth(server: &MockServer, expires_in: i64, expected_exchanges: u64) {
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/repos/org/[^/]+/installation$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "id": 7 })))
+        .mount(server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/app/installations/7/access_tokens"))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({
+            "token": TOKEN,
+            "expires_at": (Utc::now() + Duration::seconds(expires_in)).to_rfc3339(),
+        })))
+        .expect(expected_exchanges)
+        .mount(server)
+        .await;
+}
+
+#[test]
+fn test_app_jwt_is_signed_with_the_app_key() {
+    let client = GitHubClient::new(GitHubAppConfig::new(
+        APP_ID,
+        PRIVATE_KEY.to_string(),
+        GitHub::service(),
+    ))
+    .unwrap();
+
+    let jwt = client.app_jwt().unwrap();
+
+    let key = DecodingKey::from_rsa_pem(PUBLIC_KEY.as_bytes()).unwrap();
+    let claims = jsonwebtoken::decode::<AppClaims>(&jwt, &key, &Validation::new(Algorithm::RS256))
+        .unwrap()
+        .claims;
+    assert_eq!(claims.iss, APP_ID.to_string());
+    assert!(claims.exp - claims.iat <= 600);
+    assert!(claims.iat <= Utc::now().timestamp());
+}
+
+#[test]
+fn test_new_rejects_invalid_private_key() {
+    let result = GitHubClient::new(GitHubAppConfig::new(
+        APP_ID,
+        "not a key".to_string(),
+        GitHub::service(),
+    ));
+
+    assert!(matches!(result, Err(PlatformError::ConfigError(_))));
+}
+
+#[test]
+fn test_config_debug_redacts_private_key() {
+    let config = GitHubAppConfig::new(APP_ID, PRIVATE_KEY.to_string(), GitHub::service());
+
+    let debug = format!("{:?}", config);
+
+    assert!(!debug.contains("PRIVATE KEY"));
+    assert!(debug.contains("<redacted>"));
+}
+
+#[tokio::test]
+async fn test_github_client_conformance() {
+    conformance::run_all::<GitHub>().await;
+}
+
+#[tokio::test]
+async fn test_list_categories_reuses_cached_installation_token() {
+    let server = MockServer::start().await;
+    mount_auth(&server, 3600, 1).await;
+    mount_config::<GitHub>(&server).await;
+    let client = GitHub::client(&server);
+
+    let first = client.list_categories().await.unwrap();
+    let second = client.list_categories().await.unwrap();
+
+    assert_eq!(first, vec![category()]);
+    assert_eq!(second, first);
+}
+
+#[tokio::test]
+async fn test_installation_token_refreshed_when_about_to_expire() {
+    let server = MockServer::start().await;
+    mount_auth(&server, 30, 2).await;
+    mount_config::<GitHub>(&server).await;
+    let client = GitHub::client(&server);
+
+    client.list_categories().await.unwrap();
+    client.list_categories().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_app_not_installed_maps_to_repo_not_found() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/repos/org/service/installation"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&server)
+        .await;
+    let client = GitHub::client(&server);
+
+    let result = client
+        .get_repo_file(&GitHub::service(), &"README.md".to_string())
+        .await;
+
+    assert!(
+        matches!(result, Err(PlatformError::RepoNotFound { org, name }) if org == "org" && name == "service")
+    );
+}
+
+#[tokio::test]
+async fn test_rate_limit_responses_map_to_rate_limit_exceeded() {
+    for response in [
+        ResponseTemplate::new(403).insert_header("x-ratelimit-remaining", "0"),
+        ResponseTemplate::new(429),
+    ] {
+        let server = MockServer::start().await;
+        mount_auth(&server, 3600, 1).await;
+        Mock::given(method("GET"))
+            .and(path("/repos/org/service/contents/README.md"))
+            .respond_with(response)
+            .mount(&server)
+            .await;
+        let client = GitHub::client(&server);
+
+        let result = client
+            .get_repo_file(&GitHub::service(), &"README.md".to_string())
+            .await;
+
+        assert!(matches!(result, Err(PlatformError::RateLimitExceeded)));
+    }
+}
+
+#[tokio::test]
+async fn test_rejected_credentials_map_to_auth_error() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/repos/org/service/installation"))
+        .respond_with(ResponseTemplate::new(401).set_body_string("Bad credentials"))
+        .mount(&server)
+        .await;
+    let client = GitHub::client(&server);
+
+    let result = client
+        .get_repo_file(&GitHub::service(), &"README.md".to_string())
+        .await;
+
+    assert!(
+        matches!(result, Err(PlatformError::AuthError(msg)) if msg.contains("Bad credentials"))
+    );
+}
+
+#[tokio::test]
+async fn test_list_repos_by_category_uses_default_branch() {
+    let server = MockServer::start().await;
+    mount_auth(&server, 3600, 1).await;
+    mount_config::<GitHub>(&server).await;
+    Mock::given(method("GET"))
+        .and(path("/repos/org/service"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!({ "default_branch": "develop" })),
+        )
+        .mount(&server)
+        .await;
+    let client = GitHub::client(&server);
+
+    let repos = client.list_repos_by_category(&category()).await.unwrap();
+
+    assert_eq!(repos.len(), 1);
+    assert_eq!(repos[0].name(), "service");
+    assert_eq!(repos[0].default_branch(), "develop");
+}
+
+#[tokio::test]
+async fn test_update_repo_commits_to_branch_and_opens_pull_request() {
+    let server = MockServer::start().await;
+    mount_auth(&server, 3600, 1).await;
+    let change = TemplateChange::new(
+        ".github/PULL_REQUEST_TEMPLATE.md".to_string(),
+        vec![calculate_checksum(b"v1")],
+        calculate_checksum(b"v2"),
+        b"v2".to_vec(),
+    );
+    let branch = update_branch_name(std::slice::from_ref(&change));
+
+    Mock::given(method("GET"))
+        .and(path("/repos/org/service/git/ref/heads/main"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "ref": "refs/heads/main",
+            "object": { "sha": "base-commit" },
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/repos/org/service/git/commits/base-commit"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "sha": "base-commit",
+            "tree": { "sha": "base-tree" },
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/repos/org/service/git/blobs"))
+        .and(body_partial_json(
+            json!({ "content": BASE64.encode("v2"), "encoding": "base64" }),
+        ))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({ "sha": "blob-sha" })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/repos/org/service/git/trees"))
+        .and(body_partial_json(json!({
+            "base_tree": "base-tree",
+            "tree": [{ "path": ".github/PULL_REQUEST_TEMPLATE.md", "sha": "blob-sha" }],
+        })))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({ "sha": "new-tree" })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/repos/org/service/git/commits"))
+        .and(body_partial_json(
+            json!({ "tree": "new-tree", "parents": ["base-commit"] }),
+        ))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({ "sha": "new-commit" })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("/repos/org/service/git/ref/heads/{}", branch)))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/repos/org/service/git/refs"))
+        .and(body_partial_json(json!({
+            "ref": format!("refs/heads/{}", branch),
+            "sha": "new-commit",
+        })))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({
+            "ref": format!("refs/heads/{}", branch),
+            "object": { "sha": "new-commit" },
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/repos/org/service/pulls"))
+        .and(query_param("head", format!("org:{}", branch).as_str()))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/repos/org/service/pulls"))
+        .and(body_partial_json(json!({ "head": branch, "base": "main" })))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({
+            "html_url": "https://github.com/org/service/pull/42",
+            "number": 42,
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let client = GitHub::client(&server);
+
+    let result = client
+        .update_repo(&GitHub::service(), &[change])
+        .await
+        .unwrap();
+
+    assert_eq!(result.pr_url(), "https://github.com/org/service/pull/42");
+    assert_eq!(result.pr_number(), Some(42));
+    assert_eq!(
+        result.updated_files(),
+        &vec![".github/PULL_REQUEST_TEMPLATE.md".to_string()]
+    );
+}
+
+#[tokio::test]
+async fn test_update_repo_reuses_existing_branch_and_pull_request() {
+    let server = MockServer::start().await;
+    mount_auth(&server, 3600, 1).await;
+    let change = TemplateChange::new(
+        "README.md".to_string(),
+        Vec::new(),
+        calculate_checksum(b"readme"),
+        b"readme".to_vec(),
+    );
+    let branch = update_branch_name(std::slice::from_ref(&change));
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/repos/org/service/git/ref/heads/.+$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "object": { "sha": "base-commit" },
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/repos/org/service/git/commits/base-commit"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!({ "tree": { "sha": "base-tree" } })),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path_regex(
+            r"^/repos/org/service/git/(blobs|trees|commits)$",
+        ))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({ "sha": "new-sha" })))
+        .mount(&server)
+        .await;
+    Mock::given(method("PATCH"))
+        .and(path(format!(
+            "/repos/org/service/git/refs/heads/{}",
+            branch
+        )))
+        .and(body_partial_json(
+            json!({ "sha": "new-sha", "force": true }),
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "object": { "sha": "new-sha" },
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/repos/org/service/pulls"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([{
+            "html_url": "https://github.com/org/service/pull/7",
+            "number": 7,
+        }])))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/repos/org/service/pulls"))
+        .respond_with(ResponseTemplate::new(201))
+        .expect(0)
+        .mount(&server)
+        .await;
+    let client = GitHub::client(&server);
+
+    let result = client
+        .update_repo(&GitHub::service(), &[change])
+        .await
+        .unwrap();
+
+    assert_eq!(result.pr_number(), Some(7));
+}
@@ -0,0 +1,580 @@
+//! Implements `DeveloperPlatform` for GitLab, authenticating with access tokens.
+//!
+//! Projects are addressed by their URL-encoded full path (e.g. `org%2Fservice`). Requests for a
+//! project carry its project access token, or a default token, e.g. a group access token, for
+//! projects without one. Templates, the master configuration and target repository files are
+//! read through the repository files API. Updates are committed with the commits API onto a
+//! dedicated branch, for which a merge request is opened.
+//!
+//! All requests go to a configurable API base URL, so the client can be pointed at a
+//! self-managed GitLab instance or at a local stub server in tests.
+
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use reqwest::{Method, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::checksum::calculate_checksum;
+use crate::master_config::{repo_full_name, split_repo_name, MasterConfig, MASTER_CONFIG_FILE};
+use crate::update_branch::{
+    update_branch_name, update_commit_message, update_description, update_title,
+};
+use crate::{
+    DeveloperPlatform, PlatformError, RepoInfo, TemplateCategory, TemplateChange, TemplateMetadata,
+    TemplatePath, UpdateResult,
+};
+
+#[cfg(test)]
+#[path = "gitlab_tests.rs"]
+mod tests;
+
+/// The base URL of the REST API of GitLab.com.
+pub const GITLAB_API_URL: &str = "https://gitlab.com/api/v4";
+
+const USER_AGENT: &str = "template-teleporter";
+
+/// The configuration of a `GitLabClient`.
+///
+/// # Example
+/// ```rust
+/// use template_teleporter_developer_platforms::{GitLabConfig, RepoInfo};
+/// let config = GitLabConfig::new(
+///     "glpat-master-token".to_string(),
+///     RepoInfo::new("my-org".to_string(), "template-master".to_string(), "main".to_string()),
+/// )
+/// .with_project_token("my-org/service", "glpat-service-token")
+/// .with_api_base_url("https://gitlab.example.com/api/v4");
+/// assert_eq!(config.api_base_url, "https://gitlab.example.com/api/v4");
+/// ```
+#[derive(Clone)]
+pub struct GitLabConfig {
+    /// The access token for projects without a project access token of their own, e.g. a group
+    /// access token, or the project access token of the master repository.
+    pub token: String,
+
+    /// Project access tokens, keyed by the full path of their project (e.g. "org/service").
+    pub project_tokens: HashMap<String, String>,
+
+    /// The master template repository.
+    pub master_repo: RepoInfo,
+
+    /// The base URL of the GitLab REST API, including the `/api/v4` path.
+    pub api_base_url: String,
+}
+
+impl GitLabConfig {
+    /// Creates a new `GitLabConfig` for GitLab.com, using `token` for every project.
+    pub fn new(token: String, master_repo: RepoInfo) -> Self {
+        Self {
+            token,
+            project_tokens: HashMap::new(),
+            master_repo,
+            api_base_url: GITLAB_API_URL.to_string(),
+        }
+    }
+
+    /// Sets the project access token for a project, given by its full path.
+    pub fn with_project_token(
+        mut self,
+        project: impl Into<String>,
+        token: impl Into<String>,
+    ) -> Self {
+        self.project_tokens.insert(project.into(), token.into());
+        self
+    }
+
+    /// Sets the base URL of the GitLab REST API, e.g. for a self-managed instance.
+    pub fn with_api_base_url(mut self, api_base_url: impl Into<String>) -> Self {
+        self.api_base_url = api_base_url.into();
+        self
+    }
+}
+
+// Manual Debug implementation so that the tokens never end up in logs.
+impl fmt::Debug for GitLabConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut projects: Vec<&String> = self.project_tokens.keys().collect();
+        projects.sort();
+        f.debug_struct("GitLabConfig")
+            .field("token", &"<redacted>")
+            .field("project_tokens", &projects)
+            .field("master_repo", &self.master_repo)
+            .field("api_base_url", &self.api_base_url)
+            .finish()
+    }
+}
+
+/// A `DeveloperPlatform` implementation for GitLab, authenticating with access tokens.
+pub struct GitLabClient {
+    token: String,
+    project_tokens: HashMap<String, String>,
+    master_repo: RepoInfo,
+    api_base_url: Url,
+    http: reqwest::Client,
+}
+
+// Manual Debug implementation so that the tokens never end up in logs.
+impl fmt::Debug for GitLabClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GitLabClient")
+            .field("master_repo", &self.master_repo)
+            .field("api_base_url", &self.api_base_url.as_str())
+            .finish_non_exhaustive()
+    }
+}
+
+impl GitLabClient {
+    /// Creates a new `GitLabClient`.
+    ///
+    /// # Errors
+    /// Returns `PlatformError::ConfigError` if the API base URL is invalid.
+    pub fn new(config: GitLabConfig) -> Result<Self, PlatformError> {
+        let api_base_url = Url::parse(&config.api_base_url).map_err(|e| {
+            PlatformError::ConfigError(format!(
+                "Invalid GitLab API URL '{}': {}",
+                config.api_base_url, e
+            ))
+        })?;
+        let http = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .build()
+            .map_err(|e| PlatformError::ConfigError(e.to_string()))?;
+
+        Ok(Self {
+            token: config.token,
+            project_tokens: config.project_tokens,
+            master_repo: config.master_repo,
+            api_base_url,
+            http,
+        })
+    }
+
+    /// Returns the access token for the given project.
+    fn token_for(&self, project: &str) -> &str {
+        self.project_tokens
+            .get(project)
+            .map_or(&self.token, |token| token)
+    }
+
+    /// Builds an API URL for the given project from the base URL and the given path segments.
+    /// Each segment is encoded as a whole, so that the `/` in project and file paths is
+    /// encoded as `%2F`, as GitLab expects.
+    fn url(&self, repo: &RepoInfo, segments: &[&str]) -> Url {
+        let mut url = self.api_base_url.clone();
+        if let Ok(mut path) = url.path_segments_mut() {
+            path.pop_if_empty()
+                .push("projects")
+                .push(&repo_full_name(repo))
+                .extend(segments);
+        }
+        url
+    }
+
+    /// Calls the API for the given project, authenticated with its access token.
+    ///
+    /// # Returns
+    /// `None` if the API responded with 404 Not Found, otherwise the deserialized response body.
+    async fn api<T: DeserializeOwned>(
+        &self,
+        repo: &RepoInfo,
+        method: Method,
+        url: Url,
+        body: Option<Value>,
+    ) -> Result<Option<T>, PlatformError> {
+        let mut request = self
+            .http
+            .request(method, url)
+            .header("PRIVATE-TOKEN", self.token_for(&repo_full_name(repo)));
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| PlatformError::ApiError(e.to_string()))?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(map_error(response).await);
+        }
+        parse_json(response).await.map(Some)
+    }
+
+    /// Calls the API for the given project, mapping 404 Not Found to `RepoNotFound`.
+    async fn project_api<T: DeserializeOwned>(
+        &self,
+        repo: &RepoInfo,
+        method: Method,
+        segments: &[&str],
+        body: Option<Value>,
+    ) -> Result<T, PlatformError> {
+        let url = self.url(repo, segments);
+        self.api(repo, method, url, body)
+            .await?
+            .ok_or_else(|| PlatformError::RepoNotFound {
+                org: repo.org().to_string(),
+                name: repo.name().to_string(),
+            })
+    }
+
+    /// Reads a file through the repository files API.
+    ///
+    /// # Returns
+    /// `None` if the project or the file does not exist at the given reference.
+    async fn get_file(
+        &self,
+        repo: &RepoInfo,
+        path: &str,
+        reference: &str,
+    ) -> Result<Option<Vec<u8>>, PlatformError> {
+        let mut url = self.url(repo, &["repository", "files", path]);
+        url.query_pairs_mut().append_pair("ref", reference);
+        let file: Option<RepositoryFile> = self.api(repo, Method::GET, url, None).await?;
+        file.map(|f| f.decode()).transpose()
+    }
+
+    /// Reads and parses the master configuration from the master repository.
+    async fn load_config(&self) -> Result<MasterConfig, PlatformError> {
+        let content = self
+            .get_file(
+                &self.master_repo,
+                MASTER_CONFIG_FILE,
+                self.master_repo.default_branch(),
+            )
+            .await?
+            .ok_or_else(|| {
+                PlatformError::ConfigError(format!(
+                    "{} not found in {}",
+                    MASTER_CONFIG_FILE,
+                    repo_full_name(&self.master_repo)
+                ))
+            })?;
+        let content =
+            String::from_utf8(content).map_err(|e| PlatformError::InvalidContent(e.to_string()))?;
+        MasterConfig::from_toml_str(&content)
+    }
+
+    /// Returns the open merge request from `branch` into the default branch, if there is one.
+    async fn find_merge_request(
+        &self,
+        repo: &RepoInfo,
+        branch: &str,
+    ) -> Result<Option<MergeRequest>, PlatformError> {
+        let mut url = self.url(repo, &["merge_requests"]);
+        url.query_pairs_mut()
+            .append_pair("source_branch", branch)
+            .append_pair("target_branch", repo.default_branch())
+            .append_pair("state", "opened");
+        let merge_requests: Vec<MergeRequest> = self
+            .api(repo, Method::GET, url, None)
+            .await?
+            .unwrap_or_default();
+        Ok(merge_requests.into_iter().next())
+    }
+}
+
+#[async_trait]
+impl DeveloperPlatform for GitLabClient {
+    async fn list_categories(&self) -> Result<Vec<TemplateCategory>, PlatformError> {
+        Ok(self.load_config().await?.categories())
+    }
+
+    async fn get_template(
+        &self,
+        category: &TemplateCategory,
+        path: &TemplatePath,
+    ) -> Result<Vec<u8>, PlatformError> {
+        self.load_config().await?.category(category)?;
+        self.get_file(
+            &self.master_repo,
+            &MasterConfig::master_path(category, path),
+            self.master_repo.default_branch(),
+        )
+        .await?
+        .ok_or_else(|| PlatformError::TemplateNotFound(path.clone()))
+    }
+
+    async fn list_templates(
+        &self,
+        category: &TemplateCategory,
+    ) -> Result<Vec<TemplateMetadata>, PlatformError> {
+        let config = self.load_config().await?;
+        let master = &self.master_repo;
+        let mut templates = Vec::new();
+        for path in &config.category(category)?.files {
+            let master_path = MasterConfig::master_path(category, path);
+            let content = self
+                .get_file(master, &master_path, master.default_branch())
+                .await?
+                .ok_or_else(|| PlatformError::TemplateNotFound(path.clone()))?;
+
+            let mut url = self.url(master, &["repository", "commits"]);
+            url.query_pairs_mut()
+                .append_pair("path", &master_path)
+                .append_pair("ref_name", master.default_branch())
+                .append_pair("per_page", "1");
+            let commits: Vec<Commit> = self
+                .api(master, Method::GET, url, None)
+                .await?
+                .unwrap_or_default();
+            let last_updated = commits
+                .first()
+                .map(|c| c.committed_date)
+                .ok_or_else(|| PlatformError::TemplateNotFound(path.clone()))?;
+
+            templates.push(TemplateMetadata::new(
+                path.clone(),
+                calculate_checksum(&content),
+                last_updated,
+            ));
+        }
+        Ok(templates)
+    }
+
+    async fn list_repos_by_category(
+        &self,
+        category: &TemplateCategory,
+    ) -> Result<Vec<RepoInfo>, PlatformError> {
+        let config = self.load_config().await?;
+        let mut repos = Vec::new();
+        for full_name in config.repositories_for(category)? {
+            let (org, name) = split_repo_name(full_name)?;
+            let repo = RepoInfo::new(org, name, String::new());
+            let project: Project = self.project_api(&repo, Method::GET, &[], None).await?;
+            repos.push(RepoInfo::new(
+                repo.org().to_string(),
+                repo.name().to_string(),
+                project.default_branch,
+            ));
+        }
+        Ok(repos)
+    }
+
+    async fn get_repo_file(
+        &self,
+        repo: &RepoInfo,
+        path: &TemplatePath,
+    ) -> Result<Option<Vec<u8>>, PlatformError> {
+        match self.get_file(repo, path, repo.default_branch()).await? {
+            Some(content) => Ok(Some(content)),
+            None => {
+                // The repository files API reports missing files and missing projects alike.
+                let _: Project = self.project_api(repo, Method::GET, &[], None).await?;
+                Ok(None)
+            }
+        }
+    }
+
+    async fn get_updated_templates(
+        &self,
+        category: &TemplateCategory,
+        since_commit: &str,
+    ) -> Result<Vec<TemplateChange>, PlatformError> {
+        let config = self.load_config().await?;
+        let category_config = config.category(category)?;
+        let category_dir = MasterConfig::master_path(category, &String::new());
+        let master = &self.master_repo;
+
+        // Compares from the merge base, like GitHub's three-dot comparison.
+        let mut url = self.url(master, &["repository", "compare"]);
+        url.query_pairs_mut()
+            .append_pair("from", since_commit)
+            .append_pair("to", master.default_branch());
+        let comparison: Comparison =
+            self.api(master, Method::GET, url, None)
+                .await?
+                .ok_or_else(|| PlatformError::RepoNotFound {
+                    org: master.org().to_string(),
+                    name: master.name().to_string(),
+                })?;
+
+        let mut changes = Vec::new();
+        for diff in comparison.diffs {
+            // Templates removed from the master repository are not changes to apply.
+            if diff.deleted_file {
+                continue;
+            }
+            let Some(path) = diff.new_path.strip_prefix(&category_dir) else {
+                continue;
+            };
+            if !category_config.files.iter().any(|f| f == path) {
+                continue;
+            }
+
+            let content = self
+                .get_file(master, &diff.new_path, master.default_branch())
+                .await?
+                .ok_or_else(|| PlatformError::TemplateNotFound(path.to_string()))?;
+            let old_checksum = self
+                .get_file(master, &diff.old_path, since_commit)
+                .await?
+                .map(|old| calculate_checksum(&old));
+            changes.push(TemplateChange::new(
+                path.to_string(),
+                old_checksum.into_iter().collect(),
+                calculate_checksum(&content),
+                content,
+            ));
+        }
+        Ok(changes)
+    }
+
+    async fn update_repo(
+        &self,
+        repo: &RepoInfo,
+        changes: &[TemplateChange],
+    ) -> Result<UpdateResult, PlatformError> {
+        if changes.is_empty() {
+            return Err(PlatformError::OperationFailed(format!(
+                "No changes to apply to {}",
+                repo_full_name(repo)
+            )));
+        }
+
+        // 1. Build the commit actions. Files are created or updated depending on whether they
+        //    exist on the default branch, which the commit is based on.
+        let mut actions = Vec::new();
+        for change in changes {
+            let exists = self
+                .get_file(repo, change.path(), repo.default_branch())
+                .await?
+                .is_some();
+            actions.push(json!({
+                "action": if exists { "update" } else { "create" },
+                "file_path": change.path(),
+                "content": BASE64.encode(change.content()),
+                "encoding": "base64",
+            }));
+        }
+
+        // 2. Commit onto the update branch, starting it from the default branch. `force`
+        //    resets an existing branch onto the default branch as well.
+        let updated_files: Vec<TemplatePath> =
+            changes.iter().map(|change| change.path().clone()).collect();
+        let branch = update_branch_name(changes);
+        let _: Commit = self
+            .project_api(
+                repo,
+                Method::POST,
+                &["repository", "commits"],
+                Some(json!({
+                    "branch": branch,
+                    "start_branch": repo.default_branch(),
+                    "commit_message": update_commit_message(&updated_files),
+                    "actions": actions,
+                    "force": true,
+                })),
+            )
+            .await?;
+
+        // 3. Open a merge request, unless one is already open for the branch.
+        let merge_request = match self.find_merge_request(repo, &branch).await? {
+            Some(merge_request) => merge_request,
+            None => {
+                self.project_api(
+                    repo,
+                    Method::POST,
+                    &["merge_requests"],
+                    Some(json!({
+                        "title": update_title(),
+                        "source_branch": branch,
+                        "target_branch": repo.default_branch(),
+                        "description": update_description(&updated_files),
+                        "remove_source_branch": true,
+                    })),
+                )
+                .await?
+            }
+        };
+
+        Ok(UpdateResult::new(
+            merge_request.web_url,
+            merge_request.iid,
+            updated_files,
+        ))
+    }
+}
+
+/// Maps an unsuccessful API response onto a `PlatformError`.
+async fn map_error(response: reqwest::Response) -> PlatformError {
+    let status = response.status();
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        return PlatformError::RateLimitExceeded;
+    }
+
+    let url = response.url().path().to_string();
+    let body = response.text().await.unwrap_or_default();
+    match status {
+        // GitLab answers 403 for tokens lacking the scope or role for a request.
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            PlatformError::AuthError(format!("{} for {}: {}", status, url, body))
+        }
+        _ => PlatformError::ApiError(format!("{} for {}: {}", status, url, body)),
+    }
+}
+
+async fn parse_json<T: DeserializeOwned>(response: reqwest::Response) -> Result<T, PlatformError> {
+    response
+        .json()
+        .await
+        .map_err(|e| PlatformError::InvalidContent(format!("Unexpected GitLab response: {}", e)))
+}
+
+#[derive(Debug, Deserialize)]
+struct Commit {
+    committed_date: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Comparison {
+    #[serde(default)]
+    diffs: Vec<Diff>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Diff {
+    old_path: String,
+    new_path: String,
+    #[serde(default)]
+    deleted_file: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct MergeRequest {
+    iid: u64,
+    web_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Project {
+    default_branch: String,
+}
+
+/// A file returned by the repository files API.
+#[derive(Debug, Deserialize)]
+struct RepositoryFile {
+    encoding: String,
+    content: String,
+}
+
+impl RepositoryFile {
+    fn decode(self) -> Result<Vec<u8>, PlatformError> {
+        if self.encoding != "base64" {
+            return Err(PlatformError::InvalidContent(format!(
+                "Unsupported content encoding '{}'",
+                self.encoding
+            )));
+        }
+        BASE64
+            .decode(self.content)
+            .map_err(|e| PlatformError::InvalidContent(e.to_string()))
+    }
+}
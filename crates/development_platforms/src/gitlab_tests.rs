@@ -0,0 +1,366 @@
+use super::*;
+use crate::conformance::{self, category, mount_config, replay, StubPlatform};
+use wiremock::matchers::{
+    body_partial_json, header, method, path, query_param, HeaderExactMatcher,
+};
+use wiremock::{Mock, MockBuilder, MockServer, ResponseTemplate};
+
+// Responses recorded from the GitLab API, trimmed to the fields of interest.
+const PROJECT: &str = include_str!("../test_data/gitlab/project.json");
+const FILE: &str = include_str!("../test_data/gitlab/file.json");
+const COMMITS: &str = include_str!("../test_data/gitlab/commits.json");
+const COMPARE: &str = include_str!("../test_data/gitlab/compare.json");
+const COMMIT: &str = include_str!("../test_data/gitlab/commit.json");
+const MERGE_REQUEST: &str = include_str!("../test_data/gitlab/merge_request.json");
+
+const MASTER_TOKEN: &str = "glpat-master";
+const SERVICE_TOKEN: &str = "glpat-service";
+
+const MASTER_PROJECT: &str = "/api/v4/projects/org%2Ftemplate-master";
+const SERVICE_PROJECT: &str = "/api/v4/projects/org%2Fservice";
+
+struct GitLab;
+
+impl StubPlatform for GitLab {
+    type Client = GitLabClient;
+
+    const MISSING_FILE: &'static str = "404 File Not Found";
+    const MISSING_REPO: &'static str = "404 Project Not Found";
+    const REJECTED_TOKEN: &'static str = "401 Unauthorized";
+
+    fn client(server: &MockServer) -> GitLabClient {
+        let config = GitLabConfig::new(MASTER_TOKEN.to_string(), Self::master())
+            .with_project_token("org/service", SERVICE_TOKEN)
+            .with_api_base_url(format!("{}/api/v4", server.uri()));
+        GitLabClient::new(config).unwrap()
+    }
+
+    fn get_file(repo: &RepoInfo, file: &str) -> MockBuilder {
+        Mock::given(method("GET"))
+            .and(path(file_path(&project(repo), file)))
+            .and(query_param("ref", "main"))
+    }
+
+    fn file(content: &str) -> ResponseTemplate {
+        file(content)
+    }
+
+    fn get_repo(repo: &RepoInfo) -> MockBuilder {
+        Mock::given(method("GET")).and(path(project(repo)))
+    }
+
+    fn repo() -> ResponseTemplate {
+        replay(200, PROJECT)
+    }
+
+    fn authorization() -> HeaderExactMatcher {
+        header("PRIVATE-TOKEN", MASTER_TOKEN)
+    }
+
+    async fn mount_last_commit(server: &MockServer, expected: u64) {
+        Mock::given(method("GET"))
+            .and(path(format!("{}/repository/commits", MASTER_PROJECT)))
+            .and(query_param("ref_name", "main"))
+            .and(query_param("per_page", "1"))
+            .respond_with(replay(200, COMMITS))
+            .expect(expected)
+            .mount(server)
+            .await;
+    }
+
+    fn error(status: u16, message: &str) -> ResponseTemplate {
+        ResponseTemplate::new(status).set_body_json(json!({ "message": message }))
+    }
+}
+
+fn file(content: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({
+        "encoding": "base64",
+        "content": BASE64.encode(content),
+    }))
+}
+
+/// The path of a project in the projects API.
+fn project(repo: &RepoInfo) -> String {
+    format!("/api/v4/projects/{}%2F{}", repo.org(), repo.name())
+}
+
+/// The path of a file in the repository files API.
+fn file_path(project: &str, path: &str) -> String {
+    format!("{}/repository/files/{}", project, path.replace('/', "%2F"))
+}
+
+#[test]
+fn test_new_rejects_invalid_api_url() {
+    let config =
+        GitLabConfig::new(MASTER_TOKEN.to_string(), GitLab::service()).with_api_base_url("::");
+
+    let result = GitLabClient::new(config);
+
+    assert!(matches!(result, Err(PlatformError::ConfigError(_))));
+}
+
+#[test]
+fn test_config_debug_redacts_tokens() {
+    let config = GitLabConfig::new(MASTER_TOKEN.to_string(), GitLab::service())
+        .with_project_token("org/service", SERVICE_TOKEN);
+
+    let debug = format!("{:?}", config);
+
+    assert!(!debug.contains(MASTER_TOKEN));
+    assert!(!debug.contains(SERVICE_TOKEN));
+    assert!(debug.contains("org/service"));
+}
+
+#[tokio::test]
+async fn test_gitlab_client_conformance() {
+    conformance::run_all::<GitLab>().await;
+}
+
+#[tokio::test]
+async fn test_list_repos_by_category_uses_project_token_and_default_branch() {
+    let server = MockServer::start().await;
+    mount_config::<GitLab>(&server).await;
+    Mock::given(method("GET"))
+        .and(path(SERVICE_PROJECT))
+        .and(header("PRIVATE-TOKEN", SERVICE_TOKEN))
+        .respond_with(replay(200, PROJECT))
+        .mount(&server)
+        .await;
+    let client = GitLab::client(&server);
+
+    let repos = client.list_repos_by_category(&category()).await.unwrap();
+
+    assert_eq!(repos.len(), 1);
+    assert_eq!(repos[0].name(), "service");
+    assert_eq!(repos[0].default_branch(), "develop");
+}
+
+#[tokio::test]
+async fn test_rate_limit_response_maps_to_rate_limit_exceeded() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(429).insert_header("RateLimit-Remaining", "0"))
+        .mount(&server)
+        .await;
+    let client = GitLab::client(&server);
+
+    let result = client
+        .get_repo_file(&GitLab::service(), &"README.md".to_string())
+        .await;
+
+    assert!(matches!(result, Err(PlatformError::RateLimitExceeded)));
+}
+
+#[tokio::test]
+async fn test_get_updated_templates_follows_renames_and_skips_deletions() {
+    let server = MockServer::start().await;
+    mount_config::<GitLab>(&server).await;
+    Mock::given(method("GET"))
+        .and(path(format!("{}/repository/compare", MASTER_PROJECT)))
+        .and(query_param("from", "base-sha"))
+        .and(query_param("to", "main"))
+        .respond_with(replay(200, COMPARE))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(file_path(
+            MASTER_PROJECT,
+            "templates/saas_rust/.github/PULL_REQUEST_TEMPLATE.md",
+        )))
+        .and(query_param("ref", "main"))
+        .respond_with(replay(200, FILE))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(file_path(
+            MASTER_PROJECT,
+            "templates/saas_rust/.github/pull_request_template.md",
+        )))
+        .and(query_param("ref", "base-sha"))
+        .respond_with(file("template v1"))
+        .mount(&server)
+        .await;
+    let client = GitLab::client(&server);
+
+    let changes = client
+        .get_updated_templates(&category(), "base-sha")
+        .await
+        .unwrap();
+
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].path(), ".github/PULL_REQUEST_TEMPLATE.md");
+    assert_eq!(changes[0].content(), b"template v2");
+    assert_eq!(
+        changes[0].old_checksum_at(0),
+        Some(&calculate_checksum(b"template v1"))
+    );
+}
+
+#[tokio::test]
+async fn test_update_repo_commits_to_branch_and_opens_merge_request() {
+    let server = MockServer::start().await;
+    let changes = [
+        TemplateChange::new(
+            ".github/PULL_REQUEST_TEMPLATE.md".to_string(),
+            vec![calculate_checksum(b"v1")],
+            calculate_checksum(b"v2"),
+            b"v2".to_vec(),
+        ),
+        TemplateChange::new(
+            "README.md".to_string(),
+            Vec::new(),
+            calculate_checksum(b"readme"),
+            b"readme".to_vec(),
+        ),
+    ];
+    let branch = update_branch_name(&changes);
+
+    Mock::given(method("GET"))
+        .and(path(file_path(
+            SERVICE_PROJECT,
+            ".github/PULL_REQUEST_TEMPLATE.md",
+        )))
+        .and(query_param("ref", "main"))
+        .respond_with(file("v1"))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(file_path(SERVICE_PROJECT, "README.md")))
+        .respond_with(GitLab::error(404, "404 File Not Found"))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path(format!("{}/repository/commits", SERVICE_PROJECT)))
+        .and(header("PRIVATE-TOKEN", SERVICE_TOKEN))
+        .and(body_partial_json(json!({
+            "branch": branch,
+            "start_branch": "main",
+            "force": true,
+            "actions": [
+                {
+                    "action": "update",
+                    "file_path": ".github/PULL_REQUEST_TEMPLATE.md",
+                    "content": BASE64.encode("v2"),
+                    "encoding": "base64",
+                },
+                {
+                    "action": "create",
+                    "file_path": "README.md",
+                    "content": BASE64.encode("readme"),
+                    "encoding": "base64",
+                },
+            ],
+        })))
+        .respond_with(replay(201, COMMIT))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("{}/merge_requests", SERVICE_PROJECT)))
+        .and(query_param("source_branch", branch.as_str()))
+        .and(query_param("state", "opened"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path(format!("{}/merge_requests", SERVICE_PROJECT)))
+        .and(body_partial_json(json!({
+            "source_branch": branch,
+            "target_branch": "main",
+            "remove_source_branch": true,
+        })))
+        .respond_with(replay(201, MERGE_REQUEST))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let client = GitLab::client(&server);
+
+    let result = client
+        .update_repo(&GitLab::service(), &changes)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        result.pr_url(),
+        "https://gitlab.example.com/org/service/-/merge_requests/42"
+    );
+    assert_eq!(result.pr_number(), Some(42));
+    assert_eq!(
+        result.updated_files(),
+        &vec![
+            ".github/PULL_REQUEST_TEMPLATE.md".to_string(),
+            "README.md".to_string()
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_update_repo_reuses_open_merge_request() {
+    let server = MockServer::start().await;
+    let change = TemplateChange::new(
+        "README.md".to_string(),
+        Vec::new(),
+        calculate_checksum(b"readme"),
+        b"readme".to_vec(),
+    );
+
+    Mock::given(method("GET"))
+        .and(path(file_path(SERVICE_PROJECT, "README.md")))
+        .respond_with(file("old readme"))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path(format!("{}/repository/commits", SERVICE_PROJECT)))
+        .respond_with(replay(201, COMMIT))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("{}/merge_requests", SERVICE_PROJECT)))
+        .respond_with(replay(200, &format!("[{}]", MERGE_REQUEST)))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path(format!("{}/merge_requests", SERVICE_PROJECT)))
+        .respond_with(ResponseTemplate::new(201))
+        .expect(0)
+        .mount(&server)
+        .await;
+    let client = GitLab::client(&server);
+
+    let result = client
+        .update_repo(&GitLab::service(), &[change])
+        .await
+        .unwrap();
+
+    assert_eq!(result.pr_number(), Some(42));
+}
+
+#[tokio::test]
+async fn test_update_repo_rejected_commit_maps_to_api_error() {
+    let server = MockServer::start().await;
+    let change = TemplateChange::new(
+        "README.md".to_string(),
+        Vec::new(),
+        calculate_checksum(b"readme"),
+        b"readme".to_vec(),
+    );
+    Mock::given(method("GET"))
+        .respond_with(GitLab::error(404, "404 File Not Found"))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path(format!("{}/repository/commits", SERVICE_PROJECT)))
+        .respond_with(replay(
+            400,
+            r#"{"message":"A file with this name already exists"}"#,
+        ))
+        .mount(&server)
+        .await;
+    let client = GitLab::client(&server);
+
+    let result = client.update_repo(&GitLab::service(), &[change]).await;
+
+    assert!(matches!(result, Err(PlatformError::ApiError(msg)) if msg.contains("already exists")));
+}
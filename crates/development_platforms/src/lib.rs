@@ -2,9 +2,39 @@ use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+mod azure_devops;
+mod bitbucket_server;
+mod checksum;
+#[cfg(test)]
+mod conformance;
 mod errors;
-
+mod gitea;
+mod github;
+mod gitlab;
+mod local_git;
+mod master_config;
+mod update_branch;
+mod webhook;
+
+pub use azure_devops::{
+    AzureDevOpsAuth, AzureDevOpsClient, AzureDevOpsConfig, AZURE_DEVOPS_URL,
+    AZURE_IDENTITY_ENDPOINT,
+};
+pub use bitbucket_server::{BitbucketServerClient, BitbucketServerConfig};
 pub use errors::PlatformError;
+pub use gitea::{GiteaClient, GiteaConfig};
+pub use github::{GitHubAppConfig, GitHubClient, GITHUB_API_URL};
+pub use gitlab::{GitLabClient, GitLabConfig, GITLAB_API_URL};
+pub use local_git::LocalGitPlatform;
+pub use master_config::{
+    repo_full_name, split_repo_name, CategoryConfig, ConfigIssue, MasterConfig, MasterConfigError,
+    MetaConfig, RepositoryConfig, MASTER_CONFIG_FILE, TEMPLATES_DIR,
+};
+pub use update_branch::UPDATE_BRANCH_PREFIX;
+pub use webhook::{
+    verify_github_signature, verify_gitlab_token, ChangedTemplates, PushEvent, GITHUB_EVENT_HEADER,
+    GITHUB_SIGNATURE_HEADER, GITLAB_EVENT_HEADER, GITLAB_TOKEN_HEADER,
+};
 
 #[cfg(test)]
 #[path = "lib_tests.rs"]
@@ -203,12 +233,12 @@ impl TemplateChange {
 ///     vec!["/path/to/file".to_string()],
 /// );
 /// assert_eq!(result.pr_url(), "https://pr.url");
-/// assert_eq!(result.pr_number(), 42);
+/// assert_eq!(result.pr_number(), Some(42));
 /// ```
 #[derive(Debug, Serialize)]
 pub struct UpdateResult {
     pr_url: String,
-    pr_number: u64,
+    pr_number: Option<u64>,
     updated_files: Vec<TemplatePath>,
 }
 
@@ -217,7 +247,17 @@ impl UpdateResult {
     pub fn new(pr_url: String, pr_number: u64, updated_files: Vec<TemplatePath>) -> Self {
         Self {
             pr_url,
-            pr_number,
+            pr_number: Some(pr_number),
+            updated_files,
+        }
+    }
+
+    /// Creates a new `UpdateResult` for a platform without numbered pull requests, e.g. one
+    /// which pushes the update to a branch, identified by `pr_url`.
+    pub fn without_pr_number(pr_url: String, updated_files: Vec<TemplatePath>) -> Self {
+        Self {
+            pr_url,
+            pr_number: None,
             updated_files,
         }
     }
@@ -227,8 +267,9 @@ impl UpdateResult {
         &self.pr_url
     }
 
-    /// Returns the pull request number of the update result.
-    pub fn pr_number(&self) -> u64 {
+    /// Returns the number the platform assigned to the pull request, or `None` if the platform
+    /// does not number its pull requests.
+    pub fn pr_number(&self) -> Option<u64> {
         self.pr_number
     }
 
@@ -289,6 +330,21 @@ pub trait DeveloperPlatform: Send + Sync {
         category: &TemplateCategory,
     ) -> Result<Vec<RepoInfo>, PlatformError>;
 
+    /// Get the current content of a file on the default branch of a target repository.
+    ///
+    /// # Parameters
+    /// - `repo`: A reference to the `RepoInfo` representing the target repository.
+    /// - `path`: A reference to the `TemplatePath` of the file, relative to the repository root.
+    ///
+    /// # Returns
+    /// A `Result` containing `Some` with the file content if the file exists, `None` if it does not,
+    /// or a `PlatformError` otherwise.
+    async fn get_repo_file(
+        &self,
+        repo: &RepoInfo,
+        path: &TemplatePath,
+    ) -> Result<Option<Vec<u8>>, PlatformError>;
+
     /// Determine which templates within a category have changed in the master repository
     /// since a given commit SHA.
     ///
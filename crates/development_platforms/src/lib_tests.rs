@@ -60,6 +60,6 @@ fn test_update_result_new() {
         vec!["/path/to/file".to_string()],
     );
     assert_eq!(result.pr_url(), "https://pr.url");
-    assert_eq!(result.pr_number(), 42);
+    assert_eq!(result.pr_number(), Some(42));
     assert_eq!(result.updated_files(), &vec!["/path/to/file".to_string()]);
 }
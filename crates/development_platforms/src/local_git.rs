@@ -0,0 +1,454 @@
+//! Implements `DeveloperPlatform` on top of git repositories on the local filesystem.
+//!
+//! The master repository is a local checkout containing `template-teleporter.toml` and the
+//! `templates/<category>/` directories. Target repositories are checkouts below a common root
+//! directory, at `<root>/<org>/<name>`. Instead of opening pull requests, `update_repo` commits
+//! the changes to a new branch in the target repository. The working tree of the target
+//! repository is never touched, so the branch can be reviewed and merged like a pull request.
+//! As there is no pull request, the `UpdateResult` has no pull request number; its URL is the
+//! `file://` URL of the target repository with the branch name as fragment.
+//!
+//! All content is read from committed revisions, never from the working tree, so uncommitted
+//! local edits do not leak into a synchronisation run.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::path::{Path, PathBuf};
+use std::process::{Output, Stdio};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::checksum::calculate_checksum;
+use crate::master_config::{repo_full_name, split_repo_name, MasterConfig, MASTER_CONFIG_FILE};
+use crate::update_branch::{update_branch_name, update_commit_message};
+use crate::{
+    DeveloperPlatform, PlatformError, RepoInfo, TemplateCategory, TemplateChange, TemplateMetadata,
+    TemplatePath, UpdateResult,
+};
+
+#[cfg(test)]
+#[path = "local_git_tests.rs"]
+mod tests;
+
+/// The name and email used for the commits created by `update_repo`.
+const COMMITTER_NAME: &str = "Template Teleporter";
+const COMMITTER_EMAIL: &str = "template-teleporter@localhost";
+
+/// A `DeveloperPlatform` which treats a directory of local git repositories as the platform.
+///
+/// # Example
+/// ```rust,no_run
+/// use template_teleporter_developer_platforms::LocalGitPlatform;
+/// let platform = LocalGitPlatform::new("/srv/git/template-master", "/srv/git");
+/// assert_eq!(platform.repo_path("org", "service"), std::path::Path::new("/srv/git/org/service"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct LocalGitPlatform {
+    master_repo: PathBuf,
+    repos_root: PathBuf,
+}
+
+impl LocalGitPlatform {
+    /// Creates a new `LocalGitPlatform`.
+    ///
+    /// # Arguments
+    /// * `master_repo` - Path to the checkout of the master template repository.
+    /// * `repos_root` - Path to the directory containing the target repositories as
+    ///   `<org>/<name>` checkouts.
+    pub fn new(master_repo: impl Into<PathBuf>, repos_root: impl Into<PathBuf>) -> Self {
+        Self {
+            master_repo: master_repo.into(),
+            repos_root: repos_root.into(),
+        }
+    }
+
+    /// Returns the path of the checkout of the given target repository.
+    pub fn repo_path(&self, org: &str, name: &str) -> PathBuf {
+        self.repos_root.join(org).join(name)
+    }
+
+    /// Reads and parses the master configuration from the `HEAD` of the master repository.
+    async fn load_config(&self) -> Result<MasterConfig, PlatformError> {
+        let content = read_blob(&self.master_repo, "HEAD", MASTER_CONFIG_FILE)
+            .await?
+            .ok_or_else(|| {
+                PlatformError::ConfigError(format!(
+                    "{} not found in {}",
+                    MASTER_CONFIG_FILE,
+                    self.master_repo.display()
+                ))
+            })?;
+        let content =
+            String::from_utf8(content).map_err(|e| PlatformError::InvalidContent(e.to_string()))?;
+        MasterConfig::from_toml_str(&content)
+    }
+
+    /// Returns the checkout of the given target repository, or `RepoNotFound` if there is none.
+    fn existing_repo_path(&self, org: &str, name: &str) -> Result<PathBuf, PlatformError> {
+        let path = self.repo_path(org, name);
+        if path.join(".git").exists() {
+            Ok(path)
+        } else {
+            Err(PlatformError::RepoNotFound {
+                org: org.to_string(),
+                name: name.to_string(),
+            })
+        }
+    }
+}
+
+#[async_trait]
+impl DeveloperPlatform for LocalGitPlatform {
+    async fn list_categories(&self) -> Result<Vec<TemplateCategory>, PlatformError> {
+        Ok(self.load_config().await?.categories())
+    }
+
+    async fn get_template(
+        &self,
+        category: &TemplateCategory,
+        path: &TemplatePath,
+    ) -> Result<Vec<u8>, PlatformError> {
+        self.load_config().await?.category(category)?;
+        read_blob(
+            &self.master_repo,
+            "HEAD",
+            &MasterConfig::master_path(category, path),
+        )
+        .await?
+        .ok_or_else(|| PlatformError::TemplateNotFound(path.clone()))
+    }
+
+    async fn list_templates(
+        &self,
+        category: &TemplateCategory,
+    ) -> Result<Vec<TemplateMetadata>, PlatformError> {
+        let config = self.load_config().await?;
+        let mut templates = Vec::new();
+        for path in &config.category(category)?.files {
+            let master_path = MasterConfig::master_path(category, path);
+            let content = read_blob(&self.master_repo, "HEAD", &master_path)
+                .await?
+                .ok_or_else(|| PlatformError::TemplateNotFound(path.clone()))?;
+            let last_updated = last_commit_time(&self.master_repo, &master_path).await?;
+            templates.push(TemplateMetadata::new(
+                path.clone(),
+                calculate_checksum(&content),
+                last_updated,
+            ));
+        }
+        Ok(templates)
+    }
+
+    async fn list_repos_by_category(
+        &self,
+        category: &TemplateCategory,
+    ) -> Result<Vec<RepoInfo>, PlatformError> {
+        let config = self.load_config().await?;
+        let mut repos = Vec::new();
+        for full_name in config.repositories_for(category)? {
+            let (org, name) = split_repo_name(full_name)?;
+            let path = self.existing_repo_path(&org, &name)?;
+            let default_branch = git_stdout(&path, &["symbolic-ref", "--short", "HEAD"], &[])
+                .await?
+                .trim()
+                .to_string();
+            repos.push(RepoInfo::new(org, name, default_branch));
+        }
+        Ok(repos)
+    }
+
+    async fn get_repo_file(
+        &self,
+        repo: &RepoInfo,
+        path: &TemplatePath,
+    ) -> Result<Option<Vec<u8>>, PlatformError> {
+        let repo_path = self.existing_repo_path(repo.org(), repo.name())?;
+        read_blob(
+            &repo_path,
+            &format!("refs/heads/{}", repo.default_branch()),
+            path,
+        )
+        .await
+    }
+
+    async fn get_updated_templates(
+        &self,
+        category: &TemplateCategory,
+        since_commit: &str,
+    ) -> Result<Vec<TemplateChange>, PlatformError> {
+        let config = self.load_config().await?;
+        let category_config = config.category(category)?;
+        let category_dir = MasterConfig::master_path(category, &String::new());
+        let since_commit = &resolve_commit(&self.master_repo, since_commit).await?;
+
+        let diff = git_stdout(
+            &self.master_repo,
+            &[
+                "diff",
+                "--name-only",
+                "--no-renames",
+                "--end-of-options",
+                since_commit,
+                "HEAD",
+                "--",
+                &category_dir,
+            ],
+            &[],
+        )
+        .await?;
+
+        let mut changes = Vec::new();
+        for master_path in diff.lines() {
+            let Some(path) = master_path.strip_prefix(&category_dir) else {
+                continue;
+            };
+            if !category_config.files.iter().any(|file| file == path) {
+                continue;
+            }
+            // Templates removed from the master repository are not changes to apply.
+            let Some(content) = read_blob(&self.master_repo, "HEAD", master_path).await? else {
+                continue;
+            };
+            let old_checksum = read_blob(&self.master_repo, since_commit, master_path)
+                .await?
+                .map(|old| calculate_checksum(&old));
+            changes.push(TemplateChange::new(
+                path.to_string(),
+                old_checksum.into_iter().collect(),
+                calculate_checksum(&content),
+                content,
+            ));
+        }
+        Ok(changes)
+    }
+
+    async fn update_repo(
+        &self,
+        repo: &RepoInfo,
+        changes: &[TemplateChange],
+    ) -> Result<UpdateResult, PlatformError> {
+        if changes.is_empty() {
+            return Err(PlatformError::OperationFailed(format!(
+                "No changes to apply to {}",
+                repo_full_name(repo)
+            )));
+        }
+        let repo_path = self.existing_repo_path(repo.org(), repo.name())?;
+
+        let base = git_stdout(
+            &repo_path,
+            &[
+                "rev-parse",
+                "--verify",
+                &format!("refs/heads/{}", repo.default_branch()),
+            ],
+            &[],
+        )
+        .await?
+        .trim()
+        .to_string();
+
+        // Build the new tree in an index file of its own, so that the working tree and index of
+        // the checkout are left untouched and concurrent updates do not share an index. The
+        // directory holding it is removed when dropped.
+        let index_dir = tempfile::tempdir().map_err(|e| {
+            PlatformError::OperationFailed(format!("Failed to create a temporary index: {}", e))
+        })?;
+        let index_env = [(
+            "GIT_INDEX_FILE",
+            index_dir.path().join("index").to_string_lossy().to_string(),
+        )];
+        let tree = async {
+            git_stdout(&repo_path, &["read-tree", &base], &index_env).await?;
+            for change in changes {
+                let blob = git_stdout_with_input(
+                    &repo_path,
+                    &["hash-object", "-w", "--stdin"],
+                    change.content(),
+                )
+                .await?;
+                git_stdout(
+                    &repo_path,
+                    &[
+                        "update-index",
+                        "--add",
+                        "--cacheinfo",
+                        &format!("100644,{},{}", blob.trim(), change.path()),
+                    ],
+                    &index_env,
+                )
+                .await?;
+            }
+            git_stdout(&repo_path, &["write-tree"], &index_env).await
+        }
+        .await?
+        .trim()
+        .to_string();
+
+        let updated_files: Vec<TemplatePath> =
+            changes.iter().map(|change| change.path().clone()).collect();
+        let message = update_commit_message(&updated_files);
+        let committer_env = [
+            ("GIT_AUTHOR_NAME", COMMITTER_NAME.to_string()),
+            ("GIT_AUTHOR_EMAIL", COMMITTER_EMAIL.to_string()),
+            ("GIT_COMMITTER_NAME", COMMITTER_NAME.to_string()),
+            ("GIT_COMMITTER_EMAIL", COMMITTER_EMAIL.to_string()),
+        ];
+        let commit = git_stdout(
+            &repo_path,
+            &["commit-tree", &tree, "-p", &base, "-m", &message],
+            &committer_env,
+        )
+        .await?
+        .trim()
+        .to_string();
+
+        let branch = update_branch_name(changes);
+        git_stdout(
+            &repo_path,
+            &["update-ref", &format!("refs/heads/{}", branch), &commit],
+            &[],
+        )
+        .await?;
+
+        Ok(UpdateResult::without_pr_number(
+            format!("file://{}#{}", repo_path.display(), branch),
+            updated_files,
+        ))
+    }
+}
+
+/// Returns the time of the last commit touching `path` on `HEAD`.
+async fn last_commit_time(repo: &Path, path: &str) -> Result<DateTime<Utc>, PlatformError> {
+    let output = git_stdout(
+        repo,
+        &["log", "-1", "--format=%cI", "HEAD", "--", path],
+        &[],
+    )
+    .await?;
+    DateTime::parse_from_rfc3339(output.trim())
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|e| PlatformError::OperationFailed(format!("Invalid commit time: {}", e)))
+}
+
+/// Resolves a commit SHA, full or abbreviated, to the full SHA of the commit.
+///
+/// # Errors
+/// Returns `PlatformError::OperationFailed` if `sha` is not a hexadecimal object name, which
+/// also keeps it from being taken as an option or a revision expression, or if it does not name
+/// a commit in `repo`.
+async fn resolve_commit(repo: &Path, sha: &str) -> Result<String, PlatformError> {
+    if !(4..=64).contains(&sha.len()) || !sha.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(PlatformError::OperationFailed(format!(
+            "Invalid commit SHA: {:?}",
+            sha
+        )));
+    }
+    let commit = git_stdout(
+        repo,
+        &[
+            "rev-parse",
+            "--verify",
+            "--end-of-options",
+            &format!("{}^{{commit}}", sha),
+        ],
+        &[],
+    )
+    .await?;
+    Ok(commit.trim().to_string())
+}
+
+/// Reads the content of `path` at `revision`, or `None` if the path does not exist at that revision.
+async fn read_blob(
+    repo: &Path,
+    revision: &str,
+    path: &str,
+) -> Result<Option<Vec<u8>>, PlatformError> {
+    let object = format!("{}:{}", revision, path);
+    let exists = run_git(
+        repo,
+        &["cat-file", "-e", "--end-of-options", &object],
+        &[],
+        None,
+    )
+    .await?;
+    if !exists.status.success() {
+        return Ok(None);
+    }
+    let args = ["cat-file", "blob", "--end-of-options", &object];
+    let output = run_git(repo, &args, &[], None).await?;
+    if !output.status.success() {
+        return Err(git_error(&args, &output));
+    }
+    Ok(Some(output.stdout))
+}
+
+/// Runs git and returns its standard output, failing if git exits with an error.
+async fn git_stdout(
+    repo: &Path,
+    args: &[&str],
+    envs: &[(&str, String)],
+) -> Result<String, PlatformError> {
+    let output = run_git(repo, args, envs, None).await?;
+    if !output.status.success() {
+        return Err(git_error(args, &output));
+    }
+    String::from_utf8(output.stdout).map_err(|e| PlatformError::InvalidContent(e.to_string()))
+}
+
+/// Runs git with the given standard input and returns its standard output, failing if git
+/// exits with an error.
+async fn git_stdout_with_input(
+    repo: &Path,
+    args: &[&str],
+    input: &[u8],
+) -> Result<String, PlatformError> {
+    let output = run_git(repo, args, &[], Some(input)).await?;
+    if !output.status.success() {
+        return Err(git_error(args, &output));
+    }
+    String::from_utf8(output.stdout).map_err(|e| PlatformError::InvalidContent(e.to_string()))
+}
+
+fn git_error(args: &[&str], output: &Output) -> PlatformError {
+    PlatformError::OperationFailed(format!(
+        "git {} failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr).trim()
+    ))
+}
+
+/// Runs git in `repo` and returns its output, regardless of the exit status.
+async fn run_git(
+    repo: &Path,
+    args: &[&str],
+    envs: &[(&str, String)],
+    input: Option<&[u8]>,
+) -> Result<Output, PlatformError> {
+    let mut command = Command::new("git");
+    command
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .envs(envs.iter().map(|(key, value)| (key, value)))
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let mut child = command
+        .spawn()
+        .map_err(|e| PlatformError::OperationFailed(format!("Failed to run git: {}", e)))?;
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin.write_all(input).await.map_err(|e| {
+            PlatformError::OperationFailed(format!("Failed to write to git: {}", e))
+        })?;
+    }
+    child
+        .wait_with_output()
+        .await
+        .map_err(|e| PlatformError::OperationFailed(format!("Failed to run git: {}", e)))
+}
@@ -0,0 +1,391 @@
+use super::*;
+use crate::update_branch::UPDATE_BRANCH_PREFIX;
+use std::fs;
+use std::process::Command as StdCommand;
+use tempfile::{tempdir, TempDir};
+
+const CONFIG: &str = r#"
+[meta]
+config_version = "1.0"
+
+[categories.saas_rust]
+files = [".github/PULL_REQUEST_TEMPLATE.md", "README.md"]
+
+[repositories]
+"org/service" = { category = "saas_rust" }
+"#;
+
+fn git(dir: &Path, args: &[&str]) -> String {
+    let output = StdCommand::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .env("GIT_AUTHOR_NAME", "Test")
+        .env("GIT_AUTHOR_EMAIL", "test@example.com")
+        .env("GIT_COMMITTER_NAME", "Test")
+        .env("GIT_COMMITTER_EMAIL", "test@example.com")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "git {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn commit_files(dir: &Path, files: &[(&str, &str)]) -> String {
+    for (path, content) in files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+    git(dir, &["add", "-A"]);
+    git(dir, &["commit", "-q", "-m", "commit"]);
+    git(dir, &["rev-parse", "HEAD"]).trim().to_string()
+}
+
+fn init_repo(dir: &Path, files: &[(&str, &str)]) -> String {
+    fs::create_dir_all(dir).unwrap();
+    git(dir, &["init", "-q", "-b", "main"]);
+    commit_files(dir, files)
+}
+
+/// Creates a master repository and an `org/service` target repository below a temporary root.
+fn setup() -> (TempDir, LocalGitPlatform, String) {
+    let root = tempdir().unwrap();
+    let master = root.path().join("template-master");
+    let first_commit = init_repo(
+        &master,
+        &[
+            (MASTER_CONFIG_FILE, CONFIG),
+            ("templates/saas_rust/.github/PULL_REQUEST_TEMPLATE.md", "v1"),
+            ("templates/saas_rust/README.md", "readme"),
+        ],
+    );
+    init_repo(
+        &root.path().join("org/service"),
+        &[(".github/PULL_REQUEST_TEMPLATE.md", "v1")],
+    );
+    let platform = LocalGitPlatform::new(&master, root.path());
+    (root, platform, first_commit)
+}
+
+fn category() -> TemplateCategory {
+    TemplateCategory::new("saas_rust".to_string())
+}
+
+fn service() -> RepoInfo {
+    RepoInfo::new("org".to_string(), "service".to_string(), "main".to_string())
+}
+
+#[tokio::test]
+async fn test_list_categories() {
+    let (_root, platform, _) = setup();
+    let categories = platform.list_categories().await.unwrap();
+    assert_eq!(categories, vec![category()]);
+}
+
+#[tokio::test]
+async fn test_list_categories_missing_config() {
+    let root = tempdir().unwrap();
+    let master = root.path().join("template-master");
+    init_repo(&master, &[("README.md", "no config")]);
+    let platform = LocalGitPlatform::new(&master, root.path());
+
+    let result = platform.list_categories().await;
+    assert!(matches!(result, Err(PlatformError::ConfigError(_))));
+}
+
+#[tokio::test]
+async fn test_get_template() {
+    let (_root, platform, _) = setup();
+    let content = platform
+        .get_template(&category(), &"README.md".to_string())
+        .await
+        .unwrap();
+    assert_eq!(content, b"readme");
+}
+
+#[tokio::test]
+async fn test_get_template_ignores_uncommitted_changes() {
+    let (root, platform, _) = setup();
+    fs::write(
+        root.path()
+            .join("template-master/templates/saas_rust/README.md"),
+        "uncommitted",
+    )
+    .unwrap();
+
+    let content = platform
+        .get_template(&category(), &"README.md".to_string())
+        .await
+        .unwrap();
+    assert_eq!(content, b"readme");
+}
+
+#[tokio::test]
+async fn test_get_template_not_found() {
+    let (_root, platform, _) = setup();
+    let result = platform
+        .get_template(&category(), &"missing.md".to_string())
+        .await;
+    assert!(matches!(result, Err(PlatformError::TemplateNotFound(path)) if path == "missing.md"));
+
+    let result = platform
+        .get_template(
+            &TemplateCategory::new("unknown".to_string()),
+            &"README.md".to_string(),
+        )
+        .await;
+    assert!(matches!(result, Err(PlatformError::CategoryNotFound(_))));
+}
+
+#[tokio::test]
+async fn test_list_templates() {
+    let (_root, platform, _) = setup();
+    let templates = platform.list_templates(&category()).await.unwrap();
+    assert_eq!(templates.len(), 2);
+    assert_eq!(templates[0].path(), ".github/PULL_REQUEST_TEMPLATE.md");
+    assert_eq!(templates[0].checksum(), calculate_checksum(b"v1"));
+    assert_eq!(templates[1].path(), "README.md");
+    assert!(*templates[1].last_updated() <= Utc::now());
+}
+
+#[tokio::test]
+async fn test_list_repos_by_category() {
+    let (root, platform, _) = setup();
+    git(
+        &root.path().join("org/service"),
+        &["checkout", "-q", "-b", "develop"],
+    );
+
+    let repos = platform.list_repos_by_category(&category()).await.unwrap();
+    assert_eq!(repos.len(), 1);
+    assert_eq!(repos[0].org(), "org");
+    assert_eq!(repos[0].name(), "service");
+    assert_eq!(repos[0].default_branch(), "develop");
+}
+
+#[tokio::test]
+async fn test_list_repos_by_category_missing_checkout() {
+    let (root, platform, _) = setup();
+    fs::remove_dir_all(root.path().join("org/service")).unwrap();
+
+    let result = platform.list_repos_by_category(&category()).await;
+    assert!(matches!(
+        result,
+        Err(PlatformError::RepoNotFound { org, name }) if org == "org" && name == "service"
+    ));
+}
+
+#[tokio::test]
+async fn test_get_repo_file() {
+    let (_root, platform, _) = setup();
+    let content = platform
+        .get_repo_file(&service(), &".github/PULL_REQUEST_TEMPLATE.md".to_string())
+        .await
+        .unwrap();
+    assert_eq!(content, Some(b"v1".to_vec()));
+
+    let missing = platform
+        .get_repo_file(&service(), &"README.md".to_string())
+        .await
+        .unwrap();
+    assert_eq!(missing, None);
+}
+
+#[tokio::test]
+async fn test_get_updated_templates() {
+    let (root, platform, first_commit) = setup();
+    commit_files(
+        &root.path().join("template-master"),
+        &[
+            ("templates/saas_rust/.github/PULL_REQUEST_TEMPLATE.md", "v2"),
+            // Files which are not listed in the configuration are ignored
+            ("templates/saas_rust/unlisted.md", "unlisted"),
+            ("docs/template-guide.md", "docs"),
+        ],
+    );
+
+    let changes = platform
+        .get_updated_templates(&category(), &first_commit)
+        .await
+        .unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].path(), ".github/PULL_REQUEST_TEMPLATE.md");
+    assert_eq!(changes[0].content(), &b"v2".to_vec());
+    assert_eq!(changes[0].new_checksum(), calculate_checksum(b"v2"));
+    assert_eq!(
+        changes[0].old_checksum_at(0),
+        Some(&calculate_checksum(b"v1"))
+    );
+}
+
+#[tokio::test]
+async fn test_get_updated_templates_accepts_abbreviated_sha() {
+    let (root, platform, first_commit) = setup();
+    commit_files(
+        &root.path().join("template-master"),
+        &[("templates/saas_rust/README.md", "readme v2")],
+    );
+
+    let changes = platform
+        .get_updated_templates(&category(), &first_commit[..8])
+        .await
+        .unwrap();
+
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].path(), "README.md");
+}
+
+#[tokio::test]
+async fn test_get_updated_templates_rejects_invalid_sha() {
+    let (root, platform, _) = setup();
+    let output = root.path().join("output");
+
+    for since_commit in [
+        format!("--output={}", output.display()),
+        "HEAD~1".to_string(),
+        "main".to_string(),
+        "abc".to_string(),
+        // Hexadecimal, but not a commit in the master repository
+        "0123456789abcdef0123456789abcdef01234567".to_string(),
+    ] {
+        let result = platform
+            .get_updated_templates(&category(), &since_commit)
+            .await;
+
+        assert!(
+            matches!(result, Err(PlatformError::OperationFailed(_))),
+            "{} was accepted",
+            since_commit
+        );
+    }
+    assert!(!output.exists());
+}
+
+#[tokio::test]
+async fn test_update_repo_creates_branch() {
+    let (root, platform, _) = setup();
+    let target = root.path().join("org/service");
+    let head_before = git(&target, &["rev-parse", "HEAD"]);
+
+    let changes = vec![
+        TemplateChange::new(
+            ".github/PULL_REQUEST_TEMPLATE.md".to_string(),
+            vec![calculate_checksum(b"v1")],
+            calculate_checksum(b"v2"),
+            b"v2".to_vec(),
+        ),
+        TemplateChange::new(
+            "README.md".to_string(),
+            Vec::new(),
+            calculate_checksum(b"readme"),
+            b"readme".to_vec(),
+        ),
+    ];
+    let result = platform.update_repo(&service(), &changes).await.unwrap();
+
+    assert_eq!(result.pr_number(), None);
+    assert_eq!(
+        result.updated_files(),
+        &vec![
+            ".github/PULL_REQUEST_TEMPLATE.md".to_string(),
+            "README.md".to_string()
+        ]
+    );
+    let (_, branch) = result.pr_url().split_once('#').unwrap();
+    assert!(branch.starts_with(UPDATE_BRANCH_PREFIX));
+
+    // The branch holds the new content on top of the default branch
+    assert_eq!(
+        git(
+            &target,
+            &[
+                "show",
+                &format!("{}:.github/PULL_REQUEST_TEMPLATE.md", branch)
+            ]
+        ),
+        "v2"
+    );
+    assert_eq!(
+        git(&target, &["show", &format!("{}:README.md", branch)]),
+        "readme"
+    );
+    assert_eq!(
+        git(&target, &["rev-parse", &format!("{}^", branch)]),
+        head_before
+    );
+
+    // The checkout itself is left untouched
+    assert_eq!(git(&target, &["rev-parse", "HEAD"]), head_before);
+    assert_eq!(git(&target, &["status", "--porcelain"]), "");
+    assert_eq!(
+        fs::read_to_string(target.join(".github/PULL_REQUEST_TEMPLATE.md")).unwrap(),
+        "v1"
+    );
+}
+
+#[tokio::test]
+async fn test_update_repo_concurrent_updates_use_separate_indexes() {
+    let (root, platform, _) = setup();
+    let target = root.path().join("org/service");
+    let change = |content: &str| {
+        vec![TemplateChange::new(
+            "README.md".to_string(),
+            Vec::new(),
+            calculate_checksum(content.as_bytes()),
+            content.as_bytes().to_vec(),
+        )]
+    };
+    let tasks: Vec<_> = (0..8)
+        .map(|i| {
+            let platform = platform.clone();
+            let changes = change(&format!("readme {}", i));
+            tokio::spawn(async move { platform.update_repo(&service(), &changes).await })
+        })
+        .collect();
+
+    for (i, task) in tasks.into_iter().enumerate() {
+        let result = task.await.unwrap().unwrap();
+        let (_, branch) = result.pr_url().split_once('#').unwrap();
+        assert_eq!(
+            git(&target, &["show", &format!("{}:README.md", branch)]),
+            format!("readme {}", i)
+        );
+        // Each branch holds only its own change
+        assert_eq!(
+            git(
+                &target,
+                &[
+                    "show",
+                    &format!("{}:.github/PULL_REQUEST_TEMPLATE.md", branch)
+                ]
+            ),
+            "v1"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_update_repo_no_changes() {
+    let (_root, platform, _) = setup();
+    let result = platform.update_repo(&service(), &[]).await;
+    assert!(matches!(result, Err(PlatformError::OperationFailed(_))));
+}
+
+#[tokio::test]
+async fn test_update_repo_missing_repo() {
+    let (_root, platform, _) = setup();
+    let repo = RepoInfo::new("org".to_string(), "missing".to_string(), "main".to_string());
+    let change = TemplateChange::new(
+        "README.md".to_string(),
+        Vec::new(),
+        calculate_checksum(b"readme"),
+        b"readme".to_vec(),
+    );
+    let result = platform.update_repo(&repo, &[change]).await;
+    assert!(matches!(result, Err(PlatformError::RepoNotFound { .. })));
+}
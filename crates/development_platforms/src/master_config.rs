@@ -0,0 +1,427 @@
+//! Defines the model of the `template-teleporter.toml` master configuration file.
+//!
+//! The master configuration lives at the root of the master template repository and defines the
+//! template categories, the files belonging to each category and the target repositories using
+//! each category.
+//!
+//! Parsing validates the configuration beyond its TOML structure and reports every problem found,
+//! each with the line and column it was found at, so that all mistakes in a change to the master
+//! configuration can be fixed in one go.
+
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::ops::Range;
+use thiserror::Error;
+use toml::Spanned;
+
+use crate::{PlatformError, RepoInfo, TemplateCategory, TemplatePath};
+
+#[cfg(test)]
+#[path = "master_config_tests.rs"]
+mod tests;
+
+/// The name of the master configuration file at the root of the master repository.
+pub const MASTER_CONFIG_FILE: &str = "template-teleporter.toml";
+
+/// The directory in the master repository which contains one sub-directory per template category.
+pub const TEMPLATES_DIR: &str = "templates";
+
+/// A template category as defined in the `[categories.<name>]` table of the master configuration.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CategoryConfig {
+    /// Optional description of the category.
+    #[serde(default)]
+    pub description: Option<String>,
+
+    /// File paths relative to the category directory (`templates/<category>/`). These are also
+    /// the paths of the files in the target repositories.
+    #[serde(default)]
+    pub files: Vec<TemplatePath>,
+}
+
+/// The parsed content of the `template-teleporter.toml` master configuration file.
+///
+/// # Example
+/// ```rust
+/// use template_teleporter_developer_platforms::MasterConfig;
+/// let config = MasterConfig::from_toml_str(r#"
+/// [meta]
+/// config_version = "1.0"
+///
+/// [categories.saas_rust]
+/// files = [".github/PULL_REQUEST_TEMPLATE.md"]
+///
+/// [repositories]
+/// "my-org/api-service" = { category = "saas_rust" }
+/// "#).unwrap();
+/// assert_eq!(config.meta.config_version, "1.0");
+/// assert_eq!(config.categories["saas_rust"].files.len(), 1);
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MasterConfig {
+    /// The `[meta]` table.
+    pub meta: MetaConfig,
+
+    /// The template categories, keyed by category name. Category names match the directory
+    /// names under `templates/`.
+    #[serde(default)]
+    pub categories: BTreeMap<String, CategoryConfig>,
+
+    /// The target repositories, keyed by full repository name (e.g., "org/repo-name").
+    #[serde(default)]
+    pub repositories: BTreeMap<String, RepositoryConfig>,
+}
+
+impl MasterConfig {
+    /// Parses the master configuration from the content of a `template-teleporter.toml` file.
+    ///
+    /// # Errors
+    /// Returns `PlatformError::ConfigError`, describing every problem found, if the content is
+    /// not valid TOML, does not match the structure of the master configuration or fails
+    /// validation (see [`MasterConfig::parse`]).
+    pub fn from_toml_str(content: &str) -> Result<Self, PlatformError> {
+        Self::parse(content, MASTER_CONFIG_FILE).map_err(|e| {
+            PlatformError::ConfigError(format!("Invalid {}", e).trim_end().to_string())
+        })
+    }
+
+    /// Parses and validates the master configuration.
+    ///
+    /// Besides the TOML structure, the following is validated:
+    /// - `[meta]` must define a non-empty `config_version`.
+    /// - A category must not list the same file path more than once.
+    /// - Repository names must be of the form `<org>/<name>`.
+    /// - Repositories must reference categories which are defined in `[categories]`.
+    ///
+    /// # Parameters
+    /// - `content`: The content of the configuration file.
+    /// - `file`: The name of the configuration file, used in the reported locations.
+    ///
+    /// # Errors
+    /// Returns a `MasterConfigError` listing every problem found. If the content is not valid
+    /// TOML, or does not match the structure of the master configuration, only that problem can
+    /// be reported.
+    pub fn parse(content: &str, file: &str) -> Result<Self, MasterConfigError> {
+        let error = |issues| MasterConfigError {
+            file: file.to_string(),
+            issues,
+        };
+
+        let raw: RawMasterConfig = toml::from_str(content).map_err(|e| {
+            let span = e.span().unwrap_or(0..0);
+            error(vec![ConfigIssue::at(content, span, e.message())])
+        })?;
+
+        let mut issues = Vec::new();
+        let config_version = match &raw.meta {
+            None => {
+                issues.push(ConfigIssue::at(
+                    content,
+                    0..0,
+                    "missing [meta] table with config_version",
+                ));
+                String::new()
+            }
+            Some(meta) => match &meta.get_ref().config_version {
+                None => {
+                    issues.push(ConfigIssue::at(
+                        content,
+                        meta.span(),
+                        "missing config_version in [meta]",
+                    ));
+                    String::new()
+                }
+                Some(version) if version.get_ref().trim().is_empty() => {
+                    issues.push(ConfigIssue::at(
+                        content,
+                        version.span(),
+                        "config_version must not be empty",
+                    ));
+                    String::new()
+                }
+                Some(version) => version.get_ref().clone(),
+            },
+        };
+
+        let mut categories = BTreeMap::new();
+        for (name, category) in &raw.categories {
+            let mut first_seen: HashMap<&str, &Spanned<TemplatePath>> = HashMap::new();
+            for file in &category.get_ref().files {
+                match first_seen.get(file.get_ref().as_str()) {
+                    Some(first) => issues.push(ConfigIssue::at(
+                        content,
+                        file.span(),
+                        format!(
+                            "duplicate file path '{}' in category '{}' (first listed at line {})",
+                            file.get_ref(),
+                            name.get_ref(),
+                            location(content, first.span().start).0
+                        ),
+                    )),
+                    None => {
+                        first_seen.insert(file.get_ref(), file);
+                    }
+                }
+            }
+            categories.insert(
+                name.get_ref().clone(),
+                CategoryConfig {
+                    description: category.get_ref().description.clone(),
+                    files: category
+                        .get_ref()
+                        .files
+                        .iter()
+                        .map(|file| file.get_ref().clone())
+                        .collect(),
+                },
+            );
+        }
+
+        let mut repositories = BTreeMap::new();
+        for (name, repo) in &raw.repositories {
+            if let Err(PlatformError::ConfigError(msg)) = split_repo_name(name.get_ref()) {
+                issues.push(ConfigIssue::at(content, name.span(), msg));
+            }
+            let category = repo.category.get_ref();
+            if !categories.contains_key(category) {
+                issues.push(ConfigIssue::at(
+                    content,
+                    repo.category.span(),
+                    format!(
+                        "repository '{}' references unknown category '{}'",
+                        name.get_ref(),
+                        category
+                    ),
+                ));
+            }
+            repositories.insert(
+                name.get_ref().clone(),
+                RepositoryConfig {
+                    category: category.clone(),
+                },
+            );
+        }
+
+        if !issues.is_empty() {
+            return Err(error(issues));
+        }
+
+        Ok(Self {
+            meta: MetaConfig {
+                config_version,
+                description: raw.meta.and_then(|meta| meta.into_inner().description),
+            },
+            categories,
+            repositories,
+        })
+    }
+
+    /// Returns all categories defined in the configuration, ordered by name.
+    pub fn categories(&self) -> Vec<TemplateCategory> {
+        self.categories
+            .keys()
+            .map(|name| TemplateCategory::new(name.clone()))
+            .collect()
+    }
+
+    /// Returns the configuration of the given category.
+    ///
+    /// # Errors
+    /// Returns `PlatformError::CategoryNotFound` if the category is not defined.
+    pub fn category(&self, category: &TemplateCategory) -> Result<&CategoryConfig, PlatformError> {
+        self.categories
+            .get(category.name())
+            .ok_or_else(|| PlatformError::CategoryNotFound(category.name().to_string()))
+    }
+
+    /// Returns the full names (e.g., "org/repo-name") of the repositories using the given
+    /// category, ordered by name.
+    ///
+    /// # Errors
+    /// Returns `PlatformError::CategoryNotFound` if the category is not defined.
+    pub fn repositories_for(
+        &self,
+        category: &TemplateCategory,
+    ) -> Result<Vec<&str>, PlatformError> {
+        self.category(category)?;
+        Ok(self
+            .repositories
+            .iter()
+            .filter(|(_, repo)| repo.category == category.name())
+            .map(|(name, _)| name.as_str())
+            .collect())
+    }
+
+    /// Returns the path of a template in the master repository, e.g.
+    /// `templates/saas_rust/.github/PULL_REQUEST_TEMPLATE.md`.
+    pub fn master_path(category: &TemplateCategory, path: &TemplatePath) -> String {
+        format!("{}/{}/{}", TEMPLATES_DIR, category.name(), path)
+    }
+
+    /// Returns the category and path of the template at the given path in the master
+    /// repository, the inverse of [`MasterConfig::master_path`].
+    ///
+    /// # Returns
+    /// `None` if the path is not a file listed by a category of the configuration.
+    pub fn template_at(&self, master_path: &str) -> Option<(TemplateCategory, TemplatePath)> {
+        let (name, path) = master_path
+            .strip_prefix(TEMPLATES_DIR)?
+            .strip_prefix('/')?
+            .split_once('/')?;
+        let category = self.categories.get(name)?;
+        category
+            .files
+            .iter()
+            .any(|file| file == path)
+            .then(|| (TemplateCategory::new(name.to_string()), path.to_string()))
+    }
+}
+
+/// The `[meta]` table of the master configuration.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MetaConfig {
+    /// Schema version of the configuration file.
+    pub config_version: String,
+
+    /// Optional description of the master template set.
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// A target repository as defined in the `[repositories]` table of the master configuration.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RepositoryConfig {
+    /// The name of the template category the repository uses.
+    pub category: String,
+}
+
+/// Splits a full repository name (e.g., "org/repo-name") into its organization and name.
+///
+/// The name is split at the last `/`, so the organization may itself consist of several
+/// segments, such as a GitLab group and subgroup, or an Azure DevOps organization and project.
+///
+/// # Errors
+/// Returns `PlatformError::ConfigError` if the name is not of the form `<org>/<name>`, or has
+/// empty segments.
+pub fn split_repo_name(full_name: &str) -> Result<(String, String), PlatformError> {
+    match full_name.rsplit_once('/') {
+        Some((org, name)) if !name.is_empty() && org.split('/').all(|s| !s.is_empty()) => {
+            Ok((org.to_string(), name.to_string()))
+        }
+        _ => Err(PlatformError::ConfigError(format!(
+            "Repository name '{}' is not of the form '<org>/<name>'",
+            full_name
+        ))),
+    }
+}
+
+/// Returns the full name (e.g., "org/repo-name") of a repository.
+pub fn repo_full_name(repo: &RepoInfo) -> String {
+    format!("{}/{}", repo.org(), repo.name())
+}
+
+/// A problem found in the master configuration, with the location it was found at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigIssue {
+    /// The 1-based line of the problem.
+    pub line: usize,
+
+    /// The 1-based column of the problem.
+    pub column: usize,
+
+    /// A description of the problem.
+    pub message: String,
+}
+
+impl ConfigIssue {
+    /// Creates an issue located at the start of the given byte range of `content`.
+    fn at(content: &str, span: Range<usize>, message: impl Into<String>) -> Self {
+        let (line, column) = location(content, span.start);
+        Self {
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+}
+
+/// Returns the 1-based line and column of a byte offset in `content`.
+fn location(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// The error returned when the master configuration is invalid. It lists every problem found.
+///
+/// The `Display` output has one line per problem in the form `<file>:<line>:<column>: <message>`.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub struct MasterConfigError {
+    /// The name of the configuration file.
+    pub file: String,
+
+    /// The problems found, in the order they were found.
+    pub issues: Vec<ConfigIssue>,
+}
+
+impl fmt::Display for MasterConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} ({} problem(s)):", self.file, self.issues.len())?;
+        for issue in &self.issues {
+            writeln!(
+                f,
+                "{}:{}:{}: {}",
+                self.file, issue.line, issue.column, issue.message
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The master configuration as written in the file, with the locations needed for validation.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawMasterConfig {
+    #[serde(default)]
+    meta: Option<Spanned<RawMetaConfig>>,
+
+    #[serde(default)]
+    categories: BTreeMap<Spanned<String>, Spanned<RawCategoryConfig>>,
+
+    #[serde(default)]
+    repositories: BTreeMap<Spanned<String>, RawRepositoryConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawCategoryConfig {
+    #[serde(default)]
+    description: Option<String>,
+
+    #[serde(default)]
+    files: Vec<Spanned<TemplatePath>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawMetaConfig {
+    #[serde(default)]
+    config_version: Option<Spanned<String>>,
+
+    #[serde(default)]
+    description: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRepositoryConfig {
+    category: Spanned<String>,
+}
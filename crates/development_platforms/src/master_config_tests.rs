@@ -0,0 +1,226 @@
+use super::*;
+
+const CONFIG: &str = r#"
+[meta]
+config_version = "1.0"
+description = "Master templates for projects"
+
+[categories.saas_rust]
+description = "Standard template set for Rust-based SaaS applications."
+files = [
+    ".github/PULL_REQUEST_TEMPLATE.md",
+    ".gitignore",
+]
+
+[categories.library_rust]
+files = ["README.md.template"]
+
+[repositories]
+"my-org/api-service" = { category = "saas_rust" }
+"my-org/core-library" = { category = "library_rust" }
+"another-org/widget-factory" = { category = "saas_rust" }
+"#;
+
+#[test]
+fn test_from_toml_str() {
+    let config = MasterConfig::from_toml_str(CONFIG).unwrap();
+    assert_eq!(config.meta.config_version, "1.0");
+    assert_eq!(
+        config.meta.description.as_deref(),
+        Some("Master templates for projects")
+    );
+    assert_eq!(
+        config.categories["saas_rust"].files,
+        vec![".github/PULL_REQUEST_TEMPLATE.md", ".gitignore"]
+    );
+    assert_eq!(config.categories["library_rust"].description, None);
+    assert_eq!(
+        config.repositories["my-org/core-library"].category,
+        "library_rust"
+    );
+}
+
+#[test]
+fn test_from_toml_str_invalid() {
+    let result = MasterConfig::from_toml_str("[meta]\nconfig_version = 1");
+    match result.err().unwrap() {
+        PlatformError::ConfigError(msg) => assert!(msg.contains(MASTER_CONFIG_FILE)),
+        e => panic!("Expected ConfigError, got {:?}", e),
+    }
+}
+
+#[test]
+fn test_parse_reports_every_problem_with_location() {
+    let content = r#"[meta]
+description = "no version"
+
+[categories.saas_rust]
+files = ["README.md", ".gitignore", "README.md"]
+
+[repositories]
+"my-org/api-service" = { category = "saas_go" }
+"not-a-full-name" = { category = "saas_rust" }
+"#;
+    let error = MasterConfig::parse(content, "config/template-teleporter.toml").unwrap_err();
+
+    assert_eq!(error.file, "config/template-teleporter.toml");
+    assert_eq!(
+        error.issues,
+        vec![
+            ConfigIssue {
+                line: 1,
+                column: 1,
+                message: "missing config_version in [meta]".to_string(),
+            },
+            ConfigIssue {
+                line: 5,
+                column: 37,
+                message: "duplicate file path 'README.md' in category 'saas_rust' (first listed at line 5)"
+                    .to_string(),
+            },
+            ConfigIssue {
+                line: 8,
+                column: 37,
+                message: "repository 'my-org/api-service' references unknown category 'saas_go'"
+                    .to_string(),
+            },
+            ConfigIssue {
+                line: 9,
+                column: 1,
+                message: "Repository name 'not-a-full-name' is not of the form '<org>/<name>'"
+                    .to_string(),
+            },
+        ]
+    );
+    let display = error.to_string();
+    assert!(
+        display.contains("config/template-teleporter.toml:8:37: repository 'my-org/api-service'")
+    );
+    assert_eq!(display.lines().count(), 5);
+}
+
+#[test]
+fn test_parse_missing_meta() {
+    let error = MasterConfig::parse("[categories.saas_rust]\n", MASTER_CONFIG_FILE).unwrap_err();
+    assert_eq!(error.issues.len(), 1);
+    assert_eq!((error.issues[0].line, error.issues[0].column), (1, 1));
+    assert!(error.issues[0].message.contains("[meta]"));
+}
+
+#[test]
+fn test_parse_empty_config_version() {
+    let error =
+        MasterConfig::parse("\n[meta]\nconfig_version = \"  \"\n", MASTER_CONFIG_FILE).unwrap_err();
+    assert_eq!(
+        error.issues,
+        vec![ConfigIssue {
+            line: 3,
+            column: 18,
+            message: "config_version must not be empty".to_string(),
+        }]
+    );
+}
+
+#[test]
+fn test_parse_syntax_error_location() {
+    let error = MasterConfig::parse(
+        "[meta]\nconfig_version = \"1.0\"\nunknown = true\n",
+        MASTER_CONFIG_FILE,
+    )
+    .unwrap_err();
+    assert_eq!(error.issues.len(), 1);
+    assert_eq!(error.issues[0].line, 3);
+    assert!(error.issues[0].message.contains("unknown"));
+}
+
+#[test]
+fn test_from_toml_str_reports_validation_problems() {
+    let result = MasterConfig::from_toml_str(
+        "[meta]\nconfig_version = \"1.0\"\n[repositories]\n\"org/repo\" = { category = \"x\" }\n",
+    );
+    match result.err().unwrap() {
+        PlatformError::ConfigError(msg) => {
+            assert!(msg.contains("template-teleporter.toml:4:"));
+            assert!(msg.contains("unknown category 'x'"));
+        }
+        e => panic!("Expected ConfigError, got {:?}", e),
+    }
+}
+
+#[test]
+fn test_categories() {
+    let config = MasterConfig::from_toml_str(CONFIG).unwrap();
+    let names: Vec<_> = config
+        .categories()
+        .iter()
+        .map(|c| c.name().to_string())
+        .collect();
+    assert_eq!(names, vec!["library_rust", "saas_rust"]);
+}
+
+#[test]
+fn test_repositories_for() {
+    let config = MasterConfig::from_toml_str(CONFIG).unwrap();
+    let repos = config
+        .repositories_for(&TemplateCategory::new("saas_rust".to_string()))
+        .unwrap();
+    assert_eq!(
+        repos,
+        vec!["another-org/widget-factory", "my-org/api-service"]
+    );
+}
+
+#[test]
+fn test_repositories_for_unknown_category() {
+    let config = MasterConfig::from_toml_str(CONFIG).unwrap();
+    let result = config.repositories_for(&TemplateCategory::new("go".to_string()));
+    assert!(matches!(result, Err(PlatformError::CategoryNotFound(name)) if name == "go"));
+}
+
+#[test]
+fn test_master_path() {
+    let path = MasterConfig::master_path(
+        &TemplateCategory::new("saas_rust".to_string()),
+        &".gitignore".to_string(),
+    );
+    assert_eq!(path, "templates/saas_rust/.gitignore");
+}
+
+#[test]
+fn test_template_at() {
+    let config = MasterConfig::from_toml_str(CONFIG).unwrap();
+
+    assert_eq!(
+        config.template_at("templates/saas_rust/.github/PULL_REQUEST_TEMPLATE.md"),
+        Some((
+            TemplateCategory::new("saas_rust".to_string()),
+            ".github/PULL_REQUEST_TEMPLATE.md".to_string()
+        ))
+    );
+    assert_eq!(
+        config.template_at("templates/saas_rust/README.md.template"),
+        None
+    );
+    assert_eq!(config.template_at("templates/unknown/.gitignore"), None);
+    assert_eq!(
+        config.template_at("templates_old/saas_rust/.gitignore"),
+        None
+    );
+    assert_eq!(config.template_at("template-teleporter.toml"), None);
+}
+
+#[test]
+fn test_split_repo_name() {
+    assert_eq!(
+        split_repo_name("org/repo").unwrap(),
+        ("org".to_string(), "repo".to_string())
+    );
+    assert!(split_repo_name("repo").is_err());
+    assert!(split_repo_name("/repo").is_err());
+    assert!(split_repo_name("org/").is_err());
+    assert!(split_repo_name("org//repo").is_err());
+    assert_eq!(
+        split_repo_name("org/group/repo").unwrap(),
+        ("org/group".to_string(), "repo".to_string())
+    );
+}
@@ -0,0 +1,48 @@
+//! Naming and descriptions of the branches and pull requests which carry template updates in
+//! target repositories.
+
+use crate::checksum::calculate_checksum;
+use crate::{TemplateChange, TemplatePath};
+
+/// The prefix of the branches created in target repositories by `DeveloperPlatform::update_repo`.
+pub const UPDATE_BRANCH_PREFIX: &str = "template-teleporter/";
+
+/// Returns the name of the branch carrying the given changes.
+///
+/// The name is derived from the new checksums of the changes, so that re-running the same
+/// update reuses the same branch rather than creating a new one.
+pub(crate) fn update_branch_name(changes: &[TemplateChange]) -> String {
+    let checksums: String = changes.iter().map(|c| c.new_checksum()).collect();
+    format!(
+        "{}{}",
+        UPDATE_BRANCH_PREFIX,
+        &calculate_checksum(checksums.as_bytes())[..12]
+    )
+}
+
+/// Returns the title of the pull requests opened by `DeveloperPlatform::update_repo`.
+pub(crate) fn update_title() -> &'static str {
+    "Update templates from template-teleporter"
+}
+
+/// Returns the message of the commits updating the given files.
+pub(crate) fn update_commit_message(files: &[TemplatePath]) -> String {
+    format!("{}\n\n{}", update_title(), file_list(files))
+}
+
+/// Returns the description of the pull requests updating the given files.
+pub(crate) fn update_description(files: &[TemplatePath]) -> String {
+    format!(
+        "Updates the following templates from the master template repository:\n\n{}",
+        file_list(files)
+    )
+}
+
+/// Formats the given files as a Markdown list.
+fn file_list(files: &[TemplatePath]) -> String {
+    files
+        .iter()
+        .map(|path| format!("- `{}`", path))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,312 @@
+//! Verifies webhook deliveries for pushes to the master repository and parses their payloads.
+//!
+//! GitHub signs each delivery with an HMAC-SHA256 of the raw body, keyed with the webhook
+//! secret, in the `X-Hub-Signature-256` header. GitLab sends the secret token itself in the
+//! `X-Gitlab-Token` header. Both are compared in constant time. Verified push payloads are parsed
+//! into a [`PushEvent`], whose changed paths can be mapped onto the templates of the master
+//! configuration.
+
+use hmac::{Hmac, Mac};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+
+use crate::master_config::{MasterConfig, MASTER_CONFIG_FILE};
+use crate::{PlatformError, TemplateCategory, TemplatePath};
+
+#[cfg(test)]
+#[path = "webhook_tests.rs"]
+mod tests;
+
+/// The header carrying the signature of a GitHub webhook delivery.
+pub const GITHUB_SIGNATURE_HEADER: &str = "X-Hub-Signature-256";
+
+/// The header carrying the event name of a GitHub webhook delivery, `push` for pushes.
+pub const GITHUB_EVENT_HEADER: &str = "X-GitHub-Event";
+
+/// The header carrying the secret token of a GitLab webhook delivery.
+pub const GITLAB_TOKEN_HEADER: &str = "X-Gitlab-Token";
+
+/// The header carrying the event name of a GitLab webhook delivery, `Push Hook` for pushes.
+pub const GITLAB_EVENT_HEADER: &str = "X-Gitlab-Event";
+
+/// The object ID git uses for refs that do not exist, e.g. as `after` of a deleted branch.
+const ZERO_OBJECT_ID: &str = "0000000000000000000000000000000000000000";
+
+/// The maximum number of commits GitHub includes in a push payload.
+const GITHUB_MAX_COMMITS: usize = 2048;
+
+/// Verifies the `X-Hub-Signature-256` header of a GitHub webhook delivery.
+///
+/// # Parameters
+/// - `secret`: The secret of the webhook.
+/// - `payload`: The raw request body, exactly as received.
+/// - `signature`: The value of the header, `sha256=<hex digest>`.
+///
+/// # Errors
+/// Returns `PlatformError::ConfigError` if the secret is empty, and
+/// `PlatformError::WebhookVerificationFailed` if the signature is malformed or does not match.
+pub fn verify_github_signature(
+    secret: &[u8],
+    payload: &[u8],
+    signature: &str,
+) -> Result<(), PlatformError> {
+    if secret.is_empty() {
+        return Err(PlatformError::ConfigError(
+            "The webhook secret is empty".to_string(),
+        ));
+    }
+    let signature = signature
+        .strip_prefix("sha256=")
+        .and_then(|hex_digest| hex::decode(hex_digest).ok())
+        .ok_or(PlatformError::WebhookVerificationFailed)?;
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC can take a key of any size");
+    mac.update(payload);
+    // `verify_slice` compares in constant time.
+    mac.verify_slice(&signature)
+        .map_err(|_| PlatformError::WebhookVerificationFailed)
+}
+
+/// Verifies the `X-Gitlab-Token` header of a GitLab webhook delivery.
+///
+/// # Errors
+/// Returns `PlatformError::ConfigError` if the secret token is empty, and
+/// `PlatformError::WebhookVerificationFailed` if the token does not match.
+pub fn verify_gitlab_token(secret: &str, token: &str) -> Result<(), PlatformError> {
+    if secret.is_empty() {
+        return Err(PlatformError::ConfigError(
+            "The webhook secret token is empty".to_string(),
+        ));
+    }
+    // Comparing digests keeps the time taken independent of the length of the secret, too.
+    let expected = Sha256::digest(secret.as_bytes());
+    let actual = Sha256::digest(token.as_bytes());
+    let difference = expected
+        .iter()
+        .zip(actual.iter())
+        .fold(0, |acc, (a, b)| acc | (a ^ b));
+    if difference == 0 {
+        Ok(())
+    } else {
+        Err(PlatformError::WebhookVerificationFailed)
+    }
+}
+
+/// A push to a repository, as reported by a webhook.
+///
+/// The changed paths are the net changes of all pushed commits: a file added and then modified
+/// is only reported as added, and a file added and then removed is not reported at all.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PushEvent {
+    /// The full name of the repository (e.g., "org/repo-name").
+    pub repository: String,
+
+    /// The pushed ref, e.g. `refs/heads/main`.
+    pub git_ref: String,
+
+    /// The commit the ref pointed to before the push.
+    pub before: String,
+
+    /// The commit the ref points to after the push.
+    pub after: String,
+
+    /// The paths of the files added by the push.
+    pub added: BTreeSet<String>,
+
+    /// The paths of the files modified by the push.
+    pub modified: BTreeSet<String>,
+
+    /// The paths of the files removed by the push.
+    pub removed: BTreeSet<String>,
+
+    /// Whether the platform left out some of the pushed commits, so that the changed paths may
+    /// be incomplete. The changes can then be determined from `before` instead, e.g. with
+    /// `DeveloperPlatform::get_updated_templates`.
+    pub truncated: bool,
+}
+
+/// The templates changed by a push, as returned by [`PushEvent::changed_templates`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChangedTemplates {
+    /// The templates added or modified by the push.
+    pub updated: Vec<(TemplateCategory, TemplatePath)>,
+
+    /// The templates removed by the push.
+    pub removed: Vec<(TemplateCategory, TemplatePath)>,
+}
+
+impl PushEvent {
+    /// Parses the payload of a GitHub `push` event.
+    ///
+    /// # Errors
+    /// Returns `PlatformError::InvalidContent` if the payload is not a push event.
+    pub fn from_github(payload: &[u8]) -> Result<Self, PlatformError> {
+        let push: GitHubPush = parse_payload(payload)?;
+        let truncated = push.commits.len() >= GITHUB_MAX_COMMITS;
+        Ok(Self::from_commits(
+            push.repository.full_name,
+            push.git_ref,
+            push.before,
+            push.after,
+            push.commits,
+            truncated,
+        ))
+    }
+
+    /// Parses the payload of a GitLab push hook.
+    ///
+    /// # Errors
+    /// Returns `PlatformError::InvalidContent` if the payload is not a push hook.
+    pub fn from_gitlab(payload: &[u8]) -> Result<Self, PlatformError> {
+        let push: GitLabPush = parse_payload(payload)?;
+        if push.object_kind != "push" {
+            return Err(PlatformError::InvalidContent(format!(
+                "Expected a push hook, got '{}'",
+                push.object_kind
+            )));
+        }
+        // GitLab includes at most 20 commits, the most recent ones.
+        let truncated = push.total_commits_count > push.commits.len();
+        Ok(Self::from_commits(
+            push.project.path_with_namespace,
+            push.git_ref,
+            push.before,
+            push.after,
+            push.commits,
+            truncated,
+        ))
+    }
+
+    /// Builds an event from the pushed commits, oldest first, accumulating their net changes.
+    fn from_commits(
+        repository: String,
+        git_ref: String,
+        before: String,
+        after: String,
+        commits: Vec<PushCommit>,
+        truncated: bool,
+    ) -> Self {
+        let mut event = Self {
+            repository,
+            git_ref,
+            before,
+            after,
+            added: BTreeSet::new(),
+            modified: BTreeSet::new(),
+            removed: BTreeSet::new(),
+            truncated,
+        };
+        for commit in commits {
+            for path in commit.added {
+                // A file removed and added again has been modified.
+                if event.removed.remove(&path) {
+                    event.modified.insert(path);
+                } else {
+                    event.added.insert(path);
+                }
+            }
+            for path in commit.modified {
+                if !event.added.contains(&path) {
+                    event.modified.insert(path);
+                }
+            }
+            for path in commit.removed {
+                event.modified.remove(&path);
+                if !event.added.remove(&path) {
+                    event.removed.insert(path);
+                }
+            }
+        }
+        event
+    }
+
+    /// Returns the name of the pushed branch, or `None` if the push was not to a branch.
+    pub fn branch(&self) -> Option<&str> {
+        self.git_ref.strip_prefix("refs/heads/")
+    }
+
+    /// Returns whether the push deleted the ref.
+    pub fn is_deletion(&self) -> bool {
+        self.after == ZERO_OBJECT_ID
+    }
+
+    /// Returns whether the push changed the master configuration file.
+    pub fn config_changed(&self) -> bool {
+        self.added
+            .iter()
+            .chain(&self.modified)
+            .chain(&self.removed)
+            .any(|path| path == MASTER_CONFIG_FILE)
+    }
+
+    /// Maps the changed paths onto the templates of the given master configuration. Paths which
+    /// are not templates listed by a category are ignored.
+    pub fn changed_templates(&self, config: &MasterConfig) -> ChangedTemplates {
+        ChangedTemplates {
+            updated: self
+                .added
+                .iter()
+                .chain(&self.modified)
+                .filter_map(|path| config.template_at(path))
+                .collect(),
+            removed: self
+                .removed
+                .iter()
+                .filter_map(|path| config.template_at(path))
+                .collect(),
+        }
+    }
+}
+
+fn parse_payload<T: DeserializeOwned>(payload: &[u8]) -> Result<T, PlatformError> {
+    serde_json::from_slice(payload)
+        .map_err(|e| PlatformError::InvalidContent(format!("Invalid push payload: {}", e)))
+}
+
+#[derive(Debug, Deserialize)]
+struct GitHubPush {
+    #[serde(rename = "ref")]
+    git_ref: String,
+    before: String,
+    after: String,
+    repository: GitHubRepository,
+    #[serde(default)]
+    commits: Vec<PushCommit>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitHubRepository {
+    full_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitLabPush {
+    object_kind: String,
+    #[serde(rename = "ref")]
+    git_ref: String,
+    before: String,
+    after: String,
+    project: GitLabProject,
+    #[serde(default)]
+    commits: Vec<PushCommit>,
+    #[serde(default)]
+    total_commits_count: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitLabProject {
+    path_with_namespace: String,
+}
+
+/// A commit of a push payload. GitHub and GitLab describe the changed files alike.
+#[derive(Debug, Deserialize)]
+struct PushCommit {
+    #[serde(default)]
+    added: Vec<String>,
+    #[serde(default)]
+    modified: Vec<String>,
+    #[serde(default)]
+    removed: Vec<String>,
+}
@@ -0,0 +1,200 @@
+use super::*;
+
+// Payloads recorded from GitHub and GitLab webhook deliveries, trimmed to the fields of interest.
+const GITHUB_PUSH: &str = include_str!("../test_data/webhooks/github_push.json");
+const GITLAB_PUSH: &str = include_str!("../test_data/webhooks/gitlab_push.json");
+
+const CONFIG: &str = r#"
+[meta]
+config_version = "1.0"
+
+[categories.saas_rust]
+files = [".github/PULL_REQUEST_TEMPLATE.md", ".gitignore", "CODEOWNERS"]
+
+[repositories]
+"org/service" = { category = "saas_rust" }
+"#;
+
+fn paths(paths: &[&str]) -> BTreeSet<String> {
+    paths.iter().map(|path| path.to_string()).collect()
+}
+
+fn template(path: &str) -> (TemplateCategory, TemplatePath) {
+    (
+        TemplateCategory::new("saas_rust".to_string()),
+        path.to_string(),
+    )
+}
+
+#[test]
+fn test_verify_github_signature() {
+    // The example from GitHub's documentation on validating webhook deliveries.
+    let result = verify_github_signature(
+        b"It's a Secret to Everybody",
+        b"Hello, World!",
+        "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17",
+    );
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_verify_github_signature_rejects_mismatches() {
+    let secret = b"It's a Secret to Everybody";
+    let signature = "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+
+    for (secret, payload, signature) in [
+        (&secret[..], &b"Hello, World?"[..], signature),
+        (&b"another secret"[..], &b"Hello, World!"[..], signature),
+        (
+            &secret[..],
+            &b"Hello, World!"[..],
+            "757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17",
+        ),
+        (&secret[..], &b"Hello, World!"[..], "sha256=not-hex"),
+        (&secret[..], &b"Hello, World!"[..], "sha256=757107ea"),
+        (&secret[..], &b"Hello, World!"[..], ""),
+    ] {
+        let result = verify_github_signature(secret, payload, signature);
+
+        assert!(
+            matches!(result, Err(PlatformError::WebhookVerificationFailed)),
+            "{:?} was accepted",
+            signature
+        );
+    }
+}
+
+#[test]
+fn test_verify_rejects_empty_secret() {
+    assert!(matches!(
+        verify_github_signature(b"", b"", "sha256="),
+        Err(PlatformError::ConfigError(_))
+    ));
+    assert!(matches!(
+        verify_gitlab_token("", ""),
+        Err(PlatformError::ConfigError(_))
+    ));
+}
+
+#[test]
+fn test_verify_gitlab_token() {
+    assert!(verify_gitlab_token("secret-token", "secret-token").is_ok());
+    for token in ["secret-token2", "secret-toke", "", "SECRET-TOKEN"] {
+        assert!(matches!(
+            verify_gitlab_token("secret-token", token),
+            Err(PlatformError::WebhookVerificationFailed)
+        ));
+    }
+}
+
+#[test]
+fn test_from_github_accumulates_net_changes() {
+    let event = PushEvent::from_github(GITHUB_PUSH.as_bytes()).unwrap();
+
+    assert_eq!(event.repository, "org/template-master");
+    assert_eq!(event.git_ref, "refs/heads/main");
+    assert_eq!(event.branch(), Some("main"));
+    assert_eq!(event.before, "6104942438c14ec7bd21c6cd5bd995272b3faff6");
+    assert_eq!(event.after, "570e7b2abdd848b95f2f578043fc23bd6f6fd24d");
+    assert_eq!(event.added, paths(&["templates/saas_rust/.gitignore"]));
+    assert_eq!(
+        event.modified,
+        paths(&[
+            "README.md",
+            "template-teleporter.toml",
+            "templates/saas_rust/.github/PULL_REQUEST_TEMPLATE.md",
+        ])
+    );
+    assert_eq!(event.removed, paths(&["templates/saas_rust/CODEOWNERS"]));
+    assert!(!event.truncated);
+    assert!(!event.is_deletion());
+    assert!(event.config_changed());
+}
+
+#[test]
+fn test_from_gitlab_reports_truncated_commits() {
+    let event = PushEvent::from_gitlab(GITLAB_PUSH.as_bytes()).unwrap();
+
+    assert_eq!(event.repository, "org/platform/template-master");
+    assert_eq!(event.before, "95790bf891e76fee5e1747ab589903a6a1f80f22");
+    assert_eq!(event.after, "da1560886d4f094c3e6c9ef40349f7d38b5d27d7");
+    // Removed and added again within the push.
+    assert!(event.added.is_empty());
+    assert_eq!(
+        event.modified,
+        paths(&["templates/saas_rust/.editorconfig"])
+    );
+    assert!(event.removed.is_empty());
+    assert!(event.truncated);
+    assert!(!event.config_changed());
+}
+
+#[test]
+fn test_from_gitlab_rejects_other_events() {
+    let payload = GITLAB_PUSH.replacen(
+        r#""object_kind": "push""#,
+        r#""object_kind": "tag_push""#,
+        1,
+    );
+
+    let result = PushEvent::from_gitlab(payload.as_bytes());
+
+    assert!(matches!(result, Err(PlatformError::InvalidContent(_))));
+}
+
+#[test]
+fn test_from_github_rejects_other_payloads() {
+    // A `ping` event, sent when a webhook is created.
+    let payload = br#"{ "zen": "Keep it logically awesome.", "hook_id": 1 }"#;
+
+    assert!(matches!(
+        PushEvent::from_github(payload),
+        Err(PlatformError::InvalidContent(_))
+    ));
+    assert!(matches!(
+        PushEvent::from_github(b"not json"),
+        Err(PlatformError::InvalidContent(_))
+    ));
+}
+
+#[test]
+fn test_branch_deletion() {
+    let payload = GITHUB_PUSH.replacen(
+        r#""after": "570e7b2abdd848b95f2f578043fc23bd6f6fd24d""#,
+        r#""after": "0000000000000000000000000000000000000000""#,
+        1,
+    );
+
+    let event = PushEvent::from_github(payload.as_bytes()).unwrap();
+
+    assert!(event.is_deletion());
+}
+
+#[test]
+fn test_push_to_tag_has_no_branch() {
+    let payload = GITHUB_PUSH.replacen("refs/heads/main", "refs/tags/v1.0.0", 1);
+
+    let event = PushEvent::from_github(payload.as_bytes()).unwrap();
+
+    assert_eq!(event.branch(), None);
+}
+
+#[test]
+fn test_changed_templates_maps_configured_files() {
+    let config = MasterConfig::from_toml_str(CONFIG).unwrap();
+    let event = PushEvent::from_github(GITHUB_PUSH.as_bytes()).unwrap();
+
+    let changed = event.changed_templates(&config);
+
+    assert_eq!(
+        changed,
+        ChangedTemplates {
+            updated: vec![
+                template(".gitignore"),
+                template(".github/PULL_REQUEST_TEMPLATE.md"),
+            ],
+            removed: vec![template("CODEOWNERS")],
+        }
+    );
+}
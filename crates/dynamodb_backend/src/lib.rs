@@ -0,0 +1,726 @@
+//! Implements a `StatePersistence` backend on Amazon DynamoDB.
+//!
+//! States are stored in the table named by `AppConfig::table_name`, with `repo` as the hash key
+//! and `template_path` as the range key, as provisioned by the deployment's Terraform
+//! configuration. `AppConfig::database_endpoint` overrides the DynamoDB endpoint, e.g. to point
+//! at DynamoDB Local.
+//!
+//! Every write is a `PutItem` conditioned on the `version` attribute of the stored item, so
+//! conditional updates are atomic across all writers of the table. The previous versions of a
+//! state are kept in the `history` list attribute of its item, newest first.
+//!
+//! Batches of states are read with `BatchGetItem`. Batches of writes read the stored items in
+//! the same way, but each state is still written with its own conditional `PutItem`, as
+//! `BatchWriteItem` does not support conditions.
+
+use async_trait::async_trait;
+use aws_config::ConfigLoader;
+use aws_sdk_dynamodb::config::{BehaviorVersion, Region};
+use aws_sdk_dynamodb::error::{DisplayErrorContext, ProvideErrorMetadata, SdkError};
+use aws_sdk_dynamodb::operation::put_item::PutItemError;
+use aws_sdk_dynamodb::types::{
+    AttributeDefinition, AttributeValue, BillingMode, KeySchemaElement, KeyType, KeysAndAttributes,
+    ReturnValue, ScalarAttributeType,
+};
+use aws_sdk_dynamodb::Client;
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::collections::{BTreeSet, HashMap};
+use template_teleporter_core::{
+    AppConfig, CoreError, Result, StateKey, StatePage, StatePersistence, StateQuery, TemplateState,
+    DEFAULT_HISTORY_RETENTION,
+};
+
+#[cfg(test)]
+#[path = "lib_tests.rs"]
+mod tests;
+
+#[cfg(test)]
+mod stand_in;
+
+const REPO: &str = "repo";
+const TEMPLATE_PATH: &str = "template_path";
+const SOURCE_REPOSITORY: &str = "source_repository";
+const MASTER_CHECKSUM: &str = "master_checksum";
+const DEPLOYED_CHECKSUM: &str = "deployed_checksum";
+const LAST_UPDATED: &str = "last_updated";
+const VERSION: &str = "version";
+const HISTORY: &str = "history";
+
+/// The number of conditional writes `update_state` and `update_states` make for a state that
+/// keeps being modified concurrently before giving up.
+const MAX_WRITE_ATTEMPTS: usize = 10;
+
+/// The maximum number of keys DynamoDB accepts in a `BatchGetItem` request.
+const MAX_BATCH_GET_KEYS: usize = 100;
+
+/// The number of `BatchGetItem` requests made for one batch of keys before giving up on keys
+/// DynamoDB keeps returning as unprocessed, e.g. because the table is throttled.
+const MAX_BATCH_GET_ATTEMPTS: usize = 10;
+
+type Item = HashMap<String, AttributeValue>;
+
+/// A state persistence backend that stores `TemplateState` in a DynamoDB table.
+#[derive(Debug, Clone)]
+pub struct DynamoDbBackend {
+    client: Client,
+    table_name: String,
+    history_retention: usize,
+}
+
+impl DynamoDbBackend {
+    /// Creates a new `DynamoDbBackend` using an existing DynamoDB client.
+    ///
+    /// # Arguments
+    /// * `client` - The DynamoDB client to use.
+    /// * `table_name` - The name of the table storing the states.
+    pub fn new(client: Client, table_name: impl Into<String>) -> Self {
+        Self {
+            client,
+            table_name: table_name.into(),
+            history_retention: DEFAULT_HISTORY_RETENTION,
+        }
+    }
+
+    /// Sets how many versions of each state are kept in the history, including the current
+    /// version. Values below 1 are treated as 1.
+    pub fn with_history_retention(mut self, retention: usize) -> Self {
+        self.history_retention = retention.max(1);
+        self
+    }
+
+    /// Creates a new `DynamoDbBackend` from the application configuration.
+    ///
+    /// Credentials are resolved from the environment as by the AWS CLI, and so is the region
+    /// unless the `dynamodb` section of the configuration sets one. If `database_endpoint` is
+    /// set, requests are sent to that endpoint instead of the regional DynamoDB endpoint.
+    ///
+    /// # Arguments
+    /// * `config` - The application configuration providing `table_name` and, optionally,
+    ///   `database_endpoint` and `dynamodb.region`.
+    pub async fn from_app_config(config: &AppConfig) -> Self {
+        Self::from_app_config_with_loader(config, aws_config::defaults(BehaviorVersion::latest()))
+            .await
+    }
+
+    /// Creates a new `DynamoDbBackend` from the application configuration like
+    /// [`DynamoDbBackend::from_app_config`], resolving the SDK configuration with the given
+    /// loader instead of the defaults, e.g. to supply credentials explicitly. The region and
+    /// endpoint from the application configuration take precedence over those of the loader.
+    pub async fn from_app_config_with_loader(config: &AppConfig, mut loader: ConfigLoader) -> Self {
+        if let Some(region) = config.dynamodb.as_ref().and_then(|s| s.region.clone()) {
+            loader = loader.region(Region::new(region));
+        }
+        if let Some(endpoint) = &config.database_endpoint {
+            loader = loader.endpoint_url(endpoint);
+        }
+        let sdk_config = loader.load().await;
+        Self::new(Client::new(&sdk_config), config.table_name.clone())
+    }
+
+    /// Returns the name of the table storing the states.
+    pub fn table_name(&self) -> &str {
+        &self.table_name
+    }
+
+    /// Creates the state table with on-demand billing, if it does not exist yet.
+    ///
+    /// Production tables are provisioned with Terraform; this is intended for DynamoDB Local and
+    /// other development setups.
+    ///
+    /// # Returns
+    /// An empty `Result` on success, or a `CoreError::DatabaseError` if the table cannot be
+    /// created.
+    pub async fn create_table(&self) -> Result<()> {
+        let key_attribute = |name: &str, key_type: KeyType| {
+            Ok::<_, CoreError>((
+                AttributeDefinition::builder()
+                    .attribute_name(name)
+                    .attribute_type(ScalarAttributeType::S)
+                    .build()
+                    .map_err(|e| CoreError::DatabaseError(e.to_string()))?,
+                KeySchemaElement::builder()
+                    .attribute_name(name)
+                    .key_type(key_type)
+                    .build()
+                    .map_err(|e| CoreError::DatabaseError(e.to_string()))?,
+            ))
+        };
+        let (repo_definition, repo_key) = key_attribute(REPO, KeyType::Hash)?;
+        let (path_definition, path_key) = key_attribute(TEMPLATE_PATH, KeyType::Range)?;
+
+        let result = self
+            .client
+            .create_table()
+            .table_name(&self.table_name)
+            .attribute_definitions(repo_definition)
+            .attribute_definitions(path_definition)
+            .key_schema(repo_key)
+            .key_schema(path_key)
+            .billing_mode(BillingMode::PayPerRequest)
+            .send()
+            .await;
+        match result {
+            Ok(_) => Ok(()),
+            Err(e) if e.code() == Some("ResourceInUseException") => Ok(()),
+            Err(e) => Err(db_error("CreateTable", e)),
+        }
+    }
+
+    /// Reads the item of a state with a strongly consistent read.
+    async fn get_item(&self, key: &StateKey) -> Result<Option<Item>> {
+        let output = self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .set_key(Some(key_attributes(key)))
+            .consistent_read(true)
+            .send()
+            .await
+            .map_err(|e| db_error("GetItem", e))?;
+        Ok(output.item().cloned())
+    }
+
+    /// Reads the items of several states with strongly consistent `BatchGetItem` requests.
+    ///
+    /// # Returns
+    /// A `Result` containing the stored items by key, without entries for keys that have no
+    /// item, or a `CoreError::DatabaseError` if a request fails, or a
+    /// `CoreError::TransientDatabaseError` if it is throttled or keys remain unprocessed.
+    async fn get_items(&self, keys: &[StateKey]) -> Result<HashMap<StateKey, Item>> {
+        // DynamoDB rejects requests containing a key twice.
+        let unique: Vec<&StateKey> = keys.iter().collect::<BTreeSet<_>>().into_iter().collect();
+        let mut items = HashMap::new();
+        for chunk in unique.chunks(MAX_BATCH_GET_KEYS) {
+            let mut pending: Vec<Item> = chunk.iter().map(|key| key_attributes(key)).collect();
+            for _ in 0..MAX_BATCH_GET_ATTEMPTS {
+                let request = KeysAndAttributes::builder()
+                    .set_keys(Some(pending))
+                    .consistent_read(true)
+                    .build()
+                    .map_err(|e| CoreError::DatabaseError(e.to_string()))?;
+                let output = self
+                    .client
+                    .batch_get_item()
+                    .request_items(&self.table_name, request)
+                    .send()
+                    .await
+                    .map_err(|e| db_error("BatchGetItem", e))?;
+                for item in output
+                    .responses()
+                    .and_then(|responses| responses.get(&self.table_name))
+                    .into_iter()
+                    .flatten()
+                {
+                    let key = StateKey::new(
+                        string_attribute(item, REPO)?,
+                        string_attribute(item, TEMPLATE_PATH)?,
+                    );
+                    items.insert(key, item.clone());
+                }
+                pending = output
+                    .unprocessed_keys()
+                    .and_then(|unprocessed| unprocessed.get(&self.table_name))
+                    .map(|unprocessed| unprocessed.keys().to_vec())
+                    .unwrap_or_default();
+                if pending.is_empty() {
+                    break;
+                }
+            }
+            if !pending.is_empty() {
+                return Err(CoreError::TransientDatabaseError(format!(
+                    "DynamoDB BatchGetItem left {} keys unprocessed after {} attempts",
+                    pending.len(),
+                    MAX_BATCH_GET_ATTEMPTS
+                )));
+            }
+        }
+        Ok(items)
+    }
+
+    /// Writes a state with the version after that of the stored item, re-reading the stored item
+    /// if another writer modifies it in between.
+    ///
+    /// # Arguments
+    /// * `state` - The state to write.
+    /// * `stored` - The stored item of the state as last read, or `None` if there was none.
+    async fn put_state(&self, state: &TemplateState, mut stored: Option<Item>) -> Result<()> {
+        for attempt in 1..=MAX_WRITE_ATTEMPTS {
+            if self
+                .put_next_version(state, stored.as_ref())
+                .await?
+                .is_some()
+            {
+                return Ok(());
+            }
+            if attempt < MAX_WRITE_ATTEMPTS {
+                stored = self.get_item(&state.key()).await?;
+            }
+        }
+        Err(CoreError::DatabaseError(format!(
+            "Failed to update state for {}: modified concurrently {} times",
+            state.key(),
+            MAX_WRITE_ATTEMPTS
+        )))
+    }
+
+    /// Writes a state with the version after that of the `stored` item, moving the stored state
+    /// into the history. The write is conditioned on the stored version being unchanged.
+    ///
+    /// # Returns
+    /// A `Result` containing the new version, `None` if the stored item was modified in between,
+    /// or a `CoreError::DatabaseError` if the request fails.
+    async fn put_next_version(
+        &self,
+        state: &TemplateState,
+        stored: Option<&Item>,
+    ) -> Result<Option<u64>> {
+        let mut request = self.client.put_item().table_name(&self.table_name);
+        let new_version = match stored {
+            None => {
+                request = request
+                    .condition_expression("attribute_not_exists(#repo)")
+                    .expression_attribute_names("#repo", REPO);
+                1
+            }
+            Some(stored) => {
+                let current = item_to_state(stored)?;
+                request = match stored.get(VERSION) {
+                    Some(version) => request
+                        .condition_expression("#version = :expected")
+                        .expression_attribute_values(":expected", version.clone()),
+                    None => request.condition_expression("attribute_not_exists(#version)"),
+                }
+                .expression_attribute_names("#version", VERSION);
+                current.version + 1
+            }
+        };
+
+        let mut item = state_to_item(state, new_version);
+        if let Some(stored) = stored {
+            let mut history = vec![AttributeValue::M(version_attributes(stored))];
+            history.extend(history_attribute(stored)?.iter().cloned());
+            history.truncate(self.history_retention - 1);
+            if !history.is_empty() {
+                item.insert(HISTORY.to_string(), AttributeValue::L(history));
+            }
+        }
+
+        match request.set_item(Some(item)).send().await {
+            Ok(_) => Ok(Some(new_version)),
+            Err(e)
+                if matches!(
+                    e.as_service_error(),
+                    Some(PutItemError::ConditionalCheckFailedException(_))
+                ) =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(db_error("PutItem", e)),
+        }
+    }
+
+    /// Reads the current version of a state with a strongly consistent read.
+    async fn current_version(&self, key: &StateKey) -> Result<Option<u64>> {
+        let output = self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .set_key(Some(key_attributes(key)))
+            .projection_expression("#version")
+            .expression_attribute_names("#version", VERSION)
+            .consistent_read(true)
+            .send()
+            .await
+            .map_err(|e| db_error("GetItem", e))?;
+        output
+            .item()
+            .map(|item| number_attribute(item, VERSION))
+            .transpose()
+    }
+
+    /// Lists the states of one repository with a key-ordered query.
+    async fn query_repo(
+        &self,
+        repo: &str,
+        query: &StateQuery,
+        start_after: Option<StateKey>,
+    ) -> Result<StatePage> {
+        let mut exclusive_start_key = match start_after {
+            Some(after) if after.repo.as_str() > repo => return Ok(StatePage::default()),
+            Some(after) if after.repo == repo => Some(key_attributes(&after)),
+            _ => None,
+        };
+
+        // Fetch one state more than requested to find out whether there is another page.
+        let wanted = query.limit.map(|limit| limit.saturating_add(1));
+        let mut states = Vec::new();
+        loop {
+            let mut request = self
+                .client
+                .query()
+                .table_name(&self.table_name)
+                .consistent_read(true)
+                .expression_attribute_names("#repo", REPO)
+                .expression_attribute_values(":repo", AttributeValue::S(repo.to_string()))
+                .set_exclusive_start_key(exclusive_start_key.take());
+            request = match &query.template_path_prefix {
+                Some(prefix) => request
+                    .key_condition_expression("#repo = :repo AND begins_with(#path, :prefix)")
+                    .expression_attribute_names("#path", TEMPLATE_PATH)
+                    .expression_attribute_values(":prefix", AttributeValue::S(prefix.clone())),
+                None => request.key_condition_expression("#repo = :repo"),
+            };
+            if let Some(wanted) = wanted {
+                let remaining = wanted - states.len();
+                request = request.limit(i32::try_from(remaining).unwrap_or(i32::MAX));
+            }
+
+            let output = request.send().await.map_err(|e| db_error("Query", e))?;
+            for item in output.items() {
+                states.push(item_to_state(item)?);
+            }
+            exclusive_start_key = output.last_evaluated_key().cloned();
+            if exclusive_start_key.is_none() || wanted.is_some_and(|w| states.len() >= w) {
+                break;
+            }
+        }
+
+        let has_more = query.limit.is_some_and(|limit| states.len() > limit);
+        if let Some(limit) = query.limit {
+            states.truncate(limit);
+        }
+        Ok(StatePage::new(states, has_more))
+    }
+
+    /// Lists states across repositories. DynamoDB scans are not ordered, so this reads every
+    /// matching state and sorts them before paginating. Intended for cleanup jobs and reports.
+    async fn scan_all(&self, query: &StateQuery) -> Result<StatePage> {
+        let mut exclusive_start_key = None;
+        let mut states = Vec::new();
+        loop {
+            let mut request = self
+                .client
+                .scan()
+                .table_name(&self.table_name)
+                .consistent_read(true)
+                .set_exclusive_start_key(exclusive_start_key.take());
+            if let Some(prefix) = &query.template_path_prefix {
+                request = request
+                    .filter_expression("begins_with(#path, :prefix)")
+                    .expression_attribute_names("#path", TEMPLATE_PATH)
+                    .expression_attribute_values(":prefix", AttributeValue::S(prefix.clone()));
+            }
+
+            let output = request.send().await.map_err(|e| db_error("Scan", e))?;
+            for item in output.items() {
+                states.push(item_to_state(item)?);
+            }
+            exclusive_start_key = output.last_evaluated_key().cloned();
+            if exclusive_start_key.is_none() {
+                break;
+            }
+        }
+
+        states.sort_by_key(|state| state.key());
+        query.paginate(states)
+    }
+}
+
+#[async_trait]
+impl StatePersistence for DynamoDbBackend {
+    async fn get_state(&self, key: &StateKey) -> Result<Option<TemplateState>> {
+        self.get_item(key)
+            .await?
+            .as_ref()
+            .map(item_to_state)
+            .transpose()
+    }
+
+    /// Writes the state with the version after the stored one, re-reading the stored state if
+    /// another writer modifies it in between.
+    async fn update_state(&self, state: &TemplateState) -> Result<()> {
+        let stored = self.get_item(&state.key()).await?;
+        self.put_state(state, stored).await
+    }
+
+    /// Compares the stored version, then writes the state with a `PutItem` request conditioned
+    /// on the compared version, so a write in between is detected as a conflict.
+    async fn update_state_if(
+        &self,
+        state: &TemplateState,
+        expected_version: Option<u64>,
+    ) -> Result<u64> {
+        let key = state.key();
+        let stored = self.get_item(&key).await?;
+        let mut actual = stored
+            .as_ref()
+            .map(item_to_state)
+            .transpose()?
+            .map(|s| s.version);
+        if actual == expected_version {
+            if let Some(new_version) = self.put_next_version(state, stored.as_ref()).await? {
+                return Ok(new_version);
+            }
+            actual = self.current_version(&key).await?;
+        }
+        Err(CoreError::StateConflict {
+            key,
+            expected: expected_version,
+            actual,
+        })
+    }
+
+    /// Uses a key-ordered `Query` when the query selects a repository, and a `Scan` otherwise.
+    async fn list_states(&self, query: &StateQuery) -> Result<StatePage> {
+        let start_after = query.start_after()?;
+        match &query.repo {
+            Some(repo) => self.query_repo(repo, query, start_after).await,
+            None => self.scan_all(query).await,
+        }
+    }
+
+    /// Scans the table in the order DynamoDB stores the items, with one `Scan` request per page.
+    /// The page token holds the last evaluated key.
+    async fn scan_states(&self, page_token: Option<&str>, limit: usize) -> Result<StatePage> {
+        let start_after = match page_token {
+            Some(token) => StateQuery::default().with_page_token(token).start_after()?,
+            None => None,
+        };
+        let output = self
+            .client
+            .scan()
+            .table_name(&self.table_name)
+            .consistent_read(true)
+            .limit(i32::try_from(limit.max(1)).unwrap_or(i32::MAX))
+            .set_exclusive_start_key(start_after.as_ref().map(key_attributes))
+            .send()
+            .await
+            .map_err(|e| db_error("Scan", e))?;
+        let states = output
+            .items()
+            .iter()
+            .map(item_to_state)
+            .collect::<Result<Vec<_>>>()?;
+        let next_page_token = output
+            .last_evaluated_key()
+            .map(|key| {
+                Ok::<_, CoreError>(StatePage::token_after(&StateKey::new(
+                    string_attribute(key, REPO)?,
+                    string_attribute(key, TEMPLATE_PATH)?,
+                )))
+            })
+            .transpose()?;
+        Ok(StatePage {
+            states,
+            next_page_token,
+        })
+    }
+
+    async fn delete_state(&self, key: &StateKey) -> Result<bool> {
+        let output = self
+            .client
+            .delete_item()
+            .table_name(&self.table_name)
+            .set_key(Some(key_attributes(key)))
+            .return_values(ReturnValue::AllOld)
+            .send()
+            .await
+            .map_err(|e| db_error("DeleteItem", e))?;
+        Ok(output
+            .attributes()
+            .is_some_and(|attributes| !attributes.is_empty()))
+    }
+
+    /// Reads the item of the state, which holds its previous versions.
+    async fn get_state_history(&self, key: &StateKey) -> Result<Vec<TemplateState>> {
+        let Some(item) = self.get_item(key).await? else {
+            return Ok(Vec::new());
+        };
+        let mut history = vec![item_to_state(&item)?];
+        for version in history_attribute(&item)?
+            .iter()
+            .take(self.history_retention - 1)
+        {
+            let AttributeValue::M(attributes) = version else {
+                return Err(CoreError::DatabaseError(format!(
+                    "Expected map in '{}', found {:?}",
+                    HISTORY, version
+                )));
+            };
+            let mut previous = key_attributes(key);
+            previous.extend(attributes.clone());
+            history.push(item_to_state(&previous)?);
+        }
+        Ok(history)
+    }
+
+    /// Reads the items of all states with `BatchGetItem` requests of up to 100 keys each.
+    async fn get_states(&self, keys: &[StateKey]) -> Result<Vec<Option<TemplateState>>> {
+        let items = self.get_items(keys).await?;
+        keys.iter()
+            .map(|key| items.get(key).map(item_to_state).transpose())
+            .collect()
+    }
+
+    /// Reads the stored items of all states in batches, then writes each state with its own
+    /// conditional `PutItem`. The batch is not atomic: if a write fails, the states before it
+    /// remain written.
+    async fn update_states(&self, states: &[TemplateState]) -> Result<()> {
+        let keys: Vec<StateKey> = states.iter().map(TemplateState::key).collect();
+        let mut prefetched: HashMap<StateKey, Option<Item>> =
+            keys.iter().map(|key| (key.clone(), None)).collect();
+        for (key, item) in self.get_items(&keys).await? {
+            prefetched.insert(key, Some(item));
+        }
+
+        for (state, key) in states.iter().zip(&keys) {
+            // The prefetched item is only current for the first state of each key.
+            let stored = match prefetched.remove(key) {
+                Some(stored) => stored,
+                None => self.get_item(key).await?,
+            };
+            self.put_state(state, stored).await?;
+        }
+        Ok(())
+    }
+}
+
+fn key_attributes(key: &StateKey) -> Item {
+    HashMap::from([
+        (REPO.to_string(), AttributeValue::S(key.repo.clone())),
+        (
+            TEMPLATE_PATH.to_string(),
+            AttributeValue::S(key.template_path.clone()),
+        ),
+    ])
+}
+
+fn state_to_item(state: &TemplateState, version: u64) -> Item {
+    let mut item = key_attributes(&state.key());
+    item.insert(
+        SOURCE_REPOSITORY.to_string(),
+        AttributeValue::S(state.source_repository.clone()),
+    );
+    item.insert(
+        MASTER_CHECKSUM.to_string(),
+        AttributeValue::S(state.master_checksum.clone()),
+    );
+    if let Some(deployed) = &state.deployed_checksum {
+        item.insert(
+            DEPLOYED_CHECKSUM.to_string(),
+            AttributeValue::S(deployed.clone()),
+        );
+    }
+    item.insert(
+        LAST_UPDATED.to_string(),
+        AttributeValue::S(format_timestamp(&state.last_updated_utc)),
+    );
+    item.insert(VERSION.to_string(), AttributeValue::N(version.to_string()));
+    item
+}
+
+/// Returns the attributes of a stored state without its key and history, as kept in the history.
+fn version_attributes(item: &Item) -> Item {
+    item.iter()
+        .filter(|(name, _)| ![REPO, TEMPLATE_PATH, HISTORY].contains(&name.as_str()))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect()
+}
+
+/// Returns the previous versions stored in an item, newest first.
+fn history_attribute(item: &Item) -> Result<&[AttributeValue]> {
+    match item.get(HISTORY) {
+        None => Ok(&[]),
+        Some(AttributeValue::L(history)) => Ok(history),
+        Some(other) => Err(CoreError::DatabaseError(format!(
+            "Expected list attribute '{}', found {:?}",
+            HISTORY, other
+        ))),
+    }
+}
+
+fn item_to_state(item: &Item) -> Result<TemplateState> {
+    let last_updated = string_attribute(item, LAST_UPDATED)?;
+    let last_updated_utc = DateTime::parse_from_rfc3339(&last_updated)
+        .map_err(|e| {
+            CoreError::DatabaseError(format!(
+                "Invalid {} '{}': {}",
+                LAST_UPDATED, last_updated, e
+            ))
+        })?
+        .with_timezone(&Utc);
+    Ok(TemplateState {
+        repo: string_attribute(item, REPO)?,
+        template_path: string_attribute(item, TEMPLATE_PATH)?,
+        source_repository: string_attribute(item, SOURCE_REPOSITORY)?,
+        master_checksum: string_attribute(item, MASTER_CHECKSUM)?,
+        deployed_checksum: item
+            .get(DEPLOYED_CHECKSUM)
+            .map(|_| string_attribute(item, DEPLOYED_CHECKSUM))
+            .transpose()?,
+        last_updated_utc,
+        // Items written before versions were introduced read as version 0.
+        version: match item.get(VERSION) {
+            Some(_) => number_attribute(item, VERSION)?,
+            None => 0,
+        },
+    })
+}
+
+fn string_attribute(item: &Item, name: &str) -> Result<String> {
+    match item.get(name) {
+        Some(AttributeValue::S(value)) => Ok(value.clone()),
+        other => Err(CoreError::DatabaseError(format!(
+            "Expected string attribute '{}', found {:?}",
+            name, other
+        ))),
+    }
+}
+
+fn number_attribute(item: &Item, name: &str) -> Result<u64> {
+    match item.get(name) {
+        Some(AttributeValue::N(value)) => value.parse().map_err(|_| {
+            CoreError::DatabaseError(format!("Invalid number '{}' in '{}'", value, name))
+        }),
+        other => Err(CoreError::DatabaseError(format!(
+            "Expected number attribute '{}', found {:?}",
+            name, other
+        ))),
+    }
+}
+
+/// Formats a timestamp as ISO 8601, with full precision so that it round-trips exactly.
+fn format_timestamp(timestamp: &DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::Nanos, true)
+}
+
+/// The error codes of DynamoDB requests which may succeed when repeated.
+const TRANSIENT_ERROR_CODES: &[&str] = &[
+    "ProvisionedThroughputExceededException",
+    "RequestLimitExceeded",
+    "ThrottlingException",
+    "InternalServerError",
+    "ServiceUnavailable",
+];
+
+fn db_error<E, R>(operation: &str, e: SdkError<E, R>) -> CoreError
+where
+    E: std::error::Error + ProvideErrorMetadata + 'static,
+    R: std::fmt::Debug,
+{
+    let transient = match &e {
+        SdkError::TimeoutError(_) | SdkError::DispatchFailure(_) | SdkError::ResponseError(_) => {
+            true
+        }
+        SdkError::ServiceError(_) => e
+            .code()
+            .is_some_and(|code| TRANSIENT_ERROR_CODES.contains(&code)),
+        _ => false,
+    };
+    let message = format!("DynamoDB {} failed: {}", operation, DisplayErrorContext(e));
+    if transient {
+        CoreError::TransientDatabaseError(message)
+    } else {
+        CoreError::DatabaseError(message)
+    }
+}
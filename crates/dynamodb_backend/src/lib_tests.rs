@@ -0,0 +1,537 @@
+//! Integration tests for `DynamoDbBackend`.
+//!
+//! The tests run against the DynamoDB endpoint in `DYNAMODB_ENDPOINT` (e.g. DynamoDB Local,
+//! started with `docker run -p 8000:8000 amazon/dynamodb-local` and
+//! `DYNAMODB_ENDPOINT=http://localhost:8000`). Without it they run against an in-process
+//! stand-in. Each test creates its own table.
+
+use super::*;
+use aws_sdk_dynamodb::config::retry::RetryConfig;
+use aws_sdk_dynamodb::config::{Credentials, Region};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use template_teleporter_core::{DatabaseType, DynamoDbSettings};
+use wiremock::matchers::method;
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// A backend on a fresh table, keeping the stand-in server alive while in use.
+struct TestBackend {
+    backend: DynamoDbBackend,
+    endpoint: String,
+    _server: Option<MockServer>,
+}
+
+fn unique_table_name() -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    format!(
+        "template-state-{}-{}",
+        Utc::now().timestamp_nanos_opt().unwrap_or_default(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+async fn test_backend() -> TestBackend {
+    let (endpoint, server) = match std::env::var("DYNAMODB_ENDPOINT") {
+        Ok(endpoint) => (endpoint, None),
+        Err(_) => {
+            let server = stand_in::start().await;
+            (server.uri(), Some(server))
+        }
+    };
+    let config = aws_sdk_dynamodb::Config::builder()
+        .behavior_version(BehaviorVersion::latest())
+        .region(Region::new("us-east-1"))
+        .credentials_provider(Credentials::new("test", "test", None, None, "test"))
+        .endpoint_url(&endpoint)
+        .build();
+    let backend = DynamoDbBackend::new(Client::from_conf(config), unique_table_name());
+    backend.create_table().await.unwrap();
+    TestBackend {
+        backend,
+        endpoint,
+        _server: server,
+    }
+}
+
+/// An SDK config loader with an explicit region and credentials, so that tests neither read nor
+/// modify the environment.
+fn test_loader() -> ConfigLoader {
+    aws_config::defaults(BehaviorVersion::latest())
+        .region(Region::new("us-east-1"))
+        .credentials_provider(Credentials::new("test", "test", None, None, "test"))
+}
+
+fn state_for(repo: &str, template_path: &str) -> TemplateState {
+    TemplateState {
+        repo: repo.to_string(),
+        template_path: template_path.to_string(),
+        source_repository: "owner/master".to_string(),
+        master_checksum: "checksum".to_string(),
+        deployed_checksum: Some("deployed".to_string()),
+        last_updated_utc: Utc::now(),
+        version: 0,
+    }
+}
+
+fn keys(page: &StatePage) -> Vec<String> {
+    page.states.iter().map(|s| s.key().to_string()).collect()
+}
+
+#[tokio::test]
+async fn test_dynamodb_backend_from_app_config_honours_endpoint_and_table_name() {
+    let test = test_backend().await;
+    let config = AppConfig::new(DatabaseType::Dynamodb, test.backend.table_name())
+        .with_database_endpoint(test.endpoint.clone());
+
+    let backend = DynamoDbBackend::from_app_config_with_loader(&config, test_loader()).await;
+    backend
+        .update_state(&state_for("org/a", "README.md"))
+        .await
+        .unwrap();
+
+    assert_eq!(backend.table_name(), test.backend.table_name());
+    let key = StateKey::new("org/a", "README.md");
+    assert!(test.backend.get_state(&key).await.unwrap().is_some());
+
+    // A region in the configuration takes precedence over the environment
+    let mut config = config;
+    config.dynamodb = Some(DynamoDbSettings {
+        region: Some("eu-west-1".to_string()),
+    });
+    let backend = DynamoDbBackend::from_app_config_with_loader(&config, test_loader()).await;
+    assert_eq!(
+        backend.client.config().region(),
+        Some(&Region::new("eu-west-1"))
+    );
+}
+
+#[tokio::test]
+async fn test_dynamodb_backend_create_table_is_idempotent() {
+    let test = test_backend().await;
+
+    test.backend.create_table().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_dynamodb_backend_update_and_get_state() {
+    let test = test_backend().await;
+    let state = state_for("org/a", ".github/PULL_REQUEST_TEMPLATE.md");
+
+    test.backend.update_state(&state).await.unwrap();
+    let stored = test.backend.get_state(&state.key()).await.unwrap().unwrap();
+
+    assert_eq!(
+        stored,
+        TemplateState {
+            version: 1,
+            ..state
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_dynamodb_backend_get_state_not_found() {
+    let test = test_backend().await;
+
+    let result = test
+        .backend
+        .get_state(&StateKey::new("org/a", "missing"))
+        .await
+        .unwrap();
+
+    assert_eq!(result, None);
+}
+
+#[tokio::test]
+async fn test_dynamodb_backend_missing_table_is_database_error() {
+    let test = test_backend().await;
+    let backend = DynamoDbBackend::new(test.backend.client.clone(), "missing-table");
+
+    let result = backend
+        .get_state(&StateKey::new("org/a", "README.md"))
+        .await;
+
+    assert!(matches!(result, Err(CoreError::DatabaseError(msg)) if msg.contains("GetItem")));
+}
+
+#[tokio::test]
+async fn test_dynamodb_backend_throttling_is_transient_database_error() {
+    let server = MockServer::start().await;
+    let body = serde_json::json!({
+        "__type": "com.amazonaws.dynamodb.v20120810#ProvisionedThroughputExceededException",
+        "message": "The level of configured provisioned throughput for the table was exceeded.",
+    });
+    Mock::given(method("POST"))
+        .respond_with(
+            ResponseTemplate::new(400).set_body_raw(body.to_string(), "application/x-amz-json-1.0"),
+        )
+        .mount(&server)
+        .await;
+    let config = aws_sdk_dynamodb::Config::builder()
+        .behavior_version(BehaviorVersion::latest())
+        .region(Region::new("us-east-1"))
+        .credentials_provider(Credentials::new("test", "test", None, None, "test"))
+        .endpoint_url(server.uri())
+        .retry_config(RetryConfig::disabled())
+        .build();
+    let backend = DynamoDbBackend::new(Client::from_conf(config), unique_table_name());
+
+    let result = backend
+        .get_state(&StateKey::new("org/a", "README.md"))
+        .await;
+
+    assert!(
+        matches!(result, Err(CoreError::TransientDatabaseError(msg)) if msg.contains("GetItem"))
+    );
+}
+
+#[tokio::test]
+async fn test_dynamodb_backend_update_state_overwrites_and_increments_version() {
+    let test = test_backend().await;
+    let mut state = state_for("org/a", "README.md");
+    test.backend.update_state(&state).await.unwrap();
+
+    state.master_checksum = "updated".to_string();
+    state.deployed_checksum = None;
+    test.backend.update_state(&state).await.unwrap();
+
+    let stored = test.backend.get_state(&state.key()).await.unwrap().unwrap();
+    assert_eq!(stored.master_checksum, "updated");
+    assert_eq!(stored.deployed_checksum, None);
+    assert_eq!(stored.version, 2);
+}
+
+#[tokio::test]
+async fn test_dynamodb_backend_update_state_if() {
+    let test = test_backend().await;
+    let backend = &test.backend;
+    let state = state_for("org/a", "README.md");
+
+    assert_eq!(backend.update_state_if(&state, None).await.unwrap(), 1);
+    assert_eq!(backend.update_state_if(&state, Some(1)).await.unwrap(), 2);
+
+    let result = backend.update_state_if(&state, Some(1)).await;
+    assert!(matches!(
+        result,
+        Err(CoreError::StateConflict {
+            expected: Some(1),
+            actual: Some(2),
+            ..
+        })
+    ));
+    let result = backend.update_state_if(&state, None).await;
+    assert!(matches!(
+        result,
+        Err(CoreError::StateConflict {
+            expected: None,
+            actual: Some(2),
+            ..
+        })
+    ));
+    let missing = state_for("org/a", "missing");
+    let result = backend.update_state_if(&missing, Some(1)).await;
+    assert!(matches!(
+        result,
+        Err(CoreError::StateConflict { actual: None, .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_dynamodb_backend_update_state_if_allows_one_concurrent_writer() {
+    let test = test_backend().await;
+    let backend = Arc::new(test.backend.clone());
+
+    let tasks = (0..5)
+        .map(|_| {
+            let backend = Arc::clone(&backend);
+            tokio::spawn(async move {
+                backend
+                    .update_state_if(&state_for("org/a", "README.md"), None)
+                    .await
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut successes = 0;
+    for task in tasks {
+        match task.await.unwrap() {
+            Ok(version) => {
+                assert_eq!(version, 1);
+                successes += 1;
+            }
+            Err(CoreError::StateConflict { .. }) => {}
+            Err(e) => panic!("Unexpected error: {:?}", e),
+        }
+    }
+    assert_eq!(successes, 1);
+}
+
+#[tokio::test]
+async fn test_dynamodb_backend_list_states_filters_and_sorts() {
+    let test = test_backend().await;
+    let backend = &test.backend;
+    for (repo, path) in [
+        ("org/b", ".github/CODEOWNERS"),
+        ("org/a", ".github/PULL_REQUEST_TEMPLATE.md"),
+        ("org/a", "README.md"),
+        ("org/a", ".github/CODEOWNERS"),
+        ("org/a", ".githubx"),
+    ] {
+        backend.update_state(&state_for(repo, path)).await.unwrap();
+    }
+
+    let all = backend.list_states(&StateQuery::default()).await.unwrap();
+    assert_eq!(
+        keys(&all),
+        vec![
+            "org/a:.github/CODEOWNERS",
+            "org/a:.github/PULL_REQUEST_TEMPLATE.md",
+            "org/a:.githubx",
+            "org/a:README.md",
+            "org/b:.github/CODEOWNERS",
+        ]
+    );
+    assert_eq!(all.next_page_token, None);
+
+    let by_prefix = backend
+        .list_states(&StateQuery::default().with_template_path_prefix(".github/"))
+        .await
+        .unwrap();
+    assert_eq!(
+        keys(&by_prefix),
+        vec![
+            "org/a:.github/CODEOWNERS",
+            "org/a:.github/PULL_REQUEST_TEMPLATE.md",
+            "org/b:.github/CODEOWNERS",
+        ]
+    );
+
+    let by_repo = backend
+        .list_states(
+            &StateQuery::default()
+                .with_repo("org/a")
+                .with_template_path_prefix(".github/"),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        keys(&by_repo),
+        vec![
+            "org/a:.github/CODEOWNERS",
+            "org/a:.github/PULL_REQUEST_TEMPLATE.md",
+        ]
+    );
+}
+
+async fn list_pages(backend: &DynamoDbBackend, mut query: StateQuery) -> Vec<Vec<String>> {
+    let mut pages = Vec::new();
+    loop {
+        let page = backend.list_states(&query).await.unwrap();
+        pages.push(keys(&page));
+        match page.next_page_token {
+            Some(token) => query = query.with_page_token(token),
+            None => break,
+        }
+    }
+    pages
+}
+
+#[tokio::test]
+async fn test_dynamodb_backend_list_states_paginates() {
+    let test = test_backend().await;
+    for (repo, path) in [
+        ("org/a", "x"),
+        ("org/a", "y"),
+        ("org/a", "z"),
+        ("org/a", "zz"),
+        ("org/b", "x"),
+        ("org/c", "x"),
+    ] {
+        test.backend
+            .update_state(&state_for(repo, path))
+            .await
+            .unwrap();
+    }
+
+    let across_repos = list_pages(&test.backend, StateQuery::default().with_limit(4)).await;
+    assert_eq!(
+        across_repos,
+        vec![
+            vec!["org/a:x", "org/a:y", "org/a:z", "org/a:zz"],
+            vec!["org/b:x", "org/c:x"],
+        ]
+    );
+
+    let one_repo = list_pages(
+        &test.backend,
+        StateQuery::default().with_repo("org/a").with_limit(3),
+    )
+    .await;
+    assert_eq!(
+        one_repo,
+        vec![vec!["org/a:x", "org/a:y", "org/a:z"], vec!["org/a:zz"]]
+    );
+}
+
+#[tokio::test]
+async fn test_dynamodb_backend_list_states_with_token_from_other_repo() {
+    let test = test_backend().await;
+    test.backend
+        .update_state(&state_for("org/b", "x"))
+        .await
+        .unwrap();
+
+    let before = StateQuery::default()
+        .with_repo("org/b")
+        .with_page_token(StatePage::token_after(&StateKey::new("org/a", "z")));
+    let after = StateQuery::default()
+        .with_repo("org/b")
+        .with_page_token(StatePage::token_after(&StateKey::new("org/c", "a")));
+
+    assert_eq!(
+        keys(&test.backend.list_states(&before).await.unwrap()),
+        vec!["org/b:x"]
+    );
+    assert!(test
+        .backend
+        .list_states(&after)
+        .await
+        .unwrap()
+        .states
+        .is_empty());
+}
+
+#[tokio::test]
+async fn test_dynamodb_backend_scan_states_reads_every_state_once() {
+    let test = test_backend().await;
+    for (repo, path) in [
+        ("org/a", "x"),
+        ("org/a", "y"),
+        ("org/b", "x"),
+        ("org/c", "x"),
+        ("org/c", "y"),
+    ] {
+        test.backend
+            .update_state(&state_for(repo, path))
+            .await
+            .unwrap();
+    }
+
+    let mut scanned = Vec::new();
+    let mut page_token = None;
+    loop {
+        let page = test
+            .backend
+            .scan_states(page_token.as_deref(), 2)
+            .await
+            .unwrap();
+        assert!(page.states.len() <= 2);
+        scanned.extend(keys(&page));
+        page_token = page.next_page_token;
+        if page_token.is_none() {
+            break;
+        }
+    }
+
+    scanned.sort();
+    assert_eq!(
+        scanned,
+        vec!["org/a:x", "org/a:y", "org/b:x", "org/c:x", "org/c:y"]
+    );
+}
+
+#[tokio::test]
+async fn test_dynamodb_backend_delete_state() {
+    let test = test_backend().await;
+    let state = state_for("org/a", "README.md");
+    test.backend.update_state(&state).await.unwrap();
+
+    assert!(test.backend.delete_state(&state.key()).await.unwrap());
+    assert_eq!(test.backend.get_state(&state.key()).await.unwrap(), None);
+    assert!(!test.backend.delete_state(&state.key()).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_dynamodb_backend_history_retention() {
+    let test = test_backend().await;
+    let backend = test.backend.clone().with_history_retention(2);
+    let mut state = state_for("org/a", "README.md");
+    for checksum in ["one", "two", "three"] {
+        state.master_checksum = checksum.to_string();
+        backend.update_state(&state).await.unwrap();
+    }
+
+    let history = backend.get_state_history(&state.key()).await.unwrap();
+
+    let checksums: Vec<_> = history.iter().map(|s| s.master_checksum.as_str()).collect();
+    assert_eq!(checksums, ["three", "two"]);
+    assert_eq!(history[1].version, 2);
+}
+
+#[tokio::test]
+async fn test_dynamodb_backend_history_keeps_items_without_version() {
+    let test = test_backend().await;
+    let state = state_for("org/a", "README.md");
+    // Items written before versions were introduced have neither a version nor a history
+    let mut item = state_to_item(&state, 0);
+    item.remove(VERSION);
+    test.backend
+        .client
+        .put_item()
+        .table_name(test.backend.table_name())
+        .set_item(Some(item))
+        .send()
+        .await
+        .unwrap();
+
+    test.backend.update_state(&state).await.unwrap();
+
+    let history = test.backend.get_state_history(&state.key()).await.unwrap();
+    let versions: Vec<u64> = history.iter().map(|state| state.version).collect();
+    assert_eq!(versions, [1, 0]);
+}
+
+#[tokio::test]
+async fn test_dynamodb_backend_get_states_requests_unprocessed_keys() {
+    let test = test_backend().await;
+    let states: Vec<TemplateState> = (0..5)
+        .map(|i| state_for("org/a", &format!("file_{}.md", i)))
+        .collect();
+    test.backend.update_states(&states).await.unwrap();
+    let mut keys: Vec<StateKey> = states.iter().map(TemplateState::key).rev().collect();
+    keys.push(StateKey::new("org/a", "missing.md"));
+    keys.push(states[0].key());
+
+    // The stand-in processes two keys per request, so the keys take three requests
+    let stored = test.backend.get_states(&keys).await.unwrap();
+
+    let paths: Vec<Option<&str>> = stored
+        .iter()
+        .map(|state| state.as_ref().map(|s| s.template_path.as_str()))
+        .collect();
+    assert_eq!(
+        paths,
+        [
+            Some("file_4.md"),
+            Some("file_3.md"),
+            Some("file_2.md"),
+            Some("file_1.md"),
+            Some("file_0.md"),
+            None,
+            Some("file_0.md"),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_dynamodb_backend_conformance() {
+    let test_backends = std::sync::Mutex::new(Vec::new());
+    template_teleporter_core::conformance::run_all(|| async {
+        let test = test_backend().await;
+        let backend = Arc::new(test.backend.clone()) as Arc<dyn StatePersistence>;
+        // Keeps the stand-in servers running until the suite has finished.
+        test_backends.lock().unwrap().push(test);
+        backend
+    })
+    .await;
+}
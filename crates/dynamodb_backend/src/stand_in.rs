@@ -0,0 +1,262 @@
+//! A minimal in-process stand-in for DynamoDB Local, used by the tests when no `DYNAMODB_ENDPOINT`
+//! is configured.
+//!
+//! It speaks the DynamoDB JSON protocol for the operations and the subset of the expression
+//! language used by `DynamoDbBackend`. `Query` and `Scan` return at most `PAGE_SIZE` items per
+//! response, `BatchGetItem` processes at most `PAGE_SIZE` keys per request and returns the others
+//! as unprocessed, and `Scan` returns items in reverse key order, so the backend's pagination,
+//! retries and sorting are exercised as they would be against a large table.
+
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use wiremock::matchers::method;
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+const PAGE_SIZE: usize = 2;
+
+type Item = Map<String, Value>;
+type Table = BTreeMap<(String, String), Item>;
+
+/// Starts a stand-in server and returns it; its `uri()` is the endpoint to use.
+pub(crate) async fn start() -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(StandIn::default())
+        .mount(&server)
+        .await;
+    server
+}
+
+#[derive(Default)]
+struct StandIn {
+    tables: Arc<Mutex<HashMap<String, Table>>>,
+}
+
+impl Respond for StandIn {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let operation = request
+            .headers
+            .get("x-amz-target")
+            .and_then(|target| target.to_str().ok())
+            .and_then(|target| target.strip_prefix("DynamoDB_20120810."))
+            .unwrap_or_default()
+            .to_string();
+        let body: Value = serde_json::from_slice(&request.body).unwrap_or_default();
+        let mut tables = self.tables.lock().unwrap();
+
+        let result = match operation.as_str() {
+            "CreateTable" => create_table(&mut tables, &body),
+            "BatchGetItem" => batch_get_item(&tables, &body),
+            operation => match tables.get_mut(str_field(&body, "TableName")) {
+                None => Err(("ResourceNotFoundException", "Requested resource not found")),
+                Some(table) => match operation {
+                    "GetItem" => get_item(table, &body),
+                    "PutItem" => put_item(table, &body),
+                    "DeleteItem" => delete_item(table, &body),
+                    "Query" => query(table, &body),
+                    "Scan" => scan(table, &body),
+                    _ => Err(("UnknownOperationException", "Unsupported operation")),
+                },
+            },
+        };
+
+        match result {
+            Ok(body) => respond_with(200, body),
+            Err((error_type, message)) => respond_with(
+                400,
+                json!({
+                    "__type": format!("com.amazonaws.dynamodb.v20120810#{}", error_type),
+                    "message": message,
+                }),
+            ),
+        }
+    }
+}
+
+type OperationResult = Result<Value, (&'static str, &'static str)>;
+
+fn respond_with(status: u16, body: Value) -> ResponseTemplate {
+    ResponseTemplate::new(status).set_body_raw(body.to_string(), "application/x-amz-json-1.0")
+}
+
+fn create_table(tables: &mut HashMap<String, Table>, body: &Value) -> OperationResult {
+    let name = str_field(body, "TableName").to_string();
+    if tables.contains_key(&name) {
+        return Err(("ResourceInUseException", "Table already exists"));
+    }
+    tables.insert(name.clone(), Table::new());
+    Ok(json!({ "TableDescription": { "TableName": name, "TableStatus": "ACTIVE" } }))
+}
+
+fn get_item(table: &Table, body: &Value) -> OperationResult {
+    match table.get(&table_key(&body["Key"])) {
+        Some(item) => Ok(json!({ "Item": item })),
+        None => Ok(json!({})),
+    }
+}
+
+fn batch_get_item(tables: &HashMap<String, Table>, body: &Value) -> OperationResult {
+    let mut responses = Map::new();
+    let mut unprocessed = Map::new();
+    let request_items = body["RequestItems"]
+        .as_object()
+        .cloned()
+        .unwrap_or_default();
+    for (name, request) in request_items {
+        let Some(table) = tables.get(&name) else {
+            return Err(("ResourceNotFoundException", "Requested resource not found"));
+        };
+        let keys = request["Keys"].as_array().cloned().unwrap_or_default();
+        let unique: HashSet<_> = keys.iter().map(table_key).collect();
+        if unique.len() != keys.len() {
+            return Err((
+                "ValidationException",
+                "Provided list of item keys contains duplicates",
+            ));
+        }
+        let items: Vec<_> = keys
+            .iter()
+            .take(PAGE_SIZE)
+            .filter_map(|key| table.get(&table_key(key)))
+            .collect();
+        responses.insert(name.clone(), json!(items));
+        if keys.len() > PAGE_SIZE {
+            let mut rest = request.clone();
+            rest["Keys"] = json!(keys[PAGE_SIZE..]);
+            unprocessed.insert(name, rest);
+        }
+    }
+    Ok(json!({ "Responses": responses, "UnprocessedKeys": unprocessed }))
+}
+
+fn put_item(table: &mut Table, body: &Value) -> OperationResult {
+    let item = body["Item"].as_object().cloned().unwrap_or_default();
+    let key = table_key(&body["Item"]);
+    check_condition(table.get(&key), body)?;
+    table.insert(key, item);
+    Ok(json!({}))
+}
+
+fn delete_item(table: &mut Table, body: &Value) -> OperationResult {
+    let old = table.remove(&table_key(&body["Key"]));
+    match old {
+        Some(item) if str_field(body, "ReturnValues") == "ALL_OLD" => {
+            Ok(json!({ "Attributes": item }))
+        }
+        _ => Ok(json!({})),
+    }
+}
+
+fn query(table: &Table, body: &Value) -> OperationResult {
+    let start = body.get("ExclusiveStartKey").map(table_key);
+    let condition = str_field(body, "KeyConditionExpression");
+    let matching = table
+        .iter()
+        .filter(|(key, _)| start.as_ref().is_none_or(|start| *key > start))
+        .filter(|(_, item)| evaluate(Some(item), body, condition))
+        .map(|(_, item)| item);
+    page(matching, body)
+}
+
+fn scan(table: &Table, body: &Value) -> OperationResult {
+    let start = body.get("ExclusiveStartKey").map(table_key);
+    let filter = str_field(body, "FilterExpression");
+    let matching = table
+        .iter()
+        .rev()
+        .filter(|(key, _)| start.as_ref().is_none_or(|start| *key < start))
+        .filter(|(_, item)| filter.is_empty() || evaluate(Some(item), body, filter))
+        .map(|(_, item)| item);
+    page(matching, body)
+}
+
+fn page<'a>(items: impl Iterator<Item = &'a Item>, body: &Value) -> OperationResult {
+    let limit = body["Limit"]
+        .as_u64()
+        .map_or(PAGE_SIZE, |limit| (limit as usize).min(PAGE_SIZE));
+    let mut items = items.peekable();
+    let page: Vec<_> = items.by_ref().take(limit).collect();
+    let mut response = json!({ "Items": page, "Count": page.len(), "ScannedCount": page.len() });
+    if items.peek().is_some() {
+        if let Some(last) = page.last() {
+            response["LastEvaluatedKey"] = json!({
+                "repo": last["repo"],
+                "template_path": last["template_path"],
+            });
+        }
+    }
+    Ok(response)
+}
+
+fn check_condition(item: Option<&Item>, body: &Value) -> Result<(), (&'static str, &'static str)> {
+    match body["ConditionExpression"].as_str() {
+        Some(condition) if !evaluate(item, body, condition) => Err((
+            "ConditionalCheckFailedException",
+            "The conditional request failed",
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Evaluates a conjunction of `attribute_not_exists(a)`, `begins_with(a, b)` and `a = b` terms.
+fn evaluate(item: Option<&Item>, body: &Value, expression: &str) -> bool {
+    expression.split(" AND ").all(|term| {
+        let term = term.trim();
+        if let Some(args) = function_args(term, "attribute_not_exists") {
+            operand(item, body, args[0]).is_none()
+        } else if let Some(args) = function_args(term, "begins_with") {
+            let value = operand(item, body, args[0]);
+            let prefix = operand(item, body, args[1]);
+            match (value, prefix) {
+                (Some(value), Some(prefix)) => value["S"]
+                    .as_str()
+                    .zip(prefix["S"].as_str())
+                    .is_some_and(|(value, prefix)| value.starts_with(prefix)),
+                _ => false,
+            }
+        } else if let Some((left, right)) = term.split_once(" = ") {
+            let left = operand(item, body, left.trim());
+            left.is_some() && left == operand(item, body, right.trim())
+        } else {
+            false
+        }
+    })
+}
+
+fn function_args<'a>(term: &'a str, function: &str) -> Option<Vec<&'a str>> {
+    term.strip_prefix(function)?
+        .strip_prefix('(')?
+        .strip_suffix(')')
+        .map(|args| args.split(',').map(str::trim).collect())
+}
+
+/// Resolves `:value` placeholders from the request and `#name` placeholders from the item.
+fn operand(item: Option<&Item>, body: &Value, token: &str) -> Option<Value> {
+    if token.starts_with(':') {
+        body["ExpressionAttributeValues"].get(token).cloned()
+    } else {
+        item?.get(&attribute_name(body, token)).cloned()
+    }
+}
+
+fn attribute_name(body: &Value, token: &str) -> String {
+    body["ExpressionAttributeNames"][token]
+        .as_str()
+        .unwrap_or(token)
+        .to_string()
+}
+
+fn table_key(item: &Value) -> (String, String) {
+    (
+        item["repo"]["S"].as_str().unwrap_or_default().to_string(),
+        item["template_path"]["S"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+    )
+}
+
+fn str_field<'a>(body: &'a Value, field: &str) -> &'a str {
+    body[field].as_str().unwrap_or_default()
+}
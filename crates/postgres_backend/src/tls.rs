@@ -0,0 +1,334 @@
+//! TLS for PostgreSQL connections, configured like libpq by the `sslmode` and `sslrootcert`
+//! parameters of the connection string. tokio-postgres only knows the modes `disable`, `prefer`
+//! and `require` and always verifies certificates, so both parameters are removed from the
+//! connection string and handled here.
+
+use percent_encoding::percent_decode_str;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::{ring, verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{
+    CertificateError, ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+use std::sync::Arc;
+use template_teleporter_core::{CoreError, Result};
+use tokio_postgres_rustls::MakeRustlsConnect;
+
+#[cfg(test)]
+#[path = "tls_tests.rs"]
+mod tests;
+
+/// The `sslmode` of a connection, as defined by libpq.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TlsMode {
+    Disable,
+    Prefer,
+    Require,
+    VerifyCa,
+    VerifyFull,
+}
+
+/// The TLS parameters of a connection string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TlsSettings {
+    pub(crate) mode: TlsMode,
+    /// The `sslrootcert` parameter.
+    pub(crate) root_cert: Option<String>,
+}
+
+impl TlsSettings {
+    /// Removes the `sslmode` and `sslrootcert` parameters from a connection string, either a
+    /// `postgres://` URL or `key=value` pairs.
+    ///
+    /// # Returns
+    /// A `Result` containing the connection string without the parameters and the settings they
+    /// select, or a `CoreError::DatabaseError` if the connection string or `sslmode` is invalid.
+    pub(crate) fn parse(conn: &str) -> Result<(String, TlsSettings)> {
+        let mut mode = None;
+        let mut root_cert = None;
+        let mut take = |key: &str, value: String| match key {
+            "sslmode" => {
+                mode = Some(value);
+                true
+            }
+            "sslrootcert" => {
+                root_cert = Some(value);
+                true
+            }
+            _ => false,
+        };
+        let conn = if conn.starts_with("postgres://") || conn.starts_with("postgresql://") {
+            strip_url_params(conn, &mut take)?
+        } else {
+            strip_key_value_params(conn, &mut take)?
+        };
+
+        let mode = match mode.as_deref() {
+            None | Some("allow") | Some("prefer") => TlsMode::Prefer,
+            Some("disable") => TlsMode::Disable,
+            Some("require") => TlsMode::Require,
+            Some("verify-ca") => TlsMode::VerifyCa,
+            Some("verify-full") => TlsMode::VerifyFull,
+            Some(mode) => {
+                return Err(CoreError::DatabaseError(format!(
+                    "Invalid PostgreSQL config: invalid value for sslmode: {}",
+                    mode
+                )))
+            }
+        };
+        Ok((conn, TlsSettings { mode, root_cert }))
+    }
+
+    /// Returns the mode tokio-postgres negotiates TLS with.
+    pub(crate) fn ssl_mode(&self) -> deadpool_postgres::SslMode {
+        match self.mode {
+            TlsMode::Disable => deadpool_postgres::SslMode::Disable,
+            TlsMode::Prefer => deadpool_postgres::SslMode::Prefer,
+            TlsMode::Require | TlsMode::VerifyCa | TlsMode::VerifyFull => {
+                deadpool_postgres::SslMode::Require
+            }
+        }
+    }
+
+    /// Returns the TLS connector for the settings, or `None` if TLS is disabled.
+    ///
+    /// # Errors
+    /// Returns a `CoreError::DatabaseError` if the `sslrootcert` file cannot be read or holds no
+    /// certificates.
+    pub(crate) fn connector(&self) -> Result<Option<MakeRustlsConnect>> {
+        let provider = Arc::new(ring::default_provider());
+        let verifier: Arc<dyn ServerCertVerifier> = match (self.mode, &self.root_cert) {
+            (TlsMode::Disable, _) => return Ok(None),
+            (TlsMode::Prefer, _) | (TlsMode::Require, None) => {
+                Arc::new(NoVerification(provider.clone()))
+            }
+            (TlsMode::Require, Some(_)) | (TlsMode::VerifyCa, _) => {
+                Arc::new(VerifyCa(self.webpki_verifier(&provider)?))
+            }
+            (TlsMode::VerifyFull, _) => self.webpki_verifier(&provider)?,
+        };
+        let config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .map_err(|e| CoreError::DatabaseError(format!("Invalid TLS config: {}", e)))?
+            .dangerous()
+            .with_custom_certificate_verifier(verifier)
+            .with_no_client_auth();
+        Ok(Some(MakeRustlsConnect::new(config)))
+    }
+
+    fn webpki_verifier(&self, provider: &Arc<CryptoProvider>) -> Result<Arc<WebPkiServerVerifier>> {
+        WebPkiServerVerifier::builder_with_provider(Arc::new(self.root_certs()?), provider.clone())
+            .build()
+            .map_err(|e| CoreError::DatabaseError(format!("Invalid TLS config: {}", e)))
+    }
+
+    /// Returns the certificates trusted to issue server certificates.
+    fn root_certs(&self) -> Result<RootCertStore> {
+        let mut roots = RootCertStore::empty();
+        match self.root_cert.as_deref() {
+            Some("system") => {
+                roots.add_parsable_certificates(rustls_native_certs::load_native_certs().certs);
+            }
+            Some(path) => {
+                let invalid = |e: &dyn std::fmt::Display| {
+                    CoreError::DatabaseError(format!(
+                        "Invalid PostgreSQL config: cannot read sslrootcert {}: {}",
+                        path, e
+                    ))
+                };
+                for cert in CertificateDer::pem_file_iter(path).map_err(|e| invalid(&e))? {
+                    roots
+                        .add(cert.map_err(|e| invalid(&e))?)
+                        .map_err(|e| invalid(&e))?;
+                }
+                if roots.is_empty() {
+                    return Err(invalid(&"no certificates found"));
+                }
+            }
+            None => {
+                roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+                // System certificates that cannot be read or parsed are skipped, like other TLS
+                // clients do.
+                roots.add_parsable_certificates(rustls_native_certs::load_native_certs().certs);
+            }
+        }
+        Ok(roots)
+    }
+}
+
+/// Removes the parameters accepted by `take` from the query of a connection URL.
+fn strip_url_params(url: &str, take: &mut impl FnMut(&str, String) -> bool) -> Result<String> {
+    let Some((base, query)) = url.split_once('?') else {
+        return Ok(url.to_string());
+    };
+    let mut kept = Vec::new();
+    for param in query.split('&').filter(|param| !param.is_empty()) {
+        let (key, value) = param.split_once('=').unwrap_or((param, ""));
+        let value = percent_decode_str(value).decode_utf8().map_err(|e| {
+            CoreError::DatabaseError(format!("Invalid PostgreSQL config: {}: {}", key, e))
+        })?;
+        if !take(key, value.into_owned()) {
+            kept.push(param);
+        }
+    }
+    Ok(if kept.is_empty() {
+        base.to_string()
+    } else {
+        format!("{}?{}", base, kept.join("&"))
+    })
+}
+
+/// Removes the parameters accepted by `take` from a `key=value` connection string. Values may be
+/// quoted with `'` and escape characters with `\`.
+fn strip_key_value_params(
+    conn: &str,
+    take: &mut impl FnMut(&str, String) -> bool,
+) -> Result<String> {
+    let invalid =
+        |message: &str| CoreError::DatabaseError(format!("Invalid PostgreSQL config: {}", message));
+    let mut kept = Vec::new();
+    let mut chars = conn.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '=') {
+            key.push(c);
+        }
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.next() != Some('=') {
+            return Err(invalid(&format!("missing value for {}", key)));
+        }
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        let mut value = String::new();
+        if chars.next_if_eq(&'\'').is_some() {
+            loop {
+                match chars.next() {
+                    Some('\'') => break,
+                    Some('\\') => value.extend(chars.next()),
+                    Some(c) => value.push(c),
+                    None => return Err(invalid(&format!("unterminated quote in {}", key))),
+                }
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                match c {
+                    '\\' => value.extend(chars.next()),
+                    c => value.push(c),
+                }
+            }
+        }
+        if !take(&key, value.clone()) {
+            kept.push(format!(
+                "{}='{}'",
+                key,
+                value.replace('\\', "\\\\").replace('\'', "\\'")
+            ));
+        }
+    }
+    Ok(kept.join(" "))
+}
+
+/// Accepts every server certificate, so the connection is encrypted but the server is not
+/// authenticated. The signatures of the handshake are still checked.
+#[derive(Debug)]
+struct NoVerification(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// Verifies that the server certificate is issued by a trusted certificate authority, for any
+/// host name.
+#[derive(Debug)]
+struct VerifyCa(Arc<WebPkiServerVerifier>);
+
+impl ServerCertVerifier for VerifyCa {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        match self
+            .0
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+        {
+            // The name is checked after the issuer, so the certificate is otherwise valid.
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::NotValidForName | CertificateError::NotValidForNameContext { .. },
+            )) => Ok(ServerCertVerified::assertion()),
+            result => result,
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        self.0.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        self.0.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.supported_verify_schemes()
+    }
+}
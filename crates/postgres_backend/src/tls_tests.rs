@@ -0,0 +1,105 @@
+//! Tests for the TLS parameters of connection strings.
+
+use super::*;
+
+fn parse(conn: &str) -> (String, TlsSettings) {
+    TlsSettings::parse(conn).unwrap()
+}
+
+fn settings(mode: TlsMode, root_cert: Option<&str>) -> TlsSettings {
+    TlsSettings {
+        mode,
+        root_cert: root_cert.map(str::to_string),
+    }
+}
+
+#[test]
+fn test_parse_defaults_to_prefer() {
+    assert_eq!(
+        parse("postgres://user@host/db"),
+        (
+            "postgres://user@host/db".to_string(),
+            settings(TlsMode::Prefer, None)
+        )
+    );
+    assert_eq!(
+        parse("host=localhost user=postgres"),
+        (
+            "host='localhost' user='postgres'".to_string(),
+            settings(TlsMode::Prefer, None)
+        )
+    );
+}
+
+#[test]
+fn test_parse_removes_tls_params_from_url() {
+    assert_eq!(
+        parse("postgresql://user@host/db?connect_timeout=5&sslmode=verify-ca&sslrootcert=%2Fetc%2Fca%20certs.pem"),
+        (
+            "postgresql://user@host/db?connect_timeout=5".to_string(),
+            settings(TlsMode::VerifyCa, Some("/etc/ca certs.pem"))
+        )
+    );
+    assert_eq!(
+        parse("postgres://user@host/db?sslmode=disable"),
+        (
+            "postgres://user@host/db".to_string(),
+            settings(TlsMode::Disable, None)
+        )
+    );
+}
+
+#[test]
+fn test_parse_removes_tls_params_from_key_values() {
+    assert_eq!(
+        parse(
+            r"host=localhost sslmode = verify-full password='it\'s secret' sslrootcert='/etc/ca certs.pem'"
+        ),
+        (
+            r"host='localhost' password='it\'s secret'".to_string(),
+            settings(TlsMode::VerifyFull, Some("/etc/ca certs.pem"))
+        )
+    );
+}
+
+#[test]
+fn test_parse_modes() {
+    for (mode, expected) in [
+        ("disable", TlsMode::Disable),
+        ("allow", TlsMode::Prefer),
+        ("prefer", TlsMode::Prefer),
+        ("require", TlsMode::Require),
+        ("verify-ca", TlsMode::VerifyCa),
+        ("verify-full", TlsMode::VerifyFull),
+    ] {
+        assert_eq!(parse(&format!("sslmode={}", mode)).1.mode, expected);
+    }
+}
+
+#[test]
+fn test_parse_rejects_invalid_params() {
+    for conn in [
+        "postgres://host/db?sslmode=on",
+        "sslmode=on",
+        "host",
+        "password='unterminated",
+    ] {
+        assert!(
+            matches!(TlsSettings::parse(conn), Err(CoreError::DatabaseError(_))),
+            "{} was accepted",
+            conn
+        );
+    }
+}
+
+#[test]
+fn test_connector_is_none_when_tls_is_disabled() {
+    assert!(settings(TlsMode::Disable, None)
+        .connector()
+        .unwrap()
+        .is_none());
+    assert!(settings(TlsMode::Require, None)
+        .connector()
+        .unwrap()
+        .is_some());
+}
@@ -0,0 +1,499 @@
+//! Implements a `StatePersistence` backend on an embedded SQLite database.
+//!
+//! All template states are stored in a single database file, in a `template_state` table keyed
+//! by `(repo, template_path)`. The schema is created and upgraded by [`MIGRATIONS`] when the
+//! database is opened; the applied schema version is tracked in SQLite's `user_version` pragma.
+//! Because every write is a single SQLite transaction, conditional updates are atomic across
+//! all processes using the same database file.
+//!
+//! Every version written is also recorded in a `template_state_history` table, in the same
+//! transaction as the write, which keeps the most recent versions of each state.
+
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::types::Type;
+use rusqlite::{
+    params, params_from_iter, Connection, ErrorCode, OptionalExtension, Row, TransactionBehavior,
+};
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use template_teleporter_core::{
+    CoreError, Result, StateKey, StatePage, StatePersistence, StateQuery, TemplateState,
+    DEFAULT_HISTORY_RETENTION,
+};
+
+#[cfg(test)]
+#[path = "lib_tests.rs"]
+mod tests;
+
+/// The schema migrations, in order. Applying migration `n` (1-based) upgrades the schema to
+/// version `n`. Migrations must never be changed once released; add a new one instead.
+pub const MIGRATIONS: &[&str] = &[
+    // 1: Template state keyed by repository and template path. The primary key orders listings
+    //    by repository; the index serves template path prefix filters across repositories.
+    "CREATE TABLE template_state (
+        repo TEXT NOT NULL,
+        template_path TEXT NOT NULL,
+        source_repository TEXT NOT NULL,
+        master_checksum TEXT NOT NULL,
+        deployed_checksum TEXT,
+        last_updated_utc TEXT NOT NULL,
+        version INTEGER NOT NULL,
+        PRIMARY KEY (repo, template_path)
+    ) WITHOUT ROWID;
+    CREATE INDEX template_state_template_path ON template_state (template_path);",
+    // 2: Every version of each state, newest first by key. Seeded with the current states.
+    "CREATE TABLE template_state_history (
+        repo TEXT NOT NULL,
+        template_path TEXT NOT NULL,
+        source_repository TEXT NOT NULL,
+        master_checksum TEXT NOT NULL,
+        deployed_checksum TEXT,
+        last_updated_utc TEXT NOT NULL,
+        version INTEGER NOT NULL,
+        PRIMARY KEY (repo, template_path, version)
+    ) WITHOUT ROWID;
+    INSERT INTO template_state_history SELECT * FROM template_state;",
+];
+
+/// How long a write waits for other processes holding the database lock before failing.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+const COLUMNS: &str = "repo, template_path, source_repository, master_checksum, \
+                       deployed_checksum, last_updated_utc, version";
+
+/// Inserts a state with version 1, or overwrites it and increments its version.
+const UPSERT_INCREMENTING_VERSION: &str = "INSERT INTO template_state (repo, template_path, \
+     source_repository, master_checksum, deployed_checksum, last_updated_utc, version) \
+     VALUES (?1, ?2, ?3, ?4, ?5, ?6, 1) \
+     ON CONFLICT (repo, template_path) DO UPDATE SET \
+     source_repository = excluded.source_repository, \
+     master_checksum = excluded.master_checksum, \
+     deployed_checksum = excluded.deployed_checksum, \
+     last_updated_utc = excluded.last_updated_utc, \
+     version = template_state.version + 1";
+
+/// Inserts or overwrites a state with the given version.
+const UPSERT_WITH_VERSION: &str = "INSERT INTO template_state (repo, template_path, \
+     source_repository, master_checksum, deployed_checksum, last_updated_utc, version) \
+     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7) \
+     ON CONFLICT (repo, template_path) DO UPDATE SET \
+     source_repository = excluded.source_repository, \
+     master_checksum = excluded.master_checksum, \
+     deployed_checksum = excluded.deployed_checksum, \
+     last_updated_utc = excluded.last_updated_utc, \
+     version = excluded.version";
+
+/// Copies the current version of a state into the history.
+const RECORD_HISTORY: &str = "INSERT OR REPLACE INTO template_state_history \
+     SELECT * FROM template_state WHERE repo = ?1 AND template_path = ?2";
+
+/// Removes the versions of a state which are older than the retention allows.
+const TRIM_HISTORY: &str = "DELETE FROM template_state_history \
+     WHERE repo = ?1 AND template_path = ?2 AND version <= ( \
+         SELECT version - ?3 FROM template_state WHERE repo = ?1 AND template_path = ?2)";
+
+/// A state persistence backend that stores `TemplateState` in an SQLite database.
+///
+/// # Example
+/// ```rust
+/// use template_teleporter_sqlite_backend::SqliteBackend;
+/// # fn run() -> template_teleporter_core::Result<()> {
+/// let backend = SqliteBackend::new_in_memory()?;
+/// # Ok(())
+/// # }
+/// # run().unwrap();
+/// ```
+pub struct SqliteBackend {
+    // rusqlite connections are not Sync, and all calls block, so the connection is shared with
+    // the blocking thread pool behind a mutex.
+    connection: Arc<Mutex<Connection>>,
+    history_retention: usize,
+}
+
+// Manual Debug implementation because Connection is not Debug.
+impl fmt::Debug for SqliteBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SqliteBackend").finish_non_exhaustive()
+    }
+}
+
+impl SqliteBackend {
+    /// Opens (or creates) the SQLite database at `path` and migrates it to the latest schema.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the database file. Its parent directory must exist.
+    ///
+    /// # Returns
+    /// A `Result` containing the new `SqliteBackend`, or a `CoreError::DatabaseError` if the
+    /// database cannot be opened or migrated, e.g. because it was created by a newer version.
+    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        let connection = Connection::open(path.as_ref()).map_err(db_error)?;
+        // WAL lets readers in other processes continue while a write is in progress.
+        connection
+            .pragma_update(None, "journal_mode", "WAL")
+            .map_err(db_error)?;
+        Self::from_connection(connection)
+    }
+
+    /// Creates a backend on a private in-memory database, e.g. for tests.
+    pub fn new_in_memory() -> Result<Self> {
+        Self::from_connection(Connection::open_in_memory().map_err(db_error)?)
+    }
+
+    fn from_connection(mut connection: Connection) -> Result<Self> {
+        connection.busy_timeout(BUSY_TIMEOUT).map_err(db_error)?;
+        migrate(&mut connection)?;
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+            history_retention: DEFAULT_HISTORY_RETENTION,
+        })
+    }
+
+    /// Sets how many versions of each state are kept in the history, including the current
+    /// version. Values below 1 are treated as 1.
+    pub fn with_history_retention(mut self, retention: usize) -> Self {
+        self.history_retention = retention.max(1);
+        self
+    }
+
+    /// Runs a blocking operation on the connection on the blocking thread pool.
+    async fn with_connection<T, F>(&self, operation: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+    {
+        let connection = Arc::clone(&self.connection);
+        tokio::task::spawn_blocking(move || {
+            let mut connection = connection.lock().map_err(|_| {
+                CoreError::DatabaseError("SQLite connection lock poisoned".to_string())
+            })?;
+            operation(&mut connection)
+        })
+        .await
+        .map_err(|e| CoreError::DatabaseError(format!("SQLite task failed: {}", e)))?
+    }
+}
+
+#[async_trait]
+impl StatePersistence for SqliteBackend {
+    async fn get_state(&self, key: &StateKey) -> Result<Option<TemplateState>> {
+        let key = key.clone();
+        self.with_connection(move |connection| {
+            connection
+                .query_row(
+                    &format!(
+                        "SELECT {} FROM template_state WHERE repo = ?1 AND template_path = ?2",
+                        COLUMNS
+                    ),
+                    params![key.repo, key.template_path],
+                    state_from_row,
+                )
+                .optional()
+                .map_err(db_error)
+        })
+        .await
+    }
+
+    async fn update_state(&self, state: &TemplateState) -> Result<()> {
+        self.update_states(std::slice::from_ref(state)).await
+    }
+
+    /// Checks the version and writes the state in one immediate transaction, which holds the
+    /// database write lock, so the update is atomic across processes.
+    async fn update_state_if(
+        &self,
+        state: &TemplateState,
+        expected_version: Option<u64>,
+    ) -> Result<u64> {
+        let state = state.clone();
+        let retention = self.history_retention;
+        self.with_connection(move |connection| {
+            let tx = connection
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(db_error)?;
+            let actual_version: Option<u64> = tx
+                .query_row(
+                    "SELECT version FROM template_state WHERE repo = ?1 AND template_path = ?2",
+                    params![state.repo, state.template_path],
+                    |row| row.get(0),
+                )
+                .optional()
+                .map_err(db_error)?;
+            if actual_version != expected_version {
+                return Err(CoreError::StateConflict {
+                    key: state.key(),
+                    expected: expected_version,
+                    actual: actual_version,
+                });
+            }
+
+            let new_version = expected_version.map_or(1, |version| version + 1);
+            tx.execute(
+                UPSERT_WITH_VERSION,
+                params![
+                    state.repo,
+                    state.template_path,
+                    state.source_repository,
+                    state.master_checksum,
+                    state.deployed_checksum,
+                    format_timestamp(&state.last_updated_utc),
+                    new_version,
+                ],
+            )
+            .map_err(db_error)?;
+            record_history(&tx, &state.key(), retention)?;
+            tx.commit().map_err(db_error)?;
+            Ok(new_version)
+        })
+        .await
+    }
+
+    /// Lists states with an indexed range query, fetching one extra row to detect further pages.
+    async fn list_states(&self, query: &StateQuery) -> Result<StatePage> {
+        let start_after = query.start_after()?;
+        let query = query.clone();
+        self.with_connection(move |connection| {
+            let mut conditions = Vec::new();
+            let mut values = Vec::new();
+            if let Some(repo) = &query.repo {
+                values.push(repo.clone());
+                conditions.push(format!("repo = ?{}", values.len()));
+            }
+            if let Some(prefix) = &query.template_path_prefix {
+                // GLOB is case sensitive, so SQLite can use the index for the prefix.
+                values.push(format!("{}*", escape_glob(prefix)));
+                conditions.push(format!("template_path GLOB ?{}", values.len()));
+            }
+            if let Some(after) = start_after {
+                values.push(after.repo);
+                values.push(after.template_path);
+                conditions.push(format!(
+                    "(repo, template_path) > (?{}, ?{})",
+                    values.len() - 1,
+                    values.len()
+                ));
+            }
+
+            let mut sql = format!("SELECT {} FROM template_state", COLUMNS);
+            if !conditions.is_empty() {
+                sql.push_str(" WHERE ");
+                sql.push_str(&conditions.join(" AND "));
+            }
+            sql.push_str(" ORDER BY repo, template_path");
+            if let Some(limit) = query.limit {
+                sql.push_str(&format!(" LIMIT {}", limit.saturating_add(1)));
+            }
+
+            let mut statement = connection.prepare(&sql).map_err(db_error)?;
+            let mut states = statement
+                .query_map(params_from_iter(values), state_from_row)
+                .map_err(db_error)?
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .map_err(db_error)?;
+
+            let has_more = query.limit.is_some_and(|limit| states.len() > limit);
+            if let Some(limit) = query.limit {
+                states.truncate(limit);
+            }
+            Ok(StatePage::new(states, has_more))
+        })
+        .await
+    }
+
+    /// Deletes the state and its history in one transaction.
+    async fn delete_state(&self, key: &StateKey) -> Result<bool> {
+        let key = key.clone();
+        self.with_connection(move |connection| {
+            let tx = connection
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(db_error)?;
+            let deleted = tx
+                .execute(
+                    "DELETE FROM template_state WHERE repo = ?1 AND template_path = ?2",
+                    params![key.repo, key.template_path],
+                )
+                .map_err(db_error)?;
+            tx.execute(
+                "DELETE FROM template_state_history WHERE repo = ?1 AND template_path = ?2",
+                params![key.repo, key.template_path],
+            )
+            .map_err(db_error)?;
+            tx.commit().map_err(db_error)?;
+            Ok(deleted > 0)
+        })
+        .await
+    }
+
+    async fn get_state_history(&self, key: &StateKey) -> Result<Vec<TemplateState>> {
+        let key = key.clone();
+        let retention = self.history_retention;
+        self.with_connection(move |connection| {
+            let mut statement = connection
+                .prepare_cached(&format!(
+                    "SELECT {} FROM template_state_history \
+                     WHERE repo = ?1 AND template_path = ?2 ORDER BY version DESC LIMIT ?3",
+                    COLUMNS
+                ))
+                .map_err(db_error)?;
+            let history = statement
+                .query_map(
+                    params![key.repo, key.template_path, retention as i64],
+                    state_from_row,
+                )
+                .map_err(db_error)?
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .map_err(db_error)?;
+            Ok(history)
+        })
+        .await
+    }
+
+    /// Reads all states in one transaction, so they are consistent with each other.
+    async fn get_states(&self, keys: &[StateKey]) -> Result<Vec<Option<TemplateState>>> {
+        let keys = keys.to_vec();
+        self.with_connection(move |connection| {
+            let tx = connection.transaction().map_err(db_error)?;
+            let mut states = Vec::with_capacity(keys.len());
+            {
+                let mut statement = tx
+                    .prepare_cached(&format!(
+                        "SELECT {} FROM template_state WHERE repo = ?1 AND template_path = ?2",
+                        COLUMNS
+                    ))
+                    .map_err(db_error)?;
+                for key in &keys {
+                    states.push(
+                        statement
+                            .query_row(params![key.repo, key.template_path], state_from_row)
+                            .optional()
+                            .map_err(db_error)?,
+                    );
+                }
+            }
+            tx.commit().map_err(db_error)?;
+            Ok(states)
+        })
+        .await
+    }
+
+    /// Writes all states in a single transaction: either all of them are written, or none are.
+    async fn update_states(&self, states: &[TemplateState]) -> Result<()> {
+        let states = states.to_vec();
+        let retention = self.history_retention;
+        self.with_connection(move |connection| {
+            let tx = connection
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(db_error)?;
+            {
+                let mut statement = tx
+                    .prepare_cached(UPSERT_INCREMENTING_VERSION)
+                    .map_err(db_error)?;
+                for state in &states {
+                    statement
+                        .execute(params![
+                            state.repo,
+                            state.template_path,
+                            state.source_repository,
+                            state.master_checksum,
+                            state.deployed_checksum,
+                            format_timestamp(&state.last_updated_utc),
+                        ])
+                        .map_err(db_error)?;
+                    record_history(&tx, &state.key(), retention)?;
+                }
+            }
+            tx.commit().map_err(db_error)
+        })
+        .await
+    }
+}
+
+/// Applies the migrations the database has not seen yet, each in its own transaction.
+fn migrate(connection: &mut Connection) -> Result<()> {
+    let applied: usize = connection
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .map_err(db_error)?;
+    if applied > MIGRATIONS.len() {
+        return Err(CoreError::DatabaseError(format!(
+            "SQLite schema version {} is newer than the latest known version {}",
+            applied,
+            MIGRATIONS.len()
+        )));
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+        let tx = connection
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(db_error)?;
+        tx.execute_batch(migration).map_err(db_error)?;
+        tx.pragma_update(None, "user_version", index + 1)
+            .map_err(db_error)?;
+        tx.commit().map_err(db_error)?;
+    }
+    Ok(())
+}
+
+/// Records the state just written in the history and drops versions beyond the retention.
+fn record_history(connection: &Connection, key: &StateKey, retention: usize) -> Result<()> {
+    connection
+        .execute(RECORD_HISTORY, params![key.repo, key.template_path])
+        .map_err(db_error)?;
+    connection
+        .execute(
+            TRIM_HISTORY,
+            params![key.repo, key.template_path, retention as i64],
+        )
+        .map_err(db_error)?;
+    Ok(())
+}
+
+fn state_from_row(row: &Row<'_>) -> rusqlite::Result<TemplateState> {
+    let last_updated: String = row.get(5)?;
+    let last_updated_utc = DateTime::parse_from_rfc3339(&last_updated)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(5, Type::Text, Box::new(e)))?
+        .with_timezone(&Utc);
+    Ok(TemplateState {
+        repo: row.get(0)?,
+        template_path: row.get(1)?,
+        source_repository: row.get(2)?,
+        master_checksum: row.get(3)?,
+        deployed_checksum: row.get(4)?,
+        last_updated_utc,
+        version: row.get(6)?,
+    })
+}
+
+/// Formats a timestamp so that it sorts correctly as text and round-trips exactly.
+fn format_timestamp(timestamp: &DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::Nanos, true)
+}
+
+/// Escapes the GLOB wildcards in a literal prefix.
+fn escape_glob(prefix: &str) -> String {
+    let mut escaped = String::with_capacity(prefix.len());
+    for c in prefix.chars() {
+        match c {
+            '*' | '?' | '[' => {
+                escaped.push('[');
+                escaped.push(c);
+                escaped.push(']');
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn db_error(e: rusqlite::Error) -> CoreError {
+    let message = format!("SQLite error: {}", e);
+    match e.sqlite_error_code() {
+        // Another connection held the lock for longer than the busy timeout.
+        Some(ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked) => {
+            CoreError::TransientDatabaseError(message)
+        }
+        _ => CoreError::DatabaseError(message),
+    }
+}
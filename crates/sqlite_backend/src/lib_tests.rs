@@ -0,0 +1,379 @@
+use super::*;
+use tempfile::tempdir;
+
+fn state_for(repo: &str, template_path: &str) -> TemplateState {
+    TemplateState {
+        repo: repo.to_string(),
+        template_path: template_path.to_string(),
+        source_repository: "owner/master".to_string(),
+        master_checksum: "checksum".to_string(),
+        deployed_checksum: Some("deployed".to_string()),
+        last_updated_utc: Utc::now(),
+        version: 0,
+    }
+}
+
+fn user_version(path: &Path) -> usize {
+    Connection::open(path)
+        .unwrap()
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_sqlite_backend_new_migrates_and_reopens() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("state.db");
+
+    let backend = SqliteBackend::new(&path).unwrap();
+    backend
+        .update_state(&state_for("org/a", "README.md"))
+        .await
+        .unwrap();
+    drop(backend);
+    assert_eq!(user_version(&path), MIGRATIONS.len());
+
+    // Reopening neither re-runs the migrations nor loses data
+    let backend = SqliteBackend::new(&path).unwrap();
+    let key = StateKey::new("org/a", "README.md");
+    assert!(backend.get_state(&key).await.unwrap().is_some());
+}
+
+#[test]
+fn test_sqlite_backend_rejects_newer_schema() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("state.db");
+    Connection::open(&path)
+        .unwrap()
+        .pragma_update(None, "user_version", MIGRATIONS.len() + 1)
+        .unwrap();
+
+    let result = SqliteBackend::new(&path);
+
+    assert!(matches!(result, Err(CoreError::DatabaseError(msg)) if msg.contains("newer")));
+}
+
+#[tokio::test]
+async fn test_sqlite_backend_update_and_get_state() {
+    let backend = SqliteBackend::new_in_memory().unwrap();
+    let state = state_for("org/a", ".github/PULL_REQUEST_TEMPLATE.md");
+
+    backend.update_state(&state).await.unwrap();
+    let stored = backend.get_state(&state.key()).await.unwrap().unwrap();
+
+    assert_eq!(
+        stored,
+        TemplateState {
+            version: 1,
+            ..state
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_sqlite_backend_get_state_not_found() {
+    let backend = SqliteBackend::new_in_memory().unwrap();
+
+    let result = backend
+        .get_state(&StateKey::new("org/a", "missing"))
+        .await
+        .unwrap();
+
+    assert_eq!(result, None);
+}
+
+#[tokio::test]
+async fn test_sqlite_backend_update_state_overwrites_and_increments_version() {
+    let backend = SqliteBackend::new_in_memory().unwrap();
+    let mut state = state_for("org/a", "README.md");
+    backend.update_state(&state).await.unwrap();
+
+    state.master_checksum = "updated".to_string();
+    state.deployed_checksum = None;
+    backend.update_state(&state).await.unwrap();
+
+    let stored = backend.get_state(&state.key()).await.unwrap().unwrap();
+    assert_eq!(stored.master_checksum, "updated");
+    assert_eq!(stored.deployed_checksum, None);
+    assert_eq!(stored.version, 2);
+}
+
+#[tokio::test]
+async fn test_sqlite_backend_update_state_if() {
+    let backend = SqliteBackend::new_in_memory().unwrap();
+    let state = state_for("org/a", "README.md");
+
+    assert_eq!(backend.update_state_if(&state, None).await.unwrap(), 1);
+    assert_eq!(backend.update_state_if(&state, Some(1)).await.unwrap(), 2);
+
+    let result = backend.update_state_if(&state, Some(1)).await;
+    assert!(matches!(
+        result,
+        Err(CoreError::StateConflict {
+            expected: Some(1),
+            actual: Some(2),
+            ..
+        })
+    ));
+    let result = backend.update_state_if(&state, None).await;
+    assert!(matches!(
+        result,
+        Err(CoreError::StateConflict {
+            expected: None,
+            actual: Some(2),
+            ..
+        })
+    ));
+    let missing = state_for("org/a", "missing");
+    let result = backend.update_state_if(&missing, Some(1)).await;
+    assert!(matches!(
+        result,
+        Err(CoreError::StateConflict { actual: None, .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_sqlite_backend_update_state_if_is_atomic_across_connections() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("state.db");
+    // Separate connections to the same file behave like separate processes
+    let backends: Vec<_> = (0..5)
+        .map(|_| Arc::new(SqliteBackend::new(&path).unwrap()))
+        .collect();
+
+    let tasks = backends
+        .iter()
+        .map(|backend| {
+            let backend = Arc::clone(backend);
+            tokio::spawn(async move {
+                backend
+                    .update_state_if(&state_for("org/a", "README.md"), None)
+                    .await
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut successes = 0;
+    for task in tasks {
+        match task.await.unwrap() {
+            Ok(version) => {
+                assert_eq!(version, 1);
+                successes += 1;
+            }
+            Err(CoreError::StateConflict { .. }) => {}
+            Err(e) => panic!("Unexpected error: {:?}", e),
+        }
+    }
+    assert_eq!(successes, 1);
+}
+
+#[tokio::test]
+async fn test_sqlite_backend_update_states_writes_batch() {
+    let backend = SqliteBackend::new_in_memory().unwrap();
+    let states: Vec<_> = ["a", "b", "c"]
+        .iter()
+        .map(|path| state_for("org/a", path))
+        .collect();
+    backend.update_state(&states[0]).await.unwrap();
+
+    backend.update_states(&states).await.unwrap();
+
+    let page = backend.list_states(&StateQuery::default()).await.unwrap();
+    let versions: Vec<_> = page.states.iter().map(|state| state.version).collect();
+    assert_eq!(versions, vec![2, 1, 1]);
+}
+
+#[tokio::test]
+async fn test_sqlite_backend_list_states_filters_and_sorts() {
+    let backend = SqliteBackend::new_in_memory().unwrap();
+    for (repo, path) in [
+        ("org/b", ".github/CODEOWNERS"),
+        ("org/a", ".github/PULL_REQUEST_TEMPLATE.md"),
+        ("org/a", "README.md"),
+        ("org/a", ".github/CODEOWNERS"),
+        ("org/a", ".githubx"),
+    ] {
+        backend.update_state(&state_for(repo, path)).await.unwrap();
+    }
+
+    let all = backend.list_states(&StateQuery::default()).await.unwrap();
+    let keys: Vec<_> = all.states.iter().map(|s| s.key().to_string()).collect();
+    assert_eq!(
+        keys,
+        vec![
+            "org/a:.github/CODEOWNERS",
+            "org/a:.github/PULL_REQUEST_TEMPLATE.md",
+            "org/a:.githubx",
+            "org/a:README.md",
+            "org/b:.github/CODEOWNERS",
+        ]
+    );
+    assert_eq!(all.next_page_token, None);
+
+    let by_prefix = backend
+        .list_states(&StateQuery::default().with_template_path_prefix(".github/"))
+        .await
+        .unwrap();
+    let keys: Vec<_> = by_prefix
+        .states
+        .iter()
+        .map(|s| s.key().to_string())
+        .collect();
+    assert_eq!(
+        keys,
+        vec![
+            "org/a:.github/CODEOWNERS",
+            "org/a:.github/PULL_REQUEST_TEMPLATE.md",
+            "org/b:.github/CODEOWNERS",
+        ]
+    );
+
+    let by_repo = backend
+        .list_states(
+            &StateQuery::default()
+                .with_repo("org/b")
+                .with_template_path_prefix(".github/"),
+        )
+        .await
+        .unwrap();
+    assert_eq!(by_repo.states.len(), 1);
+}
+
+#[tokio::test]
+async fn test_sqlite_backend_list_states_prefix_is_literal() {
+    let backend = SqliteBackend::new_in_memory().unwrap();
+    for path in ["docs/[draft]*.md", "docs/d.md", "DOCS/readme.md"] {
+        backend
+            .update_state(&state_for("org/a", path))
+            .await
+            .unwrap();
+    }
+
+    let page = backend
+        .list_states(&StateQuery::default().with_template_path_prefix("docs/[draft]*"))
+        .await
+        .unwrap();
+
+    assert_eq!(page.states.len(), 1);
+    assert_eq!(page.states[0].template_path, "docs/[draft]*.md");
+}
+
+#[tokio::test]
+async fn test_sqlite_backend_list_states_paginates() {
+    let backend = SqliteBackend::new_in_memory().unwrap();
+    for (repo, path) in [
+        ("org/a", "x"),
+        ("org/a", "y"),
+        ("org/b", "x"),
+        ("org/c", "x"),
+    ] {
+        backend.update_state(&state_for(repo, path)).await.unwrap();
+    }
+
+    let mut query = StateQuery::default().with_limit(3);
+    let mut pages = Vec::new();
+    loop {
+        let page = backend.list_states(&query).await.unwrap();
+        pages.push(
+            page.states
+                .iter()
+                .map(|s| s.key().to_string())
+                .collect::<Vec<_>>(),
+        );
+        match page.next_page_token {
+            Some(token) => query = query.with_page_token(token),
+            None => break,
+        }
+    }
+
+    assert_eq!(
+        pages,
+        vec![vec!["org/a:x", "org/a:y", "org/b:x"], vec!["org/c:x"]]
+    );
+}
+
+#[tokio::test]
+async fn test_sqlite_backend_delete_state() {
+    let backend = SqliteBackend::new_in_memory().unwrap();
+    let state = state_for("org/a", "README.md");
+    backend.update_state(&state).await.unwrap();
+
+    assert!(backend.delete_state(&state.key()).await.unwrap());
+    assert_eq!(backend.get_state(&state.key()).await.unwrap(), None);
+    assert!(!backend.delete_state(&state.key()).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_sqlite_backend_history_retention() {
+    let backend = SqliteBackend::new_in_memory()
+        .unwrap()
+        .with_history_retention(2);
+    let mut state = state_for("org/a", "README.md");
+    for checksum in ["one", "two", "three"] {
+        state.master_checksum = checksum.to_string();
+        backend.update_state(&state).await.unwrap();
+    }
+
+    let history = backend.get_state_history(&state.key()).await.unwrap();
+
+    let checksums: Vec<_> = history.iter().map(|s| s.master_checksum.as_str()).collect();
+    assert_eq!(checksums, ["three", "two"]);
+    let stored: usize = backend
+        .connection
+        .lock()
+        .unwrap()
+        .query_row("SELECT COUNT(*) FROM template_state_history", [], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    assert_eq!(stored, 2);
+}
+
+#[tokio::test]
+async fn test_sqlite_backend_history_migration_seeds_current_states() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("state.db");
+    let connection = Connection::open(&path).unwrap();
+    connection.execute_batch(MIGRATIONS[0]).unwrap();
+    connection
+        .execute(
+            "INSERT INTO template_state VALUES ('org/a', 'README.md', 'owner/master', \
+             'checksum', NULL, '2024-01-01T00:00:00Z', 4)",
+            [],
+        )
+        .unwrap();
+    connection.pragma_update(None, "user_version", 1).unwrap();
+    drop(connection);
+
+    let backend = SqliteBackend::new(&path).unwrap();
+
+    let history = backend
+        .get_state_history(&StateKey::new("org/a", "README.md"))
+        .await
+        .unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].version, 4);
+}
+
+#[test]
+fn test_sqlite_backend_busy_database_is_transient() {
+    let busy =
+        rusqlite::Error::SqliteFailure(rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY), None);
+
+    assert!(matches!(
+        db_error(busy),
+        CoreError::TransientDatabaseError(_)
+    ));
+    assert!(matches!(
+        db_error(rusqlite::Error::InvalidQuery),
+        CoreError::DatabaseError(_)
+    ));
+}
+
+#[tokio::test]
+async fn test_sqlite_backend_conformance() {
+    template_teleporter_core::conformance::run_all(|| async {
+        Arc::new(SqliteBackend::new_in_memory().unwrap()) as Arc<dyn StatePersistence>
+    })
+    .await;
+}